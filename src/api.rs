@@ -1,6 +1,14 @@
+pub mod cassette;
+pub mod catalog;
 pub mod client;
+mod error;
+mod http;
 mod logging;
 #[cfg(test)]
 pub mod mock_client;
 pub mod stream;
-pub use client::ApiClient;
+pub use cassette::{Cassette, CassetteMode, ReplayMatch};
+pub use catalog::{ModelCatalog, ModelInfo, ModelPricing};
+pub use client::{resolve_api_protocol, ApiClient, ApiProtocol};
+pub use error::{ApiError, HttpErrorResponse};
+pub use http::{configured_auth_scheme, AuthScheme};
@@ -0,0 +1,537 @@
+//! Record and replay of API traffic.
+//!
+//! A cassette is a JSON Lines file with one [`Interaction`] per request: the
+//! request payload plus the raw response stream chunks. Recording tees live
+//! streams into the file; replay serves `create_stream` from it without
+//! touching the network, so a bug report can ship with a deterministic run.
+
+use super::client::ByteStream;
+use super::error::{ApiError, HttpErrorResponse};
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+
+/// How replayed requests are paired with recorded interactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayMatch {
+    /// The n-th request gets the n-th recorded response.
+    #[default]
+    Order,
+    /// Each request gets the first unused response recorded for an identical
+    /// payload, so reordered or skipped requests still replay.
+    Hash,
+}
+
+impl ReplayMatch {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "order" => Ok(ReplayMatch::Order),
+            "hash" | "content" => Ok(ReplayMatch::Hash),
+            other => bail!("unknown cassette match mode '{other}' (use order or hash)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record(PathBuf),
+    Replay {
+        path: PathBuf,
+        matching: ReplayMatch,
+    },
+}
+
+impl CassetteMode {
+    /// `VEX_CASSETTE_RECORD` or `VEX_CASSETTE_REPLAY` (with `VEX_CASSETTE_MATCH`).
+    pub fn from_env() -> Result<Option<Self>> {
+        let record = env_path("VEX_CASSETTE_RECORD");
+        let replay = env_path("VEX_CASSETTE_REPLAY");
+        match (record, replay) {
+            (Some(_), Some(_)) => {
+                bail!("VEX_CASSETTE_RECORD and VEX_CASSETTE_REPLAY cannot both be set")
+            }
+            (Some(path), None) => Ok(Some(CassetteMode::Record(path))),
+            (None, Some(path)) => {
+                let matching = match std::env::var("VEX_CASSETTE_MATCH") {
+                    Ok(value) if !value.trim().is_empty() => ReplayMatch::parse(&value)?,
+                    _ => ReplayMatch::default(),
+                };
+                Ok(Some(CassetteMode::Replay { path, matching }))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// One recorded request and the response stream it produced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub url: String,
+    /// FNV-1a hash of the serialized payload, used by [`ReplayMatch::Hash`].
+    pub hash: String,
+    pub payload: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// Stream chunks as received. Multi-byte characters split across reads
+    /// are carried into the next chunk so every entry is valid UTF-8.
+    pub chunks: Vec<String>,
+    /// Non-success HTTP response; replay classifies it as the live run did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_error: Option<HttpErrorResponse>,
+    /// Transport error, replayed after the chunks as a network error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+enum CassetteState {
+    Record(Mutex<File>),
+    Replay {
+        interactions: Vec<Interaction>,
+        used: Mutex<Vec<bool>>,
+        matching: ReplayMatch,
+    },
+}
+
+pub struct Cassette {
+    path: PathBuf,
+    state: CassetteState,
+}
+
+impl Cassette {
+    pub fn open(mode: &CassetteMode) -> Result<Self> {
+        match mode {
+            CassetteMode::Record(path) => Self::record(path),
+            CassetteMode::Replay { path, matching } => Self::replay(path, *matching),
+        }
+    }
+
+    /// The cassette `VEX_CASSETTE_RECORD` or `VEX_CASSETTE_REPLAY` names, if
+    /// any. Open it once and share it: every open truncates a recording and
+    /// rewinds a replay.
+    pub fn from_env() -> Result<Option<Arc<Self>>> {
+        CassetteMode::from_env()?
+            .map(|mode| Self::open(&mode).map(Arc::new))
+            .transpose()
+    }
+
+    /// Start a new cassette at `path`, replacing any existing file.
+    pub fn record(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("cannot create cassette '{}'", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: CassetteState::Record(Mutex::new(file)),
+        })
+    }
+
+    pub fn replay(path: &Path, matching: ReplayMatch) -> Result<Self> {
+        let interactions = load_interactions(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: CassetteState::Replay {
+                used: Mutex::new(vec![false; interactions.len()]),
+                interactions,
+                matching,
+            },
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self.state, CassetteState::Replay { .. })
+    }
+
+    /// Serve the response recorded for this request.
+    pub fn replay_stream(&self, payload: &Value) -> Result<ByteStream> {
+        let CassetteState::Replay {
+            interactions,
+            used,
+            matching,
+        } = &self.state
+        else {
+            bail!("cassette '{}' is not in replay mode", self.path.display());
+        };
+        let mut used = used.lock().map_err(|_| anyhow!("cassette lock poisoned"))?;
+        let hash = payload_hash(payload);
+        let position = match matching {
+            ReplayMatch::Order => used.iter().position(|used| !used),
+            ReplayMatch::Hash => interactions
+                .iter()
+                .zip(used.iter())
+                .position(|(interaction, used)| !used && interaction.request.hash == hash),
+        };
+        let Some(position) = position else {
+            bail!(
+                "cassette '{}' has no recorded response for request {} (payload hash {hash})",
+                self.path.display(),
+                used.iter().filter(|used| **used).count() + 1
+            );
+        };
+        used[position] = true;
+
+        let response = interactions[position].response.clone();
+        if let Some(failure) = response.http_error {
+            return Err(failure.to_api_error().into());
+        }
+        if response.chunks.is_empty() {
+            if let Some(error) = response.error {
+                return Err(ApiError::network(error).into());
+            }
+        }
+        let mut items: Vec<Result<Bytes>> = response
+            .chunks
+            .into_iter()
+            .map(|chunk| Ok(Bytes::from(chunk)))
+            .collect();
+        if let Some(error) = response.error {
+            items.push(Err(ApiError::network(error).into()));
+        }
+        Ok(Box::pin(stream::iter(items)))
+    }
+
+    /// Record a request that failed before any response stream existed.
+    pub fn record_error(&self, url: &str, payload: &Value, error: &anyhow::Error) {
+        self.append(Interaction {
+            request: recorded_request(url, payload),
+            response: RecordedResponse {
+                error: Some(error.to_string()),
+                ..RecordedResponse::default()
+            },
+        });
+    }
+
+    /// Record a request the endpoint answered with an error status.
+    pub fn record_http_error(&self, url: &str, payload: &Value, failure: &HttpErrorResponse) {
+        self.append(Interaction {
+            request: recorded_request(url, payload),
+            response: RecordedResponse {
+                http_error: Some(failure.clone()),
+                ..RecordedResponse::default()
+            },
+        });
+    }
+
+    /// Tee `inner` into the cassette; the interaction is written once the
+    /// stream ends, fails or is dropped.
+    pub fn record_stream(
+        self: &Arc<Self>,
+        url: &str,
+        payload: &Value,
+        inner: ByteStream,
+    ) -> ByteStream {
+        Box::pin(RecordingStream {
+            inner,
+            cassette: Arc::clone(self),
+            request: Some(recorded_request(url, payload)),
+            response: RecordedResponse::default(),
+            pending_utf8: Vec::new(),
+        })
+    }
+
+    fn append(&self, interaction: Interaction) {
+        let CassetteState::Record(file) = &self.state else {
+            return;
+        };
+        let Ok(line) = serde_json::to_string(&interaction) else {
+            return;
+        };
+        if let Ok(mut file) = file.lock() {
+            let _ = writeln!(file, "{line}");
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Read every interaction from a cassette file.
+pub fn load_interactions(path: &Path) -> Result<Vec<Interaction>> {
+    let file =
+        File::open(path).with_context(|| format!("cannot open cassette '{}'", path.display()))?;
+    let mut interactions = Vec::new();
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("cannot read cassette '{}'", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let interaction = serde_json::from_str(&line).with_context(|| {
+            format!(
+                "cassette '{}' line {} is not a recorded interaction",
+                path.display(),
+                line_number + 1
+            )
+        })?;
+        interactions.push(interaction);
+    }
+    Ok(interactions)
+}
+
+struct RecordingStream {
+    inner: ByteStream,
+    cassette: Arc<Cassette>,
+    request: Option<RecordedRequest>,
+    response: RecordedResponse,
+    pending_utf8: Vec<u8>,
+}
+
+impl RecordingStream {
+    fn push_chunk(&mut self, chunk: &[u8]) {
+        self.pending_utf8.extend_from_slice(chunk);
+        let valid_up_to = match std::str::from_utf8(&self.pending_utf8) {
+            Ok(_) => self.pending_utf8.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(_) => self.pending_utf8.len(),
+        };
+        let rest = self.pending_utf8.split_off(valid_up_to);
+        let text = String::from_utf8_lossy(&self.pending_utf8).into_owned();
+        self.pending_utf8 = rest;
+        if !text.is_empty() {
+            self.response.chunks.push(text);
+        }
+    }
+
+    fn finish(&mut self) {
+        let Some(request) = self.request.take() else {
+            return;
+        };
+        if !self.pending_utf8.is_empty() {
+            let tail = std::mem::take(&mut self.pending_utf8);
+            self.response
+                .chunks
+                .push(String::from_utf8_lossy(&tail).into_owned());
+        }
+        self.cassette.append(Interaction {
+            request,
+            response: std::mem::take(&mut self.response),
+        });
+    }
+}
+
+impl Stream for RecordingStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let polled = self.inner.as_mut().poll_next(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(chunk))) => {
+                let chunk = chunk.clone();
+                self.push_chunk(&chunk);
+            }
+            Poll::Ready(Some(Err(error))) => {
+                self.response.error = Some(error.to_string());
+                self.finish();
+            }
+            Poll::Ready(None) => self.finish(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+}
+
+impl Drop for RecordingStream {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn recorded_request(url: &str, payload: &Value) -> RecordedRequest {
+    RecordedRequest {
+        url: url.to_string(),
+        hash: payload_hash(payload),
+        payload: payload.clone(),
+    }
+}
+
+/// Stable across runs and toolchains: FNV-1a over the compact JSON, whose
+/// object keys serde_json emits in sorted order.
+pub fn payload_hash(payload: &Value) -> String {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let serialized = payload.to_string();
+    let hash = serialized.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    format!("{hash:016x}")
+}
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    std::env::var(key)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Append-only helper for tools that build cassettes by hand.
+pub fn append_interaction(path: &Path, interaction: &Interaction) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("cannot open cassette '{}'", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(interaction)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    async fn collect(stream: ByteStream) -> (String, Option<String>) {
+        let mut bytes = Vec::new();
+        let mut error = None;
+        let mut stream = stream;
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(chunk) => bytes.extend_from_slice(&chunk),
+                Err(err) => error = Some(err.to_string()),
+            }
+        }
+        (String::from_utf8(bytes).unwrap(), error)
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay_in_order_and_by_hash() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.cassette.jsonl");
+        let first = json!({"model": "m", "messages": ["one"]});
+        let second = json!({"model": "m", "messages": ["two"]});
+
+        let recorder = Arc::new(Cassette::record(&path).unwrap());
+        // "é" is split across reads to exercise the UTF-8 carry.
+        let live: ByteStream = Box::pin(stream::iter(vec![
+            Ok(Bytes::from_static(b"data: caf\xc3")),
+            Ok(Bytes::from_static(b"\xa9\n\n")),
+        ]));
+        let (text, _) = collect(recorder.record_stream("http://x/v1/messages", &first, live)).await;
+        assert_eq!(text, "data: café\n\n");
+        let failing: ByteStream = Box::pin(stream::iter(vec![
+            Ok(Bytes::from_static(b"data: partial\n\n")),
+            Err(anyhow!("connection reset")),
+        ]));
+        let _ = collect(recorder.record_stream("http://x/v1/messages", &second, failing)).await;
+
+        let interactions = load_interactions(&path).unwrap();
+        assert_eq!(interactions.len(), 2);
+        assert_eq!(interactions[0].response.chunks, vec!["data: caf", "é\n\n"]);
+        assert_eq!(interactions[1].request.hash, payload_hash(&second));
+
+        let in_order = Cassette::replay(&path, ReplayMatch::Order).unwrap();
+        let (text, error) = collect(in_order.replay_stream(&second).unwrap()).await;
+        assert_eq!((text.as_str(), error), ("data: café\n\n", None));
+        let (text, error) = collect(in_order.replay_stream(&first).unwrap()).await;
+        assert_eq!(text, "data: partial\n\n");
+        assert_eq!(error.as_deref(), Some("connection reset"));
+        assert!(in_order.replay_stream(&first).is_err());
+
+        let by_hash = Cassette::replay(&path, ReplayMatch::Hash).unwrap();
+        let (text, _) = collect(by_hash.replay_stream(&second).unwrap()).await;
+        assert_eq!(text, "data: partial\n\n");
+        let (text, _) = collect(by_hash.replay_stream(&first).unwrap()).await;
+        assert_eq!(text, "data: café\n\n");
+        let error = by_hash.replay_stream(&first).err().unwrap().to_string();
+        assert!(error.contains("no recorded response"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_replayed_errors_classify_as_api_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("errors.cassette.jsonl");
+        let url = "http://x/v1/messages";
+        let payload = json!({"model": "m"});
+
+        let recorder = Arc::new(Cassette::record(&path).unwrap());
+        recorder.record_http_error(
+            url,
+            &payload,
+            &HttpErrorResponse {
+                status: 429,
+                headers: [("retry-after".to_string(), "7".to_string())].into(),
+                body:
+                    r#"{"type":"error","error":{"type":"rate_limit_error","message":"Slow down"}}"#
+                        .to_string(),
+            },
+        );
+        let overloaded: ByteStream = Box::pin(stream::iter(vec![Ok(Bytes::from_static(
+            b"event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        ))]));
+        let _ = collect(recorder.record_stream(url, &payload, overloaded)).await;
+        let reset: ByteStream = Box::pin(stream::iter(vec![Err(ApiError::network(
+            "connection reset",
+        )
+        .into())]));
+        let _ = collect(recorder.record_stream(url, &payload, reset)).await;
+
+        let replay = Cassette::replay(&path, ReplayMatch::Order).unwrap();
+        let error = replay.replay_stream(&payload).err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<ApiError>(),
+            Some(&ApiError::RateLimited {
+                message: "Slow down".to_string(),
+                retry_after: Some(std::time::Duration::from_secs(7)),
+            })
+        );
+
+        let (text, _) = collect(replay.replay_stream(&payload).unwrap()).await;
+        let error = crate::api::stream::StreamParser::new()
+            .process(text.as_bytes())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ApiError>(),
+            Some(ApiError::Overloaded { .. })
+        ));
+
+        let error = replay.replay_stream(&payload).err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<ApiError>(),
+            Some(&ApiError::network("connection reset"))
+        );
+    }
+
+    #[test]
+    fn test_cassette_mode_from_env() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var("VEX_CASSETTE_REPLAY", "bug.jsonl");
+        std::env::set_var("VEX_CASSETTE_MATCH", "hash");
+        let replay = CassetteMode::from_env();
+        std::env::set_var("VEX_CASSETTE_RECORD", "new.jsonl");
+        let both = CassetteMode::from_env();
+        for key in [
+            "VEX_CASSETTE_RECORD",
+            "VEX_CASSETTE_REPLAY",
+            "VEX_CASSETTE_MATCH",
+        ] {
+            std::env::remove_var(key);
+        }
+
+        assert_eq!(
+            replay.unwrap(),
+            Some(CassetteMode::Replay {
+                path: PathBuf::from("bug.jsonl"),
+                matching: ReplayMatch::Hash,
+            })
+        );
+        assert!(both.is_err());
+        assert_eq!(CassetteMode::from_env().unwrap(), None);
+    }
+}
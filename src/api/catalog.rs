@@ -0,0 +1,428 @@
+use super::client::{parse_protocol, ApiProtocol};
+use crate::types::Usage;
+use crate::util::is_local_endpoint_url;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+const MODEL_CATALOG_ENV: &str = "VEX_MODEL_CATALOG";
+const MODEL_PRICING_ENV: &str = "VEX_MODEL_PRICING";
+
+/// USD prices per million tokens for one model family.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+    pub cache_write_per_mtok: f64,
+    pub cache_read_per_mtok: f64,
+}
+
+impl ModelPricing {
+    const fn new(input: f64, output: f64, cache_write: f64, cache_read: f64) -> Self {
+        Self {
+            input_per_mtok: input,
+            output_per_mtok: output,
+            cache_write_per_mtok: cache_write,
+            cache_read_per_mtok: cache_read,
+        }
+    }
+
+    pub fn cost_usd(&self, usage: &Usage) -> f64 {
+        let per_token = |tokens: u64, per_mtok: f64| tokens as f64 * per_mtok / 1_000_000.0;
+        per_token(usage.input_tokens, self.input_per_mtok)
+            + per_token(usage.output_tokens, self.output_per_mtok)
+            + per_token(usage.cache_creation_input_tokens, self.cache_write_per_mtok)
+            + per_token(usage.cache_read_input_tokens, self.cache_read_per_mtok)
+    }
+}
+
+/// What vex knows about one model or model family. `id` matches by prefix, so
+/// `claude-sonnet-4` covers dated snapshots; the longest matching id wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub id: String,
+    pub protocol: Option<ApiProtocol>,
+    pub context_window: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+    pub pricing: Option<ModelPricing>,
+}
+
+impl ModelInfo {
+    fn builtin(
+        id: &str,
+        protocol: ApiProtocol,
+        context_window: u32,
+        max_output_tokens: u32,
+        pricing: ModelPricing,
+    ) -> Self {
+        Self {
+            id: id.to_string(),
+            protocol: Some(protocol),
+            context_window: Some(context_window),
+            max_output_tokens: Some(max_output_tokens),
+            pricing: Some(pricing),
+        }
+    }
+
+    /// A model reported by the endpoint's model list; nothing else is known.
+    fn discovered(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            protocol: None,
+            context_window: None,
+            max_output_tokens: None,
+            pricing: None,
+        }
+    }
+}
+
+/// One entry of the `VEX_MODEL_CATALOG` JSON file.
+#[derive(Debug, Deserialize)]
+struct ConfiguredModel {
+    id: String,
+    #[serde(default)]
+    protocol: Option<String>,
+    #[serde(default)]
+    context_window: Option<u32>,
+    #[serde(default)]
+    max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pricing: Option<ConfiguredPricing>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfiguredPricing {
+    input: f64,
+    output: f64,
+    #[serde(default)]
+    cache_write: Option<f64>,
+    #[serde(default)]
+    cache_read: Option<f64>,
+}
+
+/// Known models: the built-in table, extended by `VEX_MODEL_CATALOG` and by
+/// models discovered from the endpoint at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelCatalog {
+    models: Vec<ModelInfo>,
+}
+
+impl ModelCatalog {
+    pub fn builtin() -> Self {
+        use ApiProtocol::{AnthropicMessages as Anthropic, OpenAiChatCompletions as OpenAi};
+        let models = vec![
+            ModelInfo::builtin(
+                "claude-opus-4-5",
+                Anthropic,
+                200_000,
+                64_000,
+                ModelPricing::new(5.0, 25.0, 6.25, 0.50),
+            ),
+            ModelInfo::builtin(
+                "claude-opus-4",
+                Anthropic,
+                200_000,
+                32_000,
+                ModelPricing::new(15.0, 75.0, 18.75, 1.50),
+            ),
+            ModelInfo::builtin(
+                "claude-sonnet-4",
+                Anthropic,
+                200_000,
+                64_000,
+                ModelPricing::new(3.0, 15.0, 3.75, 0.30),
+            ),
+            ModelInfo::builtin(
+                "claude-3-7-sonnet",
+                Anthropic,
+                200_000,
+                64_000,
+                ModelPricing::new(3.0, 15.0, 3.75, 0.30),
+            ),
+            ModelInfo::builtin(
+                "claude-haiku-4-5",
+                Anthropic,
+                200_000,
+                64_000,
+                ModelPricing::new(1.0, 5.0, 1.25, 0.10),
+            ),
+            ModelInfo::builtin(
+                "claude-3-5-haiku",
+                Anthropic,
+                200_000,
+                8_192,
+                ModelPricing::new(0.80, 4.0, 1.0, 0.08),
+            ),
+            ModelInfo::builtin(
+                "gpt-4o-mini",
+                OpenAi,
+                128_000,
+                16_384,
+                ModelPricing::new(0.15, 0.60, 0.0, 0.075),
+            ),
+            ModelInfo::builtin(
+                "gpt-4o",
+                OpenAi,
+                128_000,
+                16_384,
+                ModelPricing::new(2.50, 10.0, 0.0, 1.25),
+            ),
+            ModelInfo::builtin(
+                "gpt-4.1-mini",
+                OpenAi,
+                1_047_576,
+                32_768,
+                ModelPricing::new(0.40, 1.60, 0.0, 0.10),
+            ),
+            ModelInfo::builtin(
+                "gpt-4.1",
+                OpenAi,
+                1_047_576,
+                32_768,
+                ModelPricing::new(2.0, 8.0, 0.0, 0.50),
+            ),
+        ];
+        Self { models }
+    }
+
+    /// Built-in models plus entries from the `VEX_MODEL_CATALOG` JSON file.
+    /// Configured entries replace built-in entries with the same id.
+    pub fn load() -> Result<Self> {
+        let mut catalog = Self::builtin();
+        let Some(path) = std::env::var(MODEL_CATALOG_ENV)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+        else {
+            return Ok(catalog);
+        };
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {MODEL_CATALOG_ENV} file '{path}'"))?;
+        catalog
+            .extend_from_json(&raw)
+            .with_context(|| format!("Invalid {MODEL_CATALOG_ENV} file '{path}'"))?;
+        Ok(catalog)
+    }
+
+    fn extend_from_json(&mut self, raw: &str) -> Result<()> {
+        let configured: Vec<ConfiguredModel> = serde_json::from_str(raw)?;
+        for entry in configured {
+            let id = entry.id.trim();
+            if id.is_empty() {
+                bail!("model entry with empty id");
+            }
+            let protocol = match entry.protocol {
+                Some(value) => Some(
+                    parse_protocol(value.clone())
+                        .with_context(|| format!("{id}: unknown protocol '{value}'"))?,
+                ),
+                None => None,
+            };
+            let pricing = entry.pricing.map(|pricing| {
+                ModelPricing::new(
+                    pricing.input,
+                    pricing.output,
+                    pricing.cache_write.unwrap_or(pricing.input),
+                    pricing.cache_read.unwrap_or(pricing.input),
+                )
+            });
+            self.upsert(ModelInfo {
+                id: id.to_string(),
+                protocol,
+                context_window: entry.context_window,
+                max_output_tokens: entry.max_output_tokens,
+                pricing,
+            });
+        }
+        Ok(())
+    }
+
+    fn upsert(&mut self, model: ModelInfo) {
+        match self.models.iter_mut().find(|known| known.id == model.id) {
+            Some(known) => *known = model,
+            None => self.models.push(model),
+        }
+    }
+
+    /// Record models the endpoint reports serving, so they pass validation.
+    pub fn add_discovered<'a>(&mut self, ids: impl IntoIterator<Item = &'a str>) {
+        for id in ids {
+            if !self.models.iter().any(|known| known.id == id) {
+                self.models.push(ModelInfo::discovered(id));
+            }
+        }
+    }
+
+    pub fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    pub fn lookup(&self, model: &str) -> Option<&ModelInfo> {
+        self.models
+            .iter()
+            .filter(|known| model.starts_with(known.id.as_str()))
+            .max_by_key(|known| known.id.len())
+    }
+
+    /// Price for `model`. `VEX_MODEL_PRICING` entries take precedence, using
+    /// `prefix=input,output[,cache_write,cache_read]` separated by `;`.
+    pub fn pricing(&self, model: &str) -> Option<ModelPricing> {
+        let configured = std::env::var(MODEL_PRICING_ENV)
+            .ok()
+            .map(|value| parse_pricing_table(&value))
+            .unwrap_or_default();
+        configured
+            .into_iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, pricing)| pricing)
+            .or_else(|| self.lookup(model).and_then(|known| known.pricing))
+    }
+
+    /// Remote Anthropic endpoints only accept catalog models. Other remote
+    /// protocols accept uncataloged models, which run with default limits;
+    /// localhost servers serve whatever they have loaded.
+    pub fn validate_model(&self, api_url: &str, protocol: ApiProtocol, model: &str) -> Result<()> {
+        if is_local_endpoint_url(api_url) {
+            return Ok(());
+        }
+        if model.starts_with("local/") {
+            bail!("Local models are only allowed for localhost endpoints");
+        }
+        if protocol == ApiProtocol::AnthropicMessages && self.lookup(model).is_none() {
+            bail!(
+                "Unknown model '{model}' for remote endpoint '{api_url}'. Add it to the \
+                 {MODEL_CATALOG_ENV} file or pick a known model with /model"
+            );
+        }
+        Ok(())
+    }
+}
+
+fn parse_pricing_table(value: &str) -> Vec<(String, ModelPricing)> {
+    value
+        .split(';')
+        .filter_map(|entry| {
+            let (prefix, prices) = entry.split_once('=')?;
+            let prefix = prefix.trim();
+            if prefix.is_empty() {
+                return None;
+            }
+            let prices = prices
+                .split(',')
+                .map(|price| price.trim().parse::<f64>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            let pricing = match prices.as_slice() {
+                [input, output] => ModelPricing::new(*input, *output, *input, *input),
+                [input, output, cache_write, cache_read] => {
+                    ModelPricing::new(*input, *output, *cache_write, *cache_read)
+                }
+                _ => return None,
+            };
+            Some((prefix.to_string(), pricing))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pricing_prefers_longest_builtin_prefix() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var(MODEL_PRICING_ENV);
+        let catalog = ModelCatalog::builtin();
+
+        let opus_45 = catalog.pricing("claude-opus-4-5-20251101").unwrap();
+        assert_eq!(opus_45.input_per_mtok, 5.0);
+        let mini = catalog.pricing("gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(mini.output_per_mtok, 0.60);
+        assert!(catalog.pricing("local/llama3.3").is_none());
+    }
+
+    #[test]
+    fn test_pricing_env_table_overrides_builtin() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var(
+            MODEL_PRICING_ENV,
+            "claude-sonnet-4=1,2; local/=0.1,0.2,0.3,0.4; broken=abc",
+        );
+        let catalog = ModelCatalog::builtin();
+
+        let sonnet = catalog.pricing("claude-sonnet-4-5-20250929").unwrap();
+        assert_eq!(sonnet.input_per_mtok, 1.0);
+        assert_eq!(sonnet.output_per_mtok, 2.0);
+        let local = catalog.pricing("local/qwen").unwrap();
+        assert_eq!(local.cache_read_per_mtok, 0.4);
+        assert!(catalog.pricing("broken-model").is_none());
+
+        std::env::remove_var(MODEL_PRICING_ENV);
+    }
+
+    #[test]
+    fn test_configured_entries_extend_and_override_builtin() {
+        let mut catalog = ModelCatalog::builtin();
+        catalog
+            .extend_from_json(
+                r#"[
+                    {"id": "gateway/gpt-5", "protocol": "responses", "context_window": 400000,
+                     "max_output_tokens": 128000, "pricing": {"input": 1.25, "output": 10}},
+                    {"id": "gpt-4o", "context_window": 64000}
+                ]"#,
+            )
+            .unwrap();
+
+        let gateway = catalog.lookup("gateway/gpt-5-2025-08-07").unwrap();
+        assert_eq!(gateway.protocol, Some(ApiProtocol::OpenAiResponses));
+        assert_eq!(gateway.max_output_tokens, Some(128_000));
+        assert_eq!(gateway.pricing.unwrap().cache_read_per_mtok, 1.25);
+        assert_eq!(
+            catalog.lookup("gpt-4o").unwrap().context_window,
+            Some(64_000)
+        );
+
+        let error = catalog
+            .extend_from_json(r#"[{"id": "x", "protocol": "carrier-pigeon"}]"#)
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown protocol"));
+    }
+
+    #[test]
+    fn test_validate_model_uses_catalog_for_remote_endpoints() {
+        use ApiProtocol::{AnthropicMessages, OpenAiChatCompletions};
+        let mut catalog = ModelCatalog::builtin();
+        let anthropic = "https://api.anthropic.com/v1/messages";
+        let gateway = "https://gateway.example.com/v1/chat/completions";
+
+        assert!(catalog
+            .validate_model(anthropic, AnthropicMessages, "claude-sonnet-4-5-20250929")
+            .is_ok());
+        assert!(catalog
+            .validate_model(anthropic, AnthropicMessages, "claude-3-opus-20240229")
+            .is_err());
+        assert!(catalog
+            .validate_model(anthropic, AnthropicMessages, "mystery-model")
+            .is_err());
+        assert!(catalog
+            .validate_model(gateway, OpenAiChatCompletions, "gpt-4o-2024-11-20")
+            .is_ok());
+        assert!(catalog
+            .validate_model(gateway, OpenAiChatCompletions, "mystery-model")
+            .is_ok());
+        assert!(catalog
+            .validate_model(gateway, OpenAiChatCompletions, "local/llama3.3")
+            .is_err());
+        assert!(catalog
+            .validate_model(
+                "http://localhost:11434/api/chat",
+                ApiProtocol::OllamaChat,
+                "mystery-model"
+            )
+            .is_ok());
+
+        catalog.add_discovered(["mystery-model"]);
+        assert!(catalog
+            .validate_model(anthropic, AnthropicMessages, "mystery-model")
+            .is_ok());
+    }
+}
@@ -1,6 +1,11 @@
+use super::cassette::{Cassette, CassetteMode};
+use super::catalog::{ModelCatalog, ModelInfo, ModelPricing};
+use super::error::{ApiError, HttpErrorResponse};
+use super::http::{build_http_client, resolve_auth_scheme, AuthScheme};
 use super::logging::{debug_payload_enabled, emit_debug_payload};
+use super::stream::StreamParser;
 use crate::config::Config;
-use crate::types::{ApiMessage, Content, ContentBlock};
+use crate::types::{ApiMessage, Content, ContentBlock, ImageSource};
 use crate::util::{is_local_endpoint_url, parse_bool_flag};
 use anyhow::anyhow;
 use anyhow::Result;
@@ -8,8 +13,9 @@ use bytes::Bytes;
 use futures::{Stream, StreamExt};
 use serde_json::json;
 use serde_json::Value;
+use std::borrow::Cow;
 use std::pin::Pin;
-#[cfg(test)]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
@@ -32,6 +38,11 @@ For code edits, prefer this sequence: search_files -> read_file -> edit_file ->
 Always send non-empty string paths for file tools.\n\
 Avoid redundant loops: do not repeat identical read/search tool calls without new evidence.";
 
+const DEFAULT_OLLAMA_NUM_CTX: u32 = 16_384;
+const DEFAULT_LOCAL_CONTEXT_WINDOW: u32 = 16_384;
+const DEFAULT_REMOTE_CONTEXT_WINDOW: u32 = 128_000;
+const DEFAULT_OLLAMA_KEEP_ALIVE: &str = "30m";
+
 #[cfg(test)]
 pub trait MockStreamProducer: Send + Sync {
     fn create_mock_stream(&self, messages: &[ApiMessage]) -> Result<ByteStream>;
@@ -45,33 +56,105 @@ pub struct ApiClient {
     api_url: String,
     anthropic_version: String,
     api_protocol: ApiProtocol,
+    auth_scheme: AuthScheme,
     structured_tool_protocol: bool,
+    prompt_caching: bool,
+    thinking_budget: Option<u32>,
+    catalog: ModelCatalog,
+    project_instructions: Option<String>,
+    /// Instructions for the session's current mode, e.g. plan mode.
+    mode_instructions: Option<&'static str>,
+    /// Tools offered to the model; `None` offers every tool.
+    tool_allowlist: Option<&'static [&'static str]>,
+    cassette: Option<Arc<Cassette>>,
+    /// The chat template opens `<think>`, so answers start inside reasoning.
+    think_opened_by_template: bool,
+    /// Cleared once the endpoint rejects `stream_options`, which not every
+    /// OpenAI-compatible server accepts.
+    stream_usage_supported: Arc<AtomicBool>,
     #[cfg(test)]
     mock_stream_producer: Option<Arc<dyn MockStreamProducer>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ApiProtocol {
+pub enum ApiProtocol {
     AnthropicMessages,
     OpenAiChatCompletions,
+    OpenAiResponses,
+    OllamaChat,
+}
+
+impl ApiProtocol {
+    /// The `VEX_API_PROTOCOL` spelling shown in listings.
+    pub fn label(self) -> &'static str {
+        match self {
+            ApiProtocol::AnthropicMessages => "anthropic",
+            ApiProtocol::OpenAiChatCompletions => "openai",
+            ApiProtocol::OpenAiResponses => "responses",
+            ApiProtocol::OllamaChat => "ollama",
+        }
+    }
+
+    /// Whether a model cataloged for `self` can be served over `endpoint`;
+    /// the Chat Completions and Responses APIs serve the same OpenAI models.
+    pub fn compatible_with(self, endpoint: ApiProtocol) -> bool {
+        use ApiProtocol::{OpenAiChatCompletions, OpenAiResponses};
+        self == endpoint
+            || matches!(
+                (self, endpoint),
+                (OpenAiChatCompletions, OpenAiResponses) | (OpenAiResponses, OpenAiChatCompletions)
+            )
+    }
+
+    /// Providers whose models may inline `<think>` reasoning in answer text.
+    pub fn inlines_think_tags(self) -> bool {
+        matches!(
+            self,
+            ApiProtocol::OpenAiChatCompletions | ApiProtocol::OllamaChat
+        )
+    }
 }
 
 impl ApiClient {
     pub fn new(config: &Config) -> Result<Self> {
-        let api_protocol = std::env::var("VEX_API_PROTOCOL")
-            .ok()
-            .and_then(parse_protocol)
-            .unwrap_or_else(|| infer_api_protocol(&config.api_url));
-        let structured_tool_protocol = resolve_structured_tool_protocol(&config.api_url);
+        Self::new_with_cassette(config, Cassette::from_env()?)
+    }
+
+    /// Like [`Self::new`], but recording to or replaying from `cassette`
+    /// instead of opening the one named in the environment, so several
+    /// sessions can share a single cassette.
+    pub fn new_with_cassette(config: &Config, cassette: Option<Arc<Cassette>>) -> Result<Self> {
+        let catalog = ModelCatalog::load()?;
+        let api_protocol = resolve_api_protocol(&config.api_url, &catalog, &config.model);
+        let auth_scheme = resolve_auth_scheme(api_protocol)?;
+        let structured_tool_protocol =
+            resolve_structured_tool_protocol(&config.api_url, api_protocol);
+        let prompt_caching = resolve_prompt_caching(&config.api_url);
+        let thinking_budget = resolve_thinking_budget();
+        let think_opened_by_template = api_protocol.inlines_think_tags()
+            && std::env::var("VEX_THINK_OPENED_BY_TEMPLATE")
+                .ok()
+                .and_then(parse_bool_flag)
+                .unwrap_or(false);
 
         Ok(Self {
-            http: reqwest::Client::new(),
+            http: build_http_client()?,
             api_key: config.api_key.clone(),
             model: config.model.clone(),
             api_url: config.api_url.clone(),
             anthropic_version: config.anthropic_version.clone(),
             api_protocol,
+            auth_scheme,
             structured_tool_protocol,
+            prompt_caching,
+            thinking_budget,
+            catalog,
+            project_instructions: None,
+            mode_instructions: None,
+            tool_allowlist: None,
+            cassette,
+            think_opened_by_template,
+            stream_usage_supported: Arc::new(AtomicBool::new(true)),
             #[cfg(test)]
             mock_stream_producer: None,
         })
@@ -86,11 +169,58 @@ impl ApiClient {
             api_url: "http://localhost:8000/v1/messages".to_string(),
             anthropic_version: "2023-06-01".to_string(),
             api_protocol: ApiProtocol::AnthropicMessages,
+            auth_scheme: AuthScheme::XApiKey,
             structured_tool_protocol: true,
+            prompt_caching: false,
+            thinking_budget: None,
+            catalog: ModelCatalog::builtin(),
+            project_instructions: None,
+            mode_instructions: None,
+            tool_allowlist: None,
+            cassette: None,
+            think_opened_by_template: false,
+            stream_usage_supported: Arc::new(AtomicBool::new(true)),
             mock_stream_producer: Some(mock_producer),
         }
     }
 
+    /// Record traffic to, or replay it from, a cassette file.
+    pub fn with_cassette(mut self, mode: &CassetteMode) -> Result<Self> {
+        self.cassette = Some(Arc::new(Cassette::open(mode)?));
+        Ok(self)
+    }
+
+    pub fn cassette(&self) -> Option<&Cassette> {
+        self.cassette.as_deref()
+    }
+
+    /// Offer only `tools` to the model, e.g. for a restricted sub-agent.
+    pub fn with_tool_allowlist(mut self, tools: &'static [&'static str]) -> Self {
+        self.tool_allowlist = Some(tools);
+        self
+    }
+
+    /// Tool schemas in the Anthropic shape, filtered by the allowlist.
+    fn offered_tool_definitions(&self) -> Value {
+        let tools = tool_definitions();
+        let Some(allowlist) = self.tool_allowlist else {
+            return tools;
+        };
+        Value::Array(
+            tools
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|tool| {
+                    tool.get("name")
+                        .and_then(Value::as_str)
+                        .is_some_and(|name| allowlist.contains(&name))
+                })
+                .cloned()
+                .collect(),
+        )
+    }
+
     pub fn supports_structured_tool_protocol(&self) -> bool {
         self.structured_tool_protocol
     }
@@ -99,12 +229,173 @@ impl ApiClient {
         is_local_endpoint_url(&self.api_url)
     }
 
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn api_protocol(&self) -> ApiProtocol {
+        self.api_protocol
+    }
+
+    /// A parser for this endpoint's response streams.
+    pub fn stream_parser(&self) -> StreamParser {
+        let parser = StreamParser::for_protocol(self.api_protocol);
+        if self.think_opened_by_template {
+            parser.with_think_opened_by_template()
+        } else {
+            parser
+        }
+    }
+
+    pub fn catalog(&self) -> &ModelCatalog {
+        &self.catalog
+    }
+
+    pub fn model_info(&self) -> Option<&ModelInfo> {
+        self.catalog.lookup(&self.model)
+    }
+
+    pub fn model_pricing(&self) -> Option<ModelPricing> {
+        self.catalog.pricing(&self.model)
+    }
+
+    /// Context window of the active model: `VEX_CONTEXT_WINDOW`, the catalog,
+    /// the `num_ctx` sent to native Ollama, then a conservative default.
+    pub fn context_window(&self) -> u32 {
+        if let Some(window) = std::env::var("VEX_CONTEXT_WINDOW")
+            .ok()
+            .and_then(|v| v.trim().parse::<u32>().ok())
+        {
+            return window.clamp(2048, 2_000_000);
+        }
+        if let Some(window) = self.model_info().and_then(|model| model.context_window) {
+            return window;
+        }
+        if self.api_protocol == ApiProtocol::OllamaChat {
+            return resolve_ollama_num_ctx();
+        }
+        if self.is_local_endpoint() {
+            DEFAULT_LOCAL_CONTEXT_WINDOW
+        } else {
+            DEFAULT_REMOTE_CONTEXT_WINDOW
+        }
+    }
+
+    /// Output tokens each request reserves, including any thinking budget.
+    pub fn reserved_output_tokens(&self) -> u32 {
+        let max_tokens = resolve_max_tokens(
+            &self.api_url,
+            self.model_info().and_then(|model| model.max_output_tokens),
+        );
+        match self.api_protocol {
+            ApiProtocol::AnthropicMessages => max_tokens + self.thinking_budget.unwrap_or(0),
+            _ => max_tokens,
+        }
+    }
+
+    /// Characters sent with every request besides history: the system prompt
+    /// and, for structured tool calls, the tool schemas.
+    pub fn request_overhead_chars(&self) -> usize {
+        let tools = if self.structured_tool_protocol {
+            self.offered_tool_definitions().to_string().len()
+        } else {
+            0
+        };
+        self.system_prompt().len() + tools
+    }
+
+    /// Switch the model used for subsequent requests after catalog validation.
+    pub fn set_model(&mut self, model: &str) -> Result<()> {
+        let model = model.trim();
+        if model.is_empty() {
+            return Err(anyhow!("model name is empty"));
+        }
+        self.catalog
+            .validate_model(&self.api_url, self.api_protocol, model)?;
+        if let Some(protocol) = self.catalog.lookup(model).and_then(|info| info.protocol) {
+            if !protocol.compatible_with(self.api_protocol) {
+                return Err(anyhow!(
+                    "model '{model}' uses the {} protocol, but this endpoint speaks {}",
+                    protocol.label(),
+                    self.api_protocol.label()
+                ));
+            }
+        }
+        self.model = model.to_string();
+        Ok(())
+    }
+
+    pub fn add_discovered_models(&mut self, ids: &[String]) {
+        self.catalog.add_discovered(ids.iter().map(String::as_str));
+    }
+
+    /// Replace the project instructions appended to the system prompt.
+    pub fn set_project_instructions(&mut self, instructions: Option<String>) {
+        self.project_instructions = instructions;
+    }
+
+    /// Replace the mode instructions appended after the project instructions.
+    pub fn set_mode_instructions(&mut self, instructions: Option<&'static str>) {
+        self.mode_instructions = instructions;
+    }
+
+    /// Built-in system prompt followed by any loaded project instructions and
+    /// the current mode's instructions.
+    pub fn system_prompt(&self) -> Cow<'static, str> {
+        if self.project_instructions.is_none() && self.mode_instructions.is_none() {
+            return Cow::Borrowed(SYSTEM_PROMPT);
+        }
+        let mut prompt = SYSTEM_PROMPT.to_string();
+        for section in [self.project_instructions.as_deref(), self.mode_instructions]
+            .into_iter()
+            .flatten()
+        {
+            prompt.push_str("\n\n");
+            prompt.push_str(section);
+        }
+        Cow::Owned(prompt)
+    }
+
+    /// Model ids served by the endpoint: `/v1/models`, or `/api/tags` for native Ollama.
+    pub async fn fetch_endpoint_models(&self) -> Result<Vec<String>> {
+        let models_url = self.models_url();
+        let response = self
+            .with_auth_headers(self.http.get(&models_url))
+            .send()
+            .await
+            .map_err(|error| map_api_request_error(error, &models_url))?;
+        let response = check_response_status(response)
+            .await
+            .map_err(|failure| failure.to_api_error())?;
+        let body = response
+            .json::<Value>()
+            .await
+            .map_err(|error| map_api_request_error(error, &models_url))?;
+        Ok(parse_model_list(&body))
+    }
+
+    pub fn prompt_caching_enabled(&self) -> bool {
+        self.prompt_caching && self.api_protocol == ApiProtocol::AnthropicMessages
+    }
+
     #[cfg(test)]
     pub fn with_structured_tool_protocol(mut self, enabled: bool) -> Self {
         self.structured_tool_protocol = enabled;
         self
     }
 
+    #[cfg(test)]
+    pub fn with_api_protocol(mut self, protocol: ApiProtocol) -> Self {
+        self.api_protocol = protocol;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_think_opened_by_template(mut self) -> Self {
+        self.think_opened_by_template = true;
+        self
+    }
+
     pub async fn create_stream(&self, messages: &[ApiMessage]) -> Result<ByteStream> {
         #[cfg(test)]
         {
@@ -114,81 +405,221 @@ impl ApiClient {
         }
 
         let request_url = self.request_url();
-        let max_tokens = resolve_max_tokens(&self.api_url);
-        let payload = match self.api_protocol {
-            ApiProtocol::AnthropicMessages => {
-                let mut payload = json!({
-                    "model": self.model,
-                    "max_tokens": max_tokens,
-                    "stream": true,
-                    "system": SYSTEM_PROMPT,
-                    "messages": messages,
-                });
-                if self.structured_tool_protocol {
-                    let payload_object = payload
-                        .as_object_mut()
-                        .expect("payload must be a JSON object");
-                    payload_object.insert("tool_choice".to_string(), json!({ "type": "auto" }));
-                    payload_object.insert("tools".to_string(), tool_definitions());
-                }
-                payload
-            }
+        let max_tokens = resolve_max_tokens(
+            &self.api_url,
+            self.model_info().and_then(|model| model.max_output_tokens),
+        );
+        let mut payload = match self.api_protocol {
+            ApiProtocol::AnthropicMessages => self.anthropic_payload(messages, max_tokens),
             ApiProtocol::OpenAiChatCompletions => {
                 let mut payload = json!({
                     "model": self.model,
                     "max_tokens": max_tokens,
                     "stream": true,
-                    "messages": openai_messages(messages, SYSTEM_PROMPT),
+                    "messages": openai_messages(messages, &self.system_prompt()),
                 });
+                let payload_object = payload
+                    .as_object_mut()
+                    .expect("payload must be a JSON object");
+                if self.stream_usage_supported.load(Ordering::Relaxed) {
+                    payload_object.insert(
+                        "stream_options".to_string(),
+                        json!({ "include_usage": true }),
+                    );
+                }
                 if self.structured_tool_protocol {
-                    let payload_object = payload
-                        .as_object_mut()
-                        .expect("payload must be a JSON object");
                     payload_object.insert("tool_choice".to_string(), json!("auto"));
-                    payload_object.insert("tools".to_string(), tool_definitions_openai());
+                    payload_object.insert(
+                        "tools".to_string(),
+                        tool_definitions_openai(self.offered_tool_definitions()),
+                    );
                 }
                 payload
             }
+            ApiProtocol::OpenAiResponses => self.openai_responses_payload(messages, max_tokens),
+            ApiProtocol::OllamaChat => self.ollama_payload(messages, max_tokens),
         };
 
-        let mut request = self
-            .http
-            .post(&request_url)
-            .header("content-type", "application/json")
-            .json(&payload);
+        let result = self.open_stream(&request_url, &payload).await;
+        // Servers that reject unknown fields answer `stream_options` with a 400;
+        // retry once without it and stop sending it if that works.
+        let rejected = matches!(
+            result
+                .as_ref()
+                .err()
+                .and_then(|error| error.downcast_ref::<ApiError>()),
+            Some(ApiError::InvalidRequest { .. })
+        );
+        if !rejected {
+            return result;
+        }
+        let Some(payload_object) = payload.as_object_mut() else {
+            return result;
+        };
+        if payload_object.remove("stream_options").is_none() {
+            return result;
+        }
+        let retried = self.open_stream(&request_url, &payload).await;
+        if retried.is_ok() {
+            self.stream_usage_supported.store(false, Ordering::Relaxed);
+        }
+        retried
+    }
 
+    async fn open_stream(&self, request_url: &str, payload: &Value) -> Result<ByteStream> {
         if debug_payload_enabled() {
-            emit_debug_payload(&request_url, &payload);
+            emit_debug_payload(request_url, payload);
         }
 
-        match self.api_protocol {
-            ApiProtocol::AnthropicMessages => {
-                if let Some(api_key) = &self.api_key {
-                    request = request.header("x-api-key", api_key);
-                }
-                if !self.anthropic_version.trim().is_empty() {
-                    request = request.header("anthropic-version", &self.anthropic_version);
+        let recorder = match &self.cassette {
+            Some(cassette) if cassette.is_replay() => return cassette.replay_stream(payload),
+            Some(cassette) => Some(cassette),
+            None => None,
+        };
+        let response = match self.send_stream_request(request_url, payload).await {
+            Ok(response) => response,
+            Err(error) => {
+                if let Some(cassette) = recorder {
+                    cassette.record_error(request_url, payload, &error);
                 }
+                return Err(error);
             }
-            ApiProtocol::OpenAiChatCompletions => {
-                if let Some(api_key) = &self.api_key {
-                    request = request.header("authorization", format!("Bearer {api_key}"));
+        };
+        let response = match check_response_status(response).await {
+            Ok(response) => response,
+            Err(failure) => {
+                if let Some(cassette) = recorder {
+                    cassette.record_http_error(request_url, payload, &failure);
                 }
+                return Err(failure.to_api_error().into());
             }
-        }
+        };
+
+        let request_url_for_stream = request_url.to_string();
+        let stream: ByteStream = Box::pin(response.bytes_stream().map(move |item| {
+            item.map_err(|error| map_api_request_error(error, &request_url_for_stream))
+        }));
+        Ok(match recorder {
+            Some(cassette) => cassette.record_stream(request_url, payload, stream),
+            None => stream,
+        })
+    }
 
-        let response = request
+    async fn send_stream_request(
+        &self,
+        request_url: &str,
+        payload: &Value,
+    ) -> Result<reqwest::Response> {
+        let request = self.with_auth_headers(
+            self.http
+                .post(request_url)
+                .header("content-type", "application/json")
+                .json(payload),
+        );
+
+        request
             .send()
             .await
-            .map_err(|error| map_api_request_error(error, &request_url))?
-            .error_for_status()
-            .map_err(|error| map_api_request_error(error, &request_url))?;
+            .map_err(|error| map_api_request_error(error, request_url))
+    }
 
-        let request_url_for_stream = request_url.clone();
-        let stream = response.bytes_stream().map(move |item| {
-            item.map_err(|error| map_api_request_error(error, &request_url_for_stream))
+    fn with_auth_headers(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request = self.auth_scheme.apply(request, self.api_key.as_deref());
+        if self.api_protocol == ApiProtocol::AnthropicMessages
+            && !self.anthropic_version.trim().is_empty()
+        {
+            request = request.header("anthropic-version", &self.anthropic_version);
+        }
+        request
+    }
+
+    fn anthropic_payload(&self, messages: &[ApiMessage], max_tokens: u32) -> Value {
+        let prompt_caching = self.prompt_caching_enabled();
+        let system_prompt = self.system_prompt();
+        let system = if prompt_caching {
+            json!([{
+                "type": "text",
+                "text": system_prompt,
+                "cache_control": cache_control_ephemeral(),
+            }])
+        } else {
+            json!(system_prompt)
+        };
+        let messages = if prompt_caching {
+            anthropic_messages_with_cache_breakpoints(messages)
+        } else {
+            json!(messages)
+        };
+
+        // The thinking budget counts against max_tokens, so reserve it on top
+        // of the configured answer length.
+        let max_tokens = max_tokens + self.thinking_budget.unwrap_or(0);
+        let mut payload = json!({
+            "model": self.model,
+            "max_tokens": max_tokens,
+            "stream": true,
+            "system": system,
+            "messages": messages,
+        });
+        if let Some(budget_tokens) = self.thinking_budget {
+            payload["thinking"] = json!({
+                "type": "enabled",
+                "budget_tokens": budget_tokens,
+            });
+        }
+        if self.structured_tool_protocol {
+            let mut tools = self.offered_tool_definitions();
+            if prompt_caching {
+                if let Some(last_tool) = tools.as_array_mut().and_then(|tools| tools.last_mut()) {
+                    last_tool["cache_control"] = cache_control_ephemeral();
+                }
+            }
+            let payload_object = payload
+                .as_object_mut()
+                .expect("payload must be a JSON object");
+            payload_object.insert("tool_choice".to_string(), json!({ "type": "auto" }));
+            payload_object.insert("tools".to_string(), tools);
+        }
+        payload
+    }
+
+    fn openai_responses_payload(&self, messages: &[ApiMessage], max_tokens: u32) -> Value {
+        let mut payload = json!({
+            "model": self.model,
+            "instructions": self.system_prompt(),
+            "input": openai_responses_input(messages),
+            "max_output_tokens": max_tokens,
+            "stream": true,
+            "store": false,
+        });
+        if self.structured_tool_protocol {
+            let payload_object = payload
+                .as_object_mut()
+                .expect("payload must be a JSON object");
+            payload_object.insert("tool_choice".to_string(), json!("auto"));
+            payload_object.insert(
+                "tools".to_string(),
+                tool_definitions_openai_responses(self.offered_tool_definitions()),
+            );
+        }
+        payload
+    }
+
+    fn ollama_payload(&self, messages: &[ApiMessage], max_tokens: u32) -> Value {
+        let mut payload = json!({
+            "model": self.model,
+            "stream": true,
+            "messages": ollama_messages(messages, &self.system_prompt()),
+            "options": {
+                "num_ctx": resolve_ollama_num_ctx(),
+                "num_predict": max_tokens,
+            },
+            "keep_alive": resolve_ollama_keep_alive(),
         });
-        Ok(Box::pin(stream))
+        if self.structured_tool_protocol {
+            payload["tools"] = tool_definitions_openai(self.offered_tool_definitions());
+        }
+        payload
     }
 
     fn request_url(&self) -> String {
@@ -197,36 +628,51 @@ impl ApiClient {
             ApiProtocol::OpenAiChatCompletions => {
                 adapt_to_openai_chat_completions_url(&self.api_url)
             }
+            ApiProtocol::OpenAiResponses => adapt_to_openai_responses_url(&self.api_url),
+            ApiProtocol::OllamaChat => adapt_to_ollama_chat_url(&self.api_url),
+        }
+    }
+
+    fn models_url(&self) -> String {
+        let request_url = self.request_url();
+        if let Some(base) = request_url.strip_suffix("/api/chat") {
+            return format!("{base}/api/tags");
         }
+        let base = ["/chat/completions", "/responses", "/messages"]
+            .iter()
+            .find_map(|suffix| request_url.strip_suffix(suffix))
+            .unwrap_or(&request_url);
+        format!("{base}/models")
     }
 }
 
 fn map_api_request_error(error: reqwest::Error, request_url: &str) -> anyhow::Error {
-    if error.is_connect() && is_local_endpoint_url(request_url) {
-        return anyhow!(
-            "cannot reach local API endpoint '{}': {}. Start your local server or update ANTHROPIC_API_URL.",
-            request_url,
-            error
-        );
-    }
-    if error.is_connect() {
-        return anyhow!("cannot reach API endpoint '{}': {}", request_url, error);
-    }
-    if error.is_timeout() {
-        return anyhow!("API request to '{}' timed out: {}", request_url, error);
-    }
-    if let Some(status) = error.status() {
-        return anyhow!(
-            "API endpoint '{}' returned HTTP {}: {}",
-            request_url,
-            status,
-            error
-        );
+    let message = if error.is_connect() && is_local_endpoint_url(request_url) {
+        format!(
+            "cannot reach local API endpoint '{request_url}': {error}. Start your local server or update ANTHROPIC_API_URL."
+        )
+    } else if error.is_connect() {
+        format!("cannot reach API endpoint '{request_url}': {error}")
+    } else if error.is_timeout() {
+        format!("API request to '{request_url}' timed out: {error}")
+    } else {
+        format!("API request to '{request_url}' failed: {error}")
+    };
+    ApiError::network(message).into()
+}
+
+/// Pass successful responses through; read any other whole, for
+/// [`HttpErrorResponse::to_api_error`] to classify.
+async fn check_response_status(
+    response: reqwest::Response,
+) -> std::result::Result<reqwest::Response, HttpErrorResponse> {
+    if response.status().is_success() {
+        return Ok(response);
     }
-    anyhow!("API request to '{}' failed: {}", request_url, error)
+    Err(HttpErrorResponse::read(response).await)
 }
 
-fn resolve_structured_tool_protocol(api_url: &str) -> bool {
+fn resolve_structured_tool_protocol(api_url: &str, api_protocol: ApiProtocol) -> bool {
     if let Some(value) = std::env::var("VEX_STRUCTURED_TOOL_PROTOCOL")
         .ok()
         .and_then(parse_bool_flag)
@@ -234,27 +680,143 @@ fn resolve_structured_tool_protocol(api_url: &str) -> bool {
         return value;
     }
 
+    // Native Ollama tool calls are parsed server-side from the model template,
+    // so they stay reliable even though the endpoint is local.
+    if api_protocol == ApiProtocol::OllamaChat {
+        return true;
+    }
+
     // Local endpoints default to text-protocol fallback because many local servers
     // do not implement structured tool call blocks consistently.
     !is_local_endpoint_url(api_url)
 }
 
-fn resolve_max_tokens(api_url: &str) -> u32 {
+/// Extended thinking budget from `VEX_THINKING_BUDGET`; unset or `0` disables it.
+fn resolve_thinking_budget() -> Option<u32> {
+    std::env::var("VEX_THINKING_BUDGET")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .filter(|budget| *budget > 0)
+        .map(|budget| budget.clamp(1024, 32_000))
+}
+
+fn resolve_prompt_caching(api_url: &str) -> bool {
+    if let Some(value) = std::env::var("VEX_PROMPT_CACHE")
+        .ok()
+        .and_then(parse_bool_flag)
+    {
+        return value;
+    }
+
+    // Local Anthropic-compatible servers commonly reject unknown block fields.
+    !is_local_endpoint_url(api_url)
+}
+
+/// Context window requested from Ollama; its own default is too small for
+/// tool transcripts and silently truncates the prompt.
+fn resolve_ollama_num_ctx() -> u32 {
+    std::env::var("VEX_OLLAMA_NUM_CTX")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .map(|value| value.clamp(2048, 1_048_576))
+        .unwrap_or(DEFAULT_OLLAMA_NUM_CTX)
+}
+
+/// How long Ollama keeps the model loaded after a request. Accepts Ollama's
+/// duration strings (`10m`, `1h`) or a number of seconds (`-1` keeps it loaded).
+fn resolve_ollama_keep_alive() -> Value {
+    let value = std::env::var("VEX_OLLAMA_KEEP_ALIVE")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_OLLAMA_KEEP_ALIVE.to_string());
+    match value.parse::<i64>() {
+        Ok(seconds) => json!(seconds),
+        Err(_) => json!(value),
+    }
+}
+
+fn cache_control_ephemeral() -> Value {
+    json!({ "type": "ephemeral" })
+}
+
+/// Serialize history with rolling `cache_control` breakpoints.
+///
+/// Breakpoints go on the newest message and on the previous user message, so
+/// each request reads the prefix written by the one before it. They are placed
+/// on the slice actually sent, never stored in history, so pruning only moves
+/// them. Together with the system and tools breakpoints this stays within the
+/// four breakpoints the Messages API accepts.
+fn anthropic_messages_with_cache_breakpoints(messages: &[ApiMessage]) -> Value {
+    let mut out = messages
+        .iter()
+        .map(|message| json!(message))
+        .collect::<Vec<_>>();
+
+    let last_index = out.len().checked_sub(1);
+    let previous_user_index = last_index.and_then(|last| {
+        messages[..last]
+            .iter()
+            .rposition(|message| message.role == "user")
+    });
+    for index in [previous_user_index, last_index].into_iter().flatten() {
+        mark_cache_breakpoint(&mut out[index]);
+    }
+
+    Value::Array(out)
+}
+
+fn mark_cache_breakpoint(message: &mut Value) {
+    if let Some(text) = message.get("content").and_then(Value::as_str) {
+        if text.is_empty() {
+            return;
+        }
+        message["content"] = json!([{
+            "type": "text",
+            "text": text,
+            "cache_control": cache_control_ephemeral(),
+        }]);
+        return;
+    }
+
+    // The API rejects cache_control on empty text blocks.
+    let target = message
+        .get_mut("content")
+        .and_then(Value::as_array_mut)
+        .and_then(|blocks| {
+            blocks.iter_mut().rev().find(|block| {
+                block.get("type").and_then(Value::as_str) != Some("text")
+                    || block
+                        .get("text")
+                        .and_then(Value::as_str)
+                        .is_some_and(|text| !text.is_empty())
+            })
+        });
+    if let Some(block) = target {
+        block["cache_control"] = cache_control_ephemeral();
+    }
+}
+
+/// Output token limit per request. The catalog's `max_output_tokens` for the
+/// active model caps `VEX_MAX_TOKENS` and the defaults.
+fn resolve_max_tokens(api_url: &str, model_max_output_tokens: Option<u32>) -> u32 {
+    let ceiling = model_max_output_tokens.unwrap_or(8192).max(128);
     if let Some(value) = std::env::var("VEX_MAX_TOKENS")
         .ok()
         .and_then(|v| v.trim().parse::<u32>().ok())
     {
-        return value.clamp(128, 8192);
+        return value.clamp(128, ceiling);
     }
 
-    if is_local_endpoint_url(api_url) {
+    let default = if is_local_endpoint_url(api_url) {
         1024
     } else {
         4096
-    }
+    };
+    default.min(ceiling)
 }
 
-fn parse_protocol(value: String) -> Option<ApiProtocol> {
+pub(super) fn parse_protocol(value: String) -> Option<ApiProtocol> {
     match value.trim().to_ascii_lowercase().as_str() {
         "anthropic" | "anthropic_messages" | "messages" | "v1/messages" => {
             Some(ApiProtocol::AnthropicMessages)
@@ -262,19 +824,63 @@ fn parse_protocol(value: String) -> Option<ApiProtocol> {
         "openai" | "chat" | "chat_completions" | "openai_chat_completions" => {
             Some(ApiProtocol::OpenAiChatCompletions)
         }
+        "responses" | "openai_responses" => Some(ApiProtocol::OpenAiResponses),
+        "ollama" | "ollama_chat" | "api/chat" => Some(ApiProtocol::OllamaChat),
         _ => None,
     }
 }
 
-fn infer_api_protocol(api_url: &str) -> ApiProtocol {
+/// Protocol spoken to `api_url`: `VEX_API_PROTOCOL`, else inferred from the
+/// URL and the catalog entry for `model`.
+pub fn resolve_api_protocol(api_url: &str, catalog: &ModelCatalog, model: &str) -> ApiProtocol {
+    let catalog_protocol = catalog.lookup(model).and_then(|info| info.protocol);
+    std::env::var("VEX_API_PROTOCOL")
+        .ok()
+        .and_then(parse_protocol)
+        .unwrap_or_else(|| infer_api_protocol(api_url, catalog_protocol))
+}
+
+/// Protocol from the URL shape, then from the model's catalog entry when the
+/// URL is ambiguous (a bare host or gateway path), then Anthropic Messages.
+fn infer_api_protocol(api_url: &str, catalog_protocol: Option<ApiProtocol>) -> ApiProtocol {
     let normalized = api_url.trim().to_ascii_lowercase();
-    if normalized.contains("/chat/completions") || normalized.ends_with("/v1") {
+    let normalized = normalized.trim_end_matches('/');
+    if normalized.ends_with("/api/chat") {
+        ApiProtocol::OllamaChat
+    } else if normalized.ends_with("/responses") {
+        ApiProtocol::OpenAiResponses
+    } else if normalized.contains("/chat/completions") || normalized.ends_with("/v1") {
         ApiProtocol::OpenAiChatCompletions
-    } else {
+    } else if normalized.ends_with("/messages") {
         ApiProtocol::AnthropicMessages
+    } else {
+        catalog_protocol.unwrap_or(ApiProtocol::AnthropicMessages)
     }
 }
 
+/// Ids from an OpenAI/Anthropic `{"data": [{"id"}]}` list or Ollama's
+/// `{"models": [{"name"}]}` tags list.
+fn parse_model_list(body: &Value) -> Vec<String> {
+    let entries = body
+        .get("data")
+        .or_else(|| body.get("models"))
+        .and_then(Value::as_array);
+    let mut ids = entries
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .get("id")
+                .or_else(|| entry.get("name"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
 fn adapt_to_openai_chat_completions_url(api_url: &str) -> String {
     let normalized = api_url.trim_end_matches('/');
     if normalized.ends_with("/chat/completions") {
@@ -289,6 +895,37 @@ fn adapt_to_openai_chat_completions_url(api_url: &str) -> String {
     normalized.to_string()
 }
 
+fn adapt_to_openai_responses_url(api_url: &str) -> String {
+    let normalized = api_url.trim_end_matches('/');
+    if normalized.ends_with("/responses") {
+        return normalized.to_string();
+    }
+    if let Some(prefix) = normalized.strip_suffix("/chat/completions") {
+        return format!("{prefix}/responses");
+    }
+    if let Some(prefix) = normalized.strip_suffix("/messages") {
+        return format!("{prefix}/responses");
+    }
+    if normalized.ends_with("/v1") {
+        return format!("{normalized}/responses");
+    }
+    normalized.to_string()
+}
+
+/// Point any configured URL at `/api/chat` on the same host, so a bare
+/// `http://localhost:11434` or an OpenAI-shim URL works with `VEX_API_PROTOCOL=ollama`.
+fn adapt_to_ollama_chat_url(api_url: &str) -> String {
+    let normalized = api_url.trim_end_matches('/');
+    if normalized.ends_with("/api/chat") {
+        return normalized.to_string();
+    }
+    let base = ["/v1/chat/completions", "/v1/messages", "/v1"]
+        .iter()
+        .find_map(|suffix| normalized.strip_suffix(suffix))
+        .unwrap_or(normalized);
+    format!("{base}/api/chat")
+}
+
 fn openai_messages(messages: &[ApiMessage], system_prompt: &str) -> Vec<Value> {
     let mut out = Vec::with_capacity(messages.len() + 1);
     out.push(json!({
@@ -328,7 +965,10 @@ fn append_openai_message(out: &mut Vec<Value>, message: &ApiMessage) {
                             }
                         }));
                     }
-                    ContentBlock::ToolResult { .. } => {}
+                    ContentBlock::ToolResult { .. }
+                    | ContentBlock::Thinking { .. }
+                    | ContentBlock::RedactedThinking { .. }
+                    | ContentBlock::Image { .. } => {}
                 }
             }
 
@@ -346,6 +986,12 @@ fn append_openai_message(out: &mut Vec<Value>, message: &ApiMessage) {
         }
         (role, Content::Blocks(blocks)) => {
             let mut pushed = false;
+            // Images need the multi-part content form; plain text keeps the
+            // one-message-per-block layout local servers handle best.
+            let has_images = blocks
+                .iter()
+                .any(|block| matches!(block, ContentBlock::Image { .. }));
+            let mut parts = Vec::new();
             for block in blocks {
                 match block {
                     ContentBlock::ToolResult {
@@ -360,6 +1006,9 @@ fn append_openai_message(out: &mut Vec<Value>, message: &ApiMessage) {
                         }));
                         pushed = true;
                     }
+                    ContentBlock::Text { text } if has_images => {
+                        parts.push(json!({ "type": "text", "text": text }));
+                    }
                     ContentBlock::Text { text } => {
                         out.push(json!({
                             "role": role,
@@ -367,10 +1016,28 @@ fn append_openai_message(out: &mut Vec<Value>, message: &ApiMessage) {
                         }));
                         pushed = true;
                     }
-                    ContentBlock::ToolUse { .. } => {}
+                    ContentBlock::Image {
+                        source: ImageSource::Base64 { media_type, data },
+                    } => {
+                        parts.push(json!({
+                            "type": "image_url",
+                            "image_url": { "url": format!("data:{media_type};base64,{data}") }
+                        }));
+                    }
+                    ContentBlock::ToolUse { .. }
+                    | ContentBlock::Thinking { .. }
+                    | ContentBlock::RedactedThinking { .. } => {}
                 }
             }
 
+            if !parts.is_empty() {
+                out.push(json!({
+                    "role": role,
+                    "content": parts
+                }));
+                pushed = true;
+            }
+
             if !pushed {
                 out.push(json!({
                     "role": role,
@@ -381,6 +1048,143 @@ fn append_openai_message(out: &mut Vec<Value>, message: &ApiMessage) {
     }
 }
 
+/// Responses API `input` items. Tool calls and their outputs are standalone
+/// items linked by `call_id` rather than parts of a message.
+fn openai_responses_input(messages: &[ApiMessage]) -> Vec<Value> {
+    let mut out = Vec::with_capacity(messages.len());
+    for message in messages {
+        let blocks = match &message.content {
+            Content::Text(text) => {
+                out.push(json!({ "role": message.role, "content": text }));
+                continue;
+            }
+            Content::Blocks(blocks) => blocks,
+        };
+
+        let text_type = if message.role == "assistant" {
+            "output_text"
+        } else {
+            "input_text"
+        };
+        let mut parts = Vec::new();
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } if !text.is_empty() => {
+                    parts.push(json!({ "type": text_type, "text": text }));
+                }
+                ContentBlock::Text { .. } => {}
+                ContentBlock::Image {
+                    source: ImageSource::Base64 { media_type, data },
+                } => parts.push(json!({
+                    "type": "input_image",
+                    "image_url": format!("data:{media_type};base64,{data}"),
+                })),
+                ContentBlock::ToolUse { id, name, input } => {
+                    flush_responses_message(&mut out, &message.role, &mut parts);
+                    out.push(json!({
+                        "type": "function_call",
+                        "call_id": id,
+                        "name": name,
+                        "arguments": tool_input_to_json_string(input),
+                    }));
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    ..
+                } => {
+                    flush_responses_message(&mut out, &message.role, &mut parts);
+                    out.push(json!({
+                        "type": "function_call_output",
+                        "call_id": tool_use_id,
+                        "output": content,
+                    }));
+                }
+                ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
+            }
+        }
+        flush_responses_message(&mut out, &message.role, &mut parts);
+    }
+    out
+}
+
+fn flush_responses_message(out: &mut Vec<Value>, role: &str, parts: &mut Vec<Value>) {
+    if !parts.is_empty() {
+        out.push(json!({ "role": role, "content": std::mem::take(parts) }));
+    }
+}
+
+/// Ollama's native message list: tool call arguments are JSON objects, tool
+/// results are matched by `tool_name`, and images ride on the message.
+fn ollama_messages(messages: &[ApiMessage], system_prompt: &str) -> Vec<Value> {
+    let mut out = Vec::with_capacity(messages.len() + 1);
+    out.push(json!({
+        "role": "system",
+        "content": system_prompt
+    }));
+
+    let mut tool_names = std::collections::HashMap::new();
+    for message in messages {
+        let blocks = match &message.content {
+            Content::Text(text) => {
+                out.push(json!({ "role": message.role, "content": text }));
+                continue;
+            }
+            Content::Blocks(blocks) => blocks,
+        };
+
+        let mut content = String::new();
+        let mut images = Vec::new();
+        let mut tool_calls = Vec::new();
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } => content.push_str(text),
+                ContentBlock::Image {
+                    source: ImageSource::Base64 { data, .. },
+                } => images.push(json!(data)),
+                ContentBlock::ToolUse { id, name, input } => {
+                    tool_names.insert(id.clone(), name.clone());
+                    let arguments = match input {
+                        Value::String(text) => {
+                            serde_json::from_str(text).unwrap_or_else(|_| json!({}))
+                        }
+                        other => other.clone(),
+                    };
+                    tool_calls.push(json!({
+                        "function": { "name": name, "arguments": arguments }
+                    }));
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    ..
+                } => {
+                    let mut result = json!({ "role": "tool", "content": content });
+                    if let Some(name) = tool_names.get(tool_use_id) {
+                        result["tool_name"] = json!(name);
+                    }
+                    out.push(result);
+                }
+                ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
+            }
+        }
+
+        if content.is_empty() && images.is_empty() && tool_calls.is_empty() {
+            continue;
+        }
+        let mut entry = json!({ "role": message.role, "content": content });
+        if !images.is_empty() {
+            entry["images"] = Value::Array(images);
+        }
+        if !tool_calls.is_empty() {
+            entry["tool_calls"] = Value::Array(tool_calls);
+        }
+        out.push(entry);
+    }
+
+    out
+}
+
 fn tool_input_to_json_string(value: &Value) -> String {
     match value {
         Value::String(text) => text.clone(),
@@ -388,8 +1192,7 @@ fn tool_input_to_json_string(value: &Value) -> String {
     }
 }
 
-fn tool_definitions_openai() -> Value {
-    let anthropic = tool_definitions();
+fn tool_definitions_openai(anthropic: Value) -> Value {
     let converted = anthropic
         .as_array()
         .map(|tools| {
@@ -414,6 +1217,24 @@ fn tool_definitions_openai() -> Value {
     Value::Array(converted)
 }
 
+/// Responses API tools use a flat `{type, name, description, parameters}` shape.
+fn tool_definitions_openai_responses(anthropic: Value) -> Value {
+    let converted = tool_definitions_openai(anthropic)
+        .as_array()
+        .map(|tools| {
+            tools
+                .iter()
+                .map(|tool| {
+                    let mut flat = tool.get("function").cloned().unwrap_or_else(|| json!({}));
+                    flat["type"] = json!("function");
+                    flat
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    Value::Array(converted)
+}
+
 fn tool_definitions() -> serde_json::Value {
     json!([
         {
@@ -574,6 +1395,20 @@ fn tool_definitions() -> serde_json::Value {
                 },
                 "required": ["message"]
             }
+        },
+        {
+            "name": "delegate",
+            "description": "Hand a self-contained research task to a sub-agent with read-only tools (read, list, search, git inspection). It works in a fresh context and returns only its final summary. Use it for broad exploration that would otherwise take many searches and reads.",
+            "input_schema": {
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "What to find out and what the summary should contain."
+                    }
+                },
+                "required": ["task"]
+            }
         }
     ])
 }
@@ -585,16 +1420,68 @@ mod tests {
 
     #[test]
     fn test_protocol_inference_defaults_to_anthropic_messages() {
-        let protocol = infer_api_protocol("http://localhost:8000/v1/messages");
+        let protocol = infer_api_protocol("http://localhost:8000/v1/messages", None);
         assert_eq!(protocol, ApiProtocol::AnthropicMessages);
     }
 
     #[test]
     fn test_protocol_inference_detects_openai_chat() {
-        let protocol = infer_api_protocol("http://localhost:8000/v1/chat/completions");
+        let protocol = infer_api_protocol("http://localhost:8000/v1/chat/completions", None);
         assert_eq!(protocol, ApiProtocol::OpenAiChatCompletions);
     }
 
+    #[test]
+    fn test_protocol_inference_detects_ollama_chat() {
+        assert_eq!(
+            infer_api_protocol("http://localhost:11434/api/chat", None),
+            ApiProtocol::OllamaChat
+        );
+        assert_eq!(
+            parse_protocol("Ollama".to_string()),
+            Some(ApiProtocol::OllamaChat)
+        );
+    }
+
+    #[test]
+    fn test_responses_protocol_inference_and_url_adapter() {
+        assert_eq!(
+            infer_api_protocol("https://gateway.example.com/v1/responses", None),
+            ApiProtocol::OpenAiResponses
+        );
+        assert_eq!(
+            parse_protocol("responses".to_string()),
+            Some(ApiProtocol::OpenAiResponses)
+        );
+        assert_eq!(
+            adapt_to_openai_responses_url("http://localhost:8000/v1/chat/completions"),
+            "http://localhost:8000/v1/responses"
+        );
+        assert_eq!(
+            adapt_to_openai_responses_url("http://localhost:8000/v1/messages"),
+            "http://localhost:8000/v1/responses"
+        );
+        assert_eq!(
+            adapt_to_openai_responses_url("http://localhost:8000/v1/"),
+            "http://localhost:8000/v1/responses"
+        );
+    }
+
+    #[test]
+    fn test_ollama_url_adapter_targets_api_chat() {
+        assert_eq!(
+            adapt_to_ollama_chat_url("http://localhost:11434/"),
+            "http://localhost:11434/api/chat"
+        );
+        assert_eq!(
+            adapt_to_ollama_chat_url("http://localhost:11434/v1/chat/completions"),
+            "http://localhost:11434/api/chat"
+        );
+        assert_eq!(
+            adapt_to_ollama_chat_url("http://localhost:11434/api/chat"),
+            "http://localhost:11434/api/chat"
+        );
+    }
+
     #[test]
     fn test_openai_url_adapter_from_messages_endpoint() {
         let adapted = adapt_to_openai_chat_completions_url("http://localhost:8000/v1/messages");
@@ -607,9 +1494,72 @@ mod tests {
         assert_eq!(adapted, "http://localhost:8000/v1/chat/completions");
     }
 
+    #[test]
+    fn test_protocol_inference_falls_back_to_catalog_for_ambiguous_urls() {
+        assert_eq!(
+            infer_api_protocol(
+                "https://gateway.example.com/llm",
+                Some(ApiProtocol::OpenAiChatCompletions)
+            ),
+            ApiProtocol::OpenAiChatCompletions
+        );
+        assert_eq!(
+            infer_api_protocol(
+                "https://gateway.example.com/v1/messages",
+                Some(ApiProtocol::OpenAiChatCompletions)
+            ),
+            ApiProtocol::AnthropicMessages
+        );
+    }
+
+    #[test]
+    fn test_parse_model_list_reads_openai_and_ollama_shapes() {
+        assert_eq!(
+            parse_model_list(&json!({
+                "object": "list",
+                "data": [{ "id": "gpt-4o" }, { "id": "gpt-4.1" }, { "id": "gpt-4o" }]
+            })),
+            vec!["gpt-4.1".to_string(), "gpt-4o".to_string()]
+        );
+        assert_eq!(
+            parse_model_list(&json!({ "models": [{ "name": "qwen2.5-coder:14b" }] })),
+            vec!["qwen2.5-coder:14b".to_string()]
+        );
+        assert!(parse_model_list(&json!({ "error": "nope" })).is_empty());
+    }
+
+    #[test]
+    fn test_set_model_validates_against_catalog_and_drives_max_tokens() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var("VEX_API_PROTOCOL");
+        std::env::remove_var("VEX_MODEL_CATALOG");
+        std::env::set_var("VEX_MAX_TOKENS", "20000");
+        let mut client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        assert_eq!(client.models_url(), "https://api.anthropic.com/v1/models");
+
+        assert!(client.set_model("mystery-model").is_err());
+        let error = client.set_model("gpt-4o").unwrap_err();
+        assert!(error.to_string().contains("openai protocol"), "{error}");
+        assert_eq!(client.model(), "claude-sonnet-4-5-20250929");
+        assert!(client.set_model("claude-3-opus-20240229").is_err());
+        client
+            .set_model("claude-3-5-haiku-20241022")
+            .expect("catalog model should be accepted");
+        assert_eq!(client.model(), "claude-3-5-haiku-20241022");
+        let max_output = client
+            .model_info()
+            .and_then(|model| model.max_output_tokens);
+        assert_eq!(resolve_max_tokens(&client.api_url, max_output), 8192);
+
+        client.add_discovered_models(&["mystery-model".to_string()]);
+        assert!(client.set_model("mystery-model").is_ok());
+        assert_eq!(resolve_max_tokens(&client.api_url, None), 8192);
+        std::env::remove_var("VEX_MAX_TOKENS");
+    }
+
     #[test]
     fn test_resolve_max_tokens_defaults_for_local() {
-        let tokens = resolve_max_tokens("http://localhost:8000/v1/messages");
+        let tokens = resolve_max_tokens("http://localhost:8000/v1/messages", None);
         assert_eq!(tokens, 1024);
     }
 
@@ -630,6 +1580,7 @@ mod tests {
             "git_show",
             "git_add",
             "git_commit",
+            "delegate",
         ]);
 
         let names: BTreeSet<String> = tool_definitions()
@@ -693,6 +1644,412 @@ mod tests {
         assert!(client.supports_structured_tool_protocol());
     }
 
+    fn remote_anthropic_config() -> crate::config::Config {
+        crate::config::Config {
+            api_key: Some("test-key".to_string()),
+            model: "claude-sonnet-4-5-20250929".to_string(),
+            api_url: "https://api.anthropic.com/v1/messages".to_string(),
+            anthropic_version: "2023-06-01".to_string(),
+            working_dir: std::path::PathBuf::from("."),
+        }
+    }
+
+    fn cache_breakpoint_count(value: &Value) -> usize {
+        match value {
+            Value::Object(map) => {
+                usize::from(map.contains_key("cache_control"))
+                    + map.values().map(cache_breakpoint_count).sum::<usize>()
+            }
+            Value::Array(items) => items.iter().map(cache_breakpoint_count).sum(),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_prompt_caching_marks_system_tools_and_rolling_history() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var("VEX_PROMPT_CACHE");
+        std::env::remove_var("VEX_STRUCTURED_TOOL_PROTOCOL");
+        let client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        assert!(client.prompt_caching_enabled());
+
+        let messages = vec![
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Text("read cal.rs".to_string()),
+            },
+            ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "read_file".to_string(),
+                    input: json!({ "path": "cal.rs" }),
+                }]),
+            },
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_1".to_string(),
+                    content: "fn main() {}".to_string(),
+                    is_error: false,
+                }]),
+            },
+        ];
+        let payload = client.anthropic_payload(&messages, 1024);
+
+        assert_eq!(
+            payload["system"][0]["cache_control"]["type"],
+            json!("ephemeral")
+        );
+        let tools = payload["tools"].as_array().expect("tools must be an array");
+        assert!(tools.last().unwrap().get("cache_control").is_some());
+        assert_eq!(
+            payload["messages"][0]["content"][0]["cache_control"]["type"],
+            json!("ephemeral")
+        );
+        assert_eq!(
+            payload["messages"][2]["content"][0]["cache_control"]["type"],
+            json!("ephemeral")
+        );
+        assert!(payload["messages"][1]["content"][0]
+            .get("cache_control")
+            .is_none());
+        assert_eq!(cache_breakpoint_count(&payload), 4);
+
+        // A pruned slice gets its breakpoints recomputed from scratch.
+        let pruned = client.anthropic_payload(&messages[2..], 1024);
+        assert_eq!(cache_breakpoint_count(&pruned["messages"]), 1);
+    }
+
+    #[test]
+    fn test_prompt_caching_env_off_keeps_plain_payload() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var("VEX_PROMPT_CACHE", "off");
+        let client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        std::env::remove_var("VEX_PROMPT_CACHE");
+        assert!(!client.prompt_caching_enabled());
+
+        let messages = vec![ApiMessage {
+            role: "user".to_string(),
+            content: Content::Text("hello".to_string()),
+        }];
+        let payload = client.anthropic_payload(&messages, 1024);
+        assert_eq!(payload["system"], json!(SYSTEM_PROMPT));
+        assert_eq!(payload["messages"][0]["content"], json!("hello"));
+        assert_eq!(cache_breakpoint_count(&payload), 0);
+    }
+
+    #[test]
+    fn test_tool_allowlist_limits_offered_tools() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var("VEX_PROMPT_CACHE", "off");
+        let client = ApiClient::new(&remote_anthropic_config())
+            .expect("client should build")
+            .with_tool_allowlist(&["read_file", "git_log"]);
+        std::env::remove_var("VEX_PROMPT_CACHE");
+
+        let messages = vec![ApiMessage {
+            role: "user".to_string(),
+            content: Content::Text("hello".to_string()),
+        }];
+        let payload = client.anthropic_payload(&messages, 1024);
+        let names: Vec<&str> = payload["tools"]
+            .as_array()
+            .expect("tools should be an array")
+            .iter()
+            .filter_map(|tool| tool["name"].as_str())
+            .collect();
+        assert_eq!(names, vec!["read_file", "git_log"]);
+    }
+
+    #[test]
+    fn test_auth_scheme_env_selects_credential_header() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        let request_headers = |client: &ApiClient| {
+            client
+                .with_auth_headers(client.http.get("https://gateway.example/v1/messages"))
+                .build()
+                .expect("request should build")
+                .headers()
+                .clone()
+        };
+
+        std::env::remove_var("VEX_AUTH_SCHEME");
+        let native = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        let headers = request_headers(&native);
+        assert_eq!(headers["x-api-key"], "test-key");
+        assert_eq!(headers["anthropic-version"], "2023-06-01");
+
+        std::env::set_var("VEX_AUTH_SCHEME", "bearer");
+        let bearer = ApiClient::new(&remote_anthropic_config());
+        std::env::set_var("VEX_AUTH_SCHEME", "api-key");
+        let azure = ApiClient::new(&remote_anthropic_config());
+        std::env::set_var("VEX_AUTH_SCHEME", "none");
+        let anonymous = ApiClient::new(&remote_anthropic_config());
+        std::env::set_var("VEX_AUTH_SCHEME", "digest");
+        let invalid = ApiClient::new(&remote_anthropic_config());
+        std::env::remove_var("VEX_AUTH_SCHEME");
+
+        let headers = request_headers(&bearer.expect("bearer client"));
+        assert_eq!(headers["authorization"], "Bearer test-key");
+        assert!(headers.get("x-api-key").is_none());
+        assert_eq!(headers["anthropic-version"], "2023-06-01");
+        let headers = request_headers(&azure.expect("azure client"));
+        assert_eq!(headers["api-key"], "test-key");
+        let headers = request_headers(&anonymous.expect("anonymous client"));
+        assert!(headers.get("x-api-key").is_none() && headers.get("authorization").is_none());
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_project_instructions_are_appended_to_system_prompt() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var("VEX_PROMPT_CACHE", "off");
+        let mut client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        std::env::remove_var("VEX_PROMPT_CACHE");
+        let messages = vec![ApiMessage {
+            role: "user".to_string(),
+            content: Content::Text("hello".to_string()),
+        }];
+        client.set_project_instructions(Some("# Project instructions\nUse tabs.".to_string()));
+
+        let payload = client.anthropic_payload(&messages, 1024);
+        let system = payload["system"].as_str().expect("plain system prompt");
+        assert!(system.starts_with(SYSTEM_PROMPT));
+        assert!(system.ends_with("\n\n# Project instructions\nUse tabs."));
+        let ollama = client.ollama_payload(&messages, 1024);
+        assert_eq!(ollama["messages"][0]["content"], json!(system));
+
+        client.set_mode_instructions(Some("Plan first."));
+        assert!(client
+            .system_prompt()
+            .ends_with("\n\n# Project instructions\nUse tabs.\n\nPlan first."));
+
+        client.set_project_instructions(None);
+        client.set_mode_instructions(None);
+        assert_eq!(client.system_prompt(), SYSTEM_PROMPT);
+    }
+
+    #[test]
+    fn test_prompt_caching_defaults_off_for_local_and_openai_endpoints() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var("VEX_PROMPT_CACHE");
+        std::env::remove_var("VEX_API_PROTOCOL");
+        let local = crate::config::Config {
+            api_url: "http://localhost:8000/v1/messages".to_string(),
+            ..remote_anthropic_config()
+        };
+        let openai = crate::config::Config {
+            api_url: "https://api.openai.com/v1/chat/completions".to_string(),
+            ..remote_anthropic_config()
+        };
+
+        assert!(!ApiClient::new(&local).unwrap().prompt_caching_enabled());
+        assert!(!ApiClient::new(&openai).unwrap().prompt_caching_enabled());
+    }
+
+    #[test]
+    fn test_thinking_budget_adds_thinking_param_and_reserves_max_tokens() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var("VEX_THINKING_BUDGET", "2048");
+        let client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        std::env::set_var("VEX_THINKING_BUDGET", "0");
+        let disabled = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        std::env::remove_var("VEX_THINKING_BUDGET");
+
+        let payload = client.anthropic_payload(&[], 4096);
+        assert_eq!(
+            payload["thinking"],
+            json!({ "type": "enabled", "budget_tokens": 2048 })
+        );
+        assert_eq!(payload["max_tokens"], json!(6144));
+
+        let payload = disabled.anthropic_payload(&[], 4096);
+        assert!(payload.get("thinking").is_none());
+        assert_eq!(payload["max_tokens"], json!(4096));
+    }
+
+    #[test]
+    fn test_context_window_prefers_env_then_catalog_then_default() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        for key in [
+            "VEX_CONTEXT_WINDOW",
+            "VEX_MAX_TOKENS",
+            "VEX_THINKING_BUDGET",
+            "VEX_API_PROTOCOL",
+            "VEX_MODEL_CATALOG",
+            "VEX_STRUCTURED_TOOL_PROTOCOL",
+        ] {
+            std::env::remove_var(key);
+        }
+        let client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        assert_eq!(client.context_window(), 200_000);
+        assert_eq!(client.reserved_output_tokens(), 4096);
+        assert!(client.request_overhead_chars() > client.system_prompt().len());
+
+        std::env::set_var("VEX_CONTEXT_WINDOW", "32000");
+        assert_eq!(client.context_window(), 32_000);
+        std::env::remove_var("VEX_CONTEXT_WINDOW");
+
+        let mut config = remote_anthropic_config();
+        config.model = "unlisted-model".to_string();
+        config.api_url = "http://localhost:8000/v1/messages".to_string();
+        let local = ApiClient::new(&config).expect("client should build");
+        assert_eq!(local.context_window(), DEFAULT_LOCAL_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_openai_responses_payload_uses_input_items_and_flat_tools() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var("VEX_STRUCTURED_TOOL_PROTOCOL");
+        std::env::set_var("VEX_API_PROTOCOL", "responses");
+        let client = ApiClient::new(&remote_anthropic_config()).expect("client should build");
+        std::env::remove_var("VEX_API_PROTOCOL");
+
+        let messages = vec![
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Text("read cal.rs".to_string()),
+            },
+            ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Blocks(vec![
+                    ContentBlock::Text {
+                        text: "Reading it.".to_string(),
+                    },
+                    ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "read_file".to_string(),
+                        input: json!({ "path": "cal.rs" }),
+                    },
+                ]),
+            },
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".to_string(),
+                    content: "fn main() {}".to_string(),
+                    is_error: false,
+                }]),
+            },
+        ];
+        let payload = client.openai_responses_payload(&messages, 1024);
+
+        assert_eq!(
+            client.request_url(),
+            "https://api.anthropic.com/v1/responses"
+        );
+        assert_eq!(payload["instructions"], json!(SYSTEM_PROMPT));
+        assert_eq!(payload["max_output_tokens"], json!(1024));
+        assert_eq!(payload["tools"][0]["type"], json!("function"));
+        assert_eq!(payload["tools"][0]["name"], json!("read_file"));
+        assert_eq!(
+            payload["input"],
+            json!([
+                { "role": "user", "content": "read cal.rs" },
+                { "role": "assistant", "content": [{ "type": "output_text", "text": "Reading it." }] },
+                { "type": "function_call", "call_id": "call_1", "name": "read_file", "arguments": "{\"path\":\"cal.rs\"}" },
+                { "type": "function_call_output", "call_id": "call_1", "output": "fn main() {}" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ollama_payload_sets_tools_options_and_native_messages() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var("VEX_STRUCTURED_TOOL_PROTOCOL");
+        std::env::remove_var("VEX_API_PROTOCOL");
+        std::env::set_var("VEX_OLLAMA_NUM_CTX", "32768");
+        std::env::set_var("VEX_OLLAMA_KEEP_ALIVE", "-1");
+        let config = crate::config::Config {
+            api_key: None,
+            model: "qwen2.5-coder:14b".to_string(),
+            api_url: "http://localhost:11434/api/chat".to_string(),
+            anthropic_version: "2023-06-01".to_string(),
+            working_dir: std::path::PathBuf::from("."),
+        };
+        let client = ApiClient::new(&config).expect("client should build");
+        assert!(client.supports_structured_tool_protocol());
+
+        let messages = vec![
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Text("read cal.rs".to_string()),
+            },
+            ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolUse {
+                    id: "toolu_ollama_0".to_string(),
+                    name: "read_file".to_string(),
+                    input: json!({ "path": "cal.rs" }),
+                }]),
+            },
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_ollama_0".to_string(),
+                    content: "fn main() {}".to_string(),
+                    is_error: false,
+                }]),
+            },
+        ];
+        let payload = client.ollama_payload(&messages, 1024);
+        std::env::remove_var("VEX_OLLAMA_NUM_CTX");
+        std::env::remove_var("VEX_OLLAMA_KEEP_ALIVE");
+
+        assert_eq!(client.request_url(), "http://localhost:11434/api/chat");
+        assert_eq!(payload["options"]["num_ctx"], json!(32768));
+        assert_eq!(payload["options"]["num_predict"], json!(1024));
+        assert_eq!(payload["keep_alive"], json!(-1));
+        assert_eq!(payload["tools"][0]["function"]["name"], json!("read_file"));
+        assert_eq!(payload["messages"][0]["role"], json!("system"));
+        assert_eq!(
+            payload["messages"][2]["tool_calls"][0],
+            json!({ "function": { "name": "read_file", "arguments": { "path": "cal.rs" } } })
+        );
+        assert_eq!(
+            payload["messages"][3],
+            json!({ "role": "tool", "content": "fn main() {}", "tool_name": "read_file" })
+        );
+    }
+
+    #[test]
+    fn test_image_blocks_serialize_for_both_protocols() {
+        let message = ApiMessage {
+            role: "user".to_string(),
+            content: Content::Blocks(vec![
+                ContentBlock::Image {
+                    source: ImageSource::Base64 {
+                        media_type: "image/png".to_string(),
+                        data: "iVBORw0KGgo=".to_string(),
+                    },
+                },
+                ContentBlock::Text {
+                    text: "what is wrong here?".to_string(),
+                },
+            ]),
+        };
+
+        assert_eq!(
+            json!(message)["content"][0],
+            json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo=" }
+            })
+        );
+
+        let openai = openai_messages(std::slice::from_ref(&message), "system");
+        assert_eq!(openai.len(), 2);
+        assert_eq!(
+            openai[1]["content"][0]["image_url"]["url"],
+            json!("data:image/png;base64,iVBORw0KGgo=")
+        );
+        assert_eq!(
+            openai[1]["content"][1],
+            json!({ "type": "text", "text": "what is wrong here?" })
+        );
+    }
+
     #[test]
     fn test_openai_tool_definitions_match_base_tool_names() {
         let base_names: BTreeSet<String> = tool_definitions()
@@ -703,7 +2060,7 @@ mod tests {
             .map(ToOwned::to_owned)
             .collect();
 
-        let openai_names: BTreeSet<String> = tool_definitions_openai()
+        let openai_names: BTreeSet<String> = tool_definitions_openai(tool_definitions())
             .as_array()
             .expect("openai tool definitions must be an array")
             .iter()
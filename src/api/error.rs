@@ -0,0 +1,463 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Classified failure from a model endpoint, parsed from HTTP error bodies
+/// and in-stream error events of every supported protocol.
+///
+/// Travels inside `anyhow::Error`; recover it with `downcast_ref::<ApiError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// The API key is missing, malformed or revoked.
+    Authentication {
+        message: String,
+    },
+    /// The key is valid but may not use this model or endpoint.
+    PermissionDenied {
+        message: String,
+    },
+    RateLimited {
+        message: String,
+        /// How long the server asked us to wait, when it said.
+        retry_after: Option<Duration>,
+    },
+    Overloaded {
+        message: String,
+    },
+    /// The prompt does not fit the model's context window.
+    ContextLengthExceeded {
+        message: String,
+    },
+    InvalidRequest {
+        message: String,
+        /// Request field the server objected to, when it named one.
+        field: Option<String>,
+    },
+    /// The endpoint could not be reached or the connection failed.
+    Network {
+        message: String,
+    },
+    /// Any other server-side failure.
+    Server {
+        status: Option<u16>,
+        message: String,
+    },
+}
+
+impl ApiError {
+    /// Classify a non-success HTTP response.
+    pub fn from_response(status: u16, retry_after: Option<Duration>, body: &str) -> Self {
+        let parsed = serde_json::from_str::<Value>(body).ok();
+        let detail = parsed.as_ref().and_then(ErrorDetail::from_body);
+        let detail = detail.unwrap_or_else(|| ErrorDetail {
+            message: fallback_message(status, body),
+            ..ErrorDetail::default()
+        });
+        classify(Some(status), detail, retry_after)
+    }
+
+    /// Classify an error object carried in a stream event: Anthropic's
+    /// `{"type":"error","error":{..}}`, OpenAI's `{"error":{..}}`, or a
+    /// Responses API `error` or `response.failed` event. `None` when the
+    /// value is not an error.
+    pub fn from_stream_event(event: &Value) -> Option<Self> {
+        let detail = ErrorDetail::from_body(event)?;
+        Some(classify(None, detail, None))
+    }
+
+    /// Classify a bare error message, as sent by servers without error codes.
+    pub fn from_message(message: impl Into<String>) -> Self {
+        let detail = ErrorDetail {
+            message: message.into(),
+            ..ErrorDetail::default()
+        };
+        classify(None, detail, None)
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        ApiError::Network {
+            message: message.into(),
+        }
+    }
+}
+
+/// A non-success HTTP response as received: what [`ApiError::from_response`]
+/// classifies, kept whole so cassettes replay it the way it first failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpErrorResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl HttpErrorResponse {
+    pub async fn read(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        let body = response.text().await.unwrap_or_default();
+        Self {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub fn to_api_error(&self) -> ApiError {
+        let headers: HeaderMap = self
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect();
+        ApiError::from_response(self.status, retry_after_from_headers(&headers), &self.body)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Authentication { message } => write!(f, "authentication failed: {message}"),
+            ApiError::PermissionDenied { message } => write!(f, "permission denied: {message}"),
+            ApiError::RateLimited {
+                message,
+                retry_after,
+            } => {
+                write!(f, "rate limited: {message}")?;
+                if let Some(wait) = retry_after {
+                    write!(f, " (retry after {}s)", wait.as_secs().max(1))?;
+                }
+                Ok(())
+            }
+            ApiError::Overloaded { message } => write!(f, "API overloaded: {message}"),
+            ApiError::ContextLengthExceeded { message } => {
+                write!(f, "context length exceeded: {message}")
+            }
+            ApiError::InvalidRequest { message, field } => match field {
+                Some(field) => write!(f, "invalid request ({field}): {message}"),
+                None => write!(f, "invalid request: {message}"),
+            },
+            ApiError::Network { message } => write!(f, "{message}"),
+            ApiError::Server {
+                status: Some(status),
+                message,
+            } => write!(f, "API error (HTTP {status}): {message}"),
+            ApiError::Server {
+                status: None,
+                message,
+            } => write!(f, "API error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Fields shared by the error bodies of the supported protocols.
+#[derive(Debug, Default)]
+struct ErrorDetail {
+    kind: Option<String>,
+    code: Option<String>,
+    message: String,
+    param: Option<String>,
+}
+
+impl ErrorDetail {
+    fn from_body(body: &Value) -> Option<Self> {
+        let text = |value: Option<&Value>| match value {
+            Some(Value::String(text)) if !text.is_empty() => Some(text.clone()),
+            Some(Value::Number(number)) => Some(number.to_string()),
+            _ => None,
+        };
+        // Responses API `response.failed` events nest the error in the response.
+        let error = body.get("error").or_else(|| {
+            body.pointer("/response/error")
+                .filter(|error| error.is_object())
+        });
+        match error {
+            Some(Value::String(message)) => Some(Self {
+                message: message.clone(),
+                ..Self::default()
+            }),
+            Some(error @ Value::Object(_)) => Some(Self {
+                kind: text(error.get("type")),
+                code: text(error.get("code")),
+                message: text(error.get("message")).unwrap_or_default(),
+                param: text(error.get("param")),
+            }),
+            // Responses API `error` events keep the fields at the top level.
+            _ if body.get("type").and_then(Value::as_str) == Some("error") => Some(Self {
+                kind: None,
+                code: text(body.get("code")),
+                message: text(body.get("message")).unwrap_or_default(),
+                param: text(body.get("param")),
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn classify(status: Option<u16>, detail: ErrorDetail, retry_after: Option<Duration>) -> ApiError {
+    let kind = detail.kind.as_deref().unwrap_or_default();
+    let code = detail.code.as_deref().unwrap_or_default();
+    let message = if detail.message.is_empty() {
+        [kind, code]
+            .into_iter()
+            .find(|label| !label.is_empty())
+            .unwrap_or("no error message")
+            .to_string()
+    } else {
+        detail.message
+    };
+
+    if status == Some(401)
+        || kind == "authentication_error"
+        || code == "invalid_api_key"
+        || code == "401"
+    {
+        return ApiError::Authentication { message };
+    }
+    if status == Some(403) || kind == "permission_error" || code == "403" {
+        return ApiError::PermissionDenied { message };
+    }
+    if status == Some(429)
+        || kind == "rate_limit_error"
+        || code == "rate_limit_exceeded"
+        || kind == "insufficient_quota"
+        || code == "429"
+    {
+        return ApiError::RateLimited {
+            message,
+            retry_after,
+        };
+    }
+    if is_context_length_message(&message)
+        || code == "context_length_exceeded"
+        || kind == "exceed_context_size_error"
+        || kind == "request_too_large"
+        || status == Some(413)
+    {
+        return ApiError::ContextLengthExceeded { message };
+    }
+    if status == Some(529) || kind == "overloaded_error" || code == "529" {
+        return ApiError::Overloaded { message };
+    }
+    let client_error = status.is_some_and(|status| (400..500).contains(&status));
+    if client_error || kind == "invalid_request_error" || kind == "not_found_error" {
+        let field = detail.param.or_else(|| field_from_message(&message));
+        return ApiError::InvalidRequest { message, field };
+    }
+    ApiError::Server { status, message }
+}
+
+/// Phrases servers use when the prompt overflows the context window.
+fn is_context_length_message(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    [
+        "prompt is too long",
+        "context length",
+        "context_length",
+        "context window",
+        "maximum context",
+        "exceeds the context",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|phrase| message.contains(phrase))
+}
+
+/// Anthropic prefixes validation errors with the offending path, as in
+/// `messages.1.content.0.text: field required`.
+fn field_from_message(message: &str) -> Option<String> {
+    let (path, _) = message.split_once(": ")?;
+    let looks_like_path = !path.is_empty()
+        && !path.contains(char::is_whitespace)
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '[' | ']' | '-'));
+    looks_like_path.then(|| path.to_string())
+}
+
+fn fallback_message(status: u16, body: &str) -> String {
+    let body = body.trim();
+    if body.is_empty() {
+        return format!("HTTP {status} with an empty body");
+    }
+    let mut message: String = body.chars().take(300).collect();
+    if message.len() < body.len() {
+        message.push_str("...");
+    }
+    message
+}
+
+/// Wait requested by `retry-after`, `retry-after-ms` or OpenAI's
+/// `x-ratelimit-reset-*` headers.
+pub fn retry_after_from_headers(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    if let Some(secs) = header("retry-after").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(secs.max(0.0)));
+    }
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .into_iter()
+        .filter_map(|name| header(name).and_then(parse_reset_duration))
+        .max()
+}
+
+/// Parse OpenAI reset durations such as `20ms`, `1s` or `6m0s`.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0f64;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    let mut saw_unit = false;
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount = number.parse::<f64>().ok()?;
+        number.clear();
+        let scale = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total += amount * scale;
+        saw_unit = true;
+    }
+    (saw_unit && number.is_empty()).then(|| Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_bodies_classify_for_each_protocol() {
+        let anthropic =
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert_eq!(
+            ApiError::from_response(529, None, anthropic),
+            ApiError::Overloaded {
+                message: "Overloaded".to_string()
+            }
+        );
+
+        let openai = r#"{"error":{"message":"This model's maximum context length is 128000 tokens.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#;
+        assert!(matches!(
+            ApiError::from_response(400, None, openai),
+            ApiError::ContextLengthExceeded { .. }
+        ));
+
+        let too_long = r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#;
+        assert!(matches!(
+            ApiError::from_response(400, None, too_long),
+            ApiError::ContextLengthExceeded { .. }
+        ));
+
+        let invalid = r#"{"type":"error","error":{"type":"invalid_request_error","message":"messages.1.content.0.text: field required"}}"#;
+        assert_eq!(
+            ApiError::from_response(400, None, invalid),
+            ApiError::InvalidRequest {
+                message: "messages.1.content.0.text: field required".to_string(),
+                field: Some("messages.1.content.0.text".to_string()),
+            }
+        );
+
+        let ollama = r#"{"error":"model 'llama9' not found"}"#;
+        assert!(matches!(
+            ApiError::from_response(404, None, ollama),
+            ApiError::InvalidRequest { field: None, .. }
+        ));
+
+        assert!(matches!(
+            ApiError::from_response(401, None, "<html>Unauthorized</html>"),
+            ApiError::Authentication { .. }
+        ));
+        assert!(matches!(
+            ApiError::from_response(502, None, ""),
+            ApiError::Server {
+                status: Some(502),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_rate_limit_carries_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("6m0s"),
+        );
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("20ms"));
+        let retry_after = retry_after_from_headers(&headers);
+        assert_eq!(retry_after, Some(Duration::from_secs(360)));
+
+        headers.insert("retry-after", HeaderValue::from_static("17"));
+        let retry_after = retry_after_from_headers(&headers);
+        let error = ApiError::from_response(
+            429,
+            retry_after,
+            r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#,
+        );
+        assert_eq!(
+            error,
+            ApiError::RateLimited {
+                message: "Rate limit reached".to_string(),
+                retry_after: Some(Duration::from_secs(17)),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "rate limited: Rate limit reached (retry after 17s)"
+        );
+    }
+
+    #[test]
+    fn test_stream_events_classify_without_status() {
+        let anthropic: Value = serde_json::from_str(
+            r#"{"type":"error","error":{"type":"rate_limit_error","message":"slow down"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            ApiError::from_stream_event(&anthropic),
+            Some(ApiError::RateLimited {
+                retry_after: None,
+                ..
+            })
+        ));
+
+        let responses: Value = serde_json::from_str(
+            r#"{"type":"error","code":"context_length_exceeded","message":"Your input exceeds the context window of this model."}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            ApiError::from_stream_event(&responses),
+            Some(ApiError::ContextLengthExceeded { .. })
+        ));
+
+        let delta: Value =
+            serde_json::from_str(r#"{"type":"content_block_delta","index":0}"#).unwrap();
+        assert_eq!(ApiError::from_stream_event(&delta), None);
+    }
+}
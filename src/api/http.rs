@@ -0,0 +1,270 @@
+use super::client::ApiProtocol;
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::time::Duration;
+
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Longest silence tolerated on an open response (the read-idle timeout).
+/// Generous because local servers may load a model before the first byte
+/// arrives.
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 300;
+
+/// How the API key is attached to requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthScheme {
+    /// `x-api-key: <key>`, the Anthropic Messages API default.
+    XApiKey,
+    /// `authorization: Bearer <key>`, the OpenAI and gateway default.
+    Bearer,
+    /// `api-key: <key>`, used by Azure OpenAI deployments.
+    ApiKeyHeader,
+    /// No credentials are sent.
+    None,
+}
+
+impl AuthScheme {
+    pub fn default_for(protocol: ApiProtocol) -> Self {
+        match protocol {
+            ApiProtocol::AnthropicMessages => AuthScheme::XApiKey,
+            ApiProtocol::OpenAiChatCompletions
+            | ApiProtocol::OpenAiResponses
+            | ApiProtocol::OllamaChat => AuthScheme::Bearer,
+        }
+    }
+
+    pub fn apply(
+        self,
+        request: reqwest::RequestBuilder,
+        api_key: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        let Some(api_key) = api_key else {
+            return request;
+        };
+        match self {
+            AuthScheme::XApiKey => request.header("x-api-key", api_key),
+            AuthScheme::Bearer => request.header("authorization", format!("Bearer {api_key}")),
+            AuthScheme::ApiKeyHeader => request.header("api-key", api_key),
+            AuthScheme::None => request,
+        }
+    }
+}
+
+fn parse_auth_scheme(value: &str) -> Result<AuthScheme> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "x-api-key" | "anthropic" => Ok(AuthScheme::XApiKey),
+        "bearer" | "authorization" => Ok(AuthScheme::Bearer),
+        "api-key" | "azure" => Ok(AuthScheme::ApiKeyHeader),
+        "none" | "off" => Ok(AuthScheme::None),
+        other => {
+            bail!("unknown VEX_AUTH_SCHEME '{other}' (use x-api-key, bearer, api-key or none)")
+        }
+    }
+}
+
+/// The scheme set by `VEX_AUTH_SCHEME`, if any.
+pub fn configured_auth_scheme() -> Result<Option<AuthScheme>> {
+    env_string("VEX_AUTH_SCHEME")
+        .map(|value| parse_auth_scheme(&value))
+        .transpose()
+}
+
+/// `VEX_AUTH_SCHEME`, falling back to the protocol's native scheme.
+pub fn resolve_auth_scheme(protocol: ApiProtocol) -> Result<AuthScheme> {
+    Ok(configured_auth_scheme()?.unwrap_or_else(|| AuthScheme::default_for(protocol)))
+}
+
+/// Transport settings read from the environment when the client is built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpSettings {
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    request_timeout: Option<Duration>,
+    https_proxy: Option<String>,
+    ca_bundle: Option<String>,
+    extra_headers: Vec<(String, String)>,
+}
+
+impl HttpSettings {
+    fn from_env() -> Result<Self> {
+        Ok(Self {
+            connect_timeout: env_secs(
+                "VEX_HTTP_CONNECT_TIMEOUT_SECS",
+                DEFAULT_CONNECT_TIMEOUT_SECS,
+                1,
+                300,
+            ),
+            read_timeout: env_secs(
+                "VEX_HTTP_READ_TIMEOUT_SECS",
+                DEFAULT_READ_TIMEOUT_SECS,
+                5,
+                3_600,
+            ),
+            request_timeout: Some(env_secs("VEX_HTTP_REQUEST_TIMEOUT_SECS", 0, 0, 86_400))
+                .filter(|timeout| !timeout.is_zero()),
+            https_proxy: env_string("VEX_HTTPS_PROXY"),
+            ca_bundle: env_string("VEX_CA_BUNDLE"),
+            extra_headers: match env_string("VEX_HTTP_HEADERS") {
+                Some(spec) => parse_extra_headers(&spec)?,
+                None => Vec::new(),
+            },
+        })
+    }
+
+    fn build_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout);
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.https_proxy {
+            let proxy = reqwest::Proxy::https(proxy)
+                .with_context(|| format!("invalid VEX_HTTPS_PROXY '{proxy}'"))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_bundle {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("cannot read VEX_CA_BUNDLE '{path}'"))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem).with_context(|| {
+                format!("VEX_CA_BUNDLE '{path}' is not a PEM certificate bundle")
+            })?;
+            if certificates.is_empty() {
+                bail!("VEX_CA_BUNDLE '{path}' contains no certificates");
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if !self.extra_headers.is_empty() {
+            let mut headers = HeaderMap::new();
+            for (name, value) in &self.extra_headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| anyhow!("invalid header name '{name}' in VEX_HTTP_HEADERS"))?;
+                let mut value = HeaderValue::from_str(value).map_err(|_| {
+                    anyhow!("invalid value for header '{name}' in VEX_HTTP_HEADERS")
+                })?;
+                value.set_sensitive(true);
+                headers.insert(name, value);
+            }
+            builder = builder.default_headers(headers);
+        }
+        builder.build().context("failed to build HTTP client")
+    }
+}
+
+/// Build the HTTP client from `VEX_HTTP_*`, `VEX_HTTPS_PROXY` and `VEX_CA_BUNDLE`.
+pub fn build_http_client() -> Result<reqwest::Client> {
+    HttpSettings::from_env()?.build_client()
+}
+
+/// Parse `Name: value` pairs separated by `;` or newlines.
+fn parse_extra_headers(spec: &str) -> Result<Vec<(String, String)>> {
+    spec.split([';', '\n'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, value) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("VEX_HTTP_HEADERS entry '{entry}' is not 'Name: value'"))?;
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("VEX_HTTP_HEADERS entry '{entry}' has an empty header name");
+            }
+            Ok((name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn env_secs(key: &str, default: u64, min: u64, max: u64) -> Duration {
+    let secs = std::env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(|v| v.clamp(min, max))
+        .unwrap_or(default);
+    Duration::from_secs(secs)
+}
+
+fn env_string(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTTP_ENV_KEYS: [&str; 6] = [
+        "VEX_HTTP_CONNECT_TIMEOUT_SECS",
+        "VEX_HTTP_READ_TIMEOUT_SECS",
+        "VEX_HTTP_REQUEST_TIMEOUT_SECS",
+        "VEX_HTTPS_PROXY",
+        "VEX_CA_BUNDLE",
+        "VEX_HTTP_HEADERS",
+    ];
+
+    #[test]
+    fn test_parse_extra_headers_accepts_semicolons_and_newlines() {
+        let headers =
+            parse_extra_headers("X-Org: acme; x-trace-id:abc\n\nOcp-Apim-Subscription-Key: k:1")
+                .unwrap();
+        assert_eq!(
+            headers,
+            vec![
+                ("X-Org".to_string(), "acme".to_string()),
+                ("x-trace-id".to_string(), "abc".to_string()),
+                ("Ocp-Apim-Subscription-Key".to_string(), "k:1".to_string()),
+            ]
+        );
+        assert!(parse_extra_headers("no-colon").is_err());
+        assert!(parse_extra_headers(": value").is_err());
+    }
+
+    #[test]
+    fn test_parse_auth_scheme_spellings() {
+        assert_eq!(parse_auth_scheme("Bearer").unwrap(), AuthScheme::Bearer);
+        assert_eq!(
+            parse_auth_scheme("azure").unwrap(),
+            AuthScheme::ApiKeyHeader
+        );
+        assert_eq!(parse_auth_scheme("x-api-key").unwrap(), AuthScheme::XApiKey);
+        assert_eq!(parse_auth_scheme("none").unwrap(), AuthScheme::None);
+        assert!(parse_auth_scheme("basic").is_err());
+    }
+
+    #[test]
+    fn test_http_settings_from_env_defaults_and_overrides() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        for key in HTTP_ENV_KEYS {
+            std::env::remove_var(key);
+        }
+        let defaults = HttpSettings::from_env().unwrap();
+        assert_eq!(defaults.connect_timeout, Duration::from_secs(10));
+        assert_eq!(defaults.read_timeout, Duration::from_secs(300));
+        assert_eq!(defaults.request_timeout, None);
+        assert!(defaults.build_client().is_ok());
+
+        std::env::set_var("VEX_HTTP_CONNECT_TIMEOUT_SECS", "0");
+        std::env::set_var("VEX_HTTP_REQUEST_TIMEOUT_SECS", "600");
+        std::env::set_var("VEX_HTTPS_PROXY", "http://proxy.corp:3128");
+        std::env::set_var("VEX_HTTP_HEADERS", "X-Org: acme");
+        let settings = HttpSettings::from_env();
+        std::env::set_var("VEX_CA_BUNDLE", "/nonexistent/ca.pem");
+        let missing_ca = HttpSettings::from_env().unwrap().build_client();
+        for key in HTTP_ENV_KEYS {
+            std::env::remove_var(key);
+        }
+
+        let settings = settings.unwrap();
+        assert_eq!(settings.connect_timeout, Duration::from_secs(1));
+        assert_eq!(settings.request_timeout, Some(Duration::from_secs(600)));
+        assert_eq!(
+            settings.https_proxy.as_deref(),
+            Some("http://proxy.corp:3128")
+        );
+        assert!(settings.build_client().is_ok());
+        let error = missing_ca.unwrap_err().to_string();
+        assert!(error.contains("cannot read VEX_CA_BUNDLE"), "{error}");
+    }
+}
@@ -1,20 +1,62 @@
+mod ollama;
+mod responses;
+mod think_tags;
+
+use super::client::ApiProtocol;
+use super::error::ApiError;
 use super::logging::emit_sse_parse_error;
-use crate::types::{ContentBlock, Delta, StreamEvent};
+use crate::types::{ContentBlock, Delta, MessageDelta, StreamEvent, Usage};
 use anyhow::Result;
+use responses::ResponsesToolState;
 use serde::Deserialize;
+use std::collections::HashMap;
+use think_tags::{ThinkSegment, ThinkTagSplitter};
 
 #[derive(Default)]
 pub struct StreamParser {
     buffer: String,
+    format: StreamFormat,
+    openai_blocks: OpenAiBlockIndices,
     openai_tools: Vec<OpenAiToolState>,
+    openai_think_tags: ThinkTagSplitter,
+    ollama_saw_tool_call: bool,
+    /// Responses API tool calls keyed by `output_index`.
+    responses_tools: HashMap<usize, ResponsesToolState>,
+}
+
+/// Wire framing: server-sent events, or newline-delimited JSON for native
+/// Ollama.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum StreamFormat {
+    #[default]
+    Sse,
+    Ndjson,
+}
+
+/// Unified block indices for OpenAI chunks, which carry no block structure.
+/// Indices are handed out on first appearance, so a plain text-then-tools
+/// response keeps text at 0 and tools from 1.
+#[derive(Default)]
+struct OpenAiBlockIndices {
+    next: usize,
+    text: Option<usize>,
+    open_reasoning: Option<usize>,
+}
+
+impl OpenAiBlockIndices {
+    fn allocate(&mut self) -> usize {
+        let index = self.next;
+        self.next += 1;
+        index
+    }
 }
 
 #[derive(Default, Clone)]
 struct OpenAiToolState {
+    block_index: Option<usize>,
     id: String,
     name: String,
     pending_arguments: String,
-    started: bool,
     stopped: bool,
 }
 
@@ -22,6 +64,43 @@ struct OpenAiToolState {
 struct OpenAiChunk {
     #[serde(default)]
     choices: Vec<OpenAiChoice>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    #[serde(default)]
+    prompt_tokens_details: Option<OpenAiPromptTokensDetails>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAiPromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: Option<u64>,
+}
+
+impl OpenAiUsage {
+    /// OpenAI counts cached prompt tokens inside `prompt_tokens`; split them out
+    /// to match the Anthropic convention used by `Usage`.
+    fn to_usage(&self) -> Usage {
+        let cached = self
+            .prompt_tokens_details
+            .as_ref()
+            .and_then(|details| details.cached_tokens)
+            .unwrap_or(0)
+            .min(self.prompt_tokens);
+        Usage {
+            input_tokens: self.prompt_tokens - cached,
+            output_tokens: self.completion_tokens,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: cached,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,6 +116,11 @@ struct OpenAiDelta {
     #[serde(default)]
     content: Option<String>,
     #[serde(default)]
+    reasoning_content: Option<String>,
+    /// Newer vLLM name for `reasoning_content`; some servers send both.
+    #[serde(default)]
+    reasoning: Option<String>,
+    #[serde(default)]
     tool_calls: Option<Vec<OpenAiToolCallDelta>>,
 }
 
@@ -58,17 +142,64 @@ struct OpenAiFunctionDelta {
     arguments: Option<String>,
 }
 
+/// Error object carried in an SSE event of any supported protocol.
+fn parse_stream_error(json_data: &str) -> Option<ApiError> {
+    if !json_data.contains("\"error\"") {
+        return None;
+    }
+    let value = serde_json::from_str::<serde_json::Value>(json_data).ok()?;
+    ApiError::from_stream_event(&value)
+}
+
 impl StreamParser {
+    /// A parser for server-sent events.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// A parser for the framing `protocol` streams in.
+    pub fn for_protocol(protocol: ApiProtocol) -> Self {
+        let format = match protocol {
+            ApiProtocol::OllamaChat => StreamFormat::Ndjson,
+            ApiProtocol::AnthropicMessages
+            | ApiProtocol::OpenAiChatCompletions
+            | ApiProtocol::OpenAiResponses => StreamFormat::Sse,
+        };
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+
+    /// Treat content up to the first `</think>` as reasoning, for chat
+    /// templates that open the think block in the prompt.
+    pub fn with_think_opened_by_template(mut self) -> Self {
+        self.openai_think_tags = ThinkTagSplitter::opened_by_template();
+        self
+    }
+
+    /// Parse an event or line the body ended without terminating.
+    pub fn finish(&mut self) -> Result<Vec<StreamEvent>> {
+        if self.buffer.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let terminator = match self.format {
+            StreamFormat::Sse => "\n\n",
+            StreamFormat::Ndjson => "\n",
+        };
+        self.process(terminator.as_bytes())
+    }
+
     pub fn process(&mut self, chunk: &[u8]) -> Result<Vec<StreamEvent>> {
         self.buffer.push_str(&String::from_utf8_lossy(chunk));
         if self.buffer.contains('\r') {
             self.buffer = self.buffer.replace("\r\n", "\n");
         }
 
+        if self.format == StreamFormat::Ndjson {
+            return self.process_ndjson();
+        }
+
         let mut events = Vec::new();
         let mut start = 0;
 
@@ -99,7 +230,18 @@ impl StreamParser {
                 };
 
                 if should_parse {
+                    if let Some(error) = parse_stream_error(&json_data) {
+                        return Err(error.into());
+                    }
                     match serde_json::from_str::<StreamEvent>(&json_data) {
+                        // Responses API events carry dotted `type` names that
+                        // land in the catch-all variant.
+                        Ok(StreamEvent::Unknown) => {
+                            match self.parse_responses_event(&json_data)? {
+                                Some(responses_events) => events.extend(responses_events),
+                                None => events.push(StreamEvent::Unknown),
+                            }
+                        }
                         Ok(evt) => events.push(evt),
                         Err(anthropic_error) => {
                             if let Some(openai_events) = self.parse_openai_chunk(&json_data) {
@@ -126,29 +268,54 @@ impl StreamParser {
         Ok(events)
     }
 
+    fn process_ndjson(&mut self) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        let mut start = 0;
+
+        while let Some(end) = self.buffer[start..].find('\n') {
+            let line = self.buffer[start..start + end].trim().to_string();
+            start += end + 1;
+            if !line.is_empty() {
+                events.extend(self.parse_ollama_line(&line)?);
+            }
+        }
+
+        if start > 0 {
+            self.buffer.drain(..start);
+        }
+
+        Ok(events)
+    }
+
     fn parse_openai_chunk(&mut self, json_data: &str) -> Option<Vec<StreamEvent>> {
         if json_data == "[DONE]" {
             let mut events = Vec::new();
+            self.finish_openai_content(&mut events);
             self.close_openai_tool_blocks(&mut events);
             return Some(events);
         }
 
         let chunk = serde_json::from_str::<OpenAiChunk>(json_data).ok()?;
+        let mut events = Vec::new();
+        if let Some(usage) = &chunk.usage {
+            events.push(StreamEvent::MessageDelta {
+                delta: MessageDelta { stop_reason: None },
+                usage: Some(usage.to_usage()),
+            });
+        }
         if chunk.choices.is_empty() {
-            return Some(Vec::new());
+            return Some(events);
         }
 
-        let mut events = Vec::new();
         for choice in chunk.choices {
+            if let Some(reasoning) = choice.delta.reasoning_content.or(choice.delta.reasoning) {
+                self.push_openai_reasoning(reasoning, &mut events);
+            }
+
             if let Some(content) = choice.delta.content {
-                events.push(StreamEvent::ContentBlockDelta {
-                    index: 0,
-                    delta: Delta {
-                        delta_type: Some("text_delta".to_string()),
-                        text: Some(content),
-                        partial_json: None,
-                    },
-                });
+                for segment in self.openai_think_tags.push(&content) {
+                    self.push_openai_segment(segment, &mut events);
+                }
             }
 
             if let Some(tool_calls) = choice.delta.tool_calls {
@@ -157,22 +324,104 @@ impl StreamParser {
                 }
             }
 
-            if choice.finish_reason.is_some() {
+            if let Some(finish_reason) = choice.finish_reason {
+                self.finish_openai_content(&mut events);
                 self.close_openai_tool_blocks(&mut events);
+                events.push(StreamEvent::MessageDelta {
+                    delta: MessageDelta {
+                        stop_reason: Some(openai_stop_reason(&finish_reason).to_string()),
+                    },
+                    usage: None,
+                });
             }
         }
 
         Some(events)
     }
 
+    fn push_openai_segment(&mut self, segment: ThinkSegment, events: &mut Vec<StreamEvent>) {
+        match segment {
+            ThinkSegment::Reasoning(reasoning) => self.push_openai_reasoning(reasoning, events),
+            ThinkSegment::Text(text) => self.push_openai_text(text, events),
+        }
+    }
+
+    fn push_openai_text(&mut self, text: String, events: &mut Vec<StreamEvent>) {
+        self.close_openai_reasoning_block(events);
+        let index = match self.openai_blocks.text {
+            Some(index) => index,
+            None => {
+                let index = self.openai_blocks.allocate();
+                self.openai_blocks.text = Some(index);
+                index
+            }
+        };
+        events.push(StreamEvent::ContentBlockDelta {
+            index,
+            delta: Delta {
+                delta_type: Some("text_delta".to_string()),
+                text: Some(text),
+                partial_json: None,
+                thinking: None,
+                signature: None,
+            },
+        });
+    }
+
+    /// Map `reasoning_content` or think-tag text onto Anthropic-style thinking
+    /// block events so `ConversationManager` handles both protocols alike.
+    fn push_openai_reasoning(&mut self, reasoning: String, events: &mut Vec<StreamEvent>) {
+        if reasoning.is_empty() {
+            return;
+        }
+        let index = match self.openai_blocks.open_reasoning {
+            Some(index) => index,
+            None => {
+                let index = self.openai_blocks.allocate();
+                self.openai_blocks.open_reasoning = Some(index);
+                events.push(StreamEvent::ContentBlockStart {
+                    index,
+                    content_block: ContentBlock::Thinking {
+                        thinking: String::new(),
+                        signature: String::new(),
+                    },
+                });
+                index
+            }
+        };
+        events.push(StreamEvent::ContentBlockDelta {
+            index,
+            delta: Delta {
+                delta_type: Some("thinking_delta".to_string()),
+                text: None,
+                partial_json: None,
+                thinking: Some(reasoning),
+                signature: None,
+            },
+        });
+    }
+
+    fn close_openai_reasoning_block(&mut self, events: &mut Vec<StreamEvent>) {
+        if let Some(index) = self.openai_blocks.open_reasoning.take() {
+            events.push(StreamEvent::ContentBlockStop { index });
+        }
+    }
+
+    fn finish_openai_content(&mut self, events: &mut Vec<StreamEvent>) {
+        for segment in self.openai_think_tags.finish() {
+            self.push_openai_segment(segment, events);
+        }
+        self.close_openai_reasoning_block(events);
+    }
+
     fn apply_openai_tool_delta(
         &mut self,
         tool_call: OpenAiToolCallDelta,
         events: &mut Vec<StreamEvent>,
     ) {
-        let block_index = tool_call.index.unwrap_or(0) + 1;
-        self.ensure_openai_tool_state(block_index);
-        let state = &mut self.openai_tools[block_index];
+        let tool_index = tool_call.index.unwrap_or(0);
+        self.ensure_openai_tool_state(tool_index);
+        let state = &mut self.openai_tools[tool_index];
 
         if let Some(id) = tool_call.id {
             if !id.is_empty() {
@@ -190,7 +439,12 @@ impl StreamParser {
             }
         }
 
-        if !state.started && !state.name.is_empty() {
+        if state.block_index.is_none() && !state.name.is_empty() {
+            if let Some(index) = self.openai_blocks.open_reasoning.take() {
+                events.push(StreamEvent::ContentBlockStop { index });
+            }
+            let block_index = self.openai_blocks.allocate();
+            state.block_index = Some(block_index);
             let id = if state.id.is_empty() {
                 format!("toolu_openai_{block_index}")
             } else {
@@ -205,10 +459,12 @@ impl StreamParser {
                     input: serde_json::Value::Object(serde_json::Map::new()),
                 },
             });
-            state.started = true;
         }
 
-        if state.started && !state.pending_arguments.is_empty() {
+        let Some(block_index) = state.block_index else {
+            return;
+        };
+        if !state.pending_arguments.is_empty() {
             let partial_json = std::mem::take(&mut state.pending_arguments);
             events.push(StreamEvent::ContentBlockDelta {
                 index: block_index,
@@ -216,6 +472,8 @@ impl StreamParser {
                     delta_type: Some("input_json_delta".to_string()),
                     text: None,
                     partial_json: Some(partial_json),
+                    thinking: None,
+                    signature: None,
                 },
             });
         }
@@ -229,14 +487,21 @@ impl StreamParser {
     }
 
     fn close_openai_tool_blocks(&mut self, events: &mut Vec<StreamEvent>) {
-        for (index, state) in self.openai_tools.iter_mut().enumerate() {
-            if index == 0 {
-                continue;
-            }
-            if state.started && !state.stopped {
+        for state in &mut self.openai_tools {
+            if let (Some(index), false) = (state.block_index, state.stopped) {
                 events.push(StreamEvent::ContentBlockStop { index });
                 state.stopped = true;
             }
         }
     }
 }
+
+/// Map a Chat Completions `finish_reason` onto the Anthropic stop reasons the
+/// conversation loop acts on.
+fn openai_stop_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        _ => "end_turn",
+    }
+}
@@ -0,0 +1,143 @@
+use super::StreamParser;
+use crate::api::error::ApiError;
+use crate::api::logging::emit_sse_parse_error;
+use crate::types::{ContentBlock, Delta, MessageDelta, StreamEvent, Usage};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One line of Ollama's native `/api/chat` NDJSON stream.
+#[derive(Debug, Deserialize)]
+struct OllamaChunk {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    thinking: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<OllamaToolCall>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaToolCall {
+    #[serde(default)]
+    id: Option<String>,
+    function: OllamaFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+impl StreamParser {
+    /// Map one NDJSON line onto unified events. Ollama has no block structure
+    /// either, so this shares the OpenAI index bookkeeping and think-tag
+    /// splitting; tool calls arrive whole and open and close in one step.
+    pub(super) fn parse_ollama_line(&mut self, line: &str) -> Result<Vec<StreamEvent>> {
+        let chunk = match serde_json::from_str::<OllamaChunk>(line) {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                emit_sse_parse_error(Some("ndjson"), line, &error);
+                return Ok(Vec::new());
+            }
+        };
+        if let Some(error) = chunk.error {
+            return Err(ApiError::from_message(error).into());
+        }
+
+        let mut events = Vec::new();
+        let message = chunk.message.unwrap_or_default();
+        if let Some(thinking) = message.thinking {
+            self.push_openai_reasoning(thinking, &mut events);
+        }
+        if let Some(content) = message.content {
+            for segment in self.openai_think_tags.push(&content) {
+                self.push_openai_segment(segment, &mut events);
+            }
+        }
+        for tool_call in message.tool_calls.unwrap_or_default() {
+            self.push_ollama_tool_call(tool_call, &mut events);
+        }
+
+        if chunk.done {
+            self.finish_openai_content(&mut events);
+            let stop_reason = if self.ollama_saw_tool_call {
+                "tool_use"
+            } else if chunk.done_reason.as_deref() == Some("length") {
+                "max_tokens"
+            } else {
+                "end_turn"
+            };
+            events.push(StreamEvent::MessageDelta {
+                delta: MessageDelta {
+                    stop_reason: Some(stop_reason.to_string()),
+                },
+                usage: Some(Usage {
+                    input_tokens: chunk.prompt_eval_count.unwrap_or(0),
+                    output_tokens: chunk.eval_count.unwrap_or(0),
+                    ..Usage::default()
+                }),
+            });
+            events.push(StreamEvent::MessageStop);
+        }
+
+        Ok(events)
+    }
+
+    fn push_ollama_tool_call(&mut self, tool_call: OllamaToolCall, events: &mut Vec<StreamEvent>) {
+        if tool_call.function.name.is_empty() {
+            return;
+        }
+        self.close_openai_reasoning_block(events);
+        self.ollama_saw_tool_call = true;
+
+        let index = self.openai_blocks.allocate();
+        let id = tool_call
+            .id
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| format!("toolu_ollama_{index}"));
+        let arguments = match tool_call.function.arguments {
+            Value::String(text) => text,
+            Value::Null => "{}".to_string(),
+            other => other.to_string(),
+        };
+
+        events.push(StreamEvent::ContentBlockStart {
+            index,
+            content_block: ContentBlock::ToolUse {
+                id,
+                name: tool_call.function.name,
+                input: Value::Object(serde_json::Map::new()),
+            },
+        });
+        events.push(StreamEvent::ContentBlockDelta {
+            index,
+            delta: Delta {
+                delta_type: Some("input_json_delta".to_string()),
+                text: None,
+                partial_json: Some(arguments),
+                thinking: None,
+                signature: None,
+            },
+        });
+        events.push(StreamEvent::ContentBlockStop { index });
+    }
+}
@@ -0,0 +1,282 @@
+use super::StreamParser;
+use crate::types::{ContentBlock, Delta, MessageDelta, StreamEvent, Usage};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+/// Bookkeeping for one `function_call` output item.
+#[derive(Debug, Default)]
+pub(super) struct ResponsesToolState {
+    block_index: usize,
+    streamed_arguments: bool,
+    stopped: bool,
+}
+
+/// A typed OpenAI Responses API stream event. Only the fields vex maps are
+/// declared; everything else is ignored.
+#[derive(Debug, Deserialize)]
+struct ResponsesEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    output_index: Option<usize>,
+    #[serde(default)]
+    delta: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+    #[serde(default)]
+    item: Option<ResponsesItem>,
+    #[serde(default)]
+    response: Option<ResponsesResponse>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesItem {
+    #[serde(rename = "type")]
+    item_type: String,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    call_id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ResponsesResponse {
+    #[serde(default)]
+    usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    incomplete_details: Option<ResponsesIncompleteDetails>,
+    #[serde(default)]
+    error: Option<ResponsesError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    input_tokens_details: Option<ResponsesInputTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesInputTokensDetails {
+    #[serde(default)]
+    cached_tokens: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesIncompleteDetails {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesError {
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl ResponsesUsage {
+    /// Like Chat Completions, cached tokens are counted inside `input_tokens`.
+    fn to_usage(&self) -> Usage {
+        let cached = self
+            .input_tokens_details
+            .as_ref()
+            .and_then(|details| details.cached_tokens)
+            .unwrap_or(0)
+            .min(self.input_tokens);
+        Usage {
+            input_tokens: self.input_tokens - cached,
+            output_tokens: self.output_tokens,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: cached,
+        }
+    }
+}
+
+impl StreamParser {
+    /// Map a Responses API event onto unified events. Returns `None` for data
+    /// that is not a `response.*` event so the caller can keep it as unknown.
+    pub(super) fn parse_responses_event(
+        &mut self,
+        json_data: &str,
+    ) -> Result<Option<Vec<StreamEvent>>> {
+        let Ok(event) = serde_json::from_str::<ResponsesEvent>(json_data) else {
+            return Ok(None);
+        };
+        if event.event_type == "error" {
+            if let Some(message) = event.message {
+                bail!("Responses API stream error: {message}");
+            }
+            return Ok(None);
+        }
+        let Some(kind) = event.event_type.strip_prefix("response.") else {
+            return Ok(None);
+        };
+
+        let mut events = Vec::new();
+        match kind {
+            "output_text.delta" => {
+                if let Some(delta) = event.delta {
+                    for segment in self.openai_think_tags.push(&delta) {
+                        self.push_openai_segment(segment, &mut events);
+                    }
+                }
+            }
+            "reasoning_summary_text.delta" | "reasoning_text.delta" => {
+                if let Some(delta) = event.delta {
+                    self.push_openai_reasoning(delta, &mut events);
+                }
+            }
+            "output_item.added" => {
+                if let (Some(output_index), Some(item)) = (event.output_index, event.item) {
+                    if item.item_type == "function_call" {
+                        self.open_responses_tool(output_index, item, &mut events);
+                    }
+                }
+            }
+            "function_call_arguments.delta" => {
+                if let (Some(output_index), Some(delta)) = (event.output_index, event.delta) {
+                    if let Some(state) = self.responses_tools.get_mut(&output_index) {
+                        state.streamed_arguments = true;
+                        events.push(input_json_delta(state.block_index, delta));
+                    }
+                }
+            }
+            "function_call_arguments.done" => {
+                if let Some(output_index) = event.output_index {
+                    self.finish_responses_tool(output_index, event.arguments, &mut events);
+                }
+            }
+            "output_item.done" => {
+                if let (Some(output_index), Some(item)) = (event.output_index, event.item) {
+                    match item.item_type.as_str() {
+                        "function_call" => {
+                            self.finish_responses_tool(output_index, item.arguments, &mut events)
+                        }
+                        "reasoning" => self.close_openai_reasoning_block(&mut events),
+                        _ => {}
+                    }
+                }
+            }
+            "completed" | "incomplete" => {
+                let response = event.response.unwrap_or_default();
+                self.finish_openai_content(&mut events);
+                let open_tools = self.responses_tools.keys().copied().collect::<Vec<_>>();
+                for output_index in open_tools {
+                    self.finish_responses_tool(output_index, None, &mut events);
+                }
+
+                let hit_token_limit = response
+                    .incomplete_details
+                    .and_then(|details| details.reason)
+                    .is_some_and(|reason| reason == "max_output_tokens");
+                let stop_reason = if !self.responses_tools.is_empty() {
+                    "tool_use"
+                } else if hit_token_limit {
+                    "max_tokens"
+                } else {
+                    "end_turn"
+                };
+                events.push(StreamEvent::MessageDelta {
+                    delta: MessageDelta {
+                        stop_reason: Some(stop_reason.to_string()),
+                    },
+                    usage: response.usage.as_ref().map(ResponsesUsage::to_usage),
+                });
+                events.push(StreamEvent::MessageStop);
+            }
+            "failed" => {
+                let message = event
+                    .response
+                    .and_then(|response| response.error)
+                    .and_then(|error| error.message)
+                    .unwrap_or_else(|| "response failed".to_string());
+                bail!("Responses API stream error: {message}");
+            }
+            _ => {}
+        }
+
+        Ok(Some(events))
+    }
+
+    fn open_responses_tool(
+        &mut self,
+        output_index: usize,
+        item: ResponsesItem,
+        events: &mut Vec<StreamEvent>,
+    ) {
+        if self.responses_tools.contains_key(&output_index) {
+            return;
+        }
+        self.close_openai_reasoning_block(events);
+        let block_index = self.openai_blocks.allocate();
+        let id = item
+            .call_id
+            .or(item.id)
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| format!("toolu_responses_{block_index}"));
+
+        events.push(StreamEvent::ContentBlockStart {
+            index: block_index,
+            content_block: ContentBlock::ToolUse {
+                id,
+                name: item.name.unwrap_or_default(),
+                input: serde_json::Value::Object(serde_json::Map::new()),
+            },
+        });
+        self.responses_tools.insert(
+            output_index,
+            ResponsesToolState {
+                block_index,
+                ..ResponsesToolState::default()
+            },
+        );
+    }
+
+    /// Close a tool block once. Gateways that skip argument deltas still send
+    /// the full arguments on the `done` events, so emit those if nothing streamed.
+    fn finish_responses_tool(
+        &mut self,
+        output_index: usize,
+        arguments: Option<String>,
+        events: &mut Vec<StreamEvent>,
+    ) {
+        let Some(state) = self.responses_tools.get_mut(&output_index) else {
+            return;
+        };
+        if state.stopped {
+            return;
+        }
+        if !state.streamed_arguments {
+            if let Some(arguments) = arguments.filter(|arguments| !arguments.is_empty()) {
+                state.streamed_arguments = true;
+                events.push(input_json_delta(state.block_index, arguments));
+            }
+        }
+        state.stopped = true;
+        events.push(StreamEvent::ContentBlockStop {
+            index: state.block_index,
+        });
+    }
+}
+
+fn input_json_delta(index: usize, partial_json: String) -> StreamEvent {
+    StreamEvent::ContentBlockDelta {
+        index,
+        delta: Delta {
+            delta_type: Some("input_json_delta".to_string()),
+            text: None,
+            partial_json: Some(partial_json),
+            thinking: None,
+            signature: None,
+        },
+    }
+}
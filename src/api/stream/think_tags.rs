@@ -0,0 +1,190 @@
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+
+/// One piece of OpenAI-compatible `content` after think-tag splitting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ThinkSegment {
+    Text(String),
+    Reasoning(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ThinkTagState {
+    /// Waiting to see whether the response opens with `<think>`.
+    #[default]
+    Undecided,
+    Inside,
+    /// After `</think>`; leading blank lines of the answer are dropped.
+    AfterReasoning,
+    Passthrough,
+}
+
+/// Splits inline `<think>...</think>` reasoning out of streamed content.
+///
+/// Only a think block at the very start of the response is treated as
+/// reasoning, so answers that merely mention the tag pass through untouched.
+/// Partial tags at chunk boundaries are held back until they resolve.
+#[derive(Debug, Default)]
+pub(super) struct ThinkTagSplitter {
+    state: ThinkTagState,
+    pending: String,
+}
+
+impl ThinkTagSplitter {
+    /// A splitter for responses that begin inside reasoning because the chat
+    /// template already emitted `<think>`; the first `</think>` ends it.
+    pub(super) fn opened_by_template() -> Self {
+        Self {
+            state: ThinkTagState::Inside,
+            pending: String::new(),
+        }
+    }
+
+    pub(super) fn push(&mut self, content: &str) -> Vec<ThinkSegment> {
+        self.pending.push_str(content);
+        let mut segments = Vec::new();
+
+        loop {
+            match self.state {
+                ThinkTagState::Undecided => {
+                    let trimmed = self.pending.trim_start();
+                    if let Some(rest) = trimmed.strip_prefix(OPEN_TAG) {
+                        self.pending = rest.to_string();
+                        self.state = ThinkTagState::Inside;
+                    } else if OPEN_TAG.starts_with(trimmed) {
+                        return segments;
+                    } else {
+                        self.state = ThinkTagState::Passthrough;
+                    }
+                }
+                ThinkTagState::Inside => {
+                    if let Some(end) = self.pending.find(CLOSE_TAG) {
+                        let reasoning = self.pending[..end].to_string();
+                        self.pending.drain(..end + CLOSE_TAG.len());
+                        push_segment(&mut segments, ThinkSegment::Reasoning(reasoning));
+                        self.state = ThinkTagState::AfterReasoning;
+                    } else {
+                        let keep = partial_tag_suffix_len(&self.pending, CLOSE_TAG);
+                        let split = self.pending.len() - keep;
+                        let reasoning = self.pending[..split].to_string();
+                        self.pending.drain(..split);
+                        push_segment(&mut segments, ThinkSegment::Reasoning(reasoning));
+                        return segments;
+                    }
+                }
+                ThinkTagState::AfterReasoning => {
+                    let trimmed = self.pending.trim_start_matches(['\r', '\n']);
+                    if trimmed.is_empty() {
+                        self.pending.clear();
+                        return segments;
+                    }
+                    self.pending = trimmed.to_string();
+                    self.state = ThinkTagState::Passthrough;
+                }
+                ThinkTagState::Passthrough => {
+                    let text = std::mem::take(&mut self.pending);
+                    push_segment(&mut segments, ThinkSegment::Text(text));
+                    return segments;
+                }
+            }
+        }
+    }
+
+    /// Release anything held back when the stream ends.
+    pub(super) fn finish(&mut self) -> Vec<ThinkSegment> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut segments = Vec::new();
+        match self.state {
+            ThinkTagState::Inside => push_segment(&mut segments, ThinkSegment::Reasoning(pending)),
+            ThinkTagState::AfterReasoning => {}
+            ThinkTagState::Undecided | ThinkTagState::Passthrough => {
+                push_segment(&mut segments, ThinkSegment::Text(pending))
+            }
+        }
+        segments
+    }
+}
+
+fn push_segment(segments: &mut Vec<ThinkSegment>, segment: ThinkSegment) {
+    let is_empty = match &segment {
+        ThinkSegment::Text(text) | ThinkSegment::Reasoning(text) => text.is_empty(),
+    };
+    if !is_empty {
+        segments.push(segment);
+    }
+}
+
+fn partial_tag_suffix_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|len| {
+            text.is_char_boundary(text.len().saturating_sub(*len)) && text.ends_with(&tag[..*len])
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_all(chunks: &[&str]) -> Vec<ThinkSegment> {
+        let mut splitter = ThinkTagSplitter::default();
+        let mut segments = Vec::new();
+        for chunk in chunks {
+            segments.extend(splitter.push(chunk));
+        }
+        segments.extend(splitter.finish());
+        segments
+    }
+
+    #[test]
+    fn test_think_tags_split_across_chunk_boundaries() {
+        let segments = split_all(&["<thi", "nk>plan the ", "edit</th", "ink>\n\nDone."]);
+        assert_eq!(
+            segments,
+            vec![
+                ThinkSegment::Reasoning("plan the ".to_string()),
+                ThinkSegment::Reasoning("edit".to_string()),
+                ThinkSegment::Text("Done.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_think_tags_only_recognized_at_response_start() {
+        let segments = split_all(&["Use the ", "<think> tag."]);
+        assert_eq!(
+            segments,
+            vec![
+                ThinkSegment::Text("Use the ".to_string()),
+                ThinkSegment::Text("<think> tag.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_opened_reasoning_ends_at_first_close_tag() {
+        let mut splitter = ThinkTagSplitter::opened_by_template();
+        let mut segments = splitter.push("plan</think>\n\nUse </think> to close.");
+        segments.extend(splitter.finish());
+        assert_eq!(
+            segments,
+            vec![
+                ThinkSegment::Reasoning("plan".to_string()),
+                ThinkSegment::Text("Use </think> to close.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_think_block_flushes_as_reasoning() {
+        let segments = split_all(&["<think>still going</"]);
+        assert_eq!(
+            segments,
+            vec![
+                ThinkSegment::Reasoning("still going".to_string()),
+                ThinkSegment::Reasoning("</".to_string()),
+            ]
+        );
+    }
+}
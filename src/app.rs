@@ -1,28 +1,42 @@
-use crate::api::ApiClient;
+mod commands;
+
+use crate::api::{ApiClient, ApiError, Cassette, ModelInfo};
+use crate::audit::AuditLog;
 use crate::config::Config;
+use crate::export::parse_export_args;
 use crate::runtime::context::RuntimeContext;
 use crate::runtime::frontend::{ScrollAction, ScrollTarget, UserInputEvent};
 use crate::runtime::mode::RuntimeMode;
-use crate::runtime::policy::sanitize_assistant_text;
+use crate::runtime::policy::RuntimeCorePolicy;
 use crate::runtime::r#loop::Runtime;
+use crate::runtime::session::Session;
 use crate::runtime::UiUpdate;
-use crate::state::{ConversationManager, StreamBlock, ToolApprovalRequest};
-use crate::tools::ToolOperator;
+use crate::state::{
+    is_numbered_plan, plan_approval_prompt, ApprovalDecision, CompactionNotice, ConversationBranch,
+    ConversationManager, DelegateUpdate, ProjectInstructions, StallNotice, StreamBlock,
+    ToolApprovalRequest, TruncationNotice, UsageReport,
+};
+use crate::tools::{HookEvent, HookRun, ToolHooks, ToolOperator};
+use crate::types::Usage;
 use crate::ui::render::history_visual_line_count;
 #[cfg(test)]
 use crate::ui::render::input_visual_rows;
 use anyhow::Result;
+use commands::{parse_slash_command, SlashCommand};
 #[cfg(test)]
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use std::borrow::Cow;
+use std::sync::Arc;
 #[cfg(test)]
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use std::time::Instant;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
 struct PendingApproval {
     tool_name: String,
     input_preview: String,
-    response_tx: tokio::sync::oneshot::Sender<bool>,
+    response_tx: tokio::sync::oneshot::Sender<ApprovalDecision>,
 }
 
 struct PendingPatchApproval {
@@ -33,11 +47,23 @@ struct PendingPatchApproval {
 
 const DEFAULT_MAX_HISTORY_LINES: usize = 2000;
 const MAX_HISTORY_LINES_ENV: &str = "VEX_MAX_HISTORY_LINES";
+/// Turns offered by `/rewind`, selected with a single digit.
+const MAX_REWIND_CHOICES: usize = 9;
+/// Hook output lines shown under a `[hook]` line.
+const HOOK_OUTPUT_PREVIEW_LINES: usize = 6;
 #[cfg(test)]
 const MAX_INPUT_PANE_ROWS: usize = 6;
 
+/// Transcript line where a user prompt was echoed, used to rewind the transcript.
+#[derive(Clone)]
+struct UserTurnLine {
+    line_index: usize,
+    prompt: String,
+}
+
 struct HistoryState {
     lines: Vec<String>,
+    user_turns: Vec<UserTurnLine>,
     turn_in_progress: bool,
     cancel_pending: bool,
     active_assistant_index: Option<usize>,
@@ -49,6 +75,7 @@ impl Default for HistoryState {
     fn default() -> Self {
         Self {
             lines: Vec::new(),
+            user_turns: Vec::new(),
             turn_in_progress: false,
             cancel_pending: false,
             active_assistant_index: None,
@@ -58,13 +85,45 @@ impl Default for HistoryState {
     }
 }
 
+/// Reasoning shown in history; the line is re-rendered when expansion toggles.
+#[derive(Clone)]
+struct ReasoningEntry {
+    line_index: usize,
+    content: String,
+    redacted: bool,
+}
+
+/// A `delegate` sub-agent's block; the line is re-rendered as steps arrive
+/// and when expansion toggles.
+#[derive(Clone)]
+struct DelegateEntry {
+    line_index: usize,
+    task: String,
+    steps: Vec<String>,
+    /// Tool calls made and whether a summary came back, once finished.
+    outcome: Option<(usize, bool)>,
+}
+
 #[derive(Default)]
 struct OverlayState {
     pending_approval: Option<PendingApproval>,
     pending_patch_approval: Option<PendingPatchApproval>,
+    /// Prompts offered by `/rewind`, newest first.
+    pending_rewind: Option<Vec<String>>,
+    /// A plan from plan mode waiting for approval.
+    pending_plan: Option<String>,
     auto_approve_session: bool,
 }
 
+/// A conversation line parked by `/fork`, with the transcript that goes with it.
+struct SavedBranch {
+    conversation: ConversationBranch,
+    lines: Vec<String>,
+    user_turns: Vec<UserTurnLine>,
+    reasoning_entries: Vec<ReasoningEntry>,
+    delegate_entries: Vec<DelegateEntry>,
+}
+
 #[cfg(test)]
 #[derive(Default)]
 struct InputState {
@@ -83,6 +142,27 @@ pub struct TuiMode {
     history_line_cap: usize,
     repo_label: String,
     active_stream_blocks: std::collections::HashMap<usize, StreamBlock>,
+    usage_report: Option<UsageReport>,
+    reasoning_entries: Vec<ReasoningEntry>,
+    reasoning_expanded: bool,
+    delegate_entries: Vec<DelegateEntry>,
+    delegates_expanded: bool,
+    branches: Vec<SavedBranch>,
+    /// Mutating tools are blocked until the user approves a plan.
+    plan_mode: bool,
+    /// The running turn was started in plan mode; its reply may be a plan.
+    awaiting_plan: bool,
+    /// Full reply of the running turn, from [`UiUpdate::TurnReply`].
+    turn_reply: Option<String>,
+    /// The next submitted input is the user's edit of the plan.
+    editing_plan: bool,
+    /// Text for the frontend to load into the input box, numbered so each
+    /// request is applied once.
+    input_prefill: Option<(u64, String)>,
+    /// First line of the first prompt, shown on the session's tab.
+    title: Option<String>,
+    /// When the outstanding model request was sent, until its first bytes arrive.
+    awaiting_first_token_since: Option<Instant>,
     pending_quit: bool,
     quit_requested: bool,
 }
@@ -95,22 +175,54 @@ impl TuiMode {
             history_line_cap: resolve_history_line_cap(),
             repo_label: resolve_repo_label(),
             active_stream_blocks: std::collections::HashMap::new(),
+            usage_report: None,
+            reasoning_entries: Vec::new(),
+            reasoning_expanded: false,
+            delegate_entries: Vec::new(),
+            delegates_expanded: false,
+            branches: Vec::new(),
+            plan_mode: false,
+            awaiting_plan: false,
+            turn_reply: None,
+            editing_plan: false,
+            input_prefill: None,
+            title: None,
+            awaiting_first_token_since: None,
             pending_quit: false,
             quit_requested: false,
         }
     }
 
-    fn mode_status_label(&self) -> &'static str {
+    fn mode_status_label(&self) -> Cow<'static, str> {
+        let activity = self.activity_status_label();
+        if self.plan_mode {
+            format!("plan/{activity}").into()
+        } else {
+            activity
+        }
+    }
+
+    fn activity_status_label(&self) -> Cow<'static, str> {
         if self.overlay_active() {
-            "overlay"
+            "overlay".into()
         } else if self.pending_quit {
-            "quit-arm"
+            "quit-arm".into()
         } else if self.history_state.cancel_pending {
-            "cancelling"
+            "cancelling".into()
         } else if self.history_state.turn_in_progress {
-            "streaming"
+            if let Some(since) = self.awaiting_first_token_since {
+                format!("waiting for first token ({}s)", since.elapsed().as_secs()).into()
+            } else if self
+                .active_stream_blocks
+                .values()
+                .any(|block| matches!(block, StreamBlock::Reasoning { .. }))
+            {
+                "thinking".into()
+            } else {
+                "streaming".into()
+            }
         } else {
-            "ready"
+            "ready".into()
         }
     }
 
@@ -124,20 +236,601 @@ impl TuiMode {
         }
     }
 
+    fn usage_status_label(&self) -> String {
+        let session = self
+            .usage_report
+            .as_ref()
+            .map(|report| report.session)
+            .unwrap_or_default();
+        let cost = self
+            .usage_report
+            .as_ref()
+            .and_then(|report| report.session_cost_usd)
+            .map_or_else(|| "-".to_string(), format_cost_usd);
+        let mut label = format!(
+            "tokens:{}/{} cost:{}",
+            format_token_count(session.total_input_tokens()),
+            format_token_count(session.output_tokens),
+            cost
+        );
+        if session.cache_read_input_tokens > 0 || session.cache_creation_input_tokens > 0 {
+            label.push_str(&format!(
+                " cache:{}r/{}w",
+                format_token_count(session.cache_read_input_tokens),
+                format_token_count(session.cache_creation_input_tokens)
+            ));
+        }
+        label
+    }
+
     pub fn status_line(&self) -> String {
         let history_rows = history_visual_line_count(&self.history_state.lines);
         format!(
-            "mode:{} approval:{} history:{} repo:{}",
+            "mode:{} approval:{} history:{} {} repo:{}",
             self.mode_status_label(),
             self.approval_status_label(),
             history_rows,
+            self.usage_status_label(),
             self.repo_label
         )
     }
 
+    pub fn usage_report(&self) -> Option<&UsageReport> {
+        self.usage_report.as_ref()
+    }
+
+    fn handle_slash_command(&mut self, command: SlashCommand, ctx: &mut RuntimeContext) {
+        match command {
+            SlashCommand::Usage => self.show_usage_report(),
+            SlashCommand::Thinking => self.toggle_reasoning_expanded(),
+            SlashCommand::Delegates => self.toggle_delegates_expanded(),
+            SlashCommand::Image(path) => self.attach_image(&path, ctx),
+            SlashCommand::Instructions => match ctx.reload_instructions() {
+                Ok(instructions) => self.show_instructions(&instructions),
+                Err(error) => self.push_history_line(format!("[instructions] {error}")),
+            },
+            SlashCommand::Model(args) => match args.as_str() {
+                "" => self.show_model_catalog(ctx),
+                "fetch" => {
+                    self.push_history_line("[model] fetching models from endpoint".to_string());
+                    ctx.fetch_endpoint_models();
+                }
+                model => self.switch_model(model, ctx),
+            },
+            SlashCommand::Compact => self.start_compaction(ctx),
+            SlashCommand::Export(args) => self.export_session(&args, ctx),
+            SlashCommand::Rewind => self.open_rewind_picker(ctx),
+            SlashCommand::Plan => self.toggle_plan_mode(ctx),
+            SlashCommand::Fork(args) => match args.as_str() {
+                "" => self.save_fork(ctx),
+                number => match number.parse::<usize>() {
+                    Ok(number) => self.switch_fork(number, ctx),
+                    Err(_) => self.push_history_line(
+                        "[fork] usage: /fork saves a branch, /fork <n> switches to it".to_string(),
+                    ),
+                },
+            },
+        }
+    }
+
+    fn toggle_plan_mode(&mut self, ctx: &mut RuntimeContext) {
+        if self.history_state.turn_in_progress {
+            self.push_history_line("[plan] wait for the current turn to finish".to_string());
+            return;
+        }
+        if let Err(error) = ctx.set_plan_mode(!self.plan_mode) {
+            self.push_history_line(format!("[plan] {error}"));
+            return;
+        }
+        self.plan_mode = !self.plan_mode;
+        self.editing_plan = false;
+        self.overlay_state.pending_plan = None;
+        self.push_history_line(if self.plan_mode {
+            "[plan] plan mode on: edits are blocked until you approve a plan; /plan or Shift+Tab leaves".to_string()
+        } else {
+            "[plan] plan mode off".to_string()
+        });
+    }
+
+    /// Offer the reply of a plan-mode turn for approval when it is a plan.
+    fn offer_plan(&mut self, reply: Option<String>) {
+        if let Some(reply) = reply.filter(|reply| is_numbered_plan(reply)) {
+            self.overlay_state.pending_plan = Some(reply);
+        }
+    }
+
+    fn handle_plan_input(&mut self, input: &str, ctx: &mut RuntimeContext) {
+        let normalized = input.trim().to_lowercase();
+        match normalized.as_str() {
+            "1" | "y" | "yes" => {
+                if let Some(plan) = self.overlay_state.pending_plan.take() {
+                    self.approve_plan(plan, ctx);
+                }
+            }
+            "2" | "e" | "edit" => {
+                if let Some(plan) = self.overlay_state.pending_plan.take() {
+                    let id = self.input_prefill.as_ref().map_or(1, |(id, _)| id + 1);
+                    self.input_prefill = Some((id, plan));
+                    self.editing_plan = true;
+                    self.push_history_line(
+                        "[plan] edit the plan in the input box; Enter approves it".to_string(),
+                    );
+                }
+            }
+            "3" | "n" | "no" | "esc" => {
+                self.overlay_state.pending_plan = None;
+                self.push_history_line(
+                    "[plan] rejected; still in plan mode - say what to change, or /plan to leave"
+                        .to_string(),
+                );
+            }
+            _ => {
+                self.push_history_line("[invalid selection, expected y, e or n]".to_string());
+            }
+        }
+    }
+
+    /// Leave plan mode and start a turn that carries out `plan`.
+    fn approve_plan(&mut self, plan: String, ctx: &mut RuntimeContext) {
+        if let Err(error) = ctx.set_plan_mode(false) {
+            self.push_history_line(format!("[plan] {error}"));
+            return;
+        }
+        self.plan_mode = false;
+        self.push_history_line("[plan] approved; plan mode off".to_string());
+        self.start_user_turn(plan_approval_prompt(&plan), ctx);
+    }
+
+    /// Echo `input`, record it as a user turn and send it to the model.
+    fn start_user_turn(&mut self, input: String, ctx: &mut RuntimeContext) {
+        self.pending_quit = false;
+        self.quit_requested = false;
+        self.history_state.cancel_pending = false;
+        self.awaiting_plan = self.plan_mode;
+        self.turn_reply = None;
+        if self.title.is_none() {
+            self.title = input.lines().next().map(str::to_string);
+        }
+        self.push_history_line(format!("> {input}"));
+        self.history_state.user_turns.push(UserTurnLine {
+            line_index: self.history_state.lines.len() - 1,
+            prompt: input.clone(),
+        });
+        self.push_history_line(String::new());
+        self.history_state.active_assistant_index = Some(self.history_state.lines.len() - 1);
+        self.history_state.turn_in_progress = true;
+        ctx.start_turn(input);
+    }
+
+    fn export_session(&mut self, args: &str, ctx: &mut RuntimeContext) {
+        if self.history_state.turn_in_progress {
+            self.push_history_line("[export] wait for the current turn to finish".to_string());
+            return;
+        }
+        let line = match parse_export_args(args).and_then(|(format, path)| ctx.export(format, path))
+        {
+            Ok((path, turns)) => format!("[export] wrote {turns} turn(s) to {}", path.display()),
+            Err(error) => format!("[export] {error}"),
+        };
+        self.push_history_line(line);
+    }
+
+    fn open_rewind_picker(&mut self, ctx: &mut RuntimeContext) {
+        if self.history_state.turn_in_progress {
+            self.push_history_line("[rewind] wait for the current turn to finish".to_string());
+            return;
+        }
+        match ctx.user_turns() {
+            Ok(turns) if turns.is_empty() => {
+                self.push_history_line("[rewind] no turns to rewind".to_string());
+            }
+            Ok(turns) => {
+                self.overlay_state.pending_rewind =
+                    Some(turns.into_iter().rev().take(MAX_REWIND_CHOICES).collect());
+            }
+            Err(error) => self.push_history_line(format!("[rewind] {error}")),
+        }
+    }
+
+    fn handle_rewind_input(&mut self, input: &str, ctx: &mut RuntimeContext) {
+        let normalized = input.trim().to_lowercase();
+        if matches!(normalized.as_str(), "esc" | "n" | "no") {
+            self.overlay_state.pending_rewind = None;
+            self.push_history_line("[rewind] cancelled".to_string());
+            return;
+        }
+        let choices = self
+            .overlay_state
+            .pending_rewind
+            .as_ref()
+            .map_or(0, Vec::len);
+        let Some(turns) = normalized
+            .parse::<usize>()
+            .ok()
+            .filter(|turns| (1..=choices).contains(turns))
+        else {
+            self.push_history_line(format!("[invalid selection, expected 1-{choices} or esc]"));
+            return;
+        };
+        self.overlay_state.pending_rewind = None;
+        match ctx.rewind(turns) {
+            Ok(prompt) => {
+                self.rewind_transcript(turns, &prompt);
+                self.push_history_line(format!(
+                    "[rewind] removed {turns} turn(s); files on disk are unchanged"
+                ));
+                self.push_history_line(format!("[rewind] removed prompt: {prompt}"));
+            }
+            Err(error) => self.push_history_line(format!("[rewind] {error}")),
+        }
+    }
+
+    /// Cut the transcript at the echo of `prompt`, the `turns`-th newest turn.
+    /// Nothing is cut when that line has already left the transcript.
+    fn rewind_transcript(&mut self, turns: usize, prompt: &str) {
+        let records = &self.history_state.user_turns;
+        let searchable = records.len().saturating_sub(turns - 1);
+        let Some(position) = records[..searchable]
+            .iter()
+            .rposition(|record| record.prompt == prompt)
+        else {
+            return;
+        };
+        let line_index = records[position].line_index;
+        self.history_state.user_turns.truncate(position);
+        self.history_state.lines.truncate(line_index);
+        self.reasoning_entries
+            .retain(|entry| entry.line_index < line_index);
+        self.delegate_entries
+            .retain(|entry| entry.line_index < line_index);
+        self.history_state.active_assistant_index = None;
+        self.history_state.auto_follow = true;
+        self.set_scroll_to_bottom();
+    }
+
+    fn save_fork(&mut self, ctx: &mut RuntimeContext) {
+        if self.history_state.turn_in_progress {
+            self.push_history_line("[fork] wait for the current turn to finish".to_string());
+            return;
+        }
+        match ctx.fork() {
+            Ok(conversation) => {
+                self.branches.push(SavedBranch {
+                    conversation,
+                    lines: self.history_state.lines.clone(),
+                    user_turns: self.history_state.user_turns.clone(),
+                    reasoning_entries: self.reasoning_entries.clone(),
+                    delegate_entries: self.delegate_entries.clone(),
+                });
+                let number = self.branches.len();
+                self.push_history_line(format!(
+                    "[fork] saved this conversation as branch {number}; /fork {number} switches to it"
+                ));
+            }
+            Err(error) => self.push_history_line(format!("[fork] {error}")),
+        }
+    }
+
+    /// Swap the active conversation and transcript with branch `number`.
+    fn switch_fork(&mut self, number: usize, ctx: &mut RuntimeContext) {
+        if self.history_state.turn_in_progress {
+            self.push_history_line("[fork] wait for the current turn to finish".to_string());
+            return;
+        }
+        let Some(index) = number
+            .checked_sub(1)
+            .filter(|index| *index < self.branches.len())
+        else {
+            self.push_history_line(format!(
+                "[fork] no branch {number}; {} saved",
+                self.branches.len()
+            ));
+            return;
+        };
+        let previous = match ctx.switch_branch(self.branches[index].conversation.clone()) {
+            Ok(previous) => previous,
+            Err(error) => {
+                self.push_history_line(format!("[fork] {error}"));
+                return;
+            }
+        };
+        let target = std::mem::replace(
+            &mut self.branches[index],
+            SavedBranch {
+                conversation: previous,
+                lines: std::mem::take(&mut self.history_state.lines),
+                user_turns: std::mem::take(&mut self.history_state.user_turns),
+                reasoning_entries: std::mem::take(&mut self.reasoning_entries),
+                delegate_entries: std::mem::take(&mut self.delegate_entries),
+            },
+        );
+        self.history_state.lines = target.lines;
+        self.history_state.user_turns = target.user_turns;
+        self.reasoning_entries = target.reasoning_entries;
+        self.delegate_entries = target.delegate_entries;
+        self.history_state.active_assistant_index = None;
+        self.history_state.auto_follow = true;
+        self.push_history_line(format!(
+            "[fork] switched to branch {number} ({} messages); the previous conversation is now branch {number}",
+            target.conversation.message_count()
+        ));
+    }
+
+    fn start_compaction(&mut self, ctx: &mut RuntimeContext) {
+        if self.history_state.turn_in_progress {
+            self.push_history_line("[compact] wait for the current turn to finish".to_string());
+            return;
+        }
+        self.pending_quit = false;
+        self.history_state.cancel_pending = false;
+        self.push_history_line("[compact] summarizing earlier turns".to_string());
+        self.history_state.active_assistant_index = None;
+        self.history_state.turn_in_progress = true;
+        ctx.start_compaction();
+    }
+
+    /// Add a notice above the empty reply placeholder of the running turn, so
+    /// it stays in order with the streamed answer.
+    fn push_turn_notice(&mut self, line: String) {
+        let placeholder = self
+            .history_state
+            .active_assistant_index
+            .filter(|idx| *idx + 1 == self.history_state.lines.len())
+            .filter(|idx| self.history_state.lines[*idx].is_empty());
+        match placeholder {
+            Some(idx) => {
+                self.history_state.lines[idx] = line;
+                self.push_history_line(String::new());
+                self.history_state.active_assistant_index =
+                    Some(self.history_state.lines.len() - 1);
+            }
+            None => self.push_history_line(line),
+        }
+    }
+
+    fn show_model_catalog(&mut self, ctx: &mut RuntimeContext) {
+        let client = match ctx.client() {
+            Ok(client) => client,
+            Err(error) => {
+                self.push_history_line(format!("[model] {error}"));
+                return;
+            }
+        };
+        self.push_history_line(format!(
+            "[model] current: {} via {}",
+            client.model(),
+            client.api_protocol().label()
+        ));
+        for info in client.catalog().models() {
+            let marker = if client.model_info().is_some_and(|active| active == info) {
+                "*"
+            } else {
+                " "
+            };
+            self.push_history_line(format!("{marker} {}", format_model_info(info)));
+        }
+        self.push_history_line(
+            "[model] /model <name> switches, /model fetch lists endpoint models".to_string(),
+        );
+    }
+
+    fn switch_model(&mut self, model: &str, ctx: &mut RuntimeContext) {
+        match ctx.set_model(model) {
+            Ok(()) => self.push_history_line(format!("[model] switched to {model}")),
+            Err(error) => self.push_history_line(format!("[model] {model}: {error}")),
+        }
+    }
+
+    fn show_instructions(&mut self, instructions: &ProjectInstructions) {
+        if instructions.files.is_empty() {
+            self.push_history_line("[instructions] no VEX.md or AGENTS.md files found".to_string());
+            return;
+        }
+        let loaded_bytes: usize = instructions.loaded_files().map(|file| file.bytes).sum();
+        self.push_history_line(format!(
+            "[instructions] loaded {} file(s), {} KB",
+            instructions.loaded_files().count(),
+            loaded_bytes.div_ceil(1024)
+        ));
+        for file in &instructions.files {
+            let detail = if file.skipped {
+                "skipped: size limit reached".to_string()
+            } else if file.truncated {
+                format!("{} KB, truncated", file.bytes.div_ceil(1024))
+            } else {
+                format!("{} KB", file.bytes.div_ceil(1024))
+            };
+            self.push_history_line(format!("  {} ({detail})", file.display_path));
+        }
+    }
+
+    fn attach_image(&mut self, path: &str, ctx: &mut RuntimeContext) {
+        if path.is_empty() {
+            self.push_history_line("[image] usage: /image <workspace path>".to_string());
+            return;
+        }
+        match ctx.attach_image(path) {
+            Ok(image) => {
+                let downscaled = if image.downscaled { ", downscaled" } else { "" };
+                self.push_history_line(format!(
+                    "[image attached: {} {}x{} {} {} KB{downscaled} - sent with next prompt]",
+                    image.path,
+                    image.width,
+                    image.height,
+                    image.media_type,
+                    image.encoded_bytes.div_ceil(1024)
+                ));
+            }
+            Err(error) => self.push_history_line(format!("[image] {path}: {error}")),
+        }
+    }
+
+    fn toggle_reasoning_expanded(&mut self) {
+        if self.reasoning_entries.is_empty() {
+            self.push_history_line("[thinking] no thinking blocks in history".to_string());
+            return;
+        }
+        self.reasoning_expanded = !self.reasoning_expanded;
+        for entry in &self.reasoning_entries {
+            if let Some(line) = self.history_state.lines.get_mut(entry.line_index) {
+                *line =
+                    render_reasoning_line(&entry.content, entry.redacted, self.reasoning_expanded);
+            }
+        }
+        let state = if self.reasoning_expanded {
+            "expanded"
+        } else {
+            "collapsed"
+        };
+        self.push_history_line(format!("[thinking blocks {state}]"));
+    }
+
+    fn toggle_delegates_expanded(&mut self) {
+        if self.delegate_entries.is_empty() {
+            self.push_history_line("[delegate] no sub-agent runs in history".to_string());
+            return;
+        }
+        self.delegates_expanded = !self.delegates_expanded;
+        for index in 0..self.delegate_entries.len() {
+            self.rerender_delegate_entry(index);
+        }
+        let state = if self.delegates_expanded {
+            "expanded"
+        } else {
+            "collapsed"
+        };
+        self.push_history_line(format!("[delegate blocks {state}]"));
+    }
+
+    fn on_delegate_update(&mut self, update: DelegateUpdate) {
+        match update {
+            DelegateUpdate::Started { task } => {
+                let line = render_delegate_line(&task, &[], None, self.delegates_expanded);
+                let line_index = self.insert_block_line(line);
+                self.delegate_entries.push(DelegateEntry {
+                    line_index,
+                    task,
+                    steps: Vec::new(),
+                    outcome: None,
+                });
+                self.enforce_history_cap();
+            }
+            DelegateUpdate::Step(step) => {
+                if let Some(index) = self.running_delegate_index() {
+                    self.delegate_entries[index].steps.push(step);
+                    self.rerender_delegate_entry(index);
+                }
+            }
+            DelegateUpdate::Finished { tool_calls, ok } => {
+                if let Some(index) = self.running_delegate_index() {
+                    self.delegate_entries[index].outcome = Some((tool_calls, ok));
+                    self.rerender_delegate_entry(index);
+                }
+            }
+        }
+        if self.history_state.auto_follow {
+            self.set_scroll_to_bottom();
+        } else {
+            self.clamp_scroll_offset();
+        }
+    }
+
+    fn running_delegate_index(&self) -> Option<usize> {
+        self.delegate_entries
+            .iter()
+            .rposition(|entry| entry.outcome.is_none())
+    }
+
+    fn rerender_delegate_entry(&mut self, index: usize) {
+        let entry = &self.delegate_entries[index];
+        if let Some(line) = self.history_state.lines.get_mut(entry.line_index) {
+            *line = render_delegate_line(
+                &entry.task,
+                &entry.steps,
+                entry.outcome,
+                self.delegates_expanded,
+            );
+        }
+    }
+
+    /// Place a finished reasoning block ahead of the text it produced. When the
+    /// turn already streamed text, the block starts a new assistant line.
+    fn push_reasoning_entry(&mut self, content: String, redacted: bool) {
+        let line = render_reasoning_line(&content, redacted, self.reasoning_expanded);
+        let line_index = self.insert_block_line(line);
+        self.reasoning_entries.push(ReasoningEntry {
+            line_index,
+            content,
+            redacted,
+        });
+
+        self.enforce_history_cap();
+        if self.history_state.auto_follow {
+            self.set_scroll_to_bottom();
+        } else {
+            self.clamp_scroll_offset();
+        }
+    }
+
+    /// Put a block line where the assistant's next text would go and return
+    /// its index; any text after it continues on a new assistant line.
+    fn insert_block_line(&mut self, line: String) -> usize {
+        let empty_assistant_index = self.history_state.active_assistant_index.filter(|idx| {
+            self.history_state
+                .lines
+                .get(*idx)
+                .is_some_and(String::is_empty)
+        });
+
+        if let Some(idx) = empty_assistant_index {
+            self.history_state.lines.insert(idx, line);
+            self.history_state.active_assistant_index = Some(idx + 1);
+            idx
+        } else {
+            self.history_state.lines.push(line);
+            let idx = self.history_state.lines.len() - 1;
+            if self.history_state.active_assistant_index.is_some() {
+                self.history_state.lines.push(String::new());
+                self.history_state.active_assistant_index = Some(idx + 1);
+            }
+            idx
+        }
+    }
+
+    fn fail_turn(&mut self, message: &str) {
+        self.resolve_pending_approval(false);
+        self.resolve_pending_patch_approval(false);
+        self.active_stream_blocks.clear();
+        self.awaiting_first_token_since = None;
+        self.history_state.cancel_pending = false;
+        self.awaiting_plan = false;
+        self.turn_reply = None;
+        self.push_history_line(format!("[error] {message}"));
+        self.history_state.turn_in_progress = false;
+        self.history_state.active_assistant_index = None;
+    }
+
+    fn show_usage_report(&mut self) {
+        let Some(report) = self.usage_report.clone() else {
+            self.push_history_line("[usage] no token usage reported yet".to_string());
+            return;
+        };
+        self.push_history_line(format!("[usage] model: {}", report.model));
+        self.push_history_line(format!(
+            "[usage] last turn: {}",
+            format_usage_breakdown(&report.turn, report.turn_cost_usd)
+        ));
+        self.push_history_line(format!(
+            "[usage] session: {}",
+            format_usage_breakdown(&report.session, report.session_cost_usd)
+        ));
+    }
+
     pub fn overlay_active(&self) -> bool {
         self.overlay_state.pending_approval.is_some()
             || self.overlay_state.pending_patch_approval.is_some()
+            || self.overlay_state.pending_rewind.is_some()
+            || self.overlay_state.pending_plan.is_some()
     }
 
     fn patch_overlay_active(&self) -> bool {
@@ -167,6 +860,21 @@ impl TuiMode {
             .map(|pending| (pending.patch_preview.as_str(), pending.scroll_offset))
     }
 
+    pub fn pending_rewind_overlay(&self) -> Option<&[String]> {
+        self.overlay_state.pending_rewind.as_deref()
+    }
+
+    pub fn pending_plan_overlay(&self) -> Option<&str> {
+        self.overlay_state.pending_plan.as_deref()
+    }
+
+    /// Text the frontend should put in the input box, with its request number.
+    pub fn input_prefill(&self) -> Option<(u64, &str)> {
+        self.input_prefill
+            .as_ref()
+            .map(|(id, text)| (*id, text.as_str()))
+    }
+
     pub fn pending_tool_overlay(&self) -> Option<(&str, &str, bool)> {
         self.overlay_state.pending_approval.as_ref().map(|pending| {
             (
@@ -179,7 +887,12 @@ impl TuiMode {
 
     fn resolve_pending_approval(&mut self, approved: bool) {
         if let Some(pending) = self.overlay_state.pending_approval.take() {
-            let _ = pending.response_tx.send(approved);
+            let decision = if approved {
+                ApprovalDecision::Approved
+            } else {
+                ApprovalDecision::Denied
+            };
+            let _ = pending.response_tx.send(decision);
         }
     }
 
@@ -283,6 +996,27 @@ impl TuiMode {
             .history_state
             .active_assistant_index
             .and_then(|idx| idx.checked_sub(excess));
+        self.reasoning_entries.retain_mut(|entry| {
+            entry.line_index = match entry.line_index.checked_sub(excess) {
+                Some(idx) => idx,
+                None => return false,
+            };
+            true
+        });
+        self.delegate_entries.retain_mut(|entry| {
+            entry.line_index = match entry.line_index.checked_sub(excess) {
+                Some(idx) => idx,
+                None => return false,
+            };
+            true
+        });
+        self.history_state.user_turns.retain_mut(|turn| {
+            turn.line_index = match turn.line_index.checked_sub(excess) {
+                Some(idx) => idx,
+                None => return false,
+            };
+            true
+        });
         self.history_state.scroll_offset = self.history_state.scroll_offset.saturating_sub(excess);
         self.clamp_scroll_offset();
     }
@@ -340,20 +1074,216 @@ impl TuiMode {
     }
 }
 
-fn resolve_history_line_cap() -> usize {
-    std::env::var(MAX_HISTORY_LINES_ENV)
-        .ok()
-        .and_then(|value| value.trim().parse::<usize>().ok())
-        .filter(|cap| *cap > 0)
-        .unwrap_or(DEFAULT_MAX_HISTORY_LINES)
+fn format_token_count(tokens: u64) -> String {
+    if tokens >= 1_000_000 {
+        format!("{:.1}M", tokens as f64 / 1_000_000.0)
+    } else if tokens >= 1_000 {
+        format!("{:.1}k", tokens as f64 / 1_000.0)
+    } else {
+        tokens.to_string()
+    }
 }
 
-fn resolve_repo_label() -> String {
-    std::env::var("VEX_REPO_LABEL")
-        .ok()
-        .map(|value| value.trim().to_string())
-        .filter(|value| !value.is_empty())
-        .or_else(|| {
+fn format_cost_usd(cost: f64) -> String {
+    format!("${cost:.4}")
+}
+
+fn format_usage_breakdown(usage: &Usage, cost_usd: Option<f64>) -> String {
+    let cost = cost_usd.map_or_else(|| "unpriced".to_string(), format_cost_usd);
+    format!(
+        "input {} | output {} | cache write {} | cache read {} | cost {}",
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_creation_input_tokens,
+        usage.cache_read_input_tokens,
+        cost
+    )
+}
+
+fn format_truncation_notice(notice: &TruncationNotice) -> String {
+    let discarded = match notice.discarded_tool_calls {
+        0 => String::new(),
+        1 => " mid tool call; discarded the partial call".to_string(),
+        count => format!(" mid tool call; discarded {count} tool calls"),
+    };
+    if notice.continuing {
+        format!(
+            "[truncated] response hit the output token limit{discarded}, continuing ({}/{})",
+            notice.continuation, notice.max_continuations
+        )
+    } else {
+        format!(
+            "[truncated] response hit the output token limit{discarded}; continuation limit ({}) reached, answer may be incomplete",
+            notice.max_continuations
+        )
+    }
+}
+
+/// What the user can do about a classified endpoint error.
+fn api_error_hint(error: &ApiError) -> String {
+    match error {
+        ApiError::Authentication { .. } => {
+            "check ANTHROPIC_API_KEY, or VEX_AUTH_SCHEME when going through a gateway".to_string()
+        }
+        ApiError::PermissionDenied { .. } => {
+            "this key cannot use the model or endpoint; pick another with /model".to_string()
+        }
+        ApiError::RateLimited {
+            retry_after: Some(wait),
+            ..
+        } => format!(
+            "rate limit resets in about {}s; resend the prompt after that",
+            wait.as_secs().max(1)
+        ),
+        ApiError::RateLimited {
+            retry_after: None, ..
+        } => "rate limit reached; wait a minute before resending, or check your plan's quota"
+            .to_string(),
+        ApiError::Overloaded { .. } => {
+            "the provider is overloaded; retry shortly or switch models with /model".to_string()
+        }
+        ApiError::ContextLengthExceeded { .. } => {
+            "history no longer fits even after pruning; /clear starts a fresh conversation"
+                .to_string()
+        }
+        ApiError::InvalidRequest {
+            field: Some(field), ..
+        } => format!("the endpoint rejected `{field}`; check the model name and protocol settings"),
+        ApiError::InvalidRequest { field: None, .. } => {
+            "the endpoint rejected the request; check ANTHROPIC_API_URL, the model name and VEX_API_PROTOCOL"
+                .to_string()
+        }
+        ApiError::Network { .. } => {
+            "check ANTHROPIC_API_URL, VEX_HTTPS_PROXY and that the server is running".to_string()
+        }
+        ApiError::Server { .. } => "the provider failed to answer; resend the prompt".to_string(),
+    }
+}
+
+fn format_compaction_notice(notice: &CompactionNotice) -> String {
+    let trigger = if notice.automatic {
+        "context budget reached"
+    } else {
+        "/compact"
+    };
+    format!(
+        "[compacted] {} earlier message(s) replaced by a summary, ~{} -> ~{} tokens ({trigger})",
+        notice.summarized_messages,
+        format_token_count(notice.tokens_before as u64),
+        format_token_count(notice.tokens_after as u64),
+    )
+}
+
+fn format_stall_notice(notice: &StallNotice) -> String {
+    format!(
+        "[stalled] no response after {}s, retrying ({}/{})",
+        notice.waited.as_secs(),
+        notice.retry,
+        notice.max_retries
+    )
+}
+
+/// `[hook] pre write_file: ./guard.sh - blocked (exit 1)` followed by the
+/// first lines of the hook's output.
+fn format_hook_run(run: &HookRun) -> String {
+    let event = match run.event {
+        HookEvent::PreToolUse => "pre",
+        HookEvent::PostToolUse => "post",
+    };
+    let status = match (&run.failure, run.event) {
+        (None, _) => "ok".to_string(),
+        (Some(failure), HookEvent::PreToolUse) => format!("blocked ({failure})"),
+        (Some(failure), HookEvent::PostToolUse) => format!("failed ({failure})"),
+    };
+    let mut text = format!("[hook] {event} {}: {} - {status}", run.tool, run.command);
+    let mut lines = run.output.lines();
+    for line in lines.by_ref().take(HOOK_OUTPUT_PREVIEW_LINES) {
+        text.push_str(&format!("\n  │ {line}"));
+    }
+    if lines.next().is_some() {
+        text.push_str("\n  │ …");
+    }
+    text
+}
+
+fn format_model_info(info: &ModelInfo) -> String {
+    let mut line = info.id.clone();
+    if let Some(protocol) = info.protocol {
+        line.push_str(&format!(" {}", protocol.label()));
+    }
+    if let Some(context_window) = info.context_window {
+        line.push_str(&format!(
+            " ctx:{}",
+            format_token_count(context_window.into())
+        ));
+    }
+    if let Some(max_output_tokens) = info.max_output_tokens {
+        line.push_str(&format!(
+            " out:{}",
+            format_token_count(max_output_tokens.into())
+        ));
+    }
+    if let Some(pricing) = info.pricing {
+        line.push_str(&format!(
+            " ${}/${} per Mtok",
+            pricing.input_per_mtok, pricing.output_per_mtok
+        ));
+    }
+    line
+}
+
+fn render_reasoning_line(content: &str, redacted: bool, expanded: bool) -> String {
+    if redacted {
+        return "[thinking] redacted by provider".to_string();
+    }
+    if expanded {
+        return format!("[thinking]\n{}", content.trim_end());
+    }
+    let line_count = content.trim_end().lines().count();
+    format!("[thinking] {line_count} lines hidden - /thinking to expand")
+}
+
+/// Steps show while the sub-agent runs and collapse to a count when it ends.
+fn render_delegate_line(
+    task: &str,
+    steps: &[String],
+    outcome: Option<(usize, bool)>,
+    expanded: bool,
+) -> String {
+    let task = task.lines().next().unwrap_or_default();
+    let mut line = match outcome {
+        None => format!("[delegate] {task}"),
+        Some((tool_calls, ok)) => {
+            let state = if ok { "done" } else { "failed" };
+            if !expanded {
+                return format!(
+                    "[delegate] {task} - {state}, {tool_calls} tool calls - /delegates to expand"
+                );
+            }
+            format!("[delegate] {task} - {state}, {tool_calls} tool calls")
+        }
+    };
+    for step in steps {
+        line.push_str("\n  │ ");
+        line.push_str(step);
+    }
+    line
+}
+
+fn resolve_history_line_cap() -> usize {
+    std::env::var(MAX_HISTORY_LINES_ENV)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|cap| *cap > 0)
+        .unwrap_or(DEFAULT_MAX_HISTORY_LINES)
+}
+
+fn resolve_repo_label() -> String {
+    std::env::var("VEX_REPO_LABEL")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .or_else(|| {
             std::env::current_dir()
                 .ok()
                 .and_then(|path| {
@@ -431,19 +1361,34 @@ impl RuntimeMode for TuiMode {
                     self.apply_history_scroll_action(action);
                 }
             }
+            UserInputEvent::Session(_) => {}
+            UserInputEvent::TogglePlanMode => self.toggle_plan_mode(ctx),
         }
     }
 
+    fn on_runtime_notice(&mut self, notice: String) {
+        self.push_history_line(notice);
+    }
+
     fn on_user_input(&mut self, input: String, ctx: &mut RuntimeContext) {
         if self.overlay_active() {
             if self.patch_overlay_active() {
                 self.handle_patch_overlay_input(&input);
+            } else if self.overlay_state.pending_rewind.is_some() {
+                self.handle_rewind_input(&input, ctx);
+            } else if self.overlay_state.pending_plan.is_some() {
+                self.handle_plan_input(&input, ctx);
             } else {
                 self.handle_approval_input(&input);
             }
             return;
         }
 
+        if let Some(command) = parse_slash_command(&input) {
+            self.handle_slash_command(command, ctx);
+            return;
+        }
+
         if self.history_state.turn_in_progress {
             if self.history_state.cancel_pending {
                 self.push_history_line(
@@ -455,17 +1400,14 @@ impl RuntimeMode for TuiMode {
             return;
         }
 
-        self.pending_quit = false;
-        self.quit_requested = false;
-        self.history_state.cancel_pending = false;
-        self.push_history_line(format!("> {input}"));
-        self.push_history_line(String::new());
-        self.history_state.active_assistant_index = Some(self.history_state.lines.len() - 1);
-        self.history_state.turn_in_progress = true;
-        ctx.start_turn(input);
+        if std::mem::take(&mut self.editing_plan) {
+            self.approve_plan(input, ctx);
+            return;
+        }
+        self.start_user_turn(input, ctx);
     }
 
-    fn on_model_update(&mut self, update: UiUpdate, _ctx: &mut RuntimeContext) {
+    fn on_model_update(&mut self, update: UiUpdate, ctx: &mut RuntimeContext) {
         match update {
             UiUpdate::StreamDelta(text) => {
                 if self.history_state.cancel_pending {
@@ -485,7 +1427,7 @@ impl RuntimeMode for TuiMode {
                 };
                 if let Some(line) = self.history_state.lines.get_mut(idx) {
                     line.push_str(&text);
-                    *line = sanitize_assistant_text(line);
+                    *line = ctx.display_text(line);
                 }
                 if self.history_state.auto_follow {
                     self.set_scroll_to_bottom();
@@ -499,12 +1441,20 @@ impl RuntimeMode for TuiMode {
                     match block {
                         StreamBlock::Thinking { content, .. } => content.push_str(&delta),
                         StreamBlock::FinalText { content } => content.push_str(&delta),
+                        StreamBlock::Reasoning { content, .. } => content.push_str(&delta),
                         StreamBlock::ToolCall { .. } | StreamBlock::ToolResult { .. } => {}
                     }
                 }
             }
             UiUpdate::StreamBlockComplete { index } => {
-                self.active_stream_blocks.remove(&index);
+                if let Some(StreamBlock::Reasoning {
+                    content, redacted, ..
+                }) = self.active_stream_blocks.remove(&index)
+                {
+                    if !self.history_state.cancel_pending {
+                        self.push_reasoning_entry(content, redacted);
+                    }
+                }
             }
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
                 tool_name,
@@ -512,11 +1462,11 @@ impl RuntimeMode for TuiMode {
                 response_tx,
             }) => {
                 if self.history_state.cancel_pending {
-                    let _ = response_tx.send(false);
+                    let _ = response_tx.send(ApprovalDecision::Denied);
                     return;
                 }
                 if self.overlay_state.auto_approve_session {
-                    let _ = response_tx.send(true);
+                    let _ = response_tx.send(ApprovalDecision::ApprovedForSession);
                     self.push_history_line(format!("[auto-approved tool: {tool_name} session]"));
                     return;
                 }
@@ -531,28 +1481,72 @@ impl RuntimeMode for TuiMode {
                     response_tx,
                 });
             }
+            UiUpdate::Usage(report) => {
+                self.usage_report = Some(report);
+            }
+            UiUpdate::OutputTruncated(notice) => {
+                self.push_history_line(format_truncation_notice(&notice));
+            }
+            UiUpdate::AwaitingFirstToken => {
+                self.awaiting_first_token_since = Some(Instant::now());
+            }
+            UiUpdate::FirstTokenReceived => {
+                self.awaiting_first_token_since = None;
+            }
+            UiUpdate::StreamStalled(notice) => {
+                self.push_history_line(format_stall_notice(&notice));
+            }
+            UiUpdate::EndpointModels(Ok(ids)) => {
+                self.push_history_line(format!("[model] endpoint serves {} models", ids.len()));
+                for id in ids {
+                    self.push_history_line(format!("  {id}"));
+                }
+            }
+            UiUpdate::EndpointModels(Err(error)) => {
+                self.push_history_line(format!("[model] fetch failed: {error}"));
+            }
+            UiUpdate::TurnReply(reply) => self.turn_reply = Some(reply),
             UiUpdate::TurnComplete => {
+                let plan_turn =
+                    std::mem::take(&mut self.awaiting_plan) && !self.history_state.cancel_pending;
+                let reply = self.turn_reply.take();
                 self.resolve_pending_approval(false);
                 self.resolve_pending_patch_approval(false);
                 self.active_stream_blocks.clear();
+                self.awaiting_first_token_since = None;
                 self.history_state.cancel_pending = false;
                 self.history_state.turn_in_progress = false;
                 self.history_state.active_assistant_index = None;
+                if plan_turn {
+                    self.offer_plan(reply);
+                }
                 if self.history_state.auto_follow {
                     self.set_scroll_to_bottom();
                 } else {
                     self.clamp_scroll_offset();
                 }
             }
-            UiUpdate::Error(msg) => {
-                self.resolve_pending_approval(false);
-                self.resolve_pending_patch_approval(false);
-                self.active_stream_blocks.clear();
-                self.history_state.cancel_pending = false;
-                self.push_history_line(format!("[error] {msg}"));
-                self.history_state.turn_in_progress = false;
-                self.history_state.active_assistant_index = None;
+            UiUpdate::Error(msg) => self.fail_turn(&msg),
+            UiUpdate::ApiError(error) => {
+                self.fail_turn(&error.to_string());
+                self.push_history_line(format!("[hint] {}", api_error_hint(&error)));
+            }
+            UiUpdate::ContextPruned { removed_messages } => {
+                self.push_history_line(format!(
+                    "[context] prompt exceeded the context window; dropped {removed_messages} older message(s) and retrying"
+                ));
             }
+            UiUpdate::Compacted(notice) => {
+                self.push_turn_notice(format_compaction_notice(&notice));
+            }
+            UiUpdate::CompactionFailed(error) => {
+                self.push_turn_notice(format!(
+                    "[compact] automatic compaction failed: {error}; older history will be pruned instead"
+                ));
+            }
+            UiUpdate::Delegate(update) => self.on_delegate_update(update),
+            UiUpdate::Hook(run) => self.push_turn_notice(format_hook_run(&run)),
+            UiUpdate::AuditFailed(error) => self.push_turn_notice(format!("[audit] {error}")),
         }
     }
 
@@ -582,6 +1576,16 @@ impl RuntimeMode for TuiMode {
     fn is_turn_in_progress(&self) -> bool {
         self.history_state.turn_in_progress
     }
+
+    fn session_title(&self) -> Option<String> {
+        self.title.clone()
+    }
+
+    fn needs_attention(&self) -> bool {
+        self.overlay_state.pending_approval.is_some()
+            || self.overlay_state.pending_patch_approval.is_some()
+            || self.overlay_state.pending_plan.is_some()
+    }
 }
 
 fn summarize_tool_approval_context(tool_name: &str, input_preview: &str) -> String {
@@ -809,11 +1813,13 @@ impl InputEditor {
 
     fn apply_key(&mut self, key: KeyEvent) -> InputAction {
         match key.code {
-            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                if self.input_state.buffer.is_empty() {
-                    return InputAction::Quit;
-                }
+            KeyCode::Char('d')
+                if key.modifiers.contains(KeyModifiers::CONTROL)
+                    && self.input_state.buffer.is_empty() =>
+            {
+                return InputAction::Quit;
             }
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {}
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 return InputAction::Interrupt;
             }
@@ -847,10 +1853,8 @@ impl InputEditor {
             KeyCode::Up => self.history_up(),
             KeyCode::Down => self.history_down(),
             KeyCode::Char(ch) => self.insert_str(&ch.to_string()),
-            KeyCode::Esc => {
-                if self.input_state.buffer.is_empty() {
-                    return InputAction::Submit("esc".to_string());
-                }
+            KeyCode::Esc if self.input_state.buffer.is_empty() => {
+                return InputAction::Submit("esc".to_string());
             }
             _ => {}
         }
@@ -905,17 +1909,41 @@ fn render_pass_order(mode: &TuiMode) -> Vec<RenderPass> {
     order
 }
 
-pub fn build_runtime(config: Config) -> Result<(Runtime<TuiMode>, RuntimeContext)> {
-    let client = ApiClient::new(&config)?;
+/// One independent conversation: its own client, tool operator, update
+/// channel and cancellation token. Sessions share `policy` and `cassette`,
+/// which `build_runtime` opens once for the whole process.
+pub fn build_session(
+    config: &Config,
+    policy: &Arc<dyn RuntimeCorePolicy>,
+    cassette: Option<Arc<Cassette>>,
+) -> Result<Session<TuiMode>> {
+    let client = ApiClient::new_with_cassette(config, cassette)?;
     let operator = ToolOperator::new(config.working_dir.clone());
-    let conversation = ConversationManager::new(client, operator);
+    let mut conversation = ConversationManager::new(client, operator);
+    conversation.set_policy(Arc::clone(policy));
+    conversation.set_tool_hooks(ToolHooks::load()?);
+    conversation.set_audit_log(AuditLog::load());
+    let instructions = conversation.reload_instructions();
 
     let (update_tx, update_rx) = mpsc::unbounded_channel::<UiUpdate>();
     let ctx = RuntimeContext::new(conversation, update_tx, CancellationToken::new());
 
-    let mode = TuiMode::new();
-    let runtime = Runtime::new(mode, update_rx);
-    Ok((runtime, ctx))
+    let mut mode = TuiMode::new();
+    if !instructions.files.is_empty() {
+        mode.show_instructions(&instructions);
+    }
+    Ok(Session::new(mode, ctx, update_rx))
+}
+
+pub fn build_runtime(
+    config: Config,
+    policy: Arc<dyn RuntimeCorePolicy>,
+) -> Result<Runtime<TuiMode>> {
+    let cassette = Cassette::from_env()?;
+    let first = build_session(&config, &policy, cassette.clone())?;
+    Ok(Runtime::new(first).with_session_factory(Box::new(move || {
+        build_session(&config, &policy, cassette.clone())
+    })))
 }
 
 #[cfg(test)]
@@ -939,242 +1967,785 @@ mod tests {
         let mut ctx = setup_ctx();
         let mut mode = TuiMode::new();
 
-        let (response_tx, _rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, _rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
+        mode.on_model_update(
+            UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
+                tool_name: "read_file".to_string(),
+                input_preview: "{}".to_string(),
+                response_tx,
+            }),
+            &mut ctx,
+        );
+
+        mode.on_user_input("blocked".to_string(), &mut ctx);
+        assert!(
+            !mode.history_state.turn_in_progress,
+            "overlay must block input dispatch"
+        );
+
+        mode.on_user_input("1".to_string(), &mut ctx);
+        assert!(
+            !mode.overlay_active(),
+            "overlay should clear after decision"
+        );
+
+        mode.on_user_input("resume".to_string(), &mut ctx);
+        assert!(
+            mode.history_state.turn_in_progress,
+            "dispatch should resume after overlay clears"
+        );
+    }
+
+    #[test]
+    fn overlay_blocks_submit() {
+        let overlay_none = overlay_event_to_user_input(Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )));
+        assert!(
+            overlay_none.is_none(),
+            "overlay keymap must not route Enter as normal submit"
+        );
+
+        match overlay_event_to_user_input(Event::Key(KeyEvent::new(
+            KeyCode::Char('1'),
+            KeyModifiers::NONE,
+        ))) {
+            Some(UserInputEvent::Text(value)) => assert_eq!(value, "1"),
+            _ => panic!("overlay key '1' must route to modal action"),
+        }
+
+        match overlay_event_to_user_input(Event::Key(KeyEvent::new(
+            KeyCode::Esc,
+            KeyModifiers::NONE,
+        ))) {
+            Some(UserInputEvent::Text(value)) => assert_eq!(value, "esc"),
+            _ => panic!("overlay Esc must route to modal deny action"),
+        }
+    }
+
+    #[test]
+    fn test_ref_08_stream_delta_appends_to_assistant_placeholder_not_user_line() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        mode.on_user_input("hello".to_string(), &mut ctx);
+        mode.on_model_update(UiUpdate::StreamDelta("assistant".to_string()), &mut ctx);
+
+        assert_eq!(mode.history_state.lines[0], "> hello");
+        assert_eq!(mode.history_state.lines[1], "assistant");
+    }
+
+    #[test]
+    fn test_stream_delta_strips_tagged_tool_markup_from_history() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        mode.on_user_input("show diff".to_string(), &mut ctx);
+        mode.on_model_update(
+            UiUpdate::StreamDelta(
+                "I will check.\n<function=git_diff>\n</function>\nDone.".to_string(),
+            ),
+            &mut ctx,
+        );
+
+        assert_eq!(mode.history_state.lines[1], "I will check.\n\nDone.");
+        assert!(!mode.history_state.lines[1].contains("<function="));
+    }
+
+    #[test]
+    fn test_stream_delta_hides_incomplete_tool_tag_suffix() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        mode.on_user_input("status".to_string(), &mut ctx);
+        mode.on_model_update(
+            UiUpdate::StreamDelta("Checking\n<function=git_status".to_string()),
+            &mut ctx,
+        );
+
+        assert_eq!(mode.history_state.lines[1], "Checking\n");
+    }
+
+    #[test]
+    fn test_stream_delta_is_sanitized_by_the_session_policy() {
+        struct RedactingPolicy;
+        impl RuntimeCorePolicy for RedactingPolicy {
+            fn sanitize_assistant_text(&self, text: &str) -> String {
+                text.replace("hunter2", "[redacted]")
+            }
+            fn request_requires_tool_evidence(&self, _input: &str) -> bool {
+                false
+            }
+        }
+
+        let (tx, _rx) = mpsc::unbounded_channel::<UiUpdate>();
+        let client = ApiClient::new_mock(Arc::new(MockApiClient::new(vec![])));
+        let mut conversation = ConversationManager::new_mock(client, HashMap::new());
+        conversation.set_policy(Arc::new(RedactingPolicy));
+        let mut ctx = RuntimeContext::new(conversation, tx, CancellationToken::new());
+        let mut mode = TuiMode::new();
+        mode.on_user_input("password?".to_string(), &mut ctx);
+        mode.on_model_update(
+            UiUpdate::StreamDelta("It is hunter2.".to_string()),
+            &mut ctx,
+        );
+
+        assert_eq!(mode.history_state.lines[1], "It is [redacted].");
+    }
+
+    #[test]
+    fn test_transcript_does_not_exceed_cap_after_n_turns() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var(MAX_HISTORY_LINES_ENV, "10");
+
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        assert_eq!(mode.history_line_cap, 10);
+
+        for i in 0..20 {
+            mode.on_user_input(format!("user-{i}"), &mut ctx);
+            assert!(
+                mode.history_state.lines.len() <= 10,
+                "history must be capped after on_user_input"
+            );
+            if let Some(idx) = mode.history_state.active_assistant_index {
+                assert!(
+                    idx < mode.history_state.lines.len(),
+                    "active assistant index must remain valid after cap enforcement"
+                );
+            }
+
+            mode.on_model_update(UiUpdate::StreamDelta(format!("assistant-{i}")), &mut ctx);
+            assert!(
+                mode.history_state.lines.len() <= 10,
+                "history must be capped after stream update"
+            );
+            if let Some(idx) = mode.history_state.active_assistant_index {
+                assert!(
+                    idx < mode.history_state.lines.len(),
+                    "active assistant index must remain valid during streaming"
+                );
+            }
+
+            mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+            assert!(
+                mode.history_state.lines.len() <= 10,
+                "history must stay capped after turn completion"
+            );
+        }
+
+        std::env::remove_var(MAX_HISTORY_LINES_ENV);
+    }
+
+    #[test]
+    fn test_history_cap_env_invalid_uses_default() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var(MAX_HISTORY_LINES_ENV, "invalid-cap");
+
+        let mode = TuiMode::new();
+        assert_eq!(mode.history_line_cap, DEFAULT_MAX_HISTORY_LINES);
+
+        std::env::remove_var(MAX_HISTORY_LINES_ENV);
+    }
+
+    #[test]
+    fn test_scrollback_retains_position_during_streaming() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        mode.history_state.lines = (0..20).map(|i| format!("line-{i}")).collect();
+        mode.history_state.active_assistant_index = Some(10);
+        mode.history_state.scroll_offset = 5;
+        mode.history_state.auto_follow = false;
+
+        mode.on_model_update(UiUpdate::StreamDelta(" assistant".to_string()), &mut ctx);
+
+        assert_eq!(
+            mode.history_state.scroll_offset, 5,
+            "scrollback position must not be forced while auto-follow is disabled"
+        );
+    }
+
+    #[test]
+    fn test_scrollback_commands_update_scroll_state() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        mode.history_state.lines = (0..100).map(|i| format!("line-{i}")).collect();
+        mode.history_state.scroll_offset = 80;
+        mode.history_state.auto_follow = true;
+
+        mode.on_frontend_event(
+            UserInputEvent::Scroll {
+                target: ScrollTarget::History,
+                action: ScrollAction::PageUp(10),
+            },
+            &mut ctx,
+        );
+        assert_eq!(mode.history_state.scroll_offset, 70);
+        assert!(!mode.history_state.auto_follow);
+
+        mode.on_frontend_event(
+            UserInputEvent::Scroll {
+                target: ScrollTarget::History,
+                action: ScrollAction::PageDown(200),
+            },
+            &mut ctx,
+        );
+        assert_eq!(mode.history_state.scroll_offset, 99);
+        assert!(mode.history_state.auto_follow);
+
+        mode.on_frontend_event(
+            UserInputEvent::Scroll {
+                target: ScrollTarget::History,
+                action: ScrollAction::Home,
+            },
+            &mut ctx,
+        );
+        assert_eq!(mode.history_state.scroll_offset, 0);
+        assert!(!mode.history_state.auto_follow);
+
+        mode.on_frontend_event(
+            UserInputEvent::Scroll {
+                target: ScrollTarget::History,
+                action: ScrollAction::End,
+            },
+            &mut ctx,
+        );
+        assert_eq!(mode.history_state.scroll_offset, 99);
+        assert!(mode.history_state.auto_follow);
+        assert!(
+            !mode.history_state.turn_in_progress,
+            "scroll commands must not dispatch new turns"
+        );
+    }
+
+    #[test]
+    fn test_history_status_and_scroll_use_visual_rows() {
+        let mode = TuiMode {
+            history_state: HistoryState {
+                lines: vec!["a\nb\nc".to_string()],
+                ..HistoryState::default()
+            },
+            ..TuiMode::new()
+        };
+
+        assert_eq!(mode.max_scroll_offset(), 2);
+        assert!(mode.status_line().contains("history:3"));
+    }
+
+    #[test]
+    fn test_status_line_shows_first_token_wait_and_stall_notice() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        mode.history_state.turn_in_progress = true;
+
+        mode.on_model_update(UiUpdate::AwaitingFirstToken, &mut ctx);
+        mode.awaiting_first_token_since = Some(Instant::now() - Duration::from_secs(12));
+        assert!(mode
+            .status_line()
+            .starts_with("mode:waiting for first token (12s) "));
+
+        mode.on_model_update(
+            UiUpdate::StreamStalled(StallNotice {
+                waited: Duration::from_secs(120),
+                retry: 1,
+                max_retries: 1,
+            }),
+            &mut ctx,
+        );
+        assert_eq!(
+            mode.history_state.lines.last().map(String::as_str),
+            Some("[stalled] no response after 120s, retrying (1/1)")
+        );
+
+        mode.on_model_update(UiUpdate::FirstTokenReceived, &mut ctx);
+        assert!(mode.status_line().starts_with("mode:streaming "));
+    }
+
+    #[test]
+    fn test_hook_runs_are_reported_in_transcript() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        mode.on_model_update(
+            UiUpdate::Hook(HookRun {
+                event: HookEvent::PreToolUse,
+                tool: "write_file".to_string(),
+                command: "./guard.sh".to_string(),
+                failure: Some("exit 1".to_string()),
+                output: "only src/ may change".to_string(),
+            }),
+            &mut ctx,
+        );
+        assert_eq!(
+            mode.history_state.lines.last().map(String::as_str),
+            Some("[hook] pre write_file: ./guard.sh - blocked (exit 1)\n  │ only src/ may change")
+        );
+
+        let output = (1..=8).map(|n| format!("warning {n}")).collect::<Vec<_>>();
+        mode.on_model_update(
+            UiUpdate::Hook(HookRun {
+                event: HookEvent::PostToolUse,
+                tool: "edit_file".to_string(),
+                command: "cargo check".to_string(),
+                failure: None,
+                output: output.join("\n"),
+            }),
+            &mut ctx,
+        );
+        let line = mode.history_state.lines.last().expect("hook line");
+        assert!(line.starts_with("[hook] post edit_file: cargo check - ok\n  │ warning 1"));
+        assert!(line.ends_with("warning 6\n  │ …"), "{line}");
+    }
+
+    #[test]
+    fn test_api_error_update_shows_specific_hint() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        mode.history_state.turn_in_progress = true;
+
+        mode.on_model_update(
+            UiUpdate::ApiError(ApiError::RateLimited {
+                message: "slow down".to_string(),
+                retry_after: Some(Duration::from_secs(30)),
+            }),
+            &mut ctx,
+        );
+        assert!(!mode.history_state.turn_in_progress);
+        let lines = &mode.history_state.lines;
+        assert_eq!(
+            lines[lines.len() - 2],
+            "[error] rate limited: slow down (retry after 30s)"
+        );
+        assert_eq!(
+            lines[lines.len() - 1],
+            "[hint] rate limit resets in about 30s; resend the prompt after that"
+        );
+    }
+
+    #[test]
+    fn test_usage_update_feeds_status_line_and_usage_command() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        assert!(mode.status_line().contains("tokens:0/0 cost:-"));
+        mode.on_user_input("/usage".to_string(), &mut ctx);
+        assert!(!mode.history_state.turn_in_progress);
+        assert_eq!(
+            mode.history_state.lines.last().map(String::as_str),
+            Some("[usage] no token usage reported yet")
+        );
+
+        let turn = Usage {
+            input_tokens: 1_200,
+            output_tokens: 340,
+            cache_read_input_tokens: 800,
+            ..Usage::default()
+        };
+        mode.on_model_update(
+            UiUpdate::Usage(UsageReport {
+                model: "claude-sonnet-4-5".to_string(),
+                turn,
+                session: turn,
+                turn_cost_usd: Some(0.0089),
+                session_cost_usd: Some(0.0089),
+            }),
+            &mut ctx,
+        );
+        assert!(mode
+            .status_line()
+            .contains("tokens:2.0k/340 cost:$0.0089 cache:800r/0w"));
+
+        mode.on_user_input("/usage".to_string(), &mut ctx);
+        assert!(!mode.history_state.turn_in_progress);
+        assert!(mode.history_state.lines.iter().any(|line| line
+            == "[usage] session: input 1200 | output 340 | cache write 0 | cache read 800 | cost $0.0089"));
+    }
+
+    #[test]
+    fn test_output_truncation_notice_is_flagged_in_history() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        mode.on_model_update(
+            UiUpdate::OutputTruncated(TruncationNotice {
+                continuation: 1,
+                max_continuations: 3,
+                continuing: true,
+                discarded_tool_calls: 1,
+            }),
+            &mut ctx,
+        );
+        mode.on_model_update(
+            UiUpdate::OutputTruncated(TruncationNotice {
+                continuation: 3,
+                max_continuations: 3,
+                continuing: false,
+                discarded_tool_calls: 0,
+            }),
+            &mut ctx,
+        );
+        let lines = mode.history_lines();
+        assert_eq!(
+            &lines[lines.len() - 2..],
+            [
+                "[truncated] response hit the output token limit mid tool call; discarded the partial call, continuing (1/3)",
+                "[truncated] response hit the output token limit; continuation limit (3) reached, answer may be incomplete",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compaction_marks_transcript_above_streamed_answer() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        mode.on_user_input("question".to_string(), &mut ctx);
         mode.on_model_update(
-            UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
-                tool_name: "read_file".to_string(),
-                input_preview: "{}".to_string(),
-                response_tx,
+            UiUpdate::Compacted(CompactionNotice {
+                summarized_messages: 12,
+                tokens_before: 48_000,
+                tokens_after: 2_500,
+                automatic: true,
             }),
             &mut ctx,
         );
-
-        mode.on_user_input("blocked".to_string(), &mut ctx);
-        assert!(
-            !mode.history_state.turn_in_progress,
-            "overlay must block input dispatch"
-        );
-
-        mode.on_user_input("1".to_string(), &mut ctx);
-        assert!(
-            !mode.overlay_active(),
-            "overlay should clear after decision"
+        mode.on_model_update(UiUpdate::StreamDelta("answer".to_string()), &mut ctx);
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        let lines = mode.history_lines();
+        assert_eq!(
+            &lines[lines.len() - 3..],
+            [
+                "> question",
+                "[compacted] 12 earlier message(s) replaced by a summary, ~48.0k -> ~2.5k tokens (context budget reached)",
+                "answer",
+            ]
+        );
+
+        mode.on_user_input("/compact".to_string(), &mut ctx);
+        assert!(mode.is_turn_in_progress());
+        mode.on_user_input("/compact".to_string(), &mut ctx);
+        let lines = mode.history_lines();
+        assert_eq!(
+            &lines[lines.len() - 2..],
+            [
+                "[compact] summarizing earlier turns",
+                "[compact] wait for the current turn to finish",
+            ]
         );
+    }
 
-        mode.on_user_input("resume".to_string(), &mut ctx);
-        assert!(
-            mode.history_state.turn_in_progress,
-            "dispatch should resume after overlay clears"
-        );
+    fn run_exchange(mode: &mut TuiMode, ctx: &mut RuntimeContext, prompt: &str, reply: &str) {
+        mode.on_user_input(prompt.to_string(), ctx);
+        mode.on_model_update(UiUpdate::StreamDelta(reply.to_string()), ctx);
+        mode.on_model_update(UiUpdate::TurnReply(reply.to_string()), ctx);
+        mode.on_model_update(UiUpdate::TurnComplete, ctx);
+        ctx.test_push_exchange(prompt, reply);
     }
 
     #[test]
-    fn overlay_blocks_submit() {
-        let overlay_none = overlay_event_to_user_input(Event::Key(KeyEvent::new(
-            KeyCode::Enter,
-            KeyModifiers::NONE,
-        )));
-        assert!(
-            overlay_none.is_none(),
-            "overlay keymap must not route Enter as normal submit"
-        );
+    fn test_rewind_picker_truncates_transcript_and_history() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        run_exchange(&mut mode, &mut ctx, "first", "one");
+        run_exchange(&mut mode, &mut ctx, "second", "two");
+        run_exchange(&mut mode, &mut ctx, "third", "three");
 
-        match overlay_event_to_user_input(Event::Key(KeyEvent::new(
-            KeyCode::Char('1'),
-            KeyModifiers::NONE,
-        ))) {
-            Some(UserInputEvent::Text(value)) => assert_eq!(value, "1"),
-            _ => panic!("overlay key '1' must route to modal action"),
-        }
+        mode.on_user_input("/rewind".to_string(), &mut ctx);
+        assert_eq!(
+            mode.pending_rewind_overlay(),
+            Some(
+                &[
+                    "third".to_string(),
+                    "second".to_string(),
+                    "first".to_string()
+                ][..]
+            )
+        );
+        mode.on_user_input("7".to_string(), &mut ctx);
+        assert!(mode.overlay_active());
+        assert_eq!(
+            mode.history_lines().last().unwrap(),
+            "[invalid selection, expected 1-3 or esc]"
+        );
 
-        match overlay_event_to_user_input(Event::Key(KeyEvent::new(
-            KeyCode::Esc,
-            KeyModifiers::NONE,
-        ))) {
-            Some(UserInputEvent::Text(value)) => assert_eq!(value, "esc"),
-            _ => panic!("overlay Esc must route to modal deny action"),
-        }
+        mode.on_user_input("2".to_string(), &mut ctx);
+        assert!(!mode.overlay_active());
+        assert_eq!(
+            mode.history_lines(),
+            [
+                "> first",
+                "one",
+                "[rewind] removed 2 turn(s); files on disk are unchanged",
+                "[rewind] removed prompt: second",
+            ]
+        );
+        assert_eq!(ctx.user_turns().unwrap(), ["first"]);
     }
 
     #[test]
-    fn test_ref_08_stream_delta_appends_to_assistant_placeholder_not_user_line() {
+    fn test_plan_mode_offers_plan_and_runs_edited_version() {
         let mut mode = TuiMode::new();
         let mut ctx = setup_ctx();
-        mode.on_user_input("hello".to_string(), &mut ctx);
-        mode.on_model_update(UiUpdate::StreamDelta("assistant".to_string()), &mut ctx);
 
-        assert_eq!(mode.history_state.lines[0], "> hello");
-        assert_eq!(mode.history_state.lines[1], "assistant");
+        mode.on_user_input("/plan".to_string(), &mut ctx);
+        assert!(mode.status_line().contains("mode:plan/ready"));
+
+        let plan = "1. Read src/lib.rs\n2. Add the flag";
+        mode.on_user_input("add a flag".to_string(), &mut ctx);
+        mode.on_model_update(UiUpdate::StreamDelta(plan.to_string()), &mut ctx);
+        // The history copy may be truncated; the overlay shows the full reply.
+        ctx.test_push_exchange("add a flag", "1. Read src/lib.rs");
+        mode.on_model_update(UiUpdate::TurnReply(plan.to_string()), &mut ctx);
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        assert_eq!(mode.pending_plan_overlay(), Some(plan));
+        assert!(mode.needs_attention());
+
+        mode.on_user_input("e".to_string(), &mut ctx);
+        assert!(!mode.overlay_active());
+        assert_eq!(mode.input_prefill(), Some((1, plan)));
+
+        let edited = "1. Read src/lib.rs\n2. Add the flag and a test";
+        mode.on_user_input(edited.to_string(), &mut ctx);
+        assert!(!mode.status_line().contains("plan/"));
+        let lines = mode.history_lines();
+        assert_eq!(lines[lines.len() - 3], "[plan] approved; plan mode off");
+        assert_eq!(
+            lines[lines.len() - 2],
+            format!("> {}", plan_approval_prompt(edited))
+        );
+        assert!(mode.is_turn_in_progress());
     }
 
     #[test]
-    fn test_stream_delta_strips_tagged_tool_markup_from_history() {
+    fn test_plan_mode_ignores_replies_without_a_plan() {
         let mut mode = TuiMode::new();
         let mut ctx = setup_ctx();
-        mode.on_user_input("show diff".to_string(), &mut ctx);
+
+        mode.on_user_input("/plan".to_string(), &mut ctx);
+        mode.on_user_input("add a flag".to_string(), &mut ctx);
         mode.on_model_update(
-            UiUpdate::StreamDelta(
-                "I will check.\n<function=git_diff>\n</function>\nDone.".to_string(),
-            ),
+            UiUpdate::TurnReply("Which crate should get the flag?".to_string()),
             &mut ctx,
         );
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        assert!(!mode.overlay_active());
 
-        assert_eq!(mode.history_state.lines[1], "I will check.\n\nDone.");
-        assert!(!mode.history_state.lines[1].contains("<function="));
+        mode.awaiting_plan = true;
+        mode.history_state.turn_in_progress = true;
+        mode.on_model_update(
+            UiUpdate::TurnReply("1. Edit src/cli.rs".to_string()),
+            &mut ctx,
+        );
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        mode.on_user_input("n".to_string(), &mut ctx);
+        assert!(!mode.overlay_active());
+        assert!(mode.status_line().contains("mode:plan/ready"));
     }
 
     #[test]
-    fn test_stream_delta_hides_incomplete_tool_tag_suffix() {
+    fn test_shift_tab_leaves_plan_mode_from_the_plan_overlay() {
         let mut mode = TuiMode::new();
         let mut ctx = setup_ctx();
-        mode.on_user_input("status".to_string(), &mut ctx);
+
+        mode.on_frontend_event(UserInputEvent::TogglePlanMode, &mut ctx);
+        mode.on_user_input("add a flag".to_string(), &mut ctx);
         mode.on_model_update(
-            UiUpdate::StreamDelta("Checking\n<function=git_status".to_string()),
+            UiUpdate::TurnReply("1. Edit src/cli.rs".to_string()),
             &mut ctx,
         );
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        assert!(mode.overlay_active());
 
-        assert_eq!(mode.history_state.lines[1], "Checking\n");
+        mode.on_frontend_event(UserInputEvent::TogglePlanMode, &mut ctx);
+        assert!(!mode.overlay_active());
+        assert!(!mode.status_line().contains("plan/"));
+        assert_eq!(mode.history_lines().last().unwrap(), "[plan] plan mode off");
     }
 
     #[test]
-    fn test_transcript_does_not_exceed_cap_after_n_turns() {
-        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
-        std::env::set_var(MAX_HISTORY_LINES_ENV, "10");
-
+    fn test_export_writes_session_file() {
         let mut mode = TuiMode::new();
         let mut ctx = setup_ctx();
-        assert_eq!(mode.history_line_cap, 10);
-
-        for i in 0..20 {
-            mode.on_user_input(format!("user-{i}"), &mut ctx);
-            assert!(
-                mode.history_state.lines.len() <= 10,
-                "history must be capped after on_user_input"
-            );
-            if let Some(idx) = mode.history_state.active_assistant_index {
-                assert!(
-                    idx < mode.history_state.lines.len(),
-                    "active assistant index must remain valid after cap enforcement"
-                );
-            }
-
-            mode.on_model_update(UiUpdate::StreamDelta(format!("assistant-{i}")), &mut ctx);
-            assert!(
-                mode.history_state.lines.len() <= 10,
-                "history must be capped after stream update"
-            );
-            if let Some(idx) = mode.history_state.active_assistant_index {
-                assert!(
-                    idx < mode.history_state.lines.len(),
-                    "active assistant index must remain valid during streaming"
-                );
-            }
+        run_exchange(&mut mode, &mut ctx, "first", "one");
+        let dir = std::env::temp_dir().join(format!("vex-export-{}", std::process::id()));
+        let path = dir.join("session.json");
 
-            mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
-            assert!(
-                mode.history_state.lines.len() <= 10,
-                "history must stay capped after turn completion"
-            );
-        }
+        mode.on_user_input(format!("/export {}", path.display()), &mut ctx);
+        assert_eq!(
+            mode.history_lines().last().unwrap(),
+            &format!("[export] wrote 1 turn(s) to {}", path.display())
+        );
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["messages"].as_array().unwrap().len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
 
-        std::env::remove_var(MAX_HISTORY_LINES_ENV);
+        mode.on_user_input("/export pdf out.pdf".to_string(), &mut ctx);
+        assert_eq!(
+            mode.history_lines().last().unwrap(),
+            "[export] unknown export format 'pdf'; use md, html or json"
+        );
     }
 
     #[test]
-    fn test_history_cap_env_invalid_uses_default() {
-        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
-        std::env::set_var(MAX_HISTORY_LINES_ENV, "invalid-cap");
+    fn test_fork_saves_and_swaps_branches() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+        run_exchange(&mut mode, &mut ctx, "shared", "ok");
+        mode.on_user_input("/fork".to_string(), &mut ctx);
+        run_exchange(&mut mode, &mut ctx, "diverged", "ok");
 
-        let mode = TuiMode::new();
-        assert_eq!(mode.history_line_cap, DEFAULT_MAX_HISTORY_LINES);
+        mode.on_user_input("/fork 1".to_string(), &mut ctx);
+        assert_eq!(ctx.user_turns().unwrap(), ["shared"]);
+        assert_eq!(
+            mode.history_lines().last().unwrap(),
+            "[fork] switched to branch 1 (2 messages); the previous conversation is now branch 1"
+        );
+        assert!(!mode.history_lines().contains(&"> diverged".to_string()));
 
-        std::env::remove_var(MAX_HISTORY_LINES_ENV);
+        mode.on_user_input("/fork 1".to_string(), &mut ctx);
+        assert_eq!(ctx.user_turns().unwrap(), ["shared", "diverged"]);
+        assert!(mode.history_lines().contains(&"> diverged".to_string()));
+
+        mode.on_user_input("/fork 5".to_string(), &mut ctx);
+        assert_eq!(
+            mode.history_lines().last().unwrap(),
+            "[fork] no branch 5; 1 saved"
+        );
     }
 
     #[test]
-    fn test_scrollback_retains_position_during_streaming() {
+    fn test_model_command_lists_catalog_and_switches_between_turns() {
         let mut mode = TuiMode::new();
         let mut ctx = setup_ctx();
 
-        mode.history_state.lines = (0..20).map(|i| format!("line-{i}")).collect();
-        mode.history_state.active_assistant_index = Some(10);
-        mode.history_state.scroll_offset = 5;
-        mode.history_state.auto_follow = false;
+        mode.on_user_input("/model".to_string(), &mut ctx);
+        assert!(!mode.history_state.turn_in_progress);
+        assert!(mode
+            .history_state
+            .lines
+            .iter()
+            .any(|line| line == "[model] current: mock-model via anthropic"));
+        assert!(
+            mode.history_state
+                .lines
+                .iter()
+                .any(|line| line
+                    == "  claude-sonnet-4 anthropic ctx:200.0k out:64.0k $3/$15 per Mtok")
+        );
 
-        mode.on_model_update(UiUpdate::StreamDelta(" assistant".to_string()), &mut ctx);
+        mode.on_user_input("/model claude-sonnet-4-5".to_string(), &mut ctx);
+        assert_eq!(
+            mode.history_state.lines.last().map(String::as_str),
+            Some("[model] switched to claude-sonnet-4-5")
+        );
+        mode.on_user_input("/model".to_string(), &mut ctx);
+        assert!(
+            mode.history_state
+                .lines
+                .iter()
+                .any(|line| line
+                    == "* claude-sonnet-4 anthropic ctx:200.0k out:64.0k $3/$15 per Mtok")
+        );
 
+        mode.on_model_update(
+            UiUpdate::EndpointModels(Err("HTTP 404".to_string())),
+            &mut ctx,
+        );
         assert_eq!(
-            mode.history_state.scroll_offset, 5,
-            "scrollback position must not be forced while auto-follow is disabled"
+            mode.history_state.lines.last().map(String::as_str),
+            Some("[model] fetch failed: HTTP 404")
         );
     }
 
     #[test]
-    fn test_scrollback_commands_update_scroll_state() {
+    fn test_reasoning_block_renders_collapsed_and_toggles() {
         let mut mode = TuiMode::new();
         let mut ctx = setup_ctx();
 
-        mode.history_state.lines = (0..100).map(|i| format!("line-{i}")).collect();
-        mode.history_state.scroll_offset = 80;
-        mode.history_state.auto_follow = true;
-
-        mode.on_frontend_event(
-            UserInputEvent::Scroll {
-                target: ScrollTarget::History,
-                action: ScrollAction::PageUp(10),
+        mode.on_user_input("explain".to_string(), &mut ctx);
+        mode.on_model_update(
+            UiUpdate::StreamBlockStart {
+                index: 0,
+                block: StreamBlock::Reasoning {
+                    content: String::new(),
+                    redacted: false,
+                    collapsed: true,
+                },
             },
             &mut ctx,
         );
-        assert_eq!(mode.history_state.scroll_offset, 70);
-        assert!(!mode.history_state.auto_follow);
-
-        mode.on_frontend_event(
-            UserInputEvent::Scroll {
-                target: ScrollTarget::History,
-                action: ScrollAction::PageDown(200),
+        mode.on_model_update(
+            UiUpdate::StreamBlockDelta {
+                index: 0,
+                delta: "step one\nstep two".to_string(),
             },
             &mut ctx,
         );
-        assert_eq!(mode.history_state.scroll_offset, 99);
-        assert!(mode.history_state.auto_follow);
+        assert!(mode.status_line().contains("mode:thinking"));
+        mode.on_model_update(UiUpdate::StreamBlockComplete { index: 0 }, &mut ctx);
+        mode.on_model_update(UiUpdate::StreamDelta("answer".to_string()), &mut ctx);
 
-        mode.on_frontend_event(
-            UserInputEvent::Scroll {
-                target: ScrollTarget::History,
-                action: ScrollAction::Home,
-            },
-            &mut ctx,
-        );
-        assert_eq!(mode.history_state.scroll_offset, 0);
-        assert!(!mode.history_state.auto_follow);
+        let lines = mode.history_lines();
+        assert_eq!(lines[0], "> explain");
+        assert_eq!(lines[1], "[thinking] 2 lines hidden - /thinking to expand");
+        assert_eq!(lines[2], "answer");
 
-        mode.on_frontend_event(
-            UserInputEvent::Scroll {
-                target: ScrollTarget::History,
-                action: ScrollAction::End,
-            },
-            &mut ctx,
-        );
-        assert_eq!(mode.history_state.scroll_offset, 99);
-        assert!(mode.history_state.auto_follow);
-        assert!(
-            !mode.history_state.turn_in_progress,
-            "scroll commands must not dispatch new turns"
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        mode.on_user_input("/thinking".to_string(), &mut ctx);
+        assert_eq!(mode.history_lines()[1], "[thinking]\nstep one\nstep two");
+        assert_eq!(
+            mode.history_lines().last().map(String::as_str),
+            Some("[thinking blocks expanded]")
         );
     }
 
     #[test]
-    fn test_history_status_and_scroll_use_visual_rows() {
-        let mode = TuiMode {
-            history_state: HistoryState {
-                lines: vec!["a\nb\nc".to_string()],
-                ..HistoryState::default()
+    fn test_delegate_block_shows_steps_then_collapses() {
+        let mut mode = TuiMode::new();
+        let mut ctx = setup_ctx();
+
+        mode.on_user_input("where is the parser?".to_string(), &mut ctx);
+        mode.on_model_update(UiUpdate::StreamDelta("Delegating.".to_string()), &mut ctx);
+        for update in [
+            DelegateUpdate::Started {
+                task: "Find the stream parser".to_string(),
             },
-            ..TuiMode::new()
-        };
+            DelegateUpdate::Step("search_files StreamParser".to_string()),
+            DelegateUpdate::Step("read_file src/api/stream.rs".to_string()),
+        ] {
+            mode.on_model_update(UiUpdate::Delegate(update), &mut ctx);
+        }
+        assert_eq!(
+            mode.history_lines()[2],
+            "[delegate] Find the stream parser\n  │ search_files StreamParser\n  │ read_file src/api/stream.rs"
+        );
 
-        assert_eq!(mode.max_scroll_offset(), 2);
-        assert!(mode.status_line().contains("history:3"));
+        mode.on_model_update(
+            UiUpdate::Delegate(DelegateUpdate::Finished {
+                tool_calls: 2,
+                ok: true,
+            }),
+            &mut ctx,
+        );
+        mode.on_model_update(
+            UiUpdate::StreamDelta("It is in stream.rs.".to_string()),
+            &mut ctx,
+        );
+        let lines = mode.history_lines();
+        assert_eq!(lines[1], "Delegating.");
+        assert_eq!(
+            lines[2],
+            "[delegate] Find the stream parser - done, 2 tool calls - /delegates to expand"
+        );
+        assert_eq!(lines[3], "It is in stream.rs.");
+
+        mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
+        mode.on_user_input("/delegates".to_string(), &mut ctx);
+        assert_eq!(
+            mode.history_lines()[2],
+            "[delegate] Find the stream parser - done, 2 tool calls\n  │ search_files StreamParser\n  │ read_file src/api/stream.rs"
+        );
     }
 
     #[test]
@@ -1263,7 +2834,7 @@ mod tests {
         );
 
         let mut overlay_mode = TuiMode::new();
-        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
         overlay_mode.overlay_state.pending_approval = Some(PendingApproval {
             tool_name: "read_file".to_string(),
             input_preview: "{\"path\":\"Cargo.toml\"}".to_string(),
@@ -1371,7 +2942,7 @@ mod tests {
             "header row must remain first while streaming"
         );
 
-        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
         mode.on_model_update(
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
                 tool_name: "read_file".to_string(),
@@ -1429,7 +3000,7 @@ mod tests {
 
         mode.history_state.turn_in_progress = false;
         mode.history_state.active_assistant_index = None;
-        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
         mode.overlay_state.pending_approval = Some(PendingApproval {
             tool_name: "read_file".to_string(),
             input_preview: "{}".to_string(),
@@ -1478,7 +3049,7 @@ mod tests {
         let before_overlay_index = editor.input_state.history_index;
         let before_overlay_history_len = editor.input_state.history.len();
 
-        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
         mode.overlay_state.pending_approval = Some(PendingApproval {
             tool_name: "read_file".to_string(),
             input_preview: "{}".to_string(),
@@ -1727,7 +3298,7 @@ mod tests {
     async fn test_invalid_approval_input_keeps_overlay_active_with_feedback() {
         let mut ctx = setup_ctx();
         let mut mode = TuiMode::new();
-        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
 
         mode.on_model_update(
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
@@ -1811,7 +3382,7 @@ mod tests {
     async fn test_tool_approval_accept_once() {
         let mut ctx = setup_ctx();
         let mut mode = TuiMode::new();
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
 
         mode.on_model_update(
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
@@ -1823,14 +3394,17 @@ mod tests {
         );
         mode.on_user_input("1".to_string(), &mut ctx);
 
-        assert!(response_rx.await.expect("response should resolve"));
+        assert_eq!(
+            response_rx.await.expect("response should resolve"),
+            ApprovalDecision::Approved
+        );
     }
 
     #[tokio::test]
     async fn test_tool_approval_deny() {
         let mut ctx = setup_ctx();
         let mut mode = TuiMode::new();
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
 
         mode.on_model_update(
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
@@ -1842,7 +3416,10 @@ mod tests {
         );
         mode.on_user_input("n".to_string(), &mut ctx);
 
-        assert!(!response_rx.await.expect("response should resolve"));
+        assert_eq!(
+            response_rx.await.expect("response should resolve"),
+            ApprovalDecision::Denied
+        );
     }
 
     #[tokio::test]
@@ -1850,7 +3427,7 @@ mod tests {
         let mut ctx = setup_ctx();
         let mut mode = TuiMode::new();
 
-        let (first_tx, first_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (first_tx, first_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
         mode.on_model_update(
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
                 tool_name: "read_file".to_string(),
@@ -1866,7 +3443,7 @@ mod tests {
             "first approval sender must remain unresolved while overlay is active"
         );
 
-        let (second_tx, second_rx) = tokio::sync::oneshot::channel::<bool>();
+        let (second_tx, second_rx) = tokio::sync::oneshot::channel::<ApprovalDecision>();
         mode.on_model_update(
             UiUpdate::ToolApprovalRequest(ToolApprovalRequest {
                 tool_name: "write_file".to_string(),
@@ -1876,11 +3453,12 @@ mod tests {
             &mut ctx,
         );
 
-        assert!(
-            !first_rx
+        assert_eq!(
+            first_rx
                 .await
                 .expect("first sender should resolve when replaced"),
-            "replaced approval sender must resolve false exactly once"
+            ApprovalDecision::Denied,
+            "replaced approval sender must resolve denied exactly once"
         );
 
         let mut second_rx = Box::pin(second_rx);
@@ -1890,11 +3468,12 @@ mod tests {
         );
 
         mode.on_user_input("1".to_string(), &mut ctx);
-        assert!(
+        assert_eq!(
             second_rx
                 .await
                 .expect("second sender should resolve on accept"),
-            "approved overlay should resolve true exactly once"
+            ApprovalDecision::Approved,
+            "approved overlay should resolve approved exactly once"
         );
 
         mode.on_model_update(UiUpdate::TurnComplete, &mut ctx);
@@ -1904,4 +3483,46 @@ mod tests {
             "overlay lifecycle should clear cleanly after sender resolution"
         );
     }
+
+    #[test]
+    fn test_sessions_share_the_process_cassette() -> Result<()> {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("tabs.cassette.jsonl");
+        std::env::set_var("VEX_CASSETTE_RECORD", &path);
+        std::env::set_var("VEX_AUDIT_LOG", "off");
+        let config = Config {
+            api_key: None,
+            model: "local/mock-model".to_string(),
+            api_url: "http://localhost:8000/v1/messages".to_string(),
+            anthropic_version: "2023-06-01".to_string(),
+            working_dir: dir.path().to_path_buf(),
+        };
+        let policy: Arc<dyn RuntimeCorePolicy> =
+            Arc::new(crate::runtime::policy::default_runtime_policy());
+        let cassette = Cassette::from_env()?;
+        std::env::remove_var("VEX_CASSETTE_RECORD");
+        std::env::remove_var("VEX_AUDIT_LOG");
+
+        let first = build_session(&config, &policy, cassette.clone())?;
+        first
+            .ctx
+            .client()?
+            .cassette()
+            .expect("cassette")
+            .record_error(
+                "http://localhost:8000/v1/messages",
+                &serde_json::json!({}),
+                &anyhow::anyhow!("refused"),
+            );
+        let second = build_session(&config, &policy, cassette)?;
+
+        let (first, second) = (first.ctx.client()?, second.ctx.client()?);
+        assert!(std::ptr::eq(
+            first.cassette().expect("cassette"),
+            second.cassette().expect("cassette")
+        ));
+        assert_eq!(std::fs::read_to_string(&path)?.lines().count(), 1);
+        Ok(())
+    }
 }
@@ -0,0 +1,86 @@
+/// Built-in TUI commands intercepted before input is dispatched as a turn.
+///
+/// Input that starts with `/` but does not match a known command is sent to
+/// the model unchanged, so prompts such as `/etc/hosts looks wrong` still work.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SlashCommand {
+    Usage,
+    Thinking,
+    Delegates,
+    Image(String),
+    Model(String),
+    Instructions,
+    Compact,
+    Rewind,
+    Fork(String),
+    Export(String),
+    Plan,
+}
+
+pub(crate) fn parse_slash_command(input: &str) -> Option<SlashCommand> {
+    let trimmed = input.trim();
+    let body = trimmed.strip_prefix('/')?;
+    let (name, args) = body
+        .split_once(char::is_whitespace)
+        .map(|(name, args)| (name, args.trim()))
+        .unwrap_or((body, ""));
+
+    match name {
+        "usage" | "cost" => Some(SlashCommand::Usage),
+        "thinking" => Some(SlashCommand::Thinking),
+        "delegates" | "delegate" => Some(SlashCommand::Delegates),
+        "image" => Some(SlashCommand::Image(args.to_string())),
+        "model" | "models" => Some(SlashCommand::Model(args.to_string())),
+        "instructions" => Some(SlashCommand::Instructions),
+        "compact" => Some(SlashCommand::Compact),
+        "rewind" => Some(SlashCommand::Rewind),
+        "fork" => Some(SlashCommand::Fork(args.to_string())),
+        "export" => Some(SlashCommand::Export(args.to_string())),
+        "plan" => Some(SlashCommand::Plan),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_slash_command_recognizes_usage() {
+        assert_eq!(parse_slash_command("/usage"), Some(SlashCommand::Usage));
+        assert_eq!(parse_slash_command("  /cost  "), Some(SlashCommand::Usage));
+        assert_eq!(
+            parse_slash_command("/thinking"),
+            Some(SlashCommand::Thinking)
+        );
+        assert_eq!(
+            parse_slash_command("/delegates"),
+            Some(SlashCommand::Delegates)
+        );
+        assert_eq!(
+            parse_slash_command("/image  docs/shot.png "),
+            Some(SlashCommand::Image("docs/shot.png".to_string()))
+        );
+        assert_eq!(
+            parse_slash_command("/model gpt-4o"),
+            Some(SlashCommand::Model("gpt-4o".to_string()))
+        );
+        assert_eq!(
+            parse_slash_command("/instructions"),
+            Some(SlashCommand::Instructions)
+        );
+        assert_eq!(parse_slash_command("/compact"), Some(SlashCommand::Compact));
+        assert_eq!(parse_slash_command("/rewind"), Some(SlashCommand::Rewind));
+        assert_eq!(
+            parse_slash_command("/fork 2"),
+            Some(SlashCommand::Fork("2".to_string()))
+        );
+        assert_eq!(
+            parse_slash_command("/export html out.html"),
+            Some(SlashCommand::Export("html out.html".to_string()))
+        );
+        assert_eq!(parse_slash_command("/plan"), Some(SlashCommand::Plan));
+        assert_eq!(parse_slash_command("usage"), None);
+        assert_eq!(parse_slash_command("/etc/hosts is wrong"), None);
+    }
+}
@@ -0,0 +1,695 @@
+use crate::export::{format_timestamp, unix_ms};
+use crate::util::parse_bool_str;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
+
+const AUDIT_LOG_ENV: &str = "VEX_AUDIT_LOG";
+/// Input strings longer than this are replaced by their size and hash.
+const MAX_AUDIT_STRING_CHARS: usize = 200;
+/// Keys holding file bodies, which are always replaced by their size and hash.
+const FILE_BODY_KEYS: &[&str] = &["content", "old_str", "new_str"];
+const REDACTED: &str = "[redacted]";
+
+/// Object keys whose values are never written to the log.
+static SECRET_KEY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(api[_-]?key|token|secret|password|passwd|authorization|credential)")
+        .expect("secret key pattern")
+});
+
+/// Credentials that show up inside otherwise harmless strings.
+static SECRET_VALUE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"sk-[A-Za-z0-9_-]{16,}|gh[pousr]_[A-Za-z0-9]{20,}|AKIA[0-9A-Z]{16}|-----BEGIN [A-Z ]*PRIVATE KEY-----",
+    )
+    .expect("secret value pattern")
+});
+
+/// Append-only JSONL record of every tool call in a session, written to
+/// `VEX_AUDIT_LOG` or `$XDG_STATE_HOME/vex/audit.jsonl`.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    session_id: String,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp_unix_ms: u64,
+    pub session_id: String,
+    /// 1-based user turn within the session.
+    pub turn: usize,
+    /// Made by a `delegate` sub-agent on behalf of the turn.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delegate: bool,
+    pub working_dir: String,
+    pub tool: String,
+    /// The tool input with secrets and file bodies redacted.
+    pub input: Value,
+    pub decision: AuditDecision,
+    pub decided_by: AuditApprover,
+    pub duration_ms: u64,
+    pub status: AuditStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Files the tool writes, hashed before and after it ran.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileHashes>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditDecision {
+    Approved,
+    Denied,
+}
+
+/// Who made the [`AuditDecision`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditApprover {
+    /// The user answered the approval prompt.
+    Manual,
+    /// The user had approved tools for the rest of the session.
+    SessionAuto,
+    /// No prompt: the tool needs no approval, or plan mode, a sub-agent's
+    /// tool list or a missing argument refused it.
+    Rule,
+    /// A pre-tool-use hook vetoed the call.
+    Hook,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditStatus {
+    Ok,
+    Error,
+    /// Denied, so the tool never ran.
+    NotRun,
+}
+
+/// `sha256:<hex>` of a file before and after a tool call; `None` when the
+/// file did not exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileHashes {
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    /// The tool timed out and may still be writing, so `after` was not taken.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub after_unknown: bool,
+}
+
+/// What happened to one tool call; [`AuditLog::record`] stamps it with the
+/// session and redacts it.
+#[derive(Debug)]
+pub struct ToolCallRecord<'a> {
+    pub turn: usize,
+    pub delegate: bool,
+    pub tool: &'a str,
+    pub input: &'a Value,
+    pub decision: AuditDecision,
+    pub decided_by: AuditApprover,
+    pub duration: Duration,
+    /// `None` when the tool did not run.
+    pub result: Option<Result<(), String>>,
+    pub files: Vec<FileHashes>,
+}
+
+impl AuditLog {
+    /// The log for a new session, or `None` when `VEX_AUDIT_LOG` is `off`
+    /// or no default location exists.
+    pub fn load() -> Option<Self> {
+        audit_log_path().map(Self::new)
+    }
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            session_id: new_session_id(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Append one entry for `call` made in `working_dir`.
+    pub fn record(&self, working_dir: &Path, call: ToolCallRecord<'_>) -> std::io::Result<()> {
+        let (status, error) = match call.result {
+            None => (AuditStatus::NotRun, None),
+            Some(Ok(())) => (AuditStatus::Ok, None),
+            Some(Err(error)) => (AuditStatus::Error, Some(redact_string(&error))),
+        };
+        let entry = AuditEntry {
+            timestamp_unix_ms: unix_ms(SystemTime::now()),
+            session_id: self.session_id.clone(),
+            turn: call.turn,
+            delegate: call.delegate,
+            working_dir: working_dir.display().to_string(),
+            tool: call.tool.to_string(),
+            input: redact_tool_input(call.input),
+            decision: call.decision,
+            decided_by: call.decided_by,
+            duration_ms: call.duration.as_millis() as u64,
+            status,
+            error,
+            files: call.files,
+        };
+        self.append(&entry)
+    }
+
+    fn append(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+        line.push('\n');
+        // One write per line keeps entries from concurrent sessions whole.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+}
+
+/// `VEX_AUDIT_LOG`, or `$XDG_STATE_HOME/vex/audit.jsonl` falling back to
+/// `~/.local/state/vex/audit.jsonl`; `None` when turned off.
+pub fn audit_log_path() -> Option<PathBuf> {
+    match std::env::var(AUDIT_LOG_ENV) {
+        Ok(value) if parse_bool_str(&value) == Some(false) => None,
+        Ok(value) if !value.trim().is_empty() && parse_bool_str(&value).is_none() => {
+            Some(PathBuf::from(value.trim()))
+        }
+        _ => std::env::var_os("XDG_STATE_HOME")
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })
+            .map(|state| state.join("vex").join("audit.jsonl")),
+    }
+}
+
+pub fn read_audit_log(path: &Path) -> Result<Vec<AuditEntry>> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read audit log '{}'", path.display()))?;
+    raw.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!("Invalid audit log '{}' line {}", path.display(), index + 1)
+            })
+        })
+        .collect()
+}
+
+/// Hashes of the files `tool` writes, taken before it runs; pass them to
+/// [`rehash_touched_files`] afterwards.
+pub fn hash_touched_files(working_dir: &Path, tool: &str, input: &Value) -> Vec<FileHashes> {
+    touched_paths(tool, input)
+        .into_iter()
+        .map(|path| FileHashes {
+            before: hash_workspace_file(working_dir, &path),
+            after: None,
+            after_unknown: false,
+            path,
+        })
+        .collect()
+}
+
+pub fn rehash_touched_files(files: &mut [FileHashes], working_dir: &Path) {
+    for file in files {
+        file.after = hash_workspace_file(working_dir, &file.path);
+    }
+}
+
+/// Leave the after hashes out: the tool timed out, and its blocking task
+/// may still be writing.
+pub fn mark_after_hashes_unknown(files: &mut [FileHashes]) {
+    for file in files {
+        file.after = None;
+        file.after_unknown = true;
+    }
+}
+
+fn touched_paths(tool: &str, input: &Value) -> Vec<String> {
+    let first = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| input.get(key).and_then(Value::as_str))
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+    };
+    match tool {
+        "write_file" | "edit_file" => first(&["path", "file_path", "file", "filename"])
+            .into_iter()
+            .collect(),
+        "rename_file" => [
+            first(&["old_path", "from", "source_path"]),
+            first(&["new_path", "to", "target_path"]),
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Only relative paths inside the workspace are read; the tool itself
+/// refuses anything else.
+fn hash_workspace_file(working_dir: &Path, path: &str) -> Option<String> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+    std::fs::read(working_dir.join(relative))
+        .ok()
+        .map(|bytes| sha256_hex(&bytes))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let hash = digest(&SHA256, bytes);
+    let hex: String = hash
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256:{hex}")
+}
+
+fn new_session_id() -> String {
+    let mut bytes = [0u8; 8];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        bytes = unix_ms(SystemTime::now()).to_be_bytes();
+    }
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Tool input as logged: values under secret-looking keys and credentials
+/// inside strings are replaced, and file bodies and other long strings
+/// become their size and hash.
+pub fn redact_tool_input(input: &Value) -> Value {
+    match input {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        _ if SECRET_KEY.is_match(key) => Value::String(REDACTED.to_string()),
+                        Value::String(text) if FILE_BODY_KEYS.contains(&key.as_str()) => {
+                            size_and_hash(text)
+                        }
+                        _ => redact_tool_input(value),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_tool_input).collect()),
+        Value::String(text) if text.chars().count() > MAX_AUDIT_STRING_CHARS => size_and_hash(text),
+        Value::String(text) => Value::String(redact_string(text)),
+        other => other.clone(),
+    }
+}
+
+fn size_and_hash(text: &str) -> Value {
+    Value::String(format!(
+        "[{} bytes, {}]",
+        text.len(),
+        sha256_hex(text.as_bytes())
+    ))
+}
+
+fn redact_string(text: &str) -> String {
+    SECRET_VALUE.replace_all(text, REDACTED).into_owned()
+}
+
+/// Filters for `vex audit`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuditQuery {
+    /// Session id or a prefix of it.
+    pub session: Option<String>,
+    pub tool: Option<String>,
+    /// Entries that hashed this file.
+    pub path: Option<String>,
+    pub status: Option<AuditStatus>,
+    /// Keep only the newest matches.
+    pub last: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.session
+            .as_ref()
+            .is_none_or(|session| entry.session_id.starts_with(session.as_str()))
+            && self.tool.as_ref().is_none_or(|tool| entry.tool == *tool)
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|path| entry.files.iter().any(|file| file.path == *path))
+            && self.status.is_none_or(|status| entry.status == status)
+    }
+
+    pub fn apply<'a>(&self, entries: &'a [AuditEntry]) -> Vec<&'a AuditEntry> {
+        let matching: Vec<_> = entries.iter().filter(|entry| self.matches(entry)).collect();
+        let skip = self
+            .last
+            .map_or(0, |last| matching.len().saturating_sub(last));
+        matching.into_iter().skip(skip).collect()
+    }
+}
+
+/// `vex audit [--log PATH] [--session ID] [--tool NAME] [--file PATH]
+/// [--status ok|error|not-run] [--last N] [--json]`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuditCommand {
+    pub log: Option<PathBuf>,
+    pub query: AuditQuery,
+    /// Print matching entries as JSONL instead of one summary line each.
+    pub json: bool,
+}
+
+pub const AUDIT_USAGE: &str = "usage: vex audit [--log <path>] [--session <id>] [--tool <name>] [--file <path>] [--status ok|error|not-run] [--last <n>] [--json]";
+
+pub fn parse_audit_args(mut args: impl Iterator<Item = String>) -> Result<AuditCommand> {
+    let mut command = AuditCommand::default();
+    while let Some(arg) = args.next() {
+        if arg == "--json" {
+            command.json = true;
+            continue;
+        }
+        if matches!(arg.as_str(), "-h" | "--help") {
+            bail!("{AUDIT_USAGE}");
+        }
+        let Some(value) = args.next() else {
+            bail!("{arg} needs a value\n{AUDIT_USAGE}");
+        };
+        match arg.as_str() {
+            "--log" => command.log = Some(PathBuf::from(value)),
+            "--session" => command.query.session = Some(value),
+            "--tool" => command.query.tool = Some(value),
+            "--file" => command.query.path = Some(value),
+            "--status" => {
+                command.query.status = Some(match value.as_str() {
+                    "ok" => AuditStatus::Ok,
+                    "error" => AuditStatus::Error,
+                    "not-run" => AuditStatus::NotRun,
+                    other => bail!("unknown status '{other}'; use ok, error or not-run"),
+                })
+            }
+            "--last" => {
+                command.query.last = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("--last expects a number, got '{value}'"))?,
+                )
+            }
+            other => bail!("unknown argument '{other}'\n{AUDIT_USAGE}"),
+        }
+    }
+    Ok(command)
+}
+
+/// `2026-10-18 14:25:01 UTC 3f9a2c1e turn 3 edit_file approved (manual) ok 12 ms`,
+/// then one line per hashed file.
+pub fn format_audit_entry(entry: &AuditEntry) -> String {
+    let decision = match entry.decision {
+        AuditDecision::Approved => "approved",
+        AuditDecision::Denied => "denied",
+    };
+    let decided_by = match entry.decided_by {
+        AuditApprover::Manual => "manual",
+        AuditApprover::SessionAuto => "session-auto",
+        AuditApprover::Rule => "rule",
+        AuditApprover::Hook => "hook",
+    };
+    let status = match entry.status {
+        AuditStatus::Ok => "ok",
+        AuditStatus::Error => "error",
+        AuditStatus::NotRun => "not-run",
+    };
+    let session: String = entry.session_id.chars().take(8).collect();
+    let delegate = if entry.delegate { " (delegate)" } else { "" };
+    let mut text = format!(
+        "{} {session} turn {}{delegate} {} {decision} ({decided_by}) {status} {} ms",
+        format_timestamp(entry.timestamp_unix_ms),
+        entry.turn,
+        entry.tool,
+        entry.duration_ms,
+    );
+    if let Some(error) = entry
+        .error
+        .as_deref()
+        .and_then(|error| error.lines().next())
+    {
+        text.push_str(&format!("\n    error: {error}"));
+    }
+    for file in &entry.files {
+        text.push_str(&format!(
+            "\n    {} {} -> {}",
+            file.path,
+            short_hash(file.before.as_deref()),
+            if file.after_unknown {
+                "unknown".to_string()
+            } else {
+                short_hash(file.after.as_deref())
+            }
+        ));
+    }
+    text
+}
+
+fn short_hash(hash: Option<&str>) -> String {
+    match hash {
+        Some(hash) => hash.chars().take("sha256:".len() + 12).collect(),
+        None => "missing".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn record<'a>(tool: &'a str, input: &'a Value) -> ToolCallRecord<'a> {
+        ToolCallRecord {
+            turn: 2,
+            delegate: false,
+            tool,
+            input,
+            decision: AuditDecision::Approved,
+            decided_by: AuditApprover::Manual,
+            duration: Duration::from_millis(12),
+            result: Some(Ok(())),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_redact_tool_input_hides_secrets_and_file_bodies() {
+        let body = "fn main() {}\n".repeat(40);
+        let redacted = redact_tool_input(&json!({
+            "path": "src/main.rs",
+            "content": body,
+            "old_str": "let x = 1;",
+            "new_str": "",
+            "api_key": "abc",
+            "headers": [{"Authorization": "Bearer x"}],
+            "message": "use sk-abcdefghijklmnopqrstu here",
+            "max_entries": 5
+        }));
+        assert_eq!(redacted["path"], "src/main.rs");
+        assert_eq!(
+            redacted["content"],
+            format!("[{} bytes, {}]", body.len(), sha256_hex(body.as_bytes()))
+        );
+        assert_eq!(
+            redacted["old_str"],
+            format!("[10 bytes, {}]", sha256_hex(b"let x = 1;"))
+        );
+        assert_eq!(
+            redacted["new_str"],
+            format!("[0 bytes, {}]", sha256_hex(b""))
+        );
+        assert_eq!(redacted["api_key"], REDACTED);
+        assert_eq!(redacted["headers"][0]["Authorization"], REDACTED);
+        assert_eq!(redacted["message"], "use [redacted] here");
+        assert_eq!(redacted["max_entries"], 5);
+    }
+
+    #[test]
+    fn test_record_appends_one_json_line_per_call_with_file_hashes() {
+        let temp = TempDir::new().expect("temp dir");
+        std::fs::write(temp.path().join("a.txt"), "old").expect("write file");
+        let log = AuditLog::new(temp.path().join("logs/audit.jsonl"));
+
+        let input = json!({"path": "a.txt", "content": "new"});
+        let mut files = hash_touched_files(temp.path(), "write_file", &input);
+        std::fs::write(temp.path().join("a.txt"), "new").expect("write file");
+        rehash_touched_files(&mut files, temp.path());
+        log.record(
+            temp.path(),
+            ToolCallRecord {
+                files,
+                ..record("write_file", &input)
+            },
+        )
+        .expect("append entry");
+        log.record(
+            temp.path(),
+            ToolCallRecord {
+                decision: AuditDecision::Denied,
+                decided_by: AuditApprover::Rule,
+                result: None,
+                ..record("git_commit", &json!({"message": "wip"}))
+            },
+        )
+        .expect("append entry");
+
+        let entries = read_audit_log(log.path()).expect("read log");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].session_id, log.session_id());
+        assert_eq!(entries[0].turn, 2);
+        assert_eq!(
+            entries[0].files,
+            vec![FileHashes {
+                path: "a.txt".to_string(),
+                before: Some(sha256_hex(b"old")),
+                after: Some(sha256_hex(b"new")),
+                after_unknown: false,
+            }]
+        );
+        assert_eq!(entries[1].status, AuditStatus::NotRun);
+
+        let raw = std::fs::read_to_string(log.path()).expect("read log");
+        let line: Value =
+            serde_json::from_str(raw.lines().nth(1).expect("second line")).expect("json line");
+        assert_eq!(line["decision"], "denied");
+        assert_eq!(line["decided_by"], "rule");
+        assert_eq!(line["status"], "not-run");
+        assert!(line.get("files").is_none());
+    }
+
+    #[test]
+    fn test_timed_out_call_logs_after_hashes_as_unknown() {
+        let temp = TempDir::new().expect("temp dir");
+        std::fs::write(temp.path().join("a.txt"), "old").expect("write file");
+        let log = AuditLog::new(temp.path().join("audit.jsonl"));
+
+        let input = json!({"path": "a.txt", "content": "new"});
+        let mut files = hash_touched_files(temp.path(), "write_file", &input);
+        mark_after_hashes_unknown(&mut files);
+        log.record(
+            temp.path(),
+            ToolCallRecord {
+                result: Some(Err("Tool execution timed out after 2s".to_string())),
+                files,
+                ..record("write_file", &input)
+            },
+        )
+        .expect("append entry");
+
+        let entries = read_audit_log(log.path()).expect("read log");
+        assert!(entries[0].files[0].after_unknown);
+        assert_eq!(entries[0].files[0].after, None);
+        assert!(format_audit_entry(&entries[0]).ends_with("-> unknown"));
+    }
+
+    #[test]
+    fn test_touched_files_skip_paths_outside_the_workspace() {
+        let temp = TempDir::new().expect("temp dir");
+        let files = hash_touched_files(
+            temp.path(),
+            "rename_file",
+            &json!({"old_path": "../secret", "new_path": "b.txt"}),
+        );
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.before.is_none()));
+        assert!(hash_touched_files(temp.path(), "read_file", &json!({"path": "a"})).is_empty());
+    }
+
+    #[test]
+    fn test_audit_query_filters_and_keeps_newest() {
+        let entry = |session: &str, tool: &str, status| AuditEntry {
+            timestamp_unix_ms: 1_792_247_101_000,
+            session_id: session.to_string(),
+            turn: 1,
+            delegate: false,
+            working_dir: "/repo".to_string(),
+            tool: tool.to_string(),
+            input: json!({}),
+            decision: AuditDecision::Approved,
+            decided_by: AuditApprover::SessionAuto,
+            duration_ms: 3,
+            status,
+            error: None,
+            files: Vec::new(),
+        };
+        let entries = vec![
+            entry("aaaa1111", "edit_file", AuditStatus::Ok),
+            entry("aaaa1111", "read_file", AuditStatus::Ok),
+            entry("bbbb2222", "edit_file", AuditStatus::Error),
+            entry("aaaa1111", "edit_file", AuditStatus::Ok),
+        ];
+
+        let command = parse_audit_args(
+            ["--session", "aaaa", "--tool", "edit_file", "--last", "1"]
+                .into_iter()
+                .map(String::from),
+        )
+        .expect("valid args");
+        let matched = command.query.apply(&entries);
+        assert_eq!(matched.len(), 1);
+        assert!(std::ptr::eq(matched[0], &entries[3]));
+
+        let errors = AuditQuery {
+            status: Some(AuditStatus::Error),
+            ..AuditQuery::default()
+        };
+        assert_eq!(errors.apply(&entries).len(), 1);
+        assert_eq!(
+            format_audit_entry(&entries[2]),
+            "2026-10-17 14:25:01 UTC bbbb2222 turn 1 edit_file approved (session-auto) error 3 ms"
+        );
+
+        assert!(parse_audit_args(["--last"].into_iter().map(String::from)).is_err());
+        assert!(parse_audit_args(["--status", "done"].into_iter().map(String::from)).is_err());
+        assert!(parse_audit_args(["--verbose"].into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_audit_log_path_follows_env() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::set_var(AUDIT_LOG_ENV, "off");
+        assert_eq!(audit_log_path(), None);
+        std::env::set_var(AUDIT_LOG_ENV, "/var/log/vex.jsonl");
+        assert_eq!(audit_log_path(), Some(PathBuf::from("/var/log/vex.jsonl")));
+        std::env::remove_var(AUDIT_LOG_ENV);
+        std::env::set_var("XDG_STATE_HOME", "/state");
+        assert_eq!(
+            audit_log_path(),
+            Some(PathBuf::from("/state/vex/audit.jsonl"))
+        );
+        std::env::remove_var("XDG_STATE_HOME");
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+use vexcoder::mock_server::{MockScript, MockServer};
+
+const USAGE: &str =
+    "usage: vex-mock-server [--port <port>] [--host <addr>] [--model <id>] [--script <fixture.json>]";
+
+struct Options {
+    host: String,
+    port: u16,
+    model: Option<String>,
+    script: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut options = Options {
+        host: "127.0.0.1".to_string(),
+        port: 8089,
+        model: None,
+        script: None,
+    };
+    while let Some(arg) = args.next() {
+        if matches!(arg.as_str(), "-h" | "--help") {
+            bail!("{USAGE}");
+        }
+        let Some(value) = args.next() else {
+            bail!("{arg} needs a value\n{USAGE}");
+        };
+        match arg.as_str() {
+            "--host" => options.host = value,
+            "--port" => match value.parse() {
+                Ok(port) => options.port = port,
+                Err(_) => bail!("invalid port '{value}'"),
+            },
+            "--model" => options.model = Some(value),
+            "--script" => options.script = Some(PathBuf::from(value)),
+            other => bail!("unknown argument '{other}'\n{USAGE}"),
+        }
+    }
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = parse_args(std::env::args().skip(1))?;
+    let script = match &options.script {
+        Some(path) => MockScript::load(path)?,
+        None => MockScript::echo(),
+    };
+    let mut server =
+        MockServer::bind(&format!("{}:{}", options.host, options.port), script).await?;
+    if let Some(model) = &options.model {
+        server = server.with_model(model);
+    }
+
+    let addr = server.local_addr()?;
+    println!("vex-mock-server listening on http://{addr}");
+    println!("  ANTHROPIC_API_URL=http://{addr}/v1/messages");
+    println!("  ANTHROPIC_API_URL=http://{addr}/v1/chat/completions");
+    server.serve().await
+}
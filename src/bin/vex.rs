@@ -1,15 +1,22 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::widgets::Clear;
+use std::io::Write;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use vexcoder::app::{build_runtime, TuiMode};
+use vexcoder::audit::{audit_log_path, format_audit_entry, parse_audit_args, read_audit_log};
 use vexcoder::config::Config;
-use vexcoder::runtime::frontend::{FrontendAdapter, ScrollAction, ScrollTarget, UserInputEvent};
+use vexcoder::runtime::frontend::{
+    FrontendAdapter, ScrollAction, ScrollTarget, SessionCommand, UserInputEvent,
+};
+use vexcoder::runtime::policy::DefaultRuntimeCorePolicy;
+use vexcoder::runtime::session::SessionTab;
 use vexcoder::terminal;
 use vexcoder::ui::layout::split_three_pane_layout;
 use vexcoder::ui::render::{
     input_visual_rows, render_input, render_messages, render_overlay_modal, render_status_line,
-    OverlayModal,
+    render_tab_bar, OverlayModal,
 };
 
 const STARTUP_NOISE_GUARD: Duration = Duration::from_secs(15);
@@ -68,6 +75,8 @@ struct ManagedTuiFrontend {
     quit: bool,
     input_buffer: String,
     cursor: usize,
+    /// Last `TuiMode::input_prefill` request loaded into the input box.
+    applied_prefill: u64,
     started_at: Instant,
 }
 
@@ -80,6 +89,7 @@ impl ManagedTuiFrontend {
             quit: false,
             input_buffer: String::new(),
             cursor: 0,
+            applied_prefill: 0,
             started_at: Instant::now(),
         })
     }
@@ -170,6 +180,17 @@ impl ManagedTuiFrontend {
         self.cursor = start;
     }
 
+    /// Replace the input with text the mode asked for, such as a plan to edit.
+    fn apply_input_prefill(&mut self, mode: &TuiMode) {
+        if let Some((id, text)) = mode.input_prefill() {
+            if id != self.applied_prefill {
+                self.applied_prefill = id;
+                self.input_buffer = text.to_string();
+                self.cursor = self.input_buffer.len();
+            }
+        }
+    }
+
     fn submit_input(&mut self) -> Option<String> {
         let value = self.input_buffer.trim().to_string();
         if value.is_empty() {
@@ -185,6 +206,29 @@ impl ManagedTuiFrontend {
         Some(value)
     }
 
+    /// Ctrl+T opens a session, Ctrl+W closes it, Alt+Left/Right cycle and
+    /// Alt+1..9 jump. Available with or without an overlay.
+    fn map_session_key(key: KeyEvent) -> Option<SessionCommand> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return match key.code {
+                KeyCode::Char('t') => Some(SessionCommand::New),
+                KeyCode::Char('w') => Some(SessionCommand::Close),
+                _ => None,
+            };
+        }
+        if !key.modifiers.contains(KeyModifiers::ALT) {
+            return None;
+        }
+        match key.code {
+            KeyCode::Left => Some(SessionCommand::Previous),
+            KeyCode::Right => Some(SessionCommand::Next),
+            KeyCode::Char(ch @ '1'..='9') => Some(SessionCommand::Select(
+                ch.to_digit(10).map_or(0, |digit| digit as usize - 1),
+            )),
+            _ => None,
+        }
+    }
+
     fn map_overlay_key(&mut self, key: KeyEvent) -> Option<UserInputEvent> {
         match key.code {
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
@@ -321,6 +365,7 @@ impl FrontendAdapter<TuiMode> for ManagedTuiFrontend {
             self.quit = true;
             return None;
         }
+        self.apply_input_prefill(mode);
 
         let Ok(has_event) = event::poll(Duration::from_millis(16)) else {
             self.quit = true;
@@ -340,6 +385,12 @@ impl FrontendAdapter<TuiMode> for ManagedTuiFrontend {
                 if key.kind == KeyEventKind::Release {
                     return None;
                 }
+                if let Some(command) = Self::map_session_key(key) {
+                    return Some(UserInputEvent::Session(command));
+                }
+                if key.code == KeyCode::BackTab {
+                    return Some(UserInputEvent::TogglePlanMode);
+                }
                 if mode.overlay_active() {
                     self.map_overlay_key(key)
                 } else {
@@ -366,7 +417,7 @@ impl FrontendAdapter<TuiMode> for ManagedTuiFrontend {
         }
     }
 
-    fn render(&mut self, mode: &TuiMode) {
+    fn render(&mut self, mode: &TuiMode, tabs: &[SessionTab]) {
         let status = mode.status_line();
         let history_scroll = mode.history_scroll_offset();
         let input = self.input_buffer.as_str();
@@ -377,8 +428,11 @@ impl FrontendAdapter<TuiMode> for ManagedTuiFrontend {
             frame.render_widget(Clear, area);
             let input_width = area.width.saturating_sub(2).max(1) as usize;
             let input_rows = input_visual_rows(input, input_width).max(1) as u16;
-            let panes = split_three_pane_layout(area, input_rows);
+            let panes = split_three_pane_layout(area, input_rows, tabs.len() > 1);
 
+            let labels: Vec<String> = tabs.iter().map(SessionTab::label).collect();
+            let active = tabs.iter().position(|tab| tab.active).unwrap_or(0);
+            render_tab_bar(frame, panes.tabs, &labels, active);
             render_status_line(frame, panes.header, &status);
             render_messages(frame, panes.history, mode.history_lines(), history_scroll);
             render_input(frame, panes.input, input, cursor);
@@ -392,6 +446,10 @@ impl FrontendAdapter<TuiMode> for ManagedTuiFrontend {
                         viewport_rows: panes.history.height.max(1) as usize,
                     },
                 );
+            } else if let Some(prompts) = mode.pending_rewind_overlay() {
+                render_overlay_modal(frame, OverlayModal::Rewind { prompts });
+            } else if let Some(plan) = mode.pending_plan_overlay() {
+                render_overlay_modal(frame, OverlayModal::Plan { plan });
             } else if let Some((tool_name, input_preview, auto_approve_enabled)) =
                 mode.pending_tool_overlay()
             {
@@ -412,20 +470,103 @@ impl FrontendAdapter<TuiMode> for ManagedTuiFrontend {
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+const USAGE: &str =
+    "usage: vex [--record <cassette>] [--replay <cassette> [--replay-match order|hash]]
+       vex audit --help";
+
+/// Map command-line flags onto the environment variables read at client construction.
+fn cassette_env_from_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Vec<(&'static str, String)>> {
+    let mut env = Vec::new();
+    while let Some(arg) = args.next() {
+        let key = match arg.as_str() {
+            "--record" => "VEX_CASSETTE_RECORD",
+            "--replay" => "VEX_CASSETTE_REPLAY",
+            "--replay-match" => "VEX_CASSETTE_MATCH",
+            "-h" | "--help" => bail!("{USAGE}"),
+            other => bail!("unknown argument '{other}'\n{USAGE}"),
+        };
+        let Some(value) = args.next() else {
+            bail!("{arg} needs a value\n{USAGE}");
+        };
+        env.push((key, value));
+    }
+    Ok(env)
+}
+
+/// `vex audit`: print the audit log entries matching the query.
+fn run_audit(args: impl Iterator<Item = String>) -> Result<()> {
+    let command = parse_audit_args(args)?;
+    let Some(path) = command.log.or_else(audit_log_path) else {
+        bail!("no audit log: VEX_AUDIT_LOG is off; pass --log <path>");
+    };
+    let entries = read_audit_log(&path)?;
+    let mut out = std::io::stdout().lock();
+    for entry in command.query.apply(&entries) {
+        let line = if command.json {
+            serde_json::to_string(entry)?
+        } else {
+            format_audit_entry(entry)
+        };
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "audit") {
+        return run_audit(args.skip(1));
+    }
+    // Applied before the Tokio runtime starts so no other thread is reading
+    // the environment yet.
+    for (key, value) in cassette_env_from_args(args)? {
+        std::env::set_var(key, value);
+    }
+    tokio::runtime::Runtime::new()?.block_on(run())
+}
+
+async fn run() -> Result<()> {
     let config = Config::load()?;
     config.validate()?;
 
-    let (mut runtime, mut ctx) = build_runtime(config)?;
+    let policy = DefaultRuntimeCorePolicy::load()?;
+
+    let mut runtime = build_runtime(config, Arc::new(policy))?;
     let mut frontend = ManagedTuiFrontend::new()?;
-    runtime.run(&mut frontend, &mut ctx).await;
+    runtime.run(&mut frontend).await;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::looks_like_terminal_transcript;
+    use super::{cassette_env_from_args, looks_like_terminal_transcript};
+
+    fn args(values: &[&str]) -> impl Iterator<Item = String> {
+        values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn cassette_flags_map_to_environment() {
+        let env =
+            cassette_env_from_args(args(&["--replay", "bug.jsonl", "--replay-match", "hash"]))
+                .expect("flags should parse");
+        assert_eq!(
+            env,
+            vec![
+                ("VEX_CASSETTE_REPLAY", "bug.jsonl".to_string()),
+                ("VEX_CASSETTE_MATCH", "hash".to_string()),
+            ]
+        );
+        assert!(cassette_env_from_args(args(&["--record"])).is_err());
+        assert!(cassette_env_from_args(args(&["--verbose"])).is_err());
+        assert!(cassette_env_from_args(args(&[])).unwrap().is_empty());
+    }
 
     #[test]
     fn transcript_detection_matches_following_view_dump() {
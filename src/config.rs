@@ -2,6 +2,9 @@ use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::api::{
+    configured_auth_scheme, resolve_api_protocol, AuthScheme, CassetteMode, ModelCatalog,
+};
 use crate::util::is_local_endpoint_url;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,25 +50,18 @@ impl Config {
         }
 
         let local_endpoint = self.is_local_endpoint();
-        if !local_endpoint && self.api_key.is_none() {
+        let sends_credentials = configured_auth_scheme()? != Some(AuthScheme::None);
+        let replaying = matches!(CassetteMode::from_env()?, Some(CassetteMode::Replay { .. }));
+        if !local_endpoint && sends_credentials && !replaying && self.api_key.is_none() {
             bail!(
                 "ANTHROPIC_API_KEY must be set for non-local endpoints (url: '{}')",
                 self.api_url
             );
         }
 
-        if !local_endpoint && self.model.starts_with("local/") {
-            bail!("Local models are only allowed for localhost endpoints");
-        }
-
-        if !local_endpoint && !self.model.starts_with("claude-") {
-            bail!(
-                "Invalid model name: '{}'. Expected a model starting with 'claude-'",
-                self.model
-            );
-        }
-
-        Ok(())
+        let catalog = ModelCatalog::load()?;
+        let protocol = resolve_api_protocol(&self.api_url, &catalog, &self.model);
+        catalog.validate_model(&self.api_url, protocol, &self.model)
     }
 
     fn is_local_endpoint(&self) -> bool {
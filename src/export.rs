@@ -0,0 +1,529 @@
+use crate::edit_diff::DEFAULT_EDIT_DIFF_CONTEXT_LINES;
+use crate::state::{SessionTranscript, TranscriptEntry, TurnTiming};
+use crate::tool_preview::preview_edit_file_input;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "html" | "htm" => Some(ExportFormat::Html),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Arguments of `/export [md|html|json] [path]`. With only a path, the
+/// format follows its extension; with neither, Markdown.
+pub fn parse_export_args(args: &str) -> Result<(ExportFormat, Option<PathBuf>)> {
+    let mut parts = args.split_whitespace();
+    let (format, path) = match (parts.next(), parts.next()) {
+        (None, _) => (None, None),
+        (Some(first), second) => match (ExportFormat::parse(first), second) {
+            (Some(format), path) => (Some(format), path),
+            (None, None) => (None, Some(first)),
+            (None, Some(_)) => bail!("unknown export format '{first}'; use md, html or json"),
+        },
+    };
+    if parts.next().is_some() {
+        bail!("usage: /export [md|html|json] [path]");
+    }
+    let path = path.map(PathBuf::from);
+    let format = format
+        .or_else(|| {
+            path.as_ref()
+                .and_then(|path| path.extension())
+                .and_then(|extension| ExportFormat::parse(&extension.to_string_lossy()))
+        })
+        .unwrap_or(ExportFormat::Markdown);
+    Ok((format, path))
+}
+
+/// `vex-session-20261017-142501.md`, in UTC.
+pub fn default_export_path(format: ExportFormat, now: SystemTime) -> PathBuf {
+    let stamp = format_timestamp(unix_ms(now))
+        .replace(['-', ':'], "")
+        .replace(' ', "-")
+        .trim_end_matches("-UTC")
+        .to_string();
+    PathBuf::from(format!("vex-session-{stamp}.{}", format.extension()))
+}
+
+pub fn render_transcript(
+    transcript: &SessionTranscript,
+    format: ExportFormat,
+    exported_at: SystemTime,
+) -> Result<String> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(transcript, exported_at)),
+        ExportFormat::Html => Ok(render_html(transcript, exported_at)),
+        ExportFormat::Json => {
+            #[derive(Serialize)]
+            struct JsonExport<'a> {
+                exported_at_unix_ms: u64,
+                #[serde(flatten)]
+                transcript: &'a SessionTranscript,
+            }
+            let mut json = serde_json::to_string_pretty(&JsonExport {
+                exported_at_unix_ms: unix_ms(exported_at),
+                transcript,
+            })?;
+            json.push('\n');
+            Ok(json)
+        }
+    }
+}
+
+fn render_markdown(transcript: &SessionTranscript, exported_at: SystemTime) -> String {
+    let mut out = String::from("# vex session\n\n");
+    out.push_str(&format!("- Model: `{}`\n", transcript.model));
+    out.push_str(&format!(
+        "- Exported: {}\n",
+        format_timestamp(unix_ms(exported_at))
+    ));
+    out.push_str(&format!("- Turns: {}\n", transcript.user_turn_count()));
+
+    for entry in transcript.entries() {
+        match entry {
+            TranscriptEntry::Prompt {
+                number,
+                text,
+                timing,
+            } => {
+                out.push_str(&format!("\n## Turn {number}\n\n"));
+                if let Some(timing) = timing {
+                    out.push_str(&format!("_{}_\n\n", timing_line(timing)));
+                }
+                out.push_str("**User**\n\n");
+                out.push_str(&fenced("text", &text));
+            }
+            TranscriptEntry::Summary(summary) => {
+                out.push_str("\n## Summary of earlier turns\n\n");
+                out.push_str(summary.trim());
+                out.push('\n');
+            }
+            TranscriptEntry::AssistantText(text) => {
+                out.push_str("\n**Assistant**\n\n");
+                out.push_str(text.trim());
+                out.push('\n');
+            }
+            TranscriptEntry::Reasoning(text) => {
+                out.push_str("\n<details><summary>Reasoning</summary>\n\n");
+                out.push_str(text.trim());
+                out.push_str("\n\n</details>\n");
+            }
+            TranscriptEntry::ToolCall { name, input } => {
+                out.push_str(&format!("\n**Tool call** `{name}`\n\n"));
+                match edit_diff(name, input) {
+                    Some(diff) => out.push_str(&fenced("diff", &diff)),
+                    None => out.push_str(&fenced("json", &pretty_json(input))),
+                }
+            }
+            TranscriptEntry::ToolResult {
+                name,
+                content,
+                is_error,
+            } => {
+                out.push_str(&format!(
+                    "\n<details><summary>{}</summary>\n\n",
+                    escape_html(&result_summary(name, content, is_error))
+                ));
+                out.push_str(&fenced("text", content));
+                out.push_str("\n</details>\n");
+            }
+            TranscriptEntry::Image { media_type } => {
+                out.push_str(&format!("\n_[image attachment: {media_type}]_\n"));
+            }
+        }
+    }
+    out
+}
+
+const HTML_STYLE: &str = "\
+body{font-family:system-ui,sans-serif;max-width:60rem;margin:2rem auto;padding:0 1rem;color:#1f2328}\
+pre{background:#f6f8fa;padding:.75rem;overflow-x:auto;white-space:pre-wrap}\
+.meta{color:#656d76;font-size:.9em}.user pre{background:#ddf4ff}\
+.assistant{white-space:pre-wrap}.error summary{color:#cf222e}\
+.add{color:#116329;background:#dafbe1}.del{color:#82071e;background:#ffebe9}\
+details{margin:.5rem 0}summary{cursor:pointer}";
+
+fn render_html(transcript: &SessionTranscript, exported_at: SystemTime) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n");
+    out.push_str("<meta charset=\"utf-8\">\n<title>vex session</title>\n");
+    out.push_str(&format!("<style>{HTML_STYLE}</style>\n</head>\n<body>\n"));
+    out.push_str("<h1>vex session</h1>\n<ul class=\"meta\">\n");
+    out.push_str(&format!(
+        "<li>Model: <code>{}</code></li>\n",
+        escape_html(&transcript.model)
+    ));
+    out.push_str(&format!(
+        "<li>Exported: {}</li>\n",
+        format_timestamp(unix_ms(exported_at))
+    ));
+    out.push_str(&format!(
+        "<li>Turns: {}</li>\n</ul>\n",
+        transcript.user_turn_count()
+    ));
+
+    for entry in transcript.entries() {
+        match entry {
+            TranscriptEntry::Prompt {
+                number,
+                text,
+                timing,
+            } => {
+                out.push_str(&format!("<h2 id=\"turn-{number}\">Turn {number}</h2>\n"));
+                if let Some(timing) = timing {
+                    out.push_str(&format!(
+                        "<p class=\"meta\">{}</p>\n",
+                        escape_html(&timing_line(timing))
+                    ));
+                }
+                out.push_str(&format!(
+                    "<div class=\"user\"><strong>User</strong><pre>{}</pre></div>\n",
+                    escape_html(&text)
+                ));
+            }
+            TranscriptEntry::Summary(summary) => {
+                out.push_str(&format!(
+                    "<h2>Summary of earlier turns</h2>\n<pre>{}</pre>\n",
+                    escape_html(summary.trim())
+                ));
+            }
+            TranscriptEntry::AssistantText(text) => {
+                out.push_str(&format!(
+                    "<p><strong>Assistant</strong></p>\n<div class=\"assistant\">{}</div>\n",
+                    escape_html(text.trim())
+                ));
+            }
+            TranscriptEntry::Reasoning(text) => {
+                out.push_str(&format!(
+                    "<details><summary>Reasoning</summary><pre>{}</pre></details>\n",
+                    escape_html(text.trim())
+                ));
+            }
+            TranscriptEntry::ToolCall { name, input } => {
+                out.push_str(&format!(
+                    "<p><strong>Tool call</strong> <code>{}</code></p>\n",
+                    escape_html(name)
+                ));
+                match edit_diff(name, input) {
+                    Some(diff) => out.push_str(&html_diff(&diff)),
+                    None => out.push_str(&format!(
+                        "<pre>{}</pre>\n",
+                        escape_html(&pretty_json(input))
+                    )),
+                }
+            }
+            TranscriptEntry::ToolResult {
+                name,
+                content,
+                is_error,
+            } => {
+                let class = if is_error { " class=\"error\"" } else { "" };
+                out.push_str(&format!(
+                    "<details{class}><summary>{}</summary><pre>{}</pre></details>\n",
+                    escape_html(&result_summary(name, content, is_error)),
+                    escape_html(content)
+                ));
+            }
+            TranscriptEntry::Image { media_type } => {
+                out.push_str(&format!(
+                    "<p class=\"meta\">[image attachment: {}]</p>\n",
+                    escape_html(media_type)
+                ));
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn edit_diff(name: &str, input: &serde_json::Value) -> Option<String> {
+    (name == "edit_file")
+        .then(|| preview_edit_file_input(input, "", "", DEFAULT_EDIT_DIFF_CONTEXT_LINES))
+}
+
+/// Diff lines read `12 + text`; colour them by the marker after the number.
+fn html_diff(diff: &str) -> String {
+    let mut out = String::from("<pre>");
+    for line in diff.lines() {
+        let marker = line
+            .split_once(' ')
+            .and_then(|(number, rest)| {
+                number
+                    .chars()
+                    .all(|ch| ch.is_ascii_digit())
+                    .then(|| rest.chars().next())
+            })
+            .flatten();
+        let escaped = escape_html(line);
+        match marker {
+            Some('+') => out.push_str(&format!("<span class=\"add\">{escaped}</span>\n")),
+            Some('-') => out.push_str(&format!("<span class=\"del\">{escaped}</span>\n")),
+            _ => {
+                out.push_str(&escaped);
+                out.push('\n');
+            }
+        }
+    }
+    out.push_str("</pre>\n");
+    out
+}
+
+fn result_summary(name: Option<&str>, content: &str, is_error: bool) -> String {
+    let kind = if is_error {
+        "Tool error"
+    } else {
+        "Tool result"
+    };
+    let lines = content.lines().count();
+    match name {
+        Some(name) => format!("{kind}: {name} ({lines} lines)"),
+        None => format!("{kind} ({lines} lines)"),
+    }
+}
+
+fn timing_line(timing: &TurnTiming) -> String {
+    format!(
+        "{} · {} · {} in / {} out tokens · {}",
+        format_timestamp(timing.started_at_unix_ms),
+        format_duration(timing.duration()),
+        timing.usage.input_tokens
+            + timing.usage.cache_creation_input_tokens
+            + timing.usage.cache_read_input_tokens,
+        timing.usage.output_tokens,
+        timing.model
+    )
+}
+
+/// A code fence longer than any backtick run in `content`.
+fn fenced(language: &str, content: &str) -> String {
+    let longest_run = content
+        .split(|ch| ch != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{fence}{language}\n{}\n{fence}\n", content.trim_end())
+}
+
+fn pretty_json(value: &serde_json::Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+pub(crate) fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{seconds:.1} s")
+    } else {
+        let whole = duration.as_secs();
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    }
+}
+
+/// `2026-10-18 14:25:01 UTC`.
+pub(crate) fn format_timestamp(unix_ms: u64) -> String {
+    let seconds = unix_ms / 1000;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let of_day = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        of_day / 3600,
+        of_day % 3600 / 60,
+        of_day % 60
+    )
+}
+
+/// Gregorian date for days since 1970-01-01 (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ApiMessage, Content, ContentBlock, Usage};
+    use serde_json::json;
+
+    fn sample_transcript() -> SessionTranscript {
+        SessionTranscript {
+            model: "claude-sonnet-4-5".to_string(),
+            messages: vec![
+                ApiMessage {
+                    role: "user".to_string(),
+                    content: Content::Text("rename <Foo>".to_string()),
+                },
+                ApiMessage {
+                    role: "assistant".to_string(),
+                    content: Content::Blocks(vec![
+                        ContentBlock::Text {
+                            text: "Editing.".to_string(),
+                        },
+                        ContentBlock::ToolUse {
+                            id: "t1".to_string(),
+                            name: "edit_file".to_string(),
+                            input: json!({
+                                "path": "src/lib.rs",
+                                "old_str": "struct Foo;",
+                                "new_str": "struct Bar;",
+                            }),
+                        },
+                    ]),
+                },
+                ApiMessage {
+                    role: "user".to_string(),
+                    content: Content::Blocks(vec![ContentBlock::ToolResult {
+                        tool_use_id: "t1".to_string(),
+                        content: "edited src/lib.rs\n```".to_string(),
+                        is_error: false,
+                    }]),
+                },
+                ApiMessage {
+                    role: "assistant".to_string(),
+                    content: Content::Text("Done.".to_string()),
+                },
+            ],
+            turn_timings: vec![TurnTiming {
+                turn: 0,
+                started_at_unix_ms: 1_792_247_101_000,
+                duration_ms: 12_400,
+                model: "claude-sonnet-4-5".to_string(),
+                usage: Usage {
+                    input_tokens: 1_200,
+                    output_tokens: 80,
+                    ..Usage::default()
+                },
+            }],
+        }
+    }
+
+    fn exported_at() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_792_247_200)
+    }
+
+    #[test]
+    fn test_parse_export_args_infers_format_from_extension() {
+        assert_eq!(
+            parse_export_args("").unwrap(),
+            (ExportFormat::Markdown, None)
+        );
+        assert_eq!(
+            parse_export_args("html").unwrap(),
+            (ExportFormat::Html, None)
+        );
+        assert_eq!(
+            parse_export_args("json out/session.txt").unwrap(),
+            (ExportFormat::Json, Some(PathBuf::from("out/session.txt")))
+        );
+        assert_eq!(
+            parse_export_args("notes/pr-42.html").unwrap(),
+            (ExportFormat::Html, Some(PathBuf::from("notes/pr-42.html")))
+        );
+        assert!(parse_export_args("pdf out.pdf").is_err());
+        assert!(parse_export_args("md a.md b.md").is_err());
+    }
+
+    #[test]
+    fn test_default_export_path_uses_utc_stamp() {
+        assert_eq!(
+            default_export_path(ExportFormat::Html, exported_at()),
+            PathBuf::from("vex-session-20261017-142640.html")
+        );
+    }
+
+    #[test]
+    fn test_markdown_export_has_turns_timing_diffs_and_results() {
+        let markdown =
+            render_transcript(&sample_transcript(), ExportFormat::Markdown, exported_at()).unwrap();
+        assert!(markdown.contains("- Turns: 1\n"), "{markdown}");
+        assert!(markdown.contains(
+            "_2026-10-17 14:25:01 UTC · 12.4 s · 1200 in / 80 out tokens · claude-sonnet-4-5_"
+        ));
+        assert!(markdown.contains("```text\nrename <Foo>\n```"));
+        assert!(markdown.contains("**Tool call** `edit_file`\n\n```diff\npath: src/lib.rs\n"));
+        assert!(markdown.contains("1 - struct Foo;\n1 + struct Bar;\n"));
+        // The result holds a fence of its own, so the wrapper needs a longer one.
+        assert!(markdown.contains(
+            "<summary>Tool result: edit_file (2 lines)</summary>\n\n````text\nedited src/lib.rs\n```\n````"
+        ));
+        assert!(markdown.ends_with("**Assistant**\n\nDone.\n"));
+    }
+
+    #[test]
+    fn test_html_export_escapes_text_and_collapses_results() {
+        let html =
+            render_transcript(&sample_transcript(), ExportFormat::Html, exported_at()).unwrap();
+        assert!(html.contains("<pre>rename &lt;Foo&gt;</pre>"));
+        assert!(html.contains("<span class=\"del\">1 - struct Foo;</span>"));
+        assert!(html.contains("<span class=\"add\">1 + struct Bar;</span>"));
+        assert!(html.contains(
+            "<details><summary>Tool result: edit_file (2 lines)</summary><pre>edited src/lib.rs\n```</pre></details>"
+        ));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn test_json_export_keeps_messages_and_timings() {
+        let json =
+            render_transcript(&sample_transcript(), ExportFormat::Json, exported_at()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["exported_at_unix_ms"], 1_792_247_200_000u64);
+        assert_eq!(value["model"], "claude-sonnet-4-5");
+        assert_eq!(value["messages"].as_array().unwrap().len(), 4);
+        assert_eq!(value["messages"][1]["content"][1]["type"], "tool_use");
+        assert_eq!(value["turn_timings"][0]["duration_ms"], 12_400);
+    }
+}
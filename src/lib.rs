@@ -1,7 +1,10 @@
 pub mod api;
 pub mod app;
+pub mod audit;
 pub mod config;
 pub mod edit_diff;
+pub mod export;
+pub mod mock_server;
 pub mod runtime;
 pub mod state;
 pub mod terminal;
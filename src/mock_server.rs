@@ -0,0 +1,719 @@
+//! Scripted stand-in for a model endpoint, for offline demos and tests.
+//!
+//! Serves the Anthropic Messages and OpenAI Chat Completions streaming
+//! protocols on a local port. Each POST consumes the next turn of a
+//! [`MockScript`]; the turn is rendered for whichever protocol the request
+//! path names. Requests sent without a `tools` array (the text-protocol
+//! fallback used for local endpoints) receive tool calls as tagged text.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const DEFAULT_CHUNK_CHARS: usize = 12;
+const DEFAULT_MODEL: &str = "mock-model";
+const MAX_REQUEST_BYTES: usize = 32 * 1024 * 1024;
+
+/// Ordered model turns served to successive requests.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockScript {
+    #[serde(default)]
+    pub turns: Vec<MockTurn>,
+    /// Start over once every turn has been served instead of answering 500.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+/// One scripted response.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MockTurn {
+    /// Assistant text, streamed in `chunk_chars` pieces.
+    pub text: Option<String>,
+    /// Reply with the last user message instead of fixed text.
+    pub echo: bool,
+    pub tool_calls: Vec<MockToolCall>,
+    /// Answer with an HTTP error instead of a stream.
+    pub error: Option<MockError>,
+    /// `end_turn`, `tool_use` or `max_tokens`; derived from the content when unset.
+    pub stop_reason: Option<String>,
+    pub chunk_chars: Option<usize>,
+    /// Pause before each streamed event.
+    pub delay_ms: u64,
+    /// Drop the connection after this many events, without finishing the stream.
+    pub disconnect_after: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockToolCall {
+    pub name: String,
+    #[serde(default)]
+    pub input: Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockError {
+    pub status: u16,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl MockScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read mock script '{}'", path.display()))?;
+        Self::parse(&raw).with_context(|| format!("invalid mock script '{}'", path.display()))
+    }
+
+    pub fn parse(raw: &str) -> Result<Self> {
+        let script: MockScript = serde_json::from_str(raw)?;
+        if script.turns.is_empty() {
+            bail!("script has no turns");
+        }
+        Ok(script)
+    }
+
+    /// Echo every prompt back; used when no script is given.
+    pub fn echo() -> Self {
+        Self {
+            turns: vec![MockTurn {
+                echo: true,
+                ..MockTurn::default()
+            }],
+            repeat: true,
+        }
+    }
+}
+
+/// Wire protocol selected by the request path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockProtocol {
+    AnthropicMessages,
+    OpenAiChatCompletions,
+}
+
+impl MockProtocol {
+    fn from_path(path: &str) -> Option<Self> {
+        let path = path.split('?').next().unwrap_or(path).trim_end_matches('/');
+        if path.ends_with("/messages") {
+            Some(MockProtocol::AnthropicMessages)
+        } else if path.ends_with("/chat/completions") {
+            Some(MockProtocol::OpenAiChatCompletions)
+        } else {
+            None
+        }
+    }
+}
+
+struct ServerState {
+    script: MockScript,
+    model: String,
+    next_turn: AtomicUsize,
+}
+
+impl ServerState {
+    fn take_turn(&self) -> Option<(usize, &MockTurn)> {
+        let served = self.next_turn.fetch_add(1, Ordering::SeqCst);
+        let turns = &self.script.turns;
+        if self.script.repeat {
+            Some((served, &turns[served % turns.len()]))
+        } else {
+            turns.get(served).map(|turn| (served, turn))
+        }
+    }
+}
+
+pub struct MockServer {
+    listener: TcpListener,
+    script: MockScript,
+    model: String,
+}
+
+impl MockServer {
+    pub async fn bind(addr: &str, script: MockScript) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("cannot bind mock server to '{addr}'"))?;
+        Ok(Self {
+            listener,
+            script,
+            model: DEFAULT_MODEL.to_string(),
+        })
+    }
+
+    /// Model id reported in responses and by `/v1/models`.
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections until the task is dropped.
+    pub async fn serve(self) -> Result<()> {
+        let state = Arc::new(ServerState {
+            script: self.script,
+            model: self.model,
+            next_turn: AtomicUsize::new(0),
+        });
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let state = Arc::clone(&state);
+            tokio::spawn(async move {
+                if let Err(error) = handle_connection(stream, &state).await {
+                    eprintln!("[mock-server] {error:#}");
+                }
+            });
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Result<Option<Request>> {
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("malformed request line '{}'", request_line.trim_end());
+    };
+
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            bail!("connection closed inside request headers");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("invalid content-length '{}'", value.trim()))?;
+            }
+        }
+    }
+    if content_length > MAX_REQUEST_BYTES {
+        bail!("request body of {content_length} bytes is too large");
+    }
+
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await?;
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        body,
+    }))
+}
+
+async fn handle_connection(stream: TcpStream, state: &ServerState) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    let stream = stream.get_mut();
+
+    if request.method == "GET" && request.path.trim_end_matches('/').ends_with("/models") {
+        let body = json!({
+            "object": "list",
+            "data": [{ "id": state.model, "object": "model" }],
+        });
+        return write_json(stream, 200, &body).await;
+    }
+
+    let protocol = match MockProtocol::from_path(&request.path) {
+        Some(protocol) if request.method == "POST" => protocol,
+        _ => {
+            let body = json!({ "error": { "message": format!("no route for {} {}", request.method, request.path) } });
+            return write_json(stream, 404, &body).await;
+        }
+    };
+    let payload: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+
+    let Some((index, turn)) = state.take_turn() else {
+        let error = MockError {
+            status: 500,
+            message: "mock script exhausted".to_string(),
+        };
+        return write_json(stream, error.status, &error_body(protocol, &error)).await;
+    };
+    if let Some(error) = &turn.error {
+        return write_json(stream, error.status, &error_body(protocol, error)).await;
+    }
+
+    let events = render_events(protocol, turn, &payload, index, &state.model);
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n",
+        )
+        .await?;
+    for (sent, event) in events.iter().enumerate() {
+        if turn.disconnect_after == Some(sent) {
+            // Dropping the socket without the final chunk reads as a broken
+            // transfer on the client side.
+            return Ok(());
+        }
+        if turn.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(turn.delay_ms)).await;
+        }
+        let chunk = format!("{:x}\r\n{event}\r\n", event.len());
+        stream.write_all(chunk.as_bytes()).await?;
+        stream.flush().await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn write_json(stream: &mut TcpStream, status: u16, body: &Value) -> Result<()> {
+    let body = body.to_string();
+    let head = format!(
+        "HTTP/1.1 {status} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        reason_phrase(status),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        529 => "Overloaded",
+        _ => "Error",
+    }
+}
+
+fn error_body(protocol: MockProtocol, error: &MockError) -> Value {
+    let kind = match error.status {
+        400 => "invalid_request_error",
+        401 => "authentication_error",
+        429 => "rate_limit_error",
+        529 => "overloaded_error",
+        _ => "api_error",
+    };
+    match protocol {
+        MockProtocol::AnthropicMessages => json!({
+            "type": "error",
+            "error": { "type": kind, "message": error.message },
+        }),
+        MockProtocol::OpenAiChatCompletions => json!({
+            "error": { "type": kind, "message": error.message },
+        }),
+    }
+}
+
+/// SSE frames for one turn, each ending in a blank line.
+pub fn render_events(
+    protocol: MockProtocol,
+    turn: &MockTurn,
+    request: &Value,
+    index: usize,
+    model: &str,
+) -> Vec<String> {
+    let structured_tools = request.get("tools").is_some();
+    let mut text = if turn.echo {
+        format!("mock reply: {}", last_user_text(request))
+    } else {
+        turn.text.clone().unwrap_or_default()
+    };
+    let mut tool_calls = turn.tool_calls.as_slice();
+    if !structured_tools && !tool_calls.is_empty() {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&render_text_protocol_calls(tool_calls));
+        tool_calls = &[];
+    }
+    let stop_reason = turn.stop_reason.clone().unwrap_or_else(|| {
+        if tool_calls.is_empty() {
+            "end_turn".to_string()
+        } else {
+            "tool_use".to_string()
+        }
+    });
+    let chunk_chars = turn.chunk_chars.unwrap_or(DEFAULT_CHUNK_CHARS).max(1);
+    let reply = MockReply {
+        id: index + 1,
+        model,
+        input_tokens: (request.to_string().len() / 4) as u64,
+        text_chunks: split_chars(&text, chunk_chars),
+        tool_calls,
+        chunk_chars,
+        stop_reason: &stop_reason,
+    };
+    match protocol {
+        MockProtocol::AnthropicMessages => reply.anthropic_events(),
+        MockProtocol::OpenAiChatCompletions => reply.openai_events(),
+    }
+}
+
+struct MockReply<'a> {
+    id: usize,
+    model: &'a str,
+    input_tokens: u64,
+    text_chunks: Vec<String>,
+    tool_calls: &'a [MockToolCall],
+    chunk_chars: usize,
+    stop_reason: &'a str,
+}
+
+impl MockReply<'_> {
+    fn output_tokens(&self) -> u64 {
+        let text: usize = self.text_chunks.iter().map(String::len).sum();
+        let tools: usize = self
+            .tool_calls
+            .iter()
+            .map(|call| call.name.len() + call.input.to_string().len())
+            .sum();
+        ((text + tools) / 4).max(1) as u64
+    }
+
+    fn anthropic_events(&self) -> Vec<String> {
+        let event = |name: &str, data: Value| format!("event: {name}\ndata: {data}\n\n");
+        let mut events = vec![event(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": format!("msg_mock_{}", self.id),
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.model,
+                    "content": [],
+                    "stop_reason": null,
+                    "usage": { "input_tokens": self.input_tokens, "output_tokens": 0 },
+                },
+            }),
+        )];
+
+        let mut block = 0;
+        if !self.text_chunks.is_empty() {
+            events.push(event(
+                "content_block_start",
+                json!({ "type": "content_block_start", "index": block, "content_block": { "type": "text", "text": "" } }),
+            ));
+            for chunk in &self.text_chunks {
+                events.push(event(
+                    "content_block_delta",
+                    json!({ "type": "content_block_delta", "index": block, "delta": { "type": "text_delta", "text": chunk } }),
+                ));
+            }
+            events.push(event(
+                "content_block_stop",
+                json!({ "type": "content_block_stop", "index": block }),
+            ));
+            block += 1;
+        }
+        for (call_index, call) in self.tool_calls.iter().enumerate() {
+            events.push(event(
+                "content_block_start",
+                json!({
+                    "type": "content_block_start",
+                    "index": block,
+                    "content_block": {
+                        "type": "tool_use",
+                        "id": format!("toolu_mock_{}_{call_index}", self.id),
+                        "name": call.name,
+                        "input": {},
+                    },
+                }),
+            ));
+            for chunk in split_chars(&tool_arguments(call), self.chunk_chars) {
+                events.push(event(
+                    "content_block_delta",
+                    json!({ "type": "content_block_delta", "index": block, "delta": { "type": "input_json_delta", "partial_json": chunk } }),
+                ));
+            }
+            events.push(event(
+                "content_block_stop",
+                json!({ "type": "content_block_stop", "index": block }),
+            ));
+            block += 1;
+        }
+
+        events.push(event(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": self.stop_reason },
+                "usage": { "output_tokens": self.output_tokens() },
+            }),
+        ));
+        events.push(event("message_stop", json!({ "type": "message_stop" })));
+        events
+    }
+
+    fn openai_events(&self) -> Vec<String> {
+        let id = format!("chatcmpl-mock-{}", self.id);
+        let chunk = |choices: Value| {
+            let data = json!({
+                "id": id,
+                "object": "chat.completion.chunk",
+                "model": self.model,
+                "choices": choices,
+            });
+            format!("data: {data}\n\n")
+        };
+        let mut events = vec![chunk(json!([
+            { "index": 0, "delta": { "role": "assistant", "content": "" }, "finish_reason": null }
+        ]))];
+
+        for text in &self.text_chunks {
+            events.push(chunk(json!([
+                { "index": 0, "delta": { "content": text }, "finish_reason": null }
+            ])));
+        }
+        for (call_index, call) in self.tool_calls.iter().enumerate() {
+            events.push(chunk(json!([{
+                "index": 0,
+                "delta": { "tool_calls": [{
+                    "index": call_index,
+                    "id": format!("call_mock_{}_{call_index}", self.id),
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": "" },
+                }] },
+                "finish_reason": null,
+            }])));
+            for arguments in split_chars(&tool_arguments(call), self.chunk_chars) {
+                events.push(chunk(json!([{
+                    "index": 0,
+                    "delta": { "tool_calls": [{ "index": call_index, "function": { "arguments": arguments } }] },
+                    "finish_reason": null,
+                }])));
+            }
+        }
+
+        let finish_reason = match self.stop_reason {
+            "max_tokens" => "length",
+            "tool_use" => "tool_calls",
+            _ => "stop",
+        };
+        events.push(chunk(json!([
+            { "index": 0, "delta": {}, "finish_reason": finish_reason }
+        ])));
+        let usage = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "model": self.model,
+            "choices": [],
+            "usage": {
+                "prompt_tokens": self.input_tokens,
+                "completion_tokens": self.output_tokens(),
+                "total_tokens": self.input_tokens + self.output_tokens(),
+            },
+        });
+        events.push(format!("data: {usage}\n\n"));
+        events.push("data: [DONE]\n\n".to_string());
+        events
+    }
+}
+
+fn tool_arguments(call: &MockToolCall) -> String {
+    match &call.input {
+        Value::Null => "{}".to_string(),
+        input => input.to_string(),
+    }
+}
+
+/// Tagged tool syntax the conversation layer parses when native tool calls are off.
+fn render_text_protocol_calls(calls: &[MockToolCall]) -> String {
+    let mut out = String::new();
+    for call in calls {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("<function={}>\n", call.name));
+        if let Some(input) = call.input.as_object() {
+            for (key, value) in input {
+                let value = match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                out.push_str(&format!("<parameter={key}>\n{value}\n</parameter>\n"));
+            }
+        }
+        out.push_str("</function>");
+    }
+    out
+}
+
+fn last_user_text(request: &Value) -> String {
+    let Some(message) = request
+        .get("messages")
+        .and_then(Value::as_array)
+        .and_then(|messages| {
+            messages
+                .iter()
+                .rev()
+                .find(|message| message.get("role").and_then(Value::as_str) == Some("user"))
+        })
+    else {
+        return String::new();
+    };
+    match message.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|block| block.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn split_chars(text: &str, chunk_chars: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(chunk_chars)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::stream::StreamParser;
+    use crate::types::{ContentBlock, StreamEvent};
+
+    fn parse(events: &[String]) -> Vec<StreamEvent> {
+        let mut parser = StreamParser::new();
+        events
+            .iter()
+            .flat_map(|event| parser.process(event.as_bytes()).unwrap())
+            .collect()
+    }
+
+    fn tool_turn() -> MockTurn {
+        MockTurn {
+            text: Some("Reading it.".to_string()),
+            tool_calls: vec![MockToolCall {
+                name: "read_file".to_string(),
+                input: json!({ "path": "src/main.rs" }),
+            }],
+            ..MockTurn::default()
+        }
+    }
+
+    fn tool_starts(events: &[StreamEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ContentBlockStart {
+                    content_block: ContentBlock::ToolUse { name, .. },
+                    ..
+                } => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_structured_tool_turn_renders_for_both_protocols() {
+        let request = json!({ "messages": [], "tools": [] });
+        for protocol in [
+            MockProtocol::AnthropicMessages,
+            MockProtocol::OpenAiChatCompletions,
+        ] {
+            let events = parse(&render_events(protocol, &tool_turn(), &request, 0, "mock"));
+            assert_eq!(tool_starts(&events), vec!["read_file"], "{protocol:?}");
+            let stop_reasons: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    StreamEvent::MessageDelta { delta, .. } => delta.stop_reason.clone(),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(stop_reasons, vec!["tool_use"], "{protocol:?}");
+        }
+    }
+
+    #[test]
+    fn test_tool_calls_fall_back_to_tagged_text_without_tools() {
+        let request = json!({ "messages": [] });
+        let frames = render_events(
+            MockProtocol::AnthropicMessages,
+            &tool_turn(),
+            &request,
+            0,
+            "mock",
+        );
+        let events = parse(&frames);
+        assert!(tool_starts(&events).is_empty());
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ContentBlockDelta { delta, .. } => delta.text.clone(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            text,
+            "Reading it.\n<function=read_file>\n<parameter=path>\nsrc/main.rs\n</parameter>\n</function>"
+        );
+    }
+
+    #[test]
+    fn test_echo_turn_repeats_last_user_message() {
+        let request = json!({ "messages": [
+            { "role": "user", "content": "first" },
+            { "role": "assistant", "content": "ok" },
+            { "role": "user", "content": [{ "type": "text", "text": "héllo" }] },
+        ] });
+        let events = parse(&render_events(
+            MockProtocol::OpenAiChatCompletions,
+            &MockScript::echo().turns[0],
+            &request,
+            0,
+            "mock",
+        ));
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ContentBlockDelta { delta, .. } => delta.text.clone(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "mock reply: héllo");
+    }
+
+    #[test]
+    fn test_script_parse_rejects_empty_scripts() {
+        assert!(MockScript::parse(r#"{"turns": []}"#).is_err());
+        let script = MockScript::parse(
+            r#"{"turns": [{"error": {"status": 529, "message": "busy"}}, {"text": "hi", "delay_ms": 5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(script.turns[0].error.as_ref().unwrap().status, 529);
+        assert_eq!(script.turns[1].delay_ms, 5);
+        assert!(!script.repeat);
+    }
+}
@@ -3,6 +3,7 @@ pub mod frontend;
 pub mod r#loop;
 pub mod mode;
 pub mod policy;
+pub mod session;
 pub mod update;
 
 pub use update::UiUpdate;
@@ -49,7 +50,8 @@ mod tests {
             fn poll_user_input(&mut self, _mode: &DummyMode) -> Option<UserInputEvent> {
                 None
             }
-            fn render(&mut self, _mode: &DummyMode) {}
+            fn render(&mut self, _mode: &DummyMode, _tabs: &[crate::runtime::session::SessionTab]) {
+            }
             fn should_quit(&self) -> bool {
                 true
             }
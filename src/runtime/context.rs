@@ -1,13 +1,33 @@
+use crate::api::{ApiClient, ApiError};
+use crate::export::{default_export_path, render_transcript, ExportFormat};
+use crate::runtime::policy::{strip_think_tags, RuntimeCorePolicy};
 use crate::runtime::UiUpdate;
-use crate::state::{ConversationManager, ConversationStreamUpdate, StreamBlock};
+use crate::state::{
+    ConversationBranch, ConversationManager, ConversationStreamUpdate, ProjectInstructions,
+    StreamBlock,
+};
+use crate::tools::ImageAttachment;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::{mpsc, Mutex, MutexGuard};
 use tokio_util::sync::CancellationToken;
 
+/// Work run against the conversation as one cancellable turn.
+enum TurnJob {
+    Message(String),
+    Compact,
+}
+
 pub struct RuntimeContext {
     conversation: Arc<Mutex<ConversationManager>>,
     update_tx: mpsc::UnboundedSender<UiUpdate>,
     cancel: CancellationToken,
+    /// The conversation's policy, held here so streamed text can be
+    /// sanitized while a turn holds the conversation.
+    policy: Arc<dyn RuntimeCorePolicy>,
+    /// The endpoint's models may open answers with inline `<think>` reasoning.
+    inlines_think_tags: bool,
 }
 
 impl RuntimeContext {
@@ -16,18 +36,41 @@ impl RuntimeContext {
         update_tx: mpsc::UnboundedSender<UiUpdate>,
         cancel: CancellationToken,
     ) -> Self {
+        let policy = conversation.policy();
+        let inlines_think_tags = conversation.client().api_protocol().inlines_think_tags();
         Self {
             conversation: Arc::new(Mutex::new(conversation)),
             update_tx,
             cancel,
+            policy,
+            inlines_think_tags,
+        }
+    }
+
+    /// Streamed assistant text as it should be shown: leading inline
+    /// reasoning dropped, then sanitized by the conversation's policy.
+    pub fn display_text(&self, text: &str) -> String {
+        if self.inlines_think_tags {
+            self.policy.sanitize_assistant_text(&strip_think_tags(text))
+        } else {
+            self.policy.sanitize_assistant_text(text)
         }
     }
 
     pub fn start_turn(&mut self, input: String) {
+        self.spawn_turn(TurnJob::Message(input), "start_turn");
+    }
+
+    /// Summarize older turns as a turn of its own, reported like `start_turn`.
+    pub fn start_compaction(&mut self) {
+        self.spawn_turn(TurnJob::Compact, "start_compaction");
+    }
+
+    fn spawn_turn(&mut self, job: TurnJob, caller: &str) {
         if tokio::runtime::Handle::try_current().is_err() {
-            let _ = self.update_tx.send(UiUpdate::Error(
-                "runtime error: start_turn requires active Tokio runtime".to_string(),
-            ));
+            let _ = self.update_tx.send(UiUpdate::Error(format!(
+                "runtime error: {caller} requires active Tokio runtime"
+            )));
             return;
         }
 
@@ -40,7 +83,12 @@ impl RuntimeContext {
 
             let send_handle = tokio::spawn(async move {
                 let mut mgr = conversation.lock().await;
-                mgr.send_message(input, Some(&delta_tx)).await
+                match job {
+                    TurnJob::Message(input) => {
+                        mgr.send_message(input, Some(&delta_tx)).await.map(Some)
+                    }
+                    TurnJob::Compact => mgr.compact(Some(&delta_tx)).await.map(|_| None),
+                }
             });
 
             let mut textual_block_by_index = std::collections::HashMap::<usize, bool>::new();
@@ -62,11 +110,18 @@ impl RuntimeContext {
             }
 
             match send_handle.await {
-                Ok(Ok(_)) => {
+                Ok(Ok(reply)) => {
+                    if let Some(reply) = reply {
+                        let _ = tx.send(UiUpdate::TurnReply(reply));
+                    }
                     let _ = tx.send(UiUpdate::TurnComplete);
                 }
                 Ok(Err(e)) => {
-                    let _ = tx.send(UiUpdate::Error(e.to_string()));
+                    let update = match e.downcast_ref::<ApiError>() {
+                        Some(error) => UiUpdate::ApiError(error.clone()),
+                        None => UiUpdate::Error(e.to_string()),
+                    };
+                    let _ = tx.send(update);
                 }
                 Err(e) => {
                     if e.is_cancelled() {
@@ -92,11 +147,129 @@ impl RuntimeContext {
         self.conversation.lock().await.messages_for_api().len()
     }
 
+    #[cfg(test)]
+    pub fn test_push_exchange(&self, prompt: &str, reply: &str) {
+        self.conversation
+            .try_lock()
+            .expect("conversation idle")
+            .test_push_exchange(prompt, reply);
+    }
+
     #[cfg(test)]
     pub fn test_root_cancelled(&self) -> bool {
         self.cancel.is_cancelled()
     }
 
+    /// The conversation, unless a turn holds it. Everything that reads or
+    /// changes the session between turns goes through here, so it fails with
+    /// "turn in progress" rather than waiting for the turn to finish.
+    fn idle_conversation(&self) -> anyhow::Result<MutexGuard<'_, ConversationManager>> {
+        self.conversation
+            .try_lock()
+            .map_err(|_| anyhow::anyhow!("turn in progress"))
+    }
+
+    /// Stage an image for the next turn.
+    pub fn attach_image(&self, path: &str) -> anyhow::Result<ImageAttachment> {
+        self.idle_conversation()?.attach_image(path)
+    }
+
+    /// Snapshot of the active client for model listings.
+    pub fn client(&self) -> anyhow::Result<Arc<ApiClient>> {
+        Ok(self.idle_conversation()?.client())
+    }
+
+    /// Switch the model for subsequent turns.
+    pub fn set_model(&self, model: &str) -> anyhow::Result<()> {
+        self.idle_conversation()?.set_model(model)
+    }
+
+    /// Reload project instruction files.
+    pub fn reload_instructions(&self) -> anyhow::Result<ProjectInstructions> {
+        Ok(self.idle_conversation()?.reload_instructions())
+    }
+
+    /// Prompts of the user turns in the model history, oldest first.
+    pub fn user_turns(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.idle_conversation()?.user_turns())
+    }
+
+    /// Drop the newest `turns` user turns.
+    pub fn rewind(&self, turns: usize) -> anyhow::Result<String> {
+        self.idle_conversation()?.rewind(turns)
+    }
+
+    /// Copy the active conversation line.
+    pub fn fork(&self) -> anyhow::Result<ConversationBranch> {
+        Ok(self.idle_conversation()?.fork())
+    }
+
+    /// Activate `branch` and return the line it replaces.
+    pub fn switch_branch(&self, branch: ConversationBranch) -> anyhow::Result<ConversationBranch> {
+        Ok(self.idle_conversation()?.switch_branch(branch))
+    }
+
+    /// Turn plan mode on or off.
+    pub fn set_plan_mode(&self, enabled: bool) -> anyhow::Result<()> {
+        let mut conversation = self.idle_conversation()?;
+        conversation.set_plan_mode(enabled);
+        Ok(())
+    }
+
+    /// Write the whole session to `path`, or a timestamped file in the
+    /// working directory. Returns the path and the number of user turns.
+    pub fn export(
+        &self,
+        format: ExportFormat,
+        path: Option<PathBuf>,
+    ) -> anyhow::Result<(PathBuf, usize)> {
+        let transcript = self.idle_conversation()?.transcript();
+        let now = SystemTime::now();
+        let path = path.unwrap_or_else(|| default_export_path(format, now));
+        let rendered = render_transcript(&transcript, format, now)?;
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, rendered)
+            .map_err(|error| anyhow::anyhow!("cannot write {}: {error}", path.display()))?;
+        Ok((path, transcript.user_turn_count()))
+    }
+
+    /// Query the endpoint's model list in the background. Reported models are
+    /// added to the catalog so `/model <id>` accepts them.
+    pub fn fetch_endpoint_models(&self) {
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(error) => {
+                let _ = self
+                    .update_tx
+                    .send(UiUpdate::EndpointModels(Err(error.to_string())));
+                return;
+            }
+        };
+        if tokio::runtime::Handle::try_current().is_err() {
+            let _ = self.update_tx.send(UiUpdate::EndpointModels(Err(
+                "runtime error: model fetch requires active Tokio runtime".to_string(),
+            )));
+            return;
+        }
+
+        let tx = self.update_tx.clone();
+        let conversation = Arc::clone(&self.conversation);
+        tokio::spawn(async move {
+            let result = client.fetch_endpoint_models().await;
+            if let Ok(ids) = &result {
+                conversation.lock().await.add_discovered_models(ids);
+            }
+            let _ = tx.send(UiUpdate::EndpointModels(
+                result.map_err(|error| error.to_string()),
+            ));
+        });
+    }
+
     pub fn cancel_turn(&mut self) {
         self.cancel.cancel();
         self.cancel = CancellationToken::new();
@@ -141,6 +314,39 @@ fn forward_conversation_update(
         ConversationStreamUpdate::ToolApprovalRequest(request) => {
             let _ = tx.send(UiUpdate::ToolApprovalRequest(request));
         }
+        ConversationStreamUpdate::Usage(report) => {
+            let _ = tx.send(UiUpdate::Usage(report));
+        }
+        ConversationStreamUpdate::OutputTruncated(notice) => {
+            let _ = tx.send(UiUpdate::OutputTruncated(notice));
+        }
+        ConversationStreamUpdate::AwaitingFirstToken => {
+            let _ = tx.send(UiUpdate::AwaitingFirstToken);
+        }
+        ConversationStreamUpdate::FirstTokenReceived => {
+            let _ = tx.send(UiUpdate::FirstTokenReceived);
+        }
+        ConversationStreamUpdate::StreamStalled(notice) => {
+            let _ = tx.send(UiUpdate::StreamStalled(notice));
+        }
+        ConversationStreamUpdate::ContextPruned { removed_messages } => {
+            let _ = tx.send(UiUpdate::ContextPruned { removed_messages });
+        }
+        ConversationStreamUpdate::Compacted(notice) => {
+            let _ = tx.send(UiUpdate::Compacted(notice));
+        }
+        ConversationStreamUpdate::CompactionFailed(error) => {
+            let _ = tx.send(UiUpdate::CompactionFailed(error));
+        }
+        ConversationStreamUpdate::Delegate(update) => {
+            let _ = tx.send(UiUpdate::Delegate(update));
+        }
+        ConversationStreamUpdate::Hook(run) => {
+            let _ = tx.send(UiUpdate::Hook(run));
+        }
+        ConversationStreamUpdate::AuditFailed(error) => {
+            let _ = tx.send(UiUpdate::AuditFailed(error));
+        }
     }
 }
 
@@ -149,7 +355,9 @@ mod tests {
     use super::{forward_conversation_update, RuntimeContext};
     use crate::api::{mock_client::MockApiClient, ApiClient};
     use crate::runtime::UiUpdate;
-    use crate::state::{ConversationManager, ConversationStreamUpdate, StreamBlock};
+    use crate::state::{
+        ApprovalDecision, ConversationManager, ConversationStreamUpdate, StreamBlock,
+    };
     use std::collections::HashMap;
     use std::sync::Arc;
     use std::time::Duration;
@@ -241,6 +449,11 @@ mod tests {
                 Ok(Some(UiUpdate::StreamBlockComplete { .. })) => events.push("BlockComplete"),
                 Ok(Some(UiUpdate::StreamDelta(_))) => events.push("Delta"),
                 Ok(Some(UiUpdate::ToolApprovalRequest(_))) => events.push("ToolApproval"),
+                Ok(Some(UiUpdate::AwaitingFirstToken | UiUpdate::FirstTokenReceived)) => {}
+                Ok(Some(UiUpdate::TurnReply(reply))) => {
+                    assert_eq!(reply, "Hello world");
+                    events.push("Reply");
+                }
                 Ok(Some(UiUpdate::TurnComplete)) => {
                     events.push("TurnComplete");
                     break;
@@ -250,6 +463,11 @@ mod tests {
             }
         }
 
+        assert_eq!(
+            events[events.len().saturating_sub(2)..],
+            ["Reply", "TurnComplete"],
+            "the full reply precedes TurnComplete"
+        );
         assert!(
             events.contains(&"TurnComplete"),
             "must terminate with TurnComplete"
@@ -310,7 +528,7 @@ data: {"type":"message_stop"}"#.to_string(),
             match tokio::time::timeout(Duration::from_millis(800), rx.recv()).await {
                 Ok(Some(UiUpdate::ToolApprovalRequest(request))) => {
                     saw_request = true;
-                    let _ = request.response_tx.send(false);
+                    let _ = request.response_tx.send(ApprovalDecision::Denied);
                 }
                 Ok(Some(UiUpdate::TurnComplete)) => {
                     saw_complete = true;
@@ -1,4 +1,5 @@
 use super::mode::RuntimeMode;
+use super::session::SessionTab;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ScrollTarget {
@@ -16,6 +17,17 @@ pub enum ScrollAction {
     End,
 }
 
+/// Tab management handled by the runtime rather than the active mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionCommand {
+    New,
+    Close,
+    Next,
+    Previous,
+    /// 0-based index of the session to show.
+    Select(usize),
+}
+
 pub enum UserInputEvent {
     Text(String),
     Interrupt,
@@ -23,10 +35,13 @@ pub enum UserInputEvent {
         target: ScrollTarget,
         action: ScrollAction,
     },
+    Session(SessionCommand),
+    /// Shift+Tab: switch plan mode, even while an overlay is open.
+    TogglePlanMode,
 }
 
 pub trait FrontendAdapter<M: RuntimeMode> {
     fn poll_user_input(&mut self, mode: &M) -> Option<UserInputEvent>;
-    fn render(&mut self, mode: &M);
+    fn render(&mut self, mode: &M, tabs: &[SessionTab]);
     fn should_quit(&self) -> bool;
 }
@@ -1,23 +1,97 @@
-use crate::runtime::{frontend::FrontendAdapter, mode::RuntimeMode, UiUpdate};
-use tokio::sync::mpsc;
+use crate::runtime::{
+    frontend::{FrontendAdapter, SessionCommand, UserInputEvent},
+    mode::RuntimeMode,
+};
 
-use super::context::RuntimeContext;
+use super::session::{Session, SessionFactory, SessionTab};
 
 pub struct Runtime<M: RuntimeMode> {
-    pub mode: M,
-    update_rx: mpsc::UnboundedReceiver<UiUpdate>,
+    sessions: Vec<Session<M>>,
+    active: usize,
+    factory: Option<SessionFactory<M>>,
 }
 
 impl<M: RuntimeMode> Runtime<M> {
-    pub fn new(mode: M, update_rx: mpsc::UnboundedReceiver<UiUpdate>) -> Self {
-        Self { mode, update_rx }
+    pub fn new(session: Session<M>) -> Self {
+        Self {
+            sessions: vec![session],
+            active: 0,
+            factory: None,
+        }
+    }
+
+    /// Let the frontend open new sessions; without a factory only the first
+    /// session exists.
+    pub fn with_session_factory(mut self, factory: SessionFactory<M>) -> Self {
+        self.factory = Some(factory);
+        self
+    }
+
+    pub fn active(&self) -> &Session<M> {
+        &self.sessions[self.active]
+    }
+
+    pub fn tabs(&self) -> Vec<SessionTab> {
+        self.sessions
+            .iter()
+            .enumerate()
+            .map(|(index, session)| session.tab(index + 1, index == self.active))
+            .collect()
+    }
+
+    fn apply_session_command(&mut self, command: SessionCommand) {
+        let count = self.sessions.len();
+        match command {
+            SessionCommand::New => {
+                let Some(factory) = self.factory.as_mut() else {
+                    self.notify_active("[tabs] this frontend runs a single session".to_string());
+                    return;
+                };
+                match factory() {
+                    Ok(session) => {
+                        self.sessions.push(session);
+                        self.show(self.sessions.len() - 1);
+                    }
+                    Err(error) => {
+                        self.notify_active(format!("[tabs] could not open a session: {error}"))
+                    }
+                }
+            }
+            SessionCommand::Close => {
+                if count == 1 {
+                    self.notify_active("[tabs] the last session cannot be closed".to_string());
+                    return;
+                }
+                let mut closed = self.sessions.remove(self.active);
+                // Stop a streaming turn instead of leaving it to run unseen.
+                closed.ctx.cancel_turn();
+                self.show(self.active.min(count - 2));
+            }
+            SessionCommand::Next => self.show((self.active + 1) % count),
+            SessionCommand::Previous => self.show((self.active + count - 1) % count),
+            SessionCommand::Select(index) if index < count => self.show(index),
+            SessionCommand::Select(index) => {
+                self.notify_active(format!("[tabs] no session {}; {count} open", index + 1))
+            }
+        }
+    }
+
+    fn show(&mut self, index: usize) {
+        self.active = index;
+        self.sessions[index].mark_unread(false);
+    }
+
+    fn notify_active(&mut self, notice: String) {
+        self.sessions[self.active].mode.on_runtime_notice(notice);
     }
+
     /// Execute the runtime loop.
     ///
     /// Must be called within a Tokio runtime context (e.g., `#[tokio::main]`
     /// or `block_on`). The async signature enforces `.await` at compile time
-    /// for the loop path.
-    pub async fn run<F>(&mut self, frontend: &mut F, ctx: &mut RuntimeContext)
+    /// for the loop path. Every session keeps receiving model updates; only
+    /// the active one gets input and is rendered.
+    pub async fn run<F>(&mut self, frontend: &mut F)
     where
         F: FrontendAdapter<M>,
     {
@@ -26,15 +100,24 @@ impl<M: RuntimeMode> Runtime<M> {
                 break;
             }
 
-            if let Some(input) = frontend.poll_user_input(&self.mode) {
-                self.mode.on_frontend_event(input, ctx);
+            if let Some(input) = frontend.poll_user_input(&self.active().mode) {
+                match input {
+                    UserInputEvent::Session(command) => self.apply_session_command(command),
+                    input => {
+                        let session = &mut self.sessions[self.active];
+                        session.mode.on_frontend_event(input, &mut session.ctx);
+                    }
+                }
             }
 
-            while let Ok(update) = self.update_rx.try_recv() {
-                self.mode.on_model_update(update, ctx);
+            for (index, session) in self.sessions.iter_mut().enumerate() {
+                if session.drain_updates() && index != self.active {
+                    session.mark_unread(true);
+                }
             }
 
-            frontend.render(&self.mode);
+            let tabs = self.tabs();
+            frontend.render(&self.active().mode, &tabs);
         }
     }
 }
@@ -43,10 +126,11 @@ impl<M: RuntimeMode> Runtime<M> {
 mod tests {
     use super::*;
     use crate::api::{mock_client::MockApiClient, ApiClient};
-    use crate::runtime::frontend::UserInputEvent;
+    use crate::runtime::{context::RuntimeContext, UiUpdate};
     use crate::state::ConversationManager;
     use std::collections::{HashMap, VecDeque};
     use std::sync::Arc;
+    use tokio::sync::mpsc;
 
     struct HeadlessFrontend {
         inputs: VecDeque<String>,
@@ -69,7 +153,7 @@ mod tests {
             self.inputs.pop_front().map(UserInputEvent::Text)
         }
 
-        fn render(&mut self, _mode: &crate::app::TuiMode) {
+        fn render(&mut self, _mode: &crate::app::TuiMode, _tabs: &[SessionTab]) {
             self.render_count += 1;
         }
 
@@ -120,7 +204,7 @@ mod tests {
             self.events.pop_front()
         }
 
-        fn render(&mut self, _mode: &InterruptMode) {
+        fn render(&mut self, _mode: &InterruptMode, _tabs: &[SessionTab]) {
             self.render_count += 1;
         }
 
@@ -137,13 +221,12 @@ mod tests {
         let conversation = ConversationManager::new_mock(client, HashMap::new());
 
         let (tx, update_rx) = mpsc::unbounded_channel::<UiUpdate>();
-        let mut ctx =
-            RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
+        let ctx = RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
         let mode = crate::app::TuiMode::new();
-        let mut runtime = Runtime::new(mode, update_rx);
+        let mut runtime = Runtime::new(Session::new(mode, ctx, update_rx));
 
         let mut frontend = HeadlessFrontend::new(vec!["hello", "world"], 3);
-        runtime.run(&mut frontend, &mut ctx).await;
+        runtime.run(&mut frontend).await;
 
         assert_eq!(
             frontend.render_count, 3,
@@ -158,13 +241,12 @@ mod tests {
         let conversation = ConversationManager::new_mock(client, HashMap::new());
 
         let (tx, update_rx) = mpsc::unbounded_channel::<UiUpdate>();
-        let mut ctx =
-            RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
+        let ctx = RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
         let mode = crate::app::TuiMode::new();
-        let mut runtime = Runtime::new(mode, update_rx);
+        let mut runtime = Runtime::new(Session::new(mode, ctx, update_rx));
 
         let mut frontend = HeadlessFrontend::new(vec!["hello"], 2);
-        runtime.run(&mut frontend, &mut ctx).await;
+        runtime.run(&mut frontend).await;
 
         assert_eq!(frontend.render_count, 2);
     }
@@ -176,18 +258,58 @@ mod tests {
         let conversation = ConversationManager::new_mock(client, HashMap::new());
 
         let (tx, update_rx) = mpsc::unbounded_channel::<UiUpdate>();
-        let mut ctx =
-            RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
+        let ctx = RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
         let mode = InterruptMode {
             user_input_calls: 0,
             interrupt_calls: 0,
         };
-        let mut runtime = Runtime::new(mode, update_rx);
+        let mut runtime = Runtime::new(Session::new(mode, ctx, update_rx));
 
         let mut frontend = InterruptFrontend::new(vec![UserInputEvent::Interrupt], 1);
-        runtime.run(&mut frontend, &mut ctx).await;
+        runtime.run(&mut frontend).await;
+
+        assert_eq!(runtime.active().mode.interrupt_calls, 1);
+        assert_eq!(runtime.active().mode.user_input_calls, 0);
+    }
+
+    fn interrupt_session() -> Session<InterruptMode> {
+        let client = ApiClient::new_mock(Arc::new(MockApiClient::new(vec![])));
+        let conversation = ConversationManager::new_mock(client, HashMap::new());
+        let (tx, update_rx) = mpsc::unbounded_channel::<UiUpdate>();
+        let ctx = RuntimeContext::new(conversation, tx, tokio_util::sync::CancellationToken::new());
+        let mode = InterruptMode {
+            user_input_calls: 0,
+            interrupt_calls: 0,
+        };
+        Session::new(mode, ctx, update_rx)
+    }
 
-        assert_eq!(runtime.mode.interrupt_calls, 1);
-        assert_eq!(runtime.mode.user_input_calls, 0);
+    #[tokio::test]
+    async fn test_session_commands_route_input_to_active_session() {
+        let mut runtime = Runtime::new(interrupt_session())
+            .with_session_factory(Box::new(|| Ok(interrupt_session())));
+
+        let mut frontend = InterruptFrontend::new(
+            vec![
+                UserInputEvent::Session(SessionCommand::New),
+                UserInputEvent::Text("second tab".to_string()),
+                UserInputEvent::Session(SessionCommand::New),
+                UserInputEvent::Session(SessionCommand::Select(0)),
+                UserInputEvent::Text("first tab".to_string()),
+                UserInputEvent::Session(SessionCommand::Previous),
+                UserInputEvent::Session(SessionCommand::Close),
+            ],
+            7,
+        );
+        runtime.run(&mut frontend).await;
+
+        let tabs = runtime.tabs();
+        assert_eq!(tabs.len(), 2);
+        assert!(
+            tabs[1].active,
+            "closing the last tab shows its left neighbour"
+        );
+        assert_eq!(runtime.active().mode.user_input_calls, 1);
+        assert_eq!(runtime.sessions[0].mode.user_input_calls, 1);
     }
 }
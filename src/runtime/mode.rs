@@ -11,8 +11,20 @@ pub trait RuntimeMode {
         match event {
             UserInputEvent::Text(input) => self.on_user_input(input, ctx),
             UserInputEvent::Interrupt => self.on_interrupt(ctx),
-            UserInputEvent::Scroll { .. } => {}
+            UserInputEvent::Scroll { .. }
+            | UserInputEvent::Session(_)
+            | UserInputEvent::TogglePlanMode => {}
         }
     }
+    /// A message from the runtime itself, such as a failed session switch.
+    fn on_runtime_notice(&mut self, _notice: String) {}
     fn is_turn_in_progress(&self) -> bool;
+    /// Short name for the session's tab.
+    fn session_title(&self) -> Option<String> {
+        None
+    }
+    /// The session is blocked on the user, e.g. an approval prompt.
+    fn needs_attention(&self) -> bool {
+        false
+    }
 }
@@ -1,17 +1,77 @@
-pub trait RuntimeCorePolicy {
-    fn sanitize_assistant_text(&self, text: &str) -> String;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::api::ApiProtocol;
+
+const POLICY_ENV: &str = "VEX_POLICY";
+
+/// Decides when a turn needs tool-backed evidence and what vex tells the
+/// model when it has to steer a turn. A policy is injected through
+/// `build_runtime`; only `request_requires_tool_evidence` has no default.
+pub trait RuntimeCorePolicy: Send + Sync {
+    fn sanitize_assistant_text(&self, text: &str) -> String {
+        sanitize_assistant_text(text)
+    }
     fn request_requires_tool_evidence(&self, input: &str) -> bool;
-    fn tool_retry_instruction(&self) -> &'static str;
-    fn repeated_tool_round_instruction(&self) -> &'static str;
+    fn tool_retry_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        builtin_instruction(PolicyInstruction::ToolRetry, protocol).into()
+    }
+    fn repeated_tool_round_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        builtin_instruction(PolicyInstruction::RepeatedToolRound, protocol).into()
+    }
+    fn continuation_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        builtin_instruction(PolicyInstruction::Continuation, protocol).into()
+    }
+    fn truncated_tool_call_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        builtin_instruction(PolicyInstruction::TruncatedToolCall, protocol).into()
+    }
+}
+
+/// How the current request talks to the model; instructions may differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyProtocol {
+    pub api: ApiProtocol,
+    /// Tools are called natively rather than with tagged text syntax.
+    pub structured_tools: bool,
+}
+
+/// A message vex adds to the history to steer the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolicyInstruction {
+    /// The request needs workspace evidence and no tool was called.
+    ToolRetry,
+    /// The model repeated an identical read-only tool round.
+    RepeatedToolRound,
+    /// The response stopped at the output token limit.
+    Continuation,
+    /// The response stopped at the limit in the middle of a tool call.
+    TruncatedToolCall,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct DefaultRuntimeCorePolicy;
+/// The built-in policy, extended or overridden by the JSON file named in
+/// `VEX_POLICY`.
+#[derive(Debug, Clone)]
+pub struct DefaultRuntimeCorePolicy {
+    hints: Vec<Regex>,
+    patterns: Vec<Regex>,
+    exclude_patterns: Vec<Regex>,
+    /// Keyed by instruction, then by protocol label, `tagged`, `structured`
+    /// or `default`.
+    instructions: HashMap<PolicyInstruction, HashMap<String, String>>,
+}
 
 const TOOL_RETRY_INSTRUCTION: &str =
     "Your previous answer did not execute any tool call. This request \
 requires tool-backed evidence from the workspace. Call the appropriate tool now before \
-answering. If structured tool calls are unavailable, use tagged syntax:
+answering.";
+
+const TAGGED_TOOL_SYNTAX_REMINDER: &str =
+    "If structured tool calls are unavailable, use tagged syntax:
 <function=tool_name>
 <parameter=arg>value</parameter>
 </function>";
@@ -21,7 +81,19 @@ const REPEATED_TOOL_ROUND_INSTRUCTION: &str =
 Do not repeat identical tool calls. Use existing tool results to answer now. \
 Only call a different tool if new evidence is required.";
 
-const TOOL_REQUIRED_HINTS: [&str; 29] = [
+const CONTINUATION_INSTRUCTION: &str =
+    "Your previous response was cut off at the output token limit. \
+Continue exactly where it stopped, without repeating text you already wrote.";
+
+const TRUNCATED_TOOL_CALL_INSTRUCTION: &str =
+    "Your previous response was cut off at the output token limit while writing a tool call, \
+so its tool calls were discarded and nothing was executed. Issue them again in full. \
+If a call carries a large file body, split the change into smaller edit_file calls.";
+
+/// Words and paths that suggest a request is about the workspace. Matched
+/// case-insensitively, on word boundaries where the hint starts or ends with
+/// a letter or digit.
+const TOOL_REQUIRED_HINTS: &[&str] = &[
     "file",
     "files",
     "directory",
@@ -53,32 +125,278 @@ const TOOL_REQUIRED_HINTS: [&str; 29] = [
     "read again",
 ];
 
+static BUILTIN_HINTS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    TOOL_REQUIRED_HINTS
+        .iter()
+        .map(|hint| hint_regex(hint))
+        .collect()
+});
+
+/// Shape of the `VEX_POLICY` JSON file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PolicyConfig {
+    tool_evidence: ToolEvidenceConfig,
+    instructions: InstructionConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ToolEvidenceConfig {
+    /// Keep the built-in hints alongside the configured ones.
+    builtin: bool,
+    hints: Vec<String>,
+    patterns: Vec<String>,
+    /// A match here means no evidence is required, whatever else matched.
+    exclude_patterns: Vec<String>,
+}
+
+impl Default for ToolEvidenceConfig {
+    fn default() -> Self {
+        Self {
+            builtin: true,
+            hints: Vec::new(),
+            patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct InstructionConfig {
+    tool_retry: HashMap<String, String>,
+    repeated_tool_round: HashMap<String, String>,
+    continuation: HashMap<String, String>,
+    truncated_tool_call: HashMap<String, String>,
+}
+
 pub fn default_runtime_policy() -> DefaultRuntimeCorePolicy {
-    DefaultRuntimeCorePolicy
+    DefaultRuntimeCorePolicy::default()
 }
 
 pub fn sanitize_assistant_text(text: &str) -> String {
-    default_runtime_policy().sanitize_assistant_text(text)
+    strip_tagged_tool_markup(text)
 }
 
-impl RuntimeCorePolicy for DefaultRuntimeCorePolicy {
-    fn sanitize_assistant_text(&self, text: &str) -> String {
-        strip_tagged_tool_markup(text)
+impl Default for DefaultRuntimeCorePolicy {
+    fn default() -> Self {
+        Self {
+            hints: BUILTIN_HINTS.clone(),
+            patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+            instructions: HashMap::new(),
+        }
+    }
+}
+
+impl DefaultRuntimeCorePolicy {
+    /// The built-in policy, configured by the file in `VEX_POLICY` when set.
+    pub fn load() -> Result<Self> {
+        let Some(path) = std::env::var(POLICY_ENV)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+        else {
+            return Ok(Self::default());
+        };
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {POLICY_ENV} file '{path}'"))?;
+        Self::from_json(&raw).with_context(|| format!("Invalid {POLICY_ENV} file '{path}'"))
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self> {
+        let config: PolicyConfig = serde_json::from_str(raw)?;
+        let evidence = config.tool_evidence;
+        let mut policy = if evidence.builtin {
+            Self::default()
+        } else {
+            Self {
+                hints: Vec::new(),
+                patterns: Vec::new(),
+                exclude_patterns: Vec::new(),
+                instructions: HashMap::new(),
+            }
+        };
+        for hint in evidence.hints {
+            if hint.trim().is_empty() {
+                bail!("tool_evidence.hints: empty hint");
+            }
+            policy.hints.push(hint_regex(hint.trim()));
+        }
+        policy.patterns.extend(compile_patterns(
+            "tool_evidence.patterns",
+            &evidence.patterns,
+        )?);
+        policy.exclude_patterns =
+            compile_patterns("tool_evidence.exclude_patterns", &evidence.exclude_patterns)?;
+
+        for (kind, name, texts) in [
+            (
+                PolicyInstruction::ToolRetry,
+                "tool_retry",
+                config.instructions.tool_retry,
+            ),
+            (
+                PolicyInstruction::RepeatedToolRound,
+                "repeated_tool_round",
+                config.instructions.repeated_tool_round,
+            ),
+            (
+                PolicyInstruction::Continuation,
+                "continuation",
+                config.instructions.continuation,
+            ),
+            (
+                PolicyInstruction::TruncatedToolCall,
+                "truncated_tool_call",
+                config.instructions.truncated_tool_call,
+            ),
+        ] {
+            for (key, text) in &texts {
+                if !is_instruction_key(key) {
+                    bail!(
+                        "instructions.{name}: unknown key '{key}' (expected default, tagged, structured, anthropic, openai, responses or ollama)"
+                    );
+                }
+                if text.trim().is_empty() {
+                    bail!("instructions.{name}.{key}: empty instruction");
+                }
+            }
+            if !texts.is_empty() {
+                policy.instructions.insert(kind, texts);
+            }
+        }
+        Ok(policy)
+    }
+
+    /// The configured text for `kind` under the most specific matching key:
+    /// API protocol, then tool protocol, then `default`.
+    fn configured_instruction(
+        &self,
+        kind: PolicyInstruction,
+        protocol: PolicyProtocol,
+    ) -> Option<&str> {
+        let texts = self.instructions.get(&kind)?;
+        let tool_protocol = if protocol.structured_tools {
+            "structured"
+        } else {
+            "tagged"
+        };
+        [protocol.api.label(), tool_protocol, "default"]
+            .iter()
+            .find_map(|key| texts.get(*key))
+            .map(String::as_str)
+    }
+
+    fn instruction(&self, kind: PolicyInstruction, protocol: PolicyProtocol) -> Cow<'_, str> {
+        match self.configured_instruction(kind, protocol) {
+            Some(text) => Cow::Borrowed(text),
+            None => builtin_instruction(kind, protocol).into(),
+        }
     }
+}
 
+impl RuntimeCorePolicy for DefaultRuntimeCorePolicy {
     fn request_requires_tool_evidence(&self, input: &str) -> bool {
-        let normalized = input.to_ascii_lowercase();
-        TOOL_REQUIRED_HINTS
+        if self
+            .exclude_patterns
             .iter()
-            .any(|hint| normalized.contains(hint))
+            .any(|pattern| pattern.is_match(input))
+        {
+            return false;
+        }
+        self.hints
+            .iter()
+            .chain(&self.patterns)
+            .any(|pattern| pattern.is_match(input))
+    }
+
+    fn tool_retry_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        self.instruction(PolicyInstruction::ToolRetry, protocol)
+    }
+
+    fn repeated_tool_round_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        self.instruction(PolicyInstruction::RepeatedToolRound, protocol)
+    }
+
+    fn continuation_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        self.instruction(PolicyInstruction::Continuation, protocol)
+    }
+
+    fn truncated_tool_call_instruction(&self, protocol: PolicyProtocol) -> Cow<'_, str> {
+        self.instruction(PolicyInstruction::TruncatedToolCall, protocol)
     }
+}
 
-    fn tool_retry_instruction(&self) -> &'static str {
-        TOOL_RETRY_INSTRUCTION
+/// Built-in text for `kind`; the tool retry reminds text-protocol models of
+/// the tagged call syntax.
+pub fn builtin_instruction(kind: PolicyInstruction, protocol: PolicyProtocol) -> String {
+    match kind {
+        PolicyInstruction::ToolRetry if protocol.structured_tools => {
+            TOOL_RETRY_INSTRUCTION.to_string()
+        }
+        PolicyInstruction::ToolRetry => {
+            format!("{TOOL_RETRY_INSTRUCTION} {TAGGED_TOOL_SYNTAX_REMINDER}")
+        }
+        PolicyInstruction::RepeatedToolRound => REPEATED_TOOL_ROUND_INSTRUCTION.to_string(),
+        PolicyInstruction::Continuation => CONTINUATION_INSTRUCTION.to_string(),
+        PolicyInstruction::TruncatedToolCall => TRUNCATED_TOOL_CALL_INSTRUCTION.to_string(),
     }
+}
+
+fn hint_regex(hint: &str) -> Regex {
+    let boundary = |ch: Option<char>| {
+        if ch.is_some_and(char::is_alphanumeric) {
+            r"\b"
+        } else {
+            ""
+        }
+    };
+    Regex::new(&format!(
+        "(?i){}{}{}",
+        boundary(hint.chars().next()),
+        regex::escape(hint),
+        boundary(hint.chars().last())
+    ))
+    .expect("escaped hint is a valid regex")
+}
 
-    fn repeated_tool_round_instruction(&self) -> &'static str {
-        REPEATED_TOOL_ROUND_INSTRUCTION
+fn compile_patterns(field: &str, patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).with_context(|| format!("{field}: invalid regex '{pattern}'"))
+        })
+        .collect()
+}
+
+fn is_instruction_key(key: &str) -> bool {
+    matches!(key, "default" | "tagged" | "structured")
+        || [
+            ApiProtocol::AnthropicMessages,
+            ApiProtocol::OpenAiChatCompletions,
+            ApiProtocol::OpenAiResponses,
+            ApiProtocol::OllamaChat,
+        ]
+        .iter()
+        .any(|protocol| protocol.label() == key)
+}
+
+/// Remove a leading `<think>...</think>` block that reached assistant text,
+/// or everything after a leading `<think>` that never closed.
+///
+/// Tags later in the text are answer content and are left alone. Reasoning
+/// opened by the chat template is split off by the stream parser instead.
+pub fn strip_think_tags(text: &str) -> String {
+    let Some(inside) = text.trim_start().strip_prefix("<think>") else {
+        return text.to_string();
+    };
+    match inside.find("</think>") {
+        Some(end) => inside[end + "</think>".len()..]
+            .trim_start_matches(['\r', '\n'])
+            .to_string(),
+        None => String::new(),
     }
 }
 
@@ -126,7 +444,20 @@ fn strip_incomplete_tool_tag_suffix(text: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{default_runtime_policy, sanitize_assistant_text, RuntimeCorePolicy};
+    use super::{
+        builtin_instruction, default_runtime_policy, sanitize_assistant_text, strip_think_tags,
+        DefaultRuntimeCorePolicy, PolicyInstruction, PolicyProtocol, RuntimeCorePolicy,
+    };
+    use crate::api::ApiProtocol;
+
+    const OLLAMA_TAGGED: PolicyProtocol = PolicyProtocol {
+        api: ApiProtocol::OllamaChat,
+        structured_tools: false,
+    };
+    const OPENAI_STRUCTURED: PolicyProtocol = PolicyProtocol {
+        api: ApiProtocol::OpenAiChatCompletions,
+        structured_tools: true,
+    };
 
     #[test]
     fn test_sanitize_assistant_text_removes_tool_block() {
@@ -140,11 +471,107 @@ mod tests {
         assert_eq!(sanitize_assistant_text(text), "Checking.\n");
     }
 
+    #[test]
+    fn test_strip_think_tags_handles_closed_and_open_blocks() {
+        assert_eq!(strip_think_tags("<think>plan</think>\n\nDone."), "Done.");
+        assert_eq!(strip_think_tags("<think>still planning"), "");
+        let text = "Close the block with </think> when done.";
+        assert_eq!(strip_think_tags(text), text);
+        assert_eq!(strip_think_tags("no tags here"), "no tags here");
+    }
+
+    #[test]
+    fn test_strip_think_tags_keeps_tags_mentioned_in_the_answer() {
+        let text = "Wrap reasoning in <think>...</think> tags.";
+        assert_eq!(strip_think_tags(text), text);
+        let text = "Use <think> to open the block.\nThen close it.";
+        assert_eq!(strip_think_tags(text), text);
+        assert_eq!(
+            sanitize_assistant_text("Qwen emits <think>plan</think> first."),
+            "Qwen emits <think>plan</think> first."
+        );
+    }
+
     #[test]
     fn test_request_requires_tool_evidence_detects_repo_facts() {
         let policy = default_runtime_policy();
         assert!(policy.request_requires_tool_evidence("how many files are in this tree"));
         assert!(policy.request_requires_tool_evidence("what's in docs/src/"));
         assert!(!policy.request_requires_tool_evidence("say hello"));
+        assert!(policy.request_requires_tool_evidence("Explain src/runtime.rs"));
+        assert!(policy.request_requires_tool_evidence("SHOW me the README"));
+        assert!(!policy.request_requires_tool_evidence("write a profile blurb"));
+    }
+
+    #[test]
+    fn test_configured_hints_patterns_and_exclusions() {
+        let policy = DefaultRuntimeCorePolicy::from_json(
+            r#"{"tool_evidence": {
+                "builtin": false,
+                "hints": ["Datei", "wie viele"],
+                "patterns": ["(?i)\\bcommit [0-9a-f]{7}\\b"],
+                "exclude_patterns": ["^/explain "]
+            }}"#,
+        )
+        .expect("valid policy");
+        assert!(policy.request_requires_tool_evidence("Wie viele Dateien gibt es?"));
+        assert!(policy.request_requires_tool_evidence("zeig die Datei"));
+        assert!(policy.request_requires_tool_evidence("what changed in commit 3832673"));
+        assert!(!policy.request_requires_tool_evidence("show the files"));
+        assert!(!policy.request_requires_tool_evidence("/explain commit 3832673"));
+
+        let extended =
+            DefaultRuntimeCorePolicy::from_json(r#"{"tool_evidence": {"hints": ["Datei"]}}"#)
+                .expect("valid policy");
+        assert!(extended.request_requires_tool_evidence("show the files"));
+        assert!(extended.request_requires_tool_evidence("zeig die Datei"));
+    }
+
+    #[test]
+    fn test_instructions_resolve_per_protocol() {
+        let builtin = default_runtime_policy();
+        assert!(builtin
+            .tool_retry_instruction(OLLAMA_TAGGED)
+            .contains("<function=tool_name>"));
+        assert!(!builtin
+            .tool_retry_instruction(OPENAI_STRUCTURED)
+            .contains("<function=tool_name>"));
+
+        let policy = DefaultRuntimeCorePolicy::from_json(
+            r#"{"instructions": {
+                "tool_retry": {"default": "Use a tool.", "ollama": "Call a tool with <function=...>."},
+                "continuation": {"structured": "Keep going."}
+            }}"#,
+        )
+        .expect("valid policy");
+        assert_eq!(
+            policy.tool_retry_instruction(OLLAMA_TAGGED),
+            "Call a tool with <function=...>."
+        );
+        assert_eq!(
+            policy.tool_retry_instruction(OPENAI_STRUCTURED),
+            "Use a tool."
+        );
+        assert_eq!(
+            policy.continuation_instruction(OPENAI_STRUCTURED),
+            "Keep going."
+        );
+        assert_eq!(
+            policy.continuation_instruction(OLLAMA_TAGGED),
+            builtin_instruction(PolicyInstruction::Continuation, OLLAMA_TAGGED)
+        );
+    }
+
+    #[test]
+    fn test_invalid_policy_config_is_rejected() {
+        for raw in [
+            r#"{"tool_evidence": {"patterns": ["("]}}"#,
+            r#"{"instructions": {"continuation": {"gemini": "Go on."}}}"#,
+            r#"{"instructions": {"continuation": {"default": " "}}}"#,
+            r#"{"tool_evidence": {"hints": [""]}}"#,
+            r#"{"hints": ["file"]}"#,
+        ] {
+            assert!(DefaultRuntimeCorePolicy::from_json(raw).is_err(), "{raw}");
+        }
     }
 }
@@ -0,0 +1,118 @@
+use tokio::sync::mpsc;
+
+use super::context::RuntimeContext;
+use super::mode::RuntimeMode;
+use super::UiUpdate;
+
+/// Longest tab title before it is cut with an ellipsis.
+const TAB_TITLE_CHARS: usize = 24;
+
+/// Builds a fresh session when the frontend asks for a new tab.
+pub type SessionFactory<M> = Box<dyn FnMut() -> anyhow::Result<Session<M>>>;
+
+/// One conversation with its own context, cancellation token and transcript.
+pub struct Session<M: RuntimeMode> {
+    pub mode: M,
+    pub ctx: RuntimeContext,
+    update_rx: mpsc::UnboundedReceiver<UiUpdate>,
+    /// Updates arrived while another session was shown.
+    unread: bool,
+}
+
+impl<M: RuntimeMode> Session<M> {
+    pub fn new(mode: M, ctx: RuntimeContext, update_rx: mpsc::UnboundedReceiver<UiUpdate>) -> Self {
+        Self {
+            mode,
+            ctx,
+            update_rx,
+            unread: false,
+        }
+    }
+
+    /// Apply queued model updates; returns whether there were any.
+    pub(super) fn drain_updates(&mut self) -> bool {
+        let mut received = false;
+        while let Ok(update) = self.update_rx.try_recv() {
+            self.mode.on_model_update(update, &mut self.ctx);
+            received = true;
+        }
+        received
+    }
+
+    pub(super) fn mark_unread(&mut self, unread: bool) {
+        self.unread = unread;
+    }
+
+    pub(super) fn tab(&self, number: usize, active: bool) -> SessionTab {
+        SessionTab {
+            number,
+            title: self.mode.session_title(),
+            active,
+            busy: self.mode.is_turn_in_progress(),
+            unread: self.unread,
+            needs_attention: self.mode.needs_attention(),
+        }
+    }
+}
+
+/// What the tab bar shows for one session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionTab {
+    /// 1-based, matching the Alt+digit shortcut.
+    pub number: usize,
+    pub title: Option<String>,
+    pub active: bool,
+    pub busy: bool,
+    pub unread: bool,
+    /// Waiting on the user, e.g. for a tool approval.
+    pub needs_attention: bool,
+}
+
+impl SessionTab {
+    /// `3 fix the parser*`: `!` waits on the user, `*` is streaming, `+` has
+    /// output not seen yet.
+    pub fn label(&self) -> String {
+        let mut label = self.number.to_string();
+        if let Some(title) = &self.title {
+            label.push(' ');
+            let mut chars = title.chars();
+            label.extend(chars.by_ref().take(TAB_TITLE_CHARS));
+            if chars.next().is_some() {
+                label.push('…');
+            }
+        }
+        if self.needs_attention {
+            label.push('!');
+        } else if self.busy {
+            label.push('*');
+        } else if self.unread {
+            label.push('+');
+        }
+        label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tab_label_truncates_title_and_marks_state() {
+        let mut tab = SessionTab {
+            number: 2,
+            title: Some("refactor the stream parser into smaller pieces".to_string()),
+            active: false,
+            busy: true,
+            unread: true,
+            needs_attention: false,
+        };
+        assert_eq!(tab.label(), "2 refactor the stream pars…*");
+
+        tab.busy = false;
+        tab.title = None;
+        assert_eq!(tab.label(), "2+");
+
+        tab.needs_attention = true;
+        assert_eq!(tab.label(), "2!");
+    }
+}
@@ -1,11 +1,52 @@
-use crate::state::{StreamBlock, ToolApprovalRequest};
+use crate::api::ApiError;
+use crate::state::{
+    CompactionNotice, DelegateUpdate, StallNotice, StreamBlock, ToolApprovalRequest,
+    TruncationNotice, UsageReport,
+};
+use crate::tools::HookRun;
 
 pub enum UiUpdate {
     StreamDelta(String),
-    StreamBlockStart { index: usize, block: StreamBlock },
-    StreamBlockDelta { index: usize, delta: String },
-    StreamBlockComplete { index: usize },
+    StreamBlockStart {
+        index: usize,
+        block: StreamBlock,
+    },
+    StreamBlockDelta {
+        index: usize,
+        delta: String,
+    },
+    StreamBlockComplete {
+        index: usize,
+    },
     ToolApprovalRequest(ToolApprovalRequest),
+    Usage(UsageReport),
+    /// A response hit the output token limit; see [`TruncationNotice`].
+    OutputTruncated(TruncationNotice),
+    /// A model request is out and no response bytes have arrived yet.
+    AwaitingFirstToken,
+    FirstTokenReceived,
+    /// No first token within the timeout; the request is being retried.
+    StreamStalled(StallNotice),
+    /// Older history was dropped after a context-length error; the request is retried.
+    ContextPruned {
+        removed_messages: usize,
+    },
+    /// Older turns were replaced by a model-written summary.
+    Compacted(CompactionNotice),
+    /// Automatic compaction failed before a turn; pruning applies instead.
+    CompactionFailed(String),
+    /// Progress of a `delegate` sub-agent, shown as a nested block.
+    Delegate(DelegateUpdate),
+    /// A tool hook finished; vetoes and post-hook output are in the tool result.
+    Hook(HookRun),
+    /// A tool call could not be written to the audit log.
+    AuditFailed(String),
+    /// Result of a `/model fetch` request against the endpoint's model list.
+    EndpointModels(Result<Vec<String>, String>),
+    /// The full final reply of a message turn; `TurnComplete` follows.
+    TurnReply(String),
     TurnComplete,
     Error(String),
+    /// A turn failed with a classified endpoint error.
+    ApiError(ApiError),
 }
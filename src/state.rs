@@ -1,5 +1,22 @@
+mod compaction;
 mod conversation;
+mod delegate;
+mod instructions;
+mod plan;
 mod stream_block;
+mod token_budget;
+mod transcript;
+mod usage;
 
-pub use conversation::{ConversationManager, ConversationStreamUpdate, ToolApprovalRequest};
+pub use compaction::CompactionNotice;
+pub use conversation::{
+    ApprovalDecision, ConversationBranch, ConversationManager, ConversationStreamUpdate,
+    StallNotice, ToolApprovalRequest, TruncationNotice,
+};
+pub use delegate::DelegateUpdate;
+pub use instructions::{InstructionFile, InstructionScope, ProjectInstructions};
+pub use plan::{is_numbered_plan, plan_approval_prompt};
 pub use stream_block::{StreamBlock, ToolStatus};
+pub use token_budget::{ModelFamily, TokenBudget, TokenEstimator};
+pub use transcript::{SessionTranscript, TranscriptEntry, TurnTiming};
+pub use usage::{UsageLedger, UsageReport};
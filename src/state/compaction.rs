@@ -0,0 +1,131 @@
+use super::conversation::{is_text_protocol_tool_result, message_contains_tool_result};
+use crate::types::{ApiMessage, Content};
+
+/// Most recent user turns left verbatim when older history is summarized.
+pub(super) const COMPACT_KEEP_RECENT_TURNS: usize = 1;
+/// First line of the user message that replaces compacted turns.
+pub(super) const SUMMARY_MESSAGE_PREFIX: &str = "[summary of earlier conversation]";
+const SUMMARY_ACKNOWLEDGEMENT: &str = "Understood. I will continue from this summary.";
+
+const SUMMARY_REQUEST: &str = "\
+Summarize the conversation so far so it can replace the turns above. Do not \
+call any tools. Write plain text with these sections:
+Decisions: what was decided or concluded, and why.
+Files: every file read, created or changed, with a few words on each.
+Open TODOs: work that is unfinished, failing or still to be verified.
+Keep exact paths, identifiers and error messages. Leave out greetings and \
+anything already superseded.";
+
+/// Older turns were replaced by a model-written summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionNotice {
+    /// Messages removed from the request history and moved to the archive.
+    pub summarized_messages: usize,
+    /// Estimated tokens of the history before and after compaction.
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    /// Triggered by the context budget rather than `/compact`.
+    pub automatic: bool,
+}
+
+/// Index of the first message kept verbatim, or `None` when there are not
+/// enough completed turns to summarize.
+///
+/// Turns start at plain user messages, so the kept tail never begins with an
+/// orphaned tool_result.
+pub(super) fn compaction_cut(messages: &[ApiMessage], keep_recent_turns: usize) -> Option<usize> {
+    let turn_starts: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| is_turn_start(message))
+        .map(|(index, _)| index)
+        .collect();
+    let cut = *turn_starts.get(turn_starts.len().checked_sub(keep_recent_turns)?)?;
+    // A single earlier summary is not worth summarizing again.
+    let only_summary = cut <= 2 && messages.first().is_some_and(is_summary_message);
+    (cut > 0 && !only_summary).then_some(cut)
+}
+
+/// Request asking the model to summarize `older` turns.
+pub(super) fn summary_request(older: &[ApiMessage]) -> Vec<ApiMessage> {
+    let mut request = older.to_vec();
+    request.push(ApiMessage {
+        role: "user".to_string(),
+        content: Content::Text(SUMMARY_REQUEST.to_string()),
+    });
+    request
+}
+
+/// The summary as a user message plus an acknowledgement, so the kept turns
+/// still alternate roles.
+pub(super) fn summary_messages(summary: &str) -> [ApiMessage; 2] {
+    [
+        ApiMessage {
+            role: "user".to_string(),
+            content: Content::Text(format!("{SUMMARY_MESSAGE_PREFIX}\n{}", summary.trim())),
+        },
+        ApiMessage {
+            role: "assistant".to_string(),
+            content: Content::Text(SUMMARY_ACKNOWLEDGEMENT.to_string()),
+        },
+    ]
+}
+
+pub(super) fn is_summary_message(message: &ApiMessage) -> bool {
+    matches!(&message.content, Content::Text(text) if text.starts_with(SUMMARY_MESSAGE_PREFIX))
+}
+
+/// A plain user prompt, as opposed to a user message carrying tool results.
+pub(super) fn is_turn_start(message: &ApiMessage) -> bool {
+    message.role == "user"
+        && !message_contains_tool_result(message)
+        && !matches!(&message.content, Content::Text(text) if is_text_protocol_tool_result(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(role: &str, text: &str) -> ApiMessage {
+        ApiMessage {
+            role: role.to_string(),
+            content: Content::Text(text.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_compaction_cut_keeps_latest_turn_and_skips_tool_results() {
+        let messages = vec![
+            text("user", "first question"),
+            text("assistant", "reading"),
+            text("user", "tool_result read_file:\nfn main() {}"),
+            text("assistant", "done"),
+            text("user", "second question"),
+            text("assistant", "answer"),
+        ];
+        assert_eq!(compaction_cut(&messages, 1), Some(4));
+        assert_eq!(compaction_cut(&messages, 2), None);
+        assert_eq!(compaction_cut(&messages[..4], 1), None);
+    }
+
+    #[test]
+    fn test_compaction_cut_skips_history_that_is_only_a_summary() {
+        let [summary, ack] = summary_messages("Decisions: none");
+        let messages = vec![
+            summary.clone(),
+            ack.clone(),
+            text("user", "next"),
+            text("assistant", "ok"),
+        ];
+        assert_eq!(compaction_cut(&messages, 1), None);
+
+        let messages = vec![
+            summary,
+            ack,
+            text("user", "next"),
+            text("assistant", "ok"),
+            text("user", "latest"),
+        ];
+        assert_eq!(compaction_cut(&messages, 1), Some(4));
+    }
+}
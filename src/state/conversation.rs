@@ -1,44 +1,148 @@
+use super::compaction::{
+    compaction_cut, summary_messages, summary_request, CompactionNotice, COMPACT_KEEP_RECENT_TURNS,
+};
+use super::delegate::{
+    delegate_prompt, delegate_step_summary, DelegateUpdate, DEFAULT_DELEGATE_MAX_ROUNDS,
+    DELEGATE_TOOLS, DELEGATE_TOOL_NAME,
+};
+use super::instructions::ProjectInstructions;
+use super::plan::PLAN_MODE_PROMPT;
 use super::stream_block::{StreamBlock, ToolStatus};
-use crate::api::{stream::StreamParser, ApiClient};
+use super::token_budget::{TokenBudget, TokenEstimator};
+use super::transcript::{count_user_turns, is_user_turn, SessionTranscript, TurnTiming};
+use super::usage::{UsageLedger, UsageReport};
+use crate::api::{client::ByteStream, ApiClient, ApiError};
+use crate::audit::{
+    hash_touched_files, mark_after_hashes_unknown, rehash_touched_files, AuditApprover,
+    AuditDecision, AuditLog, ToolCallRecord,
+};
 use crate::edit_diff::DEFAULT_EDIT_DIFF_CONTEXT_LINES;
-use crate::runtime::policy::{default_runtime_policy, RuntimeCorePolicy};
+use crate::runtime::policy::{
+    default_runtime_policy, strip_think_tags, PolicyProtocol, RuntimeCorePolicy,
+};
 use crate::tool_preview::{
     format_read_file_snapshot_message, preview_tool_input, read_file_path, ReadFileSnapshotCache,
     ReadFileSnapshotSummary, ReadFileSummaryMessageStyle, ToolPreviewStyle,
 };
-use crate::tools::ToolOperator;
-use crate::types::{ApiMessage, Content, ContentBlock, StreamEvent};
+use crate::tools::{
+    is_image_path, load_image_attachment, HookRun, ImageAttachment, ToolHooks, ToolOperator,
+};
+use crate::types::{ApiMessage, Content, ContentBlock, StreamEvent, Usage};
 use crate::util::parse_bool_flag;
 use anyhow::bail;
 use anyhow::Result;
 use futures::StreamExt;
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 #[cfg(test)]
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 #[cfg(test)]
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, oneshot};
 
 pub enum ConversationStreamUpdate {
     Delta(String),
-    BlockStart { index: usize, block: StreamBlock },
-    BlockDelta { index: usize, delta: String },
-    BlockComplete { index: usize },
+    BlockStart {
+        index: usize,
+        block: StreamBlock,
+    },
+    BlockDelta {
+        index: usize,
+        delta: String,
+    },
+    BlockComplete {
+        index: usize,
+    },
     ToolApprovalRequest(ToolApprovalRequest),
+    Usage(UsageReport),
+    OutputTruncated(TruncationNotice),
+    /// A request was sent and no response bytes have arrived yet.
+    AwaitingFirstToken,
+    FirstTokenReceived,
+    StreamStalled(StallNotice),
+    /// The prompt overflowed the context window; older history was dropped
+    /// before retrying.
+    ContextPruned {
+        removed_messages: usize,
+    },
+    /// Older turns were replaced by a summary; see [`CompactionNotice`].
+    Compacted(CompactionNotice),
+    /// Automatic compaction failed; the turn continues with budget pruning.
+    CompactionFailed(String),
+    /// Progress of a `delegate` tool call running a sub-agent.
+    Delegate(DelegateUpdate),
+    /// A `VEX_HOOKS` command ran before or after a tool call.
+    Hook(HookRun),
+    /// A tool call could not be written to the audit log.
+    AuditFailed(String),
+}
+
+/// A response stopped at the output token limit (`max_tokens`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncationNotice {
+    /// Continuation requests made so far this turn, including this one.
+    pub continuation: usize,
+    pub max_continuations: usize,
+    /// Whether vex asked the model to continue; false once the limit is spent.
+    pub continuing: bool,
+    /// Tool calls dropped because their input JSON was cut off.
+    pub discarded_tool_calls: usize,
+}
+
+/// No response arrived within the first-token timeout and the request is being retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StallNotice {
+    pub waited: Duration,
+    /// Retries made so far for this request, including this one.
+    pub retry: usize,
+    pub max_retries: usize,
+}
+
+/// A saved line of the conversation; `/fork` swaps it with the active one.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationBranch {
+    api_messages: Vec<ApiMessage>,
+    archived_messages: Vec<ApiMessage>,
+    full_messages: BTreeMap<usize, ApiMessage>,
+    turn_timings: Vec<TurnTiming>,
+}
+
+impl ConversationBranch {
+    pub fn message_count(&self) -> usize {
+        self.api_messages.len()
+    }
 }
 
 pub struct ToolApprovalRequest {
     pub tool_name: String,
     pub input_preview: String,
-    pub response_tx: oneshot::Sender<bool>,
+    pub response_tx: oneshot::Sender<ApprovalDecision>,
+}
+
+/// The frontend's answer to a [`ToolApprovalRequest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    /// The user approved this call.
+    Approved,
+    /// Approved without asking; the user allowed tools for the session.
+    ApprovedForSession,
+    Denied,
+}
+
+impl ApprovalDecision {
+    pub fn is_approved(self) -> bool {
+        !matches!(self, Self::Denied)
+    }
 }
 
 #[cfg(test)]
 impl ToolApprovalRequest {
     pub fn test_stub() -> Self {
-        let (response_tx, _response_rx) = oneshot::channel::<bool>();
+        let (response_tx, _response_rx) = oneshot::channel::<ApprovalDecision>();
         Self {
             tool_name: "read_file".to_string(),
             input_preview: "{}".to_string(),
@@ -48,27 +152,80 @@ impl ToolApprovalRequest {
 }
 
 const LOCAL_DEFAULT_MAX_ASSISTANT_HISTORY_CHARS: usize = 1_200;
-const LOCAL_DEFAULT_MAX_TOOL_RESULT_HISTORY_CHARS: usize = 2_500;
-const LOCAL_DEFAULT_MAX_API_MESSAGES: usize = 14;
 const LOCAL_DEFAULT_TOOL_TIMEOUT_SECS: u64 = 20;
 const REMOTE_DEFAULT_MAX_ASSISTANT_HISTORY_CHARS: usize = 3_000;
-const REMOTE_DEFAULT_MAX_TOOL_RESULT_HISTORY_CHARS: usize = 6_000;
-const REMOTE_DEFAULT_MAX_API_MESSAGES: usize = 32;
 const REMOTE_DEFAULT_TOOL_TIMEOUT_SECS: u64 = 60;
+const LOCAL_DEFAULT_FIRST_TOKEN_TIMEOUT_SECS: usize = 300;
+const REMOTE_DEFAULT_FIRST_TOKEN_TIMEOUT_SECS: usize = 120;
+const LOCAL_DEFAULT_STREAM_IDLE_TIMEOUT_SECS: usize = 120;
+const REMOTE_DEFAULT_STREAM_IDLE_TIMEOUT_SECS: usize = 60;
+const DEFAULT_STALL_RETRIES: usize = 1;
+/// History size, as a percentage of the token budget, that triggers automatic compaction.
+const DEFAULT_AUTO_COMPACT_PERCENT: usize = 80;
+/// A single tool result may take at most this fraction of the history budget.
+const TOOL_RESULT_BUDGET_DIVISOR: usize = 8;
+/// Replacement text for a tool result evicted to fit the token budget.
+const EVICTED_TOOL_RESULT_PREFIX: &str = "[tool result evicted to fit the context window:";
+/// Prune-and-retry attempts per turn after a context-length error.
+const MAX_CONTEXT_OVERFLOW_RETRIES: usize = 2;
+const DEFAULT_MAX_CONTINUATIONS: usize = 3;
+const MAX_CONTINUATIONS_LIMIT: usize = 10;
+/// Assistant history entry for a cut-off response that produced no text.
+const TRUNCATED_RESPONSE_PLACEHOLDER: &str = "[response cut off at the output token limit]";
+
+/// How a call through `execute_tool_with_timeout` ended.
+enum ToolRun {
+    /// A pre hook vetoed the call, so the tool did not run.
+    Vetoed(String),
+    /// The tool outlived its timeout; its blocking task may still be running.
+    TimedOut(anyhow::Error),
+    /// The tool's result and what its post hooks printed.
+    Finished(Result<String>, String),
+}
+
+/// Watchdog for model streams; `None` disables a timeout.
+#[derive(Clone, Copy)]
+struct StreamTimeouts {
+    first_token: Option<Duration>,
+    idle: Option<Duration>,
+    stall_retries: usize,
+}
 
 #[derive(Clone, Copy)]
 struct HistoryLimits {
     max_assistant_history_chars: usize,
     max_tool_result_history_chars: usize,
-    max_api_messages: usize,
+    /// Optional hard cap from `VEX_MAX_API_MESSAGES`; the token budget applies regardless.
+    max_api_messages: Option<usize>,
 }
 
 pub struct ConversationManager {
     client: Arc<ApiClient>,
     tool_operator: ToolOperator,
     api_messages: Vec<ApiMessage>,
+    /// Messages dropped from the request history by compaction or pruning,
+    /// oldest first.
+    archived_messages: Vec<ApiMessage>,
+    /// Untruncated copies of messages that were shortened or evicted in the
+    /// history, keyed by position in the session (archived, then live).
+    /// The transcript reads these instead.
+    full_messages: BTreeMap<usize, ApiMessage>,
+    turn_timings: Vec<TurnTiming>,
     current_turn_blocks: Vec<StreamBlock>,
     read_file_history_cache: ReadFileSnapshotCache,
+    usage: UsageLedger,
+    pending_images: Vec<ImageAttachment>,
+    /// Set for a `delegate` sub-agent: its round limit. Sub-agents only run
+    /// [`DELEGATE_TOOLS`].
+    delegate_max_rounds: Option<usize>,
+    /// Mutating tools are refused while the model works out a plan.
+    plan_mode: bool,
+    policy: Arc<dyn RuntimeCorePolicy>,
+    tool_hooks: Arc<ToolHooks>,
+    audit: Option<Arc<AuditLog>>,
+    /// 1-based user turn that tool calls are audited under; a sub-agent
+    /// keeps its parent's.
+    audit_turn: usize,
     #[cfg(test)]
     mock_tool_operator_responses: Option<Arc<Mutex<HashMap<String, String>>>>,
 }
@@ -79,8 +236,19 @@ impl ConversationManager {
             client: Arc::new(client),
             tool_operator: operator,
             api_messages: Vec::new(),
+            archived_messages: Vec::new(),
+            full_messages: BTreeMap::new(),
+            turn_timings: Vec::new(),
             current_turn_blocks: Vec::new(),
             read_file_history_cache: ReadFileSnapshotCache::default(),
+            usage: UsageLedger::default(),
+            pending_images: Vec::new(),
+            delegate_max_rounds: None,
+            plan_mode: false,
+            policy: Arc::new(default_runtime_policy()),
+            tool_hooks: Arc::new(ToolHooks::default()),
+            audit: None,
+            audit_turn: 0,
             #[cfg(test)]
             mock_tool_operator_responses: None,
         }
@@ -92,12 +260,33 @@ impl ConversationManager {
             client: Arc::new(client),
             tool_operator: ToolOperator::new(std::path::PathBuf::from("/tmp")), // Dummy executor
             api_messages: Vec::new(),
+            archived_messages: Vec::new(),
+            full_messages: BTreeMap::new(),
+            turn_timings: Vec::new(),
             current_turn_blocks: Vec::new(),
             read_file_history_cache: ReadFileSnapshotCache::default(),
+            usage: UsageLedger::default(),
+            pending_images: Vec::new(),
+            delegate_max_rounds: None,
+            plan_mode: false,
+            policy: Arc::new(default_runtime_policy()),
+            tool_hooks: Arc::new(ToolHooks::default()),
+            audit: None,
+            audit_turn: 0,
             mock_tool_operator_responses: Some(Arc::new(Mutex::new(tool_operator_responses))),
         }
     }
 
+    /// Append `message` to the history. `full` is its untruncated form, kept
+    /// for the transcript when the history copy was shortened.
+    fn push_history_message(&mut self, message: ApiMessage, full: Option<ApiMessage>) {
+        if let Some(full) = full {
+            let index = self.archived_messages.len() + self.api_messages.len();
+            self.full_messages.insert(index, full);
+        }
+        self.api_messages.push(message);
+    }
+
     pub fn push_user_message(&mut self, input: String) {
         self.api_messages.push(ApiMessage {
             role: "user".to_string(),
@@ -105,6 +294,129 @@ impl ConversationManager {
         });
     }
 
+    fn push_user_message_with_images(&mut self, input: String, images: Vec<ImageAttachment>) {
+        if images.is_empty() {
+            self.push_user_message(input);
+            return;
+        }
+        let mut blocks: Vec<ContentBlock> = images
+            .iter()
+            .map(ImageAttachment::to_content_block)
+            .collect();
+        blocks.push(ContentBlock::Text { text: input });
+        self.api_messages.push(ApiMessage {
+            role: "user".to_string(),
+            content: Content::Blocks(blocks),
+        });
+    }
+
+    /// Stage a workspace image for the next user message.
+    pub fn attach_image(&mut self, path: &str) -> Result<ImageAttachment> {
+        let attachment = load_image_attachment(&self.tool_operator, path)?;
+        self.pending_images.push(attachment.clone());
+        Ok(attachment)
+    }
+
+    /// Staged images plus any `@path.png` mentions in `input` that name a
+    /// workspace file. Mentions load before staged images are taken, so an
+    /// unreadable image keeps them staged.
+    fn take_turn_images(&mut self, input: &str) -> Result<Vec<ImageAttachment>> {
+        let mut mentioned = Vec::new();
+        // A mention that names no workspace file stays plain prompt text.
+        for path in image_mentions(input) {
+            if !self.tool_operator.is_file(&path) {
+                continue;
+            }
+            let already_attached = self
+                .pending_images
+                .iter()
+                .chain(mentioned.iter())
+                .any(|image: &ImageAttachment| image.path == path);
+            if !already_attached {
+                mentioned.push(load_image_attachment(&self.tool_operator, &path)?);
+            }
+        }
+        let mut images = std::mem::take(&mut self.pending_images);
+        images.extend(mentioned);
+        Ok(images)
+    }
+
+    /// Prompts of the user turns still in the history, oldest first. Tool
+    /// results and compaction summaries are not turns.
+    pub fn user_turns(&self) -> Vec<String> {
+        self.user_turn_indices()
+            .into_iter()
+            .map(|index| user_prompt_text(&self.api_messages[index]))
+            .collect()
+    }
+
+    fn user_turn_indices(&self) -> Vec<usize> {
+        self.api_messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| is_user_turn(message))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Drop the newest `turns` user turns and everything after them. Returns
+    /// the prompt of the earliest dropped turn. Files on disk are untouched.
+    pub fn rewind(&mut self, turns: usize) -> Result<String> {
+        let indices = self.user_turn_indices();
+        if turns == 0 || turns > indices.len() {
+            bail!(
+                "cannot rewind {turns} turn(s); {} turn(s) remain in the model history",
+                indices.len()
+            );
+        }
+        let cut = indices[indices.len() - turns];
+        let prompt = user_prompt_text(&self.api_messages[cut]);
+        self.api_messages.truncate(cut);
+        self.full_messages
+            .split_off(&(self.archived_messages.len() + cut));
+        let remaining = count_user_turns(self.archived_messages.iter().chain(&self.api_messages));
+        self.turn_timings.retain(|timing| timing.turn < remaining);
+        self.pending_images.clear();
+        // Snapshot summaries refer to reads the model may no longer see.
+        self.read_file_history_cache = ReadFileSnapshotCache::default();
+        Ok(prompt)
+    }
+
+    /// Copy of the active line, to be restored later with [`Self::switch_branch`].
+    pub fn fork(&self) -> ConversationBranch {
+        ConversationBranch {
+            api_messages: self.api_messages.clone(),
+            archived_messages: self.archived_messages.clone(),
+            full_messages: self.full_messages.clone(),
+            turn_timings: self.turn_timings.clone(),
+        }
+    }
+
+    /// Make `branch` the active line and return the one it replaces.
+    pub fn switch_branch(&mut self, branch: ConversationBranch) -> ConversationBranch {
+        let previous = ConversationBranch {
+            api_messages: std::mem::replace(&mut self.api_messages, branch.api_messages),
+            archived_messages: std::mem::replace(
+                &mut self.archived_messages,
+                branch.archived_messages,
+            ),
+            full_messages: std::mem::replace(&mut self.full_messages, branch.full_messages),
+            turn_timings: std::mem::replace(&mut self.turn_timings, branch.turn_timings),
+        };
+        self.pending_images.clear();
+        self.read_file_history_cache = ReadFileSnapshotCache::default();
+        previous
+    }
+
+    #[cfg(test)]
+    pub fn test_push_exchange(&mut self, prompt: &str, reply: &str) {
+        self.push_user_message(prompt.to_string());
+        self.api_messages.push(ApiMessage {
+            role: "assistant".to_string(),
+            content: Content::Text(reply.to_string()),
+        });
+    }
+
     pub fn messages_for_api(&self) -> Vec<ApiMessage> {
         self.api_messages.clone()
     }
@@ -113,24 +425,358 @@ impl ConversationManager {
         Arc::clone(&self.client)
     }
 
+    /// Switch models between turns; the next request uses `model`.
+    pub fn set_model(&mut self, model: &str) -> Result<()> {
+        Arc::make_mut(&mut self.client).set_model(model)
+    }
+
+    pub fn add_discovered_models(&mut self, ids: &[String]) {
+        Arc::make_mut(&mut self.client).add_discovered_models(ids);
+    }
+
+    /// Rediscover project instruction files under the workspace and use them
+    /// for subsequent requests.
+    pub fn reload_instructions(&mut self) -> ProjectInstructions {
+        let instructions = ProjectInstructions::load(self.tool_operator.working_dir());
+        Arc::make_mut(&mut self.client)
+            .set_project_instructions(instructions.prompt_section().map(str::to_string));
+        instructions
+    }
+
+    /// Replace the policy that steers turns, e.g. with one loaded from
+    /// `VEX_POLICY`.
+    pub fn set_policy(&mut self, policy: Arc<dyn RuntimeCorePolicy>) {
+        self.policy = policy;
+    }
+
+    pub fn policy(&self) -> Arc<dyn RuntimeCorePolicy> {
+        Arc::clone(&self.policy)
+    }
+
+    /// Commands to run around tool calls, e.g. from `VEX_HOOKS`.
+    pub fn set_tool_hooks(&mut self, hooks: ToolHooks) {
+        self.tool_hooks = Arc::new(hooks);
+    }
+
+    /// Record every tool call in `log`, or stop recording with `None`.
+    pub fn set_audit_log(&mut self, log: Option<AuditLog>) {
+        self.audit = log.map(Arc::new);
+    }
+
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit.as_deref()
+    }
+
+    /// Drop leading think-tag reasoning from providers that inline it.
+    fn strip_inline_reasoning(&self, text: &str) -> String {
+        if self.client.api_protocol().inlines_think_tags() {
+            strip_think_tags(text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn policy_protocol(&self) -> PolicyProtocol {
+        PolicyProtocol {
+            api: self.client.api_protocol(),
+            structured_tools: self.client.supports_structured_tool_protocol(),
+        }
+    }
+
+    /// Turn plan mode on or off for subsequent requests.
+    pub fn set_plan_mode(&mut self, enabled: bool) {
+        self.plan_mode = enabled;
+        Arc::make_mut(&mut self.client).set_mode_instructions(enabled.then_some(PLAN_MODE_PROMPT));
+    }
+
+    pub fn plan_mode(&self) -> bool {
+        self.plan_mode
+    }
+
+    pub fn usage_report(&self) -> UsageReport {
+        self.usage.report(self.client.model())
+    }
+
+    /// Messages dropped from the request history by compaction or pruning,
+    /// oldest first. The archive lives in memory for the life of the session;
+    /// `/export` is how it reaches disk.
+    pub fn archived_messages(&self) -> &[ApiMessage] {
+        &self.archived_messages
+    }
+
+    /// The whole session for export, including archived messages, with the
+    /// full text of anything shortened for the model.
+    pub fn transcript(&self) -> SessionTranscript {
+        SessionTranscript {
+            model: self.client.model().to_string(),
+            messages: self
+                .archived_messages
+                .iter()
+                .chain(&self.api_messages)
+                .enumerate()
+                .map(|(index, message)| self.full_messages.get(&index).unwrap_or(message))
+                .cloned()
+                .collect(),
+            turn_timings: self.turn_timings.clone(),
+        }
+    }
+
+    /// Replace all but the most recent turn with a model-written summary.
+    /// The replaced messages move to [`Self::archived_messages`].
+    pub async fn compact(
+        &mut self,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> Result<CompactionNotice> {
+        self.usage.begin_turn();
+        self.compact_history(false, stream_delta_tx).await
+    }
+
+    /// Compact before a new turn once history passes `VEX_AUTO_COMPACT_PERCENT`
+    /// of the token budget. Failures are reported and left to budget pruning.
+    async fn auto_compact_if_needed(
+        &mut self,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) {
+        let percent = resolve_auto_compact_percent();
+        if percent == 0 || compaction_cut(&self.api_messages, COMPACT_KEEP_RECENT_TURNS).is_none() {
+            return;
+        }
+        let estimator = TokenEstimator::for_model(self.client.model());
+        let limit = self.token_budget(&estimator).history_limit();
+        if estimator.history_tokens(&self.api_messages) * 100 < limit * percent {
+            return;
+        }
+        if let Err(error) = self.compact_history(true, stream_delta_tx).await {
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::CompactionFailed(error.to_string()),
+            );
+        }
+    }
+
+    async fn compact_history(
+        &mut self,
+        automatic: bool,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> Result<CompactionNotice> {
+        let Some(cut) = compaction_cut(&self.api_messages, COMPACT_KEEP_RECENT_TURNS) else {
+            bail!("nothing to compact: no earlier turns to summarize");
+        };
+        let estimator = TokenEstimator::for_model(self.client.model());
+        let tokens_before = estimator.history_tokens(&self.api_messages);
+        let request = summary_request(&self.api_messages[..cut]);
+        let timeouts = resolve_stream_timeouts(self.client.is_local_endpoint());
+
+        let mut stream = self
+            .open_model_stream(&request, timeouts, stream_delta_tx)
+            .await?;
+        let mut parser = self.client.stream_parser();
+        let mut summary = String::new();
+        let mut response_usage = Usage::default();
+        let mut stream_ended = false;
+        while !stream_ended {
+            let parsed = match next_stream_chunk(&mut stream, timeouts.idle).await {
+                Ok(Some(chunk)) => parser.process(&chunk),
+                Ok(None) => {
+                    stream_ended = true;
+                    parser.finish()
+                }
+                Err(error) => Err(error),
+            };
+            let events = match parsed {
+                Ok(events) => events,
+                Err(error) => {
+                    self.record_response_usage(&response_usage, stream_delta_tx);
+                    return Err(error);
+                }
+            };
+            for event in events {
+                match event {
+                    StreamEvent::MessageStart { message } => {
+                        if let Some(usage) = &message.usage {
+                            response_usage.absorb(usage);
+                        }
+                    }
+                    StreamEvent::ContentBlockStart {
+                        content_block: ContentBlock::Text { text },
+                        ..
+                    } => summary.push_str(&text),
+                    StreamEvent::ContentBlockDelta { delta, .. } => {
+                        if let Some(text) = delta.text {
+                            summary.push_str(&text);
+                        }
+                    }
+                    StreamEvent::MessageDelta {
+                        usage: Some(usage), ..
+                    } => response_usage.absorb(&usage),
+                    _ => {}
+                }
+            }
+        }
+        self.record_response_usage(&response_usage, stream_delta_tx);
+
+        let summary = self.strip_inline_reasoning(&summary);
+        if summary.trim().is_empty() {
+            bail!("compaction failed: the model returned an empty summary");
+        }
+        let summary = summary_messages(&summary);
+        // Messages after the cut move back by the summary inserted before them.
+        let kept_start = self.archived_messages.len() + cut;
+        let kept = self.full_messages.split_off(&kept_start);
+        self.full_messages.extend(
+            kept.into_iter()
+                .map(|(index, message)| (index + summary.len(), message)),
+        );
+        let older: Vec<ApiMessage> = self.api_messages.splice(..cut, summary).collect();
+        let notice = CompactionNotice {
+            summarized_messages: older.len(),
+            tokens_before,
+            tokens_after: estimator.history_tokens(&self.api_messages),
+            automatic,
+        };
+        self.archived_messages.extend(older);
+        emit_stream_update(
+            stream_delta_tx,
+            ConversationStreamUpdate::Compacted(notice.clone()),
+        );
+        Ok(notice)
+    }
+
+    /// Halve the history after the endpoint rejected the prompt as too long.
+    /// Returns false when nothing could be removed.
+    fn prune_after_context_overflow(
+        &mut self,
+        turn_user_anchor_index: &mut usize,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> bool {
+        let before = self.api_messages.len();
+        *turn_user_anchor_index =
+            self.prune_message_history_preserving(before / 2, *turn_user_anchor_index);
+        let removed_messages = before - self.api_messages.len();
+        if removed_messages == 0 {
+            return false;
+        }
+        emit_stream_update(
+            stream_delta_tx,
+            ConversationStreamUpdate::ContextPruned { removed_messages },
+        );
+        true
+    }
+
+    /// Send the request and wait for the first response bytes, retrying when
+    /// none arrive within the first-token timeout.
+    async fn open_model_stream(
+        &self,
+        messages: &[ApiMessage],
+        timeouts: StreamTimeouts,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> Result<ByteStream> {
+        let mut retry = 0usize;
+        loop {
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::AwaitingFirstToken,
+            );
+            let started = Instant::now();
+            let first_chunk = async {
+                let mut stream = self.client.create_stream(messages).await?;
+                let first = stream.next().await;
+                Ok::<_, anyhow::Error>((stream, first))
+            };
+            let opened = match timeouts.first_token {
+                Some(limit) => tokio::time::timeout(limit, first_chunk).await.ok(),
+                None => Some(first_chunk.await),
+            };
+
+            let Some(opened) = opened else {
+                let waited = started.elapsed();
+                if retry >= timeouts.stall_retries {
+                    bail!(
+                        "no response from the model after {}s waiting for the first token (VEX_FIRST_TOKEN_TIMEOUT_SECS)",
+                        waited.as_secs()
+                    );
+                }
+                retry += 1;
+                emit_stream_update(
+                    stream_delta_tx,
+                    ConversationStreamUpdate::StreamStalled(StallNotice {
+                        waited,
+                        retry,
+                        max_retries: timeouts.stall_retries,
+                    }),
+                );
+                continue;
+            };
+
+            let (stream, first) = opened?;
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::FirstTokenReceived,
+            );
+            return Ok(match first {
+                Some(first) => Box::pin(futures::stream::once(async move { first }).chain(stream)),
+                None => stream,
+            });
+        }
+    }
+
     pub async fn send_message(
         &mut self,
         content: String,
         stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> Result<String> {
+        let turn = count_user_turns(self.archived_messages.iter().chain(&self.api_messages));
+        if self.delegate_max_rounds.is_none() {
+            self.audit_turn = turn + 1;
+        }
+        let started_at = SystemTime::now();
+        let clock = Instant::now();
+        let result = self.run_turn(content, stream_delta_tx).await;
+        // Turns that failed before their prompt was recorded get no timing.
+        if count_user_turns(self.archived_messages.iter().chain(&self.api_messages)) > turn {
+            self.turn_timings.push(TurnTiming {
+                turn,
+                started_at_unix_ms: started_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as u64),
+                duration_ms: clock.elapsed().as_millis() as u64,
+                model: self.client.model().to_string(),
+                usage: self.usage_report().turn,
+            });
+        }
+        result
+    }
+
+    async fn run_turn(
+        &mut self,
+        content: String,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
     ) -> Result<String> {
         self.current_turn_blocks.clear();
+        self.usage.begin_turn();
+        self.auto_compact_if_needed(stream_delta_tx).await;
         let original_user_input = content.clone();
-        self.push_user_message(content);
+        let images = self.take_turn_images(&content)?;
+        self.push_user_message_with_images(content, images);
         let mut turn_user_anchor_index = self.api_messages.len().saturating_sub(1);
 
-        let core_policy = default_runtime_policy();
+        let core_policy = Arc::clone(&self.policy);
         let use_structured_tool_protocol = self.client.supports_structured_tool_protocol();
+        let policy_protocol = self.policy_protocol();
         let use_structured_blocks = structured_blocks_enabled();
         let requires_tool_evidence =
             core_policy.request_requires_tool_evidence(&original_user_input);
-        let limits = resolve_history_limits(self.client.is_local_endpoint());
+        let estimator = TokenEstimator::for_model(self.client.model());
+        let history_token_limit = self.token_budget(&estimator).history_limit();
+        let limits = resolve_history_limits(
+            self.client.is_local_endpoint(),
+            estimator.tokens_to_chars(history_token_limit / TOOL_RESULT_BUDGET_DIVISOR),
+        );
         let tool_timeout = resolve_tool_timeout(self.client.is_local_endpoint());
-        let max_tool_rounds = resolve_max_tool_rounds(self.client.is_local_endpoint());
+        let max_tool_rounds = self
+            .delegate_max_rounds
+            .unwrap_or_else(|| resolve_max_tool_rounds(self.client.is_local_endpoint()));
+        let stream_timeouts = resolve_stream_timeouts(self.client.is_local_endpoint());
         let stream_server_events = stream_server_events_enabled();
         let stream_local_tool_events = stream_local_tool_events_enabled();
         let require_tool_approval = tool_approval_enabled(self.client.is_local_endpoint());
@@ -142,10 +788,18 @@ impl ConversationManager {
         let mut repeated_mutating_rounds = 0usize;
         let mut repeated_round_nudge_used = false;
         let mut last_assistant_text_for_history = String::new();
-        loop {
+        let max_continuations = resolve_max_continuations();
+        let mut continuations = 0usize;
+        let mut continued_text = String::new();
+        let mut context_overflow_retries = 0usize;
+        'round: loop {
             self.current_turn_blocks.clear();
-            turn_user_anchor_index = self
-                .prune_message_history_preserving(limits.max_api_messages, turn_user_anchor_index);
+            turn_user_anchor_index =
+                self.fit_history_to_budget(&estimator, history_token_limit, turn_user_anchor_index);
+            if let Some(max_api_messages) = limits.max_api_messages {
+                turn_user_anchor_index =
+                    self.prune_message_history_preserving(max_api_messages, turn_user_anchor_index);
+            }
             rounds += 1;
             if rounds > max_tool_rounds {
                 return Ok(render_loop_limit_guard_message(
@@ -154,21 +808,81 @@ impl ConversationManager {
                 ));
             }
 
-            let mut stream = self.client.create_stream(&self.api_messages).await?;
-            let mut parser = StreamParser::new();
+            let mut stream = match self
+                .open_model_stream(&self.api_messages, stream_timeouts, stream_delta_tx)
+                .await
+            {
+                Ok(stream) => stream,
+                Err(error)
+                    if is_context_overflow(&error)
+                        && context_overflow_retries < MAX_CONTEXT_OVERFLOW_RETRIES =>
+                {
+                    if !self
+                        .prune_after_context_overflow(&mut turn_user_anchor_index, stream_delta_tx)
+                    {
+                        return Err(error);
+                    }
+                    context_overflow_retries += 1;
+                    rounds -= 1;
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            let mut parser = self.client.stream_parser();
             let mut assistant_text = String::new();
             let mut tool_use_blocks = Vec::new();
             let mut tool_input_buffers: Vec<Option<String>> = Vec::new();
             let mut tool_input_event_emitted: Vec<bool> = Vec::new();
             let mut deferred_text_block_indices = BTreeSet::new();
-
-            while let Some(chunk_result) = stream.next().await {
-                let chunk = chunk_result?;
-                let events = parser.process(&chunk)?;
+            let mut reasoning_blocks: Vec<Option<ContentBlock>> = Vec::new();
+            let mut response_usage = Usage::default();
+            let mut stop_reason: Option<String> = None;
+
+            let mut stream_ended = false;
+            while !stream_ended {
+                let parsed = match next_stream_chunk(&mut stream, stream_timeouts.idle).await {
+                    Ok(Some(chunk)) => parser.process(&chunk),
+                    Ok(None) => {
+                        stream_ended = true;
+                        parser.finish()
+                    }
+                    Err(error) => Err(error),
+                };
+                let events = match parsed {
+                    Ok(events) => events,
+                    // Some servers accept the request and report the overflow
+                    // as the stream's first event; nothing has been produced
+                    // yet, so the round is retried as if the open had failed.
+                    // The tokens billed before the failure are recorded either way.
+                    Err(error)
+                        if is_context_overflow(&error)
+                            && context_overflow_retries < MAX_CONTEXT_OVERFLOW_RETRIES
+                            && assistant_text.is_empty()
+                            && tool_use_blocks.is_empty() =>
+                    {
+                        self.record_response_usage(&response_usage, stream_delta_tx);
+                        if !self.prune_after_context_overflow(
+                            &mut turn_user_anchor_index,
+                            stream_delta_tx,
+                        ) {
+                            return Err(error);
+                        }
+                        context_overflow_retries += 1;
+                        rounds -= 1;
+                        continue 'round;
+                    }
+                    Err(error) => {
+                        self.record_response_usage(&response_usage, stream_delta_tx);
+                        return Err(error);
+                    }
+                };
 
                 for event in events {
                     match event {
-                        StreamEvent::MessageStart { .. } => {
+                        StreamEvent::MessageStart { message } => {
+                            if let Some(usage) = &message.usage {
+                                response_usage.absorb(usage);
+                            }
                             if !use_structured_blocks && stream_server_events {
                                 emit_text_update(
                                     stream_delta_tx,
@@ -209,7 +923,30 @@ impl ConversationManager {
                                             stream_delta_tx,
                                         );
                                     }
-                                    ContentBlock::ToolResult { .. } => {}
+                                    ContentBlock::Thinking { thinking, .. } => {
+                                        self.upsert_turn_block(
+                                            index,
+                                            StreamBlock::Reasoning {
+                                                content: thinking.clone(),
+                                                redacted: false,
+                                                collapsed: true,
+                                            },
+                                            stream_delta_tx,
+                                        );
+                                    }
+                                    ContentBlock::RedactedThinking { .. } => {
+                                        self.upsert_turn_block(
+                                            index,
+                                            StreamBlock::Reasoning {
+                                                content: String::new(),
+                                                redacted: true,
+                                                collapsed: true,
+                                            },
+                                            stream_delta_tx,
+                                        );
+                                    }
+                                    ContentBlock::ToolResult { .. }
+                                    | ContentBlock::Image { .. } => {}
                                 }
                             } else if stream_server_events {
                                 let event_label = match &content_block {
@@ -220,10 +957,28 @@ impl ConversationManager {
                                     ContentBlock::ToolResult { .. } => {
                                         format!("\n* Event: tool_result_block#{index}\n")
                                     }
+                                    ContentBlock::Image { .. } => {
+                                        format!("\n* Event: image_block#{index}\n")
+                                    }
+                                    ContentBlock::Thinking { .. }
+                                    | ContentBlock::RedactedThinking { .. } => {
+                                        "\n* Reasoning\n".to_string()
+                                    }
                                 };
                                 emit_text_update(stream_delta_tx, event_label);
                             }
 
+                            if matches!(
+                                content_block,
+                                ContentBlock::Thinking { .. }
+                                    | ContentBlock::RedactedThinking { .. }
+                            ) {
+                                while reasoning_blocks.len() <= index {
+                                    reasoning_blocks.push(None);
+                                }
+                                reasoning_blocks[index] = Some(content_block.clone());
+                            }
+
                             let tool_name =
                                 if let ContentBlock::ToolUse { name, .. } = &content_block {
                                     Some(name.clone())
@@ -241,6 +996,27 @@ impl ConversationManager {
                             }
                         }
                         StreamEvent::ContentBlockDelta { index, delta } => {
+                            if let Some(Some(ContentBlock::Thinking {
+                                thinking,
+                                signature,
+                                ..
+                            })) = reasoning_blocks.get_mut(index)
+                            {
+                                if let Some(signature_delta) = &delta.signature {
+                                    signature.push_str(signature_delta);
+                                }
+                                if let Some(thinking_delta) = &delta.thinking {
+                                    thinking.push_str(thinking_delta);
+                                    if use_structured_blocks {
+                                        self.append_reasoning_delta(
+                                            index,
+                                            thinking_delta,
+                                            stream_delta_tx,
+                                        );
+                                    }
+                                }
+                            }
+
                             if let Some(text) = delta.text {
                                 if use_structured_blocks {
                                     let delta_tx = if deferred_text_block_indices.contains(&index) {
@@ -328,7 +1104,13 @@ impl ConversationManager {
                                 );
                             }
                         }
-                        StreamEvent::MessageDelta { delta } => {
+                        StreamEvent::MessageDelta { delta, usage } => {
+                            if let Some(usage) = &usage {
+                                response_usage.absorb(usage);
+                            }
+                            if delta.stop_reason.is_some() {
+                                stop_reason = delta.stop_reason.clone();
+                            }
                             if !use_structured_blocks && stream_server_events {
                                 let stop_reason =
                                     delta.stop_reason.unwrap_or_else(|| "none".to_string());
@@ -358,7 +1140,104 @@ impl ConversationManager {
                 }
             }
 
-            let mut assistant_text_for_history = assistant_text.clone();
+            self.record_response_usage(&response_usage, stream_delta_tx);
+
+            // A response cut off at max_tokens may end inside a tool call. Its
+            // input JSON is incomplete and must never be executed.
+            if stop_reason.as_deref() == Some("max_tokens") {
+                let partial_tool_calls = tool_use_blocks
+                    .iter()
+                    .zip(&tool_input_buffers)
+                    .filter(|(block, buffer)| {
+                        block.is_some()
+                            && buffer.as_deref().is_some_and(|buffer| {
+                                serde_json::from_str::<serde_json::Value>(buffer).is_err()
+                            })
+                    })
+                    .count();
+                let complete_tool_calls = tool_use_blocks.iter().flatten().count();
+                let continuing = continuations < max_continuations && rounds < max_tool_rounds;
+                if continuing && (partial_tool_calls > 0 || complete_tool_calls == 0) {
+                    continuations += 1;
+                    let discarded_tool_calls = if partial_tool_calls > 0 {
+                        complete_tool_calls
+                    } else {
+                        0
+                    };
+                    self.cancel_tool_calls(tool_use_blocks.iter().flatten(), stream_delta_tx);
+                    emit_stream_update(
+                        stream_delta_tx,
+                        ConversationStreamUpdate::OutputTruncated(TruncationNotice {
+                            continuation: continuations,
+                            max_continuations,
+                            continuing: true,
+                            discarded_tool_calls,
+                        }),
+                    );
+
+                    let partial_text = self.strip_inline_reasoning(&assistant_text);
+                    let instruction = if discarded_tool_calls > 0 {
+                        core_policy.truncated_tool_call_instruction(policy_protocol)
+                    } else {
+                        if use_structured_blocks {
+                            self.promote_thinking_blocks_to_final_text(
+                                &deferred_text_block_indices,
+                                stream_delta_tx,
+                            );
+                        }
+                        continued_text.push_str(&partial_text);
+                        core_policy.continuation_instruction(policy_protocol)
+                    };
+                    let history_text = if partial_text.is_empty() {
+                        TRUNCATED_RESPONSE_PLACEHOLDER.to_string()
+                    } else {
+                        truncate_for_history(&partial_text, limits.max_assistant_history_chars)
+                    };
+                    let full =
+                        (!partial_text.is_empty() && history_text != partial_text).then(|| {
+                            ApiMessage {
+                                role: "assistant".to_string(),
+                                content: Content::Text(partial_text.clone()),
+                            }
+                        });
+                    self.push_history_message(
+                        ApiMessage {
+                            role: "assistant".to_string(),
+                            content: Content::Text(history_text),
+                        },
+                        full,
+                    );
+                    self.api_messages.push(ApiMessage {
+                        role: "user".to_string(),
+                        content: Content::Text(instruction.to_string()),
+                    });
+                    continue;
+                }
+
+                let mut discarded_tool_calls = 0usize;
+                for (block, buffer) in tool_use_blocks.iter_mut().zip(&tool_input_buffers) {
+                    let partial = buffer.as_deref().is_some_and(|buffer| {
+                        serde_json::from_str::<serde_json::Value>(buffer).is_err()
+                    });
+                    if partial {
+                        if let Some(dropped) = block.take() {
+                            self.cancel_tool_calls(std::iter::once(&dropped), stream_delta_tx);
+                            discarded_tool_calls += 1;
+                        }
+                    }
+                }
+                emit_stream_update(
+                    stream_delta_tx,
+                    ConversationStreamUpdate::OutputTruncated(TruncationNotice {
+                        continuation: continuations,
+                        max_continuations,
+                        continuing: false,
+                        discarded_tool_calls,
+                    }),
+                );
+            }
+
+            let mut assistant_text_for_history = self.strip_inline_reasoning(&assistant_text);
             let mut used_tagged_fallback = false;
             let mut tool_use_blocks: Vec<ContentBlock> =
                 tool_use_blocks.into_iter().flatten().collect();
@@ -454,31 +1333,56 @@ impl ConversationManager {
                 repeated_mutating_rounds = 0;
             }
 
-            let assistant_history_text = assistant_history_source;
-            let assistant_history_text =
-                truncate_for_history(&assistant_history_text, limits.max_assistant_history_chars);
-
             if use_structured_round {
-                let mut assistant_content_blocks = Vec::new();
+                // Thinking blocks lead the message and are replayed verbatim:
+                // the API verifies their signatures on the next tool round.
+                let mut assistant_content_blocks: Vec<ContentBlock> =
+                    reasoning_blocks.into_iter().flatten().collect();
+                let text_index = assistant_content_blocks.len();
+                let history_text = truncate_for_history(
+                    &assistant_text_for_history,
+                    limits.max_assistant_history_chars,
+                );
+                let shortened = history_text != assistant_text_for_history;
                 if !assistant_text_for_history.is_empty() {
-                    assistant_content_blocks.push(ContentBlock::Text {
-                        text: truncate_for_history(
-                            &assistant_text_for_history,
-                            limits.max_assistant_history_chars,
-                        ),
-                    });
+                    assistant_content_blocks.push(ContentBlock::Text { text: history_text });
                 }
                 assistant_content_blocks.extend(tool_use_blocks.clone());
 
-                self.api_messages.push(ApiMessage {
-                    role: "assistant".to_string(),
-                    content: Content::Blocks(assistant_content_blocks),
+                let full = shortened.then(|| {
+                    let mut blocks = assistant_content_blocks.clone();
+                    blocks[text_index] = ContentBlock::Text {
+                        text: assistant_text_for_history.clone(),
+                    };
+                    ApiMessage {
+                        role: "assistant".to_string(),
+                        content: Content::Blocks(blocks),
+                    }
                 });
+                self.push_history_message(
+                    ApiMessage {
+                        role: "assistant".to_string(),
+                        content: Content::Blocks(assistant_content_blocks),
+                    },
+                    full,
+                );
             } else {
-                self.api_messages.push(ApiMessage {
-                    role: "assistant".to_string(),
-                    content: Content::Text(assistant_history_text),
-                });
+                let assistant_history_text = truncate_for_history(
+                    &assistant_history_source,
+                    limits.max_assistant_history_chars,
+                );
+                let full =
+                    (assistant_history_text != assistant_history_source).then(|| ApiMessage {
+                        role: "assistant".to_string(),
+                        content: Content::Text(assistant_history_source),
+                    });
+                self.push_history_message(
+                    ApiMessage {
+                        role: "assistant".to_string(),
+                        content: Content::Text(assistant_history_text),
+                    },
+                    full,
+                );
             }
             last_assistant_text_for_history = assistant_text_for_history.clone();
 
@@ -486,7 +1390,9 @@ impl ConversationManager {
                 self.api_messages.push(ApiMessage {
                     role: "user".to_string(),
                     content: Content::Text(
-                        core_policy.repeated_tool_round_instruction().to_string(),
+                        core_policy
+                            .repeated_tool_round_instruction(policy_protocol)
+                            .into_owned(),
                     ),
                 });
                 continue;
@@ -502,7 +1408,11 @@ impl ConversationManager {
                     forced_tool_retry_count += 1;
                     self.api_messages.push(ApiMessage {
                         role: "user".to_string(),
-                        content: Content::Text(core_policy.tool_retry_instruction().to_string()),
+                        content: Content::Text(
+                            core_policy
+                                .tool_retry_instruction(policy_protocol)
+                                .into_owned(),
+                        ),
                     });
                     continue;
                 }
@@ -518,11 +1428,18 @@ impl ConversationManager {
                         stream_delta_tx,
                     );
                 }
+                if !continued_text.is_empty() {
+                    return Ok(format!("{continued_text}{assistant_text_for_history}"));
+                }
                 return Ok(assistant_text_for_history);
             }
 
             let mut tool_result_blocks = Vec::new();
             let mut text_protocol_tool_results = Vec::new();
+            // Untruncated copies of the results above, for the transcript.
+            let mut full_tool_result_blocks = Vec::new();
+            let mut full_text_protocol_tool_results = Vec::new();
+            let mut tool_results_shortened = false;
             for block in tool_use_blocks {
                 if let ContentBlock::ToolUse { id, name, input } = block {
                     if let Some(clarification) = missing_mutating_location_prompt(&name, &input) {
@@ -543,11 +1460,20 @@ impl ConversationManager {
                             );
                         }
                         emit_text_update(stream_delta_tx, clarification.clone());
+                        self.audit_tool_call(
+                            ToolCallRecord {
+                                decision: AuditDecision::Denied,
+                                ..self.tool_call_record(&name, &input)
+                            },
+                            stream_delta_tx,
+                        );
                         return Ok(clarification);
                     }
 
-                    let tool_requires_approval =
-                        require_tool_approval || tool_requires_confirmation(&name);
+                    // Refused without asking: there is nothing to approve.
+                    let blocked_reason = self.blocked_tool_reason(&name);
+                    let tool_requires_approval = blocked_reason.is_none()
+                        && (require_tool_approval || tool_requires_confirmation(&name));
 
                     if use_structured_blocks && tool_requires_approval {
                         self.set_tool_call_status(
@@ -556,11 +1482,20 @@ impl ConversationManager {
                             stream_delta_tx,
                         );
                     }
-                    let approved = if tool_requires_approval {
-                        self.request_tool_approval(&name, &input, stream_delta_tx)
+                    let (approved, decided_by) = if tool_requires_approval {
+                        match self
+                            .request_tool_approval(&name, &input, stream_delta_tx)
                             .await
+                        {
+                            Some(ApprovalDecision::ApprovedForSession) => {
+                                (true, AuditApprover::SessionAuto)
+                            }
+                            Some(decision) => (decision.is_approved(), AuditApprover::Manual),
+                            // Nobody to ask, as when running headless.
+                            None => (true, AuditApprover::Rule),
+                        }
                     } else {
-                        true
+                        (true, AuditApprover::Rule)
                     };
 
                     if use_structured_blocks {
@@ -589,12 +1524,85 @@ impl ConversationManager {
                             );
                         }
                         emit_text_update(stream_delta_tx, denial.clone());
+                        self.audit_tool_call(
+                            ToolCallRecord {
+                                decision: AuditDecision::Denied,
+                                decided_by,
+                                ..self.tool_call_record(&name, &input)
+                            },
+                            stream_delta_tx,
+                        );
                         return Ok(denial);
                     }
 
-                    let result = self
-                        .execute_tool_with_timeout(&name, &input, tool_timeout)
-                        .await;
+                    let working_dir = self.tool_operator.working_dir().to_path_buf();
+                    let mut touched_files = if self.audit.is_some() && blocked_reason.is_none() {
+                        hash_touched_files(&working_dir, &name, &input)
+                    } else {
+                        Vec::new()
+                    };
+                    let started = Instant::now();
+                    let mut hook_notes = String::new();
+                    let mut vetoed = false;
+                    let mut timed_out = false;
+                    let result = if let Some(reason) = &blocked_reason {
+                        Err(anyhow::anyhow!(reason.clone()))
+                    } else if name == DELEGATE_TOOL_NAME {
+                        self.run_delegate(&input, stream_delta_tx).await
+                    } else {
+                        if self.delegate_max_rounds.is_some() {
+                            emit_stream_update(
+                                stream_delta_tx,
+                                ConversationStreamUpdate::Delegate(DelegateUpdate::Step(
+                                    delegate_step_summary(&name, &input),
+                                )),
+                            );
+                        }
+                        match self
+                            .execute_tool_with_timeout(&name, &input, tool_timeout, stream_delta_tx)
+                            .await
+                        {
+                            ToolRun::Finished(result, notes) => {
+                                hook_notes = notes;
+                                result
+                            }
+                            ToolRun::Vetoed(veto) => {
+                                vetoed = true;
+                                Err(anyhow::anyhow!(veto))
+                            }
+                            ToolRun::TimedOut(error) => {
+                                timed_out = true;
+                                Err(error)
+                            }
+                        }
+                    };
+                    if timed_out {
+                        mark_after_hashes_unknown(&mut touched_files);
+                    } else {
+                        rehash_touched_files(&mut touched_files, &working_dir);
+                    }
+                    let audit_record = if blocked_reason.is_some() {
+                        ToolCallRecord {
+                            decision: AuditDecision::Denied,
+                            ..self.tool_call_record(&name, &input)
+                        }
+                    } else if vetoed {
+                        ToolCallRecord {
+                            decision: AuditDecision::Denied,
+                            decided_by: AuditApprover::Hook,
+                            duration: started.elapsed(),
+                            ..self.tool_call_record(&name, &input)
+                        }
+                    } else {
+                        ToolCallRecord {
+                            decided_by,
+                            duration: started.elapsed(),
+                            result: Some(result.as_ref().map(|_| ()).map_err(ToString::to_string)),
+                            files: touched_files,
+                            ..self.tool_call_record(&name, &input)
+                        }
+                    };
+                    self.audit_tool_call(audit_record, stream_delta_tx);
                     if use_structured_blocks {
                         let final_status = if approved {
                             ToolStatus::Complete
@@ -603,9 +1611,10 @@ impl ConversationManager {
                         };
                         self.set_tool_call_status(&id, final_status, stream_delta_tx);
 
-                        let output_for_stream = result
-                            .as_ref()
-                            .map_or_else(|e| e.to_string(), ToString::to_string);
+                        let output_for_stream = result.as_ref().map_or_else(
+                            |e| e.to_string(),
+                            |output| format!("{output}{hook_notes}"),
+                        );
                         self.push_tool_result_block(
                             StreamBlock::ToolResult {
                                 tool_call_id: id.clone(),
@@ -631,41 +1640,181 @@ impl ConversationManager {
                         }
                     }
 
-                    let history_content = truncate_for_history(
-                        &self.format_tool_result_for_history(&name, &input, &result),
-                        limits.max_tool_result_history_chars,
-                    );
+                    let full_content =
+                        self.format_tool_result_for_history(&name, &input, &result) + &hook_notes;
+                    let history_content =
+                        truncate_for_history(&full_content, limits.max_tool_result_history_chars);
                     if use_structured_round {
+                        tool_results_shortened |= history_content != full_content;
                         tool_result_blocks.push(ContentBlock::ToolResult {
-                            tool_use_id: id,
+                            tool_use_id: id.clone(),
                             content: history_content,
                             is_error: result.is_err(),
                         });
+                        full_tool_result_blocks.push(ContentBlock::ToolResult {
+                            tool_use_id: id,
+                            content: full_content,
+                            is_error: result.is_err(),
+                        });
                     } else {
-                        let rendered = result.as_ref().map_or_else(
-                            |_| format!("tool_error {name}:\n{history_content}"),
-                            |_| format!("tool_result {name}:\n{history_content}"),
-                        );
-                        text_protocol_tool_results.push(truncate_for_history(
-                            &rendered,
+                        let render = |content: &str| {
+                            result.as_ref().map_or_else(
+                                |_| format!("tool_error {name}:\n{content}"),
+                                |_| format!("tool_result {name}:\n{content}"),
+                            )
+                        };
+                        let rendered = truncate_for_history(
+                            &render(&history_content),
                             limits.max_tool_result_history_chars,
-                        ));
+                        );
+                        let full_rendered = render(&full_content);
+                        tool_results_shortened |= rendered != full_rendered;
+                        text_protocol_tool_results.push(rendered);
+                        full_text_protocol_tool_results.push(full_rendered);
                     }
                 }
             }
 
-            if use_structured_round {
-                self.api_messages.push(ApiMessage {
-                    role: "user".to_string(),
-                    content: Content::Blocks(tool_result_blocks),
-                });
+            let (content, full_content) = if use_structured_round {
+                (
+                    Content::Blocks(tool_result_blocks),
+                    Content::Blocks(full_tool_result_blocks),
+                )
             } else {
-                self.api_messages.push(ApiMessage {
+                (
+                    Content::Text(text_protocol_tool_results.join("\n\n")),
+                    Content::Text(full_text_protocol_tool_results.join("\n\n")),
+                )
+            };
+            let full = tool_results_shortened.then(|| ApiMessage {
+                role: "user".to_string(),
+                content: full_content,
+            });
+            self.push_history_message(
+                ApiMessage {
                     role: "user".to_string(),
-                    content: Content::Text(text_protocol_tool_results.join("\n\n")),
-                });
+                    content,
+                },
+                full,
+            );
+        }
+    }
+
+    /// Why `name` may not run in this session, if it may not.
+    fn blocked_tool_reason(&self, name: &str) -> Option<String> {
+        if self.delegate_max_rounds.is_some() && !DELEGATE_TOOLS.contains(&name) {
+            return Some(format!(
+                "{name} is not available to a delegate sub-agent; only read-only tools are"
+            ));
+        }
+        if self.plan_mode && tool_requires_confirmation(name) {
+            return Some(format!(
+                "{name} is blocked in plan mode; investigate with read-only tools and reply with a numbered plan"
+            ));
+        }
+        None
+    }
+
+    /// Run `task` in a fresh sub-agent with read-only tools and return its
+    /// final reply. Only the sub-agent's tool calls reach the frontend, as
+    /// [`DelegateUpdate`]s; its usage is added to this session's.
+    ///
+    /// Boxed because the sub-agent's turn runs this same tool loop.
+    fn run_delegate<'a>(
+        &'a mut self,
+        input: &'a serde_json::Value,
+        stream_delta_tx: Option<&'a mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let task = required_tool_string(input, DELEGATE_TOOL_NAME, "task")?;
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::Delegate(DelegateUpdate::Started {
+                    task: task.to_string(),
+                }),
+            );
+
+            let mut sub_agent = self.delegate_sub_agent();
+            let (sub_tx, mut sub_rx) = mpsc::unbounded_channel();
+            let mut tool_calls = 0usize;
+            let result = {
+                let turn = sub_agent.send_message(
+                    delegate_prompt(task),
+                    // Without a frontend there is nobody to ask, so approvals
+                    // resolve the way they do for the parent.
+                    stream_delta_tx.map(|_| &sub_tx),
+                );
+                tokio::pin!(turn);
+                loop {
+                    tokio::select! {
+                        result = &mut turn => break result,
+                        Some(update) = sub_rx.recv() => {
+                            tool_calls += forward_delegate_update(update, stream_delta_tx);
+                        }
+                    }
+                }
+            };
+            while let Ok(update) = sub_rx.try_recv() {
+                tool_calls += forward_delegate_update(update, stream_delta_tx);
             }
+
+            self.usage.absorb(&sub_agent.usage_report());
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::Usage(self.usage_report()),
+            );
+            let summary = result
+                .map_err(|error| anyhow::anyhow!("delegate sub-agent failed: {error}"))
+                .and_then(|summary| {
+                    let summary = summary.trim();
+                    if summary.is_empty() {
+                        bail!("delegate sub-agent finished without a summary");
+                    }
+                    Ok(summary.to_string())
+                });
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::Delegate(DelegateUpdate::Finished {
+                    tool_calls,
+                    ok: summary.is_ok(),
+                }),
+            );
+            summary
+        })
+    }
+
+    /// A manager with an empty history sharing this one's client and
+    /// workspace, limited to [`DELEGATE_TOOLS`].
+    fn delegate_sub_agent(&self) -> Self {
+        let mut client = (*self.client).clone().with_tool_allowlist(DELEGATE_TOOLS);
+        client.set_mode_instructions(None);
+        let mut sub_agent = Self::new(client, self.tool_operator.clone());
+        sub_agent.delegate_max_rounds = Some(resolve_delegate_max_rounds());
+        sub_agent.policy = Arc::clone(&self.policy);
+        sub_agent.tool_hooks = Arc::clone(&self.tool_hooks);
+        sub_agent.audit = self.audit.clone();
+        sub_agent.audit_turn = self.audit_turn;
+        #[cfg(test)]
+        {
+            sub_agent.mock_tool_operator_responses = self.mock_tool_operator_responses.clone();
         }
+        sub_agent
+    }
+
+    fn record_response_usage(
+        &mut self,
+        usage: &Usage,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) {
+        if usage.is_empty() {
+            return;
+        }
+        self.usage
+            .record_response(self.client.model_pricing(), usage);
+        emit_stream_update(
+            stream_delta_tx,
+            ConversationStreamUpdate::Usage(self.usage_report()),
+        );
     }
 
     async fn request_tool_approval(
@@ -673,10 +1822,8 @@ impl ConversationManager {
         name: &str,
         input: &serde_json::Value,
         stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
-    ) -> bool {
-        let Some(tx) = stream_delta_tx else {
-            return true;
-        };
+    ) -> Option<ApprovalDecision> {
+        let tx = stream_delta_tx?;
 
         let (response_tx, response_rx) = oneshot::channel();
         let request = ToolApprovalRequest {
@@ -689,10 +1836,49 @@ impl ConversationManager {
             .send(ConversationStreamUpdate::ToolApprovalRequest(request))
             .is_err()
         {
-            return false;
+            return Some(ApprovalDecision::Denied);
+        }
+
+        Some(response_rx.await.unwrap_or(ApprovalDecision::Denied))
+    }
+
+    /// An audit record for a call that did not run, approved by rule;
+    /// callers override what actually happened.
+    fn tool_call_record<'a>(
+        &self,
+        name: &'a str,
+        input: &'a serde_json::Value,
+    ) -> ToolCallRecord<'a> {
+        ToolCallRecord {
+            turn: self.audit_turn,
+            delegate: self.delegate_max_rounds.is_some(),
+            tool: name,
+            input,
+            decision: AuditDecision::Approved,
+            decided_by: AuditApprover::Rule,
+            duration: Duration::ZERO,
+            result: None,
+            files: Vec::new(),
         }
+    }
 
-        response_rx.await.unwrap_or(false)
+    fn audit_tool_call(
+        &self,
+        record: ToolCallRecord<'_>,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) {
+        let Some(log) = &self.audit else {
+            return;
+        };
+        if let Err(error) = log.record(self.tool_operator.working_dir(), record) {
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::AuditFailed(format!(
+                    "could not write {}: {error}",
+                    log.path().display()
+                )),
+            );
+        }
     }
 
     #[cfg(test)]
@@ -712,19 +1898,38 @@ impl ConversationManager {
         }
     }
 
+    /// Run `name` between its hooks and return how that went as a [`ToolRun`]:
+    /// vetoed, timed out, or finished with the tool's result and what the
+    /// post hooks printed. Only the tool itself is held to `tool_timeout`;
+    /// hooks have their own, so a slow post hook still returns the result.
     async fn execute_tool_with_timeout(
         &self,
         name: &str,
         input: &serde_json::Value,
         tool_timeout: Duration,
-    ) -> Result<String> {
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) -> ToolRun {
         let tool_name = name.to_string();
+        let working_dir = self.tool_operator.working_dir().to_path_buf();
+
+        let hooks = Arc::clone(&self.tool_hooks);
+        let (hook_name, hook_input, hook_dir) =
+            (tool_name.clone(), input.clone(), working_dir.clone());
+        let pre = run_hooks_blocking(stream_delta_tx, move |report| {
+            hooks.run_pre(&hook_name, &hook_input, &hook_dir, report)
+        })
+        .await;
+        match pre {
+            Ok(Ok(())) => {}
+            Ok(Err(veto)) => return ToolRun::Vetoed(veto),
+            Err(error) => return ToolRun::Finished(Err(error), String::new()),
+        }
+
         let task_name = tool_name.clone();
         let task_input = input.clone();
         let task_executor = self.tool_operator.clone();
         #[cfg(test)]
         let task_mock_responses = self.mock_tool_operator_responses.clone();
-
         let mut task = tokio::task::spawn_blocking(move || {
             #[cfg(test)]
             {
@@ -741,21 +1946,34 @@ impl ConversationManager {
             }
         });
 
-        match tokio::time::timeout(tool_timeout, &mut task).await {
-            Ok(join_result) => match join_result {
-                Ok(result) => result,
-                Err(join_error) => Err(anyhow::anyhow!(
-                    "Tool execution task failed for {tool_name}: {join_error}"
-                )),
-            },
+        let output = match tokio::time::timeout(tool_timeout, &mut task).await {
+            Ok(Ok(Ok(output))) => output,
+            Ok(Ok(Err(error))) => return ToolRun::Finished(Err(error), String::new()),
+            Ok(Err(join_error)) => {
+                return ToolRun::Finished(
+                    Err(anyhow::anyhow!(
+                        "Tool execution task failed for {tool_name}: {join_error}"
+                    )),
+                    String::new(),
+                )
+            }
             Err(_) => {
                 task.abort();
-                Err(anyhow::anyhow!(
+                return ToolRun::TimedOut(anyhow::anyhow!(
                     "Tool execution timed out after {}s for {tool_name}",
                     tool_timeout.as_secs()
-                ))
+                ));
             }
-        }
+        };
+
+        let hooks = Arc::clone(&self.tool_hooks);
+        let (hook_input, hook_output) = (input.clone(), output.clone());
+        let notes = run_hooks_blocking(stream_delta_tx, move |report| {
+            hooks.run_post(&tool_name, &hook_input, &hook_output, &working_dir, report)
+        })
+        .await
+        .unwrap_or_else(|error| format!("\n\n[{error}]"));
+        ToolRun::Finished(Ok(output), notes)
     }
 
     #[cfg(test)]
@@ -821,18 +2039,105 @@ impl ConversationManager {
         }
 
         if keep_start >= len {
-            self.api_messages.clear();
+            self.archived_messages.append(&mut self.api_messages);
             return 0;
         }
 
         if keep_start > 0 {
-            self.api_messages.drain(0..keep_start);
+            self.archived_messages
+                .extend(self.api_messages.drain(0..keep_start));
             preserve_index.saturating_sub(keep_start)
         } else {
             preserve_index
         }
     }
 
+    fn token_budget(&self, estimator: &TokenEstimator) -> TokenBudget {
+        TokenBudget {
+            context_window: self.client.context_window() as usize,
+            reserved_output: self.client.reserved_output_tokens() as usize,
+            prompt_overhead: estimator.chars_to_tokens(self.client.request_overhead_chars()),
+        }
+    }
+
+    /// Shrink history to `limit` estimated tokens and return the moved anchor.
+    ///
+    /// Tool results are evicted first, largest first and oldest on ties, by
+    /// replacing their content in place so every tool_use keeps its
+    /// tool_result. The newest message is left alone: its results have not
+    /// been seen by the model yet. If that is not enough, whole exchanges are
+    /// dropped from the front with the same rules as count-based pruning.
+    fn fit_history_to_budget(
+        &mut self,
+        estimator: &TokenEstimator,
+        limit: usize,
+        preserve_index: usize,
+    ) -> usize {
+        let mut total = estimator.history_tokens(&self.api_messages);
+        if total <= limit {
+            return preserve_index;
+        }
+
+        let newest = self.api_messages.len().saturating_sub(1);
+        let mut candidates = Vec::new();
+        for (message_index, message) in self.api_messages[..newest].iter().enumerate() {
+            match &message.content {
+                Content::Blocks(blocks) => {
+                    for (block_index, block) in blocks.iter().enumerate() {
+                        if let ContentBlock::ToolResult { content, .. } = block {
+                            let tokens = estimator.text_tokens(content);
+                            candidates.push((tokens, message_index, Some(block_index)));
+                        }
+                    }
+                }
+                Content::Text(text)
+                    if message.role == "user" && is_text_protocol_tool_result(text) =>
+                {
+                    candidates.push((estimator.text_tokens(text), message_index, None));
+                }
+                Content::Text(_) => {}
+            }
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        for (tokens, message_index, block_index) in candidates {
+            if total <= limit {
+                break;
+            }
+            let stub = format!("{EVICTED_TOOL_RESULT_PREFIX} ~{tokens} tokens]");
+            let saved = tokens.saturating_sub(estimator.text_tokens(&stub));
+            if saved == 0 {
+                continue;
+            }
+            let session_index = self.archived_messages.len() + message_index;
+            if !self.full_messages.contains_key(&session_index) {
+                let original = self.api_messages[message_index].clone();
+                self.full_messages.insert(session_index, original);
+            }
+            match (&mut self.api_messages[message_index].content, block_index) {
+                (Content::Blocks(blocks), Some(block_index)) => {
+                    if let ContentBlock::ToolResult { content, .. } = &mut blocks[block_index] {
+                        *content = stub;
+                    }
+                }
+                (Content::Text(text), None) => *text = stub,
+                _ => continue,
+            }
+            total -= saved;
+        }
+
+        let mut anchor = preserve_index;
+        while total > limit {
+            let before = self.api_messages.len();
+            anchor = self.prune_message_history_preserving(before - 1, anchor);
+            if self.api_messages.len() == before {
+                break;
+            }
+            total = estimator.history_tokens(&self.api_messages);
+        }
+        anchor
+    }
+
     fn format_tool_result_for_history(
         &mut self,
         name: &str,
@@ -960,6 +2265,38 @@ impl ConversationManager {
         appended
     }
 
+    fn append_reasoning_delta(
+        &mut self,
+        index: usize,
+        text: &str,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) {
+        if let Some(StreamBlock::Reasoning { content, .. }) =
+            self.current_turn_blocks.get_mut(index)
+        {
+            content.push_str(text);
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::BlockDelta {
+                    index,
+                    delta: text.to_string(),
+                },
+            );
+        }
+    }
+
+    fn cancel_tool_calls<'a>(
+        &mut self,
+        blocks: impl Iterator<Item = &'a ContentBlock>,
+        stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    ) {
+        for block in blocks {
+            if let ContentBlock::ToolUse { id, .. } = block {
+                self.set_tool_call_status(id, ToolStatus::Cancelled, stream_delta_tx);
+            }
+        }
+    }
+
     fn set_tool_call_status(
         &mut self,
         tool_call_id: &str,
@@ -1238,7 +2575,37 @@ fn execute_tool_dispatch(
     }
 }
 
-fn message_contains_tool_result(message: &ApiMessage) -> bool {
+fn image_mentions(input: &str) -> Vec<String> {
+    input
+        .split_whitespace()
+        .filter_map(|token| token.strip_prefix('@'))
+        .map(|path| path.trim_end_matches([',', '.', ';', ':', ')', '!', '?', '"', '\'']))
+        .filter(|path| is_image_path(path))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Text of a user prompt; image blocks are skipped.
+pub(super) fn user_prompt_text(message: &ApiMessage) -> String {
+    match &message.content {
+        Content::Text(text) => text.clone(),
+        Content::Blocks(blocks) => blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Text-protocol rounds send tool output back as a plain user message.
+pub(super) fn is_text_protocol_tool_result(text: &str) -> bool {
+    text.starts_with("tool_result ") || text.starts_with("tool_error ")
+}
+
+pub(super) fn message_contains_tool_result(message: &ApiMessage) -> bool {
     match &message.content {
         Content::Blocks(blocks) => blocks
             .iter()
@@ -1256,6 +2623,56 @@ fn emit_stream_update(
     }
 }
 
+/// Pass a sub-agent's update on to the parent's frontend: its tool calls
+/// and approval prompts. Returns 1 for a tool call, 0 otherwise.
+fn forward_delegate_update(
+    update: ConversationStreamUpdate,
+    stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+) -> usize {
+    match update {
+        ConversationStreamUpdate::Delegate(DelegateUpdate::Step(step)) => {
+            emit_stream_update(
+                stream_delta_tx,
+                ConversationStreamUpdate::Delegate(DelegateUpdate::Step(step)),
+            );
+            1
+        }
+        update @ (ConversationStreamUpdate::ToolApprovalRequest(_)
+        | ConversationStreamUpdate::Hook(_)
+        | ConversationStreamUpdate::AuditFailed(_)) => {
+            emit_stream_update(stream_delta_tx, update);
+            0
+        }
+        _ => 0,
+    }
+}
+
+fn is_context_overflow(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<ApiError>(),
+        Some(ApiError::ContextLengthExceeded { .. })
+    )
+}
+
+/// Next chunk of a model stream, failing when the stream goes quiet for
+/// longer than the idle timeout.
+async fn next_stream_chunk(
+    stream: &mut ByteStream,
+    idle_timeout: Option<Duration>,
+) -> Result<Option<bytes::Bytes>> {
+    let next = match idle_timeout {
+        Some(limit) => match tokio::time::timeout(limit, stream.next()).await {
+            Ok(next) => next,
+            Err(_) => bail!(
+                "model stream stalled: no data for {}s (VEX_STREAM_IDLE_TIMEOUT_SECS)",
+                limit.as_secs()
+            ),
+        },
+        None => stream.next().await,
+    };
+    next.transpose()
+}
+
 fn emit_text_update(
     stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
     text: String,
@@ -1312,40 +2729,35 @@ fn append_incremental_suffix(existing: &mut String, incoming: &str) -> String {
     suffix
 }
 
-fn resolve_history_limits(is_local_endpoint: bool) -> HistoryLimits {
-    let defaults = if is_local_endpoint {
-        HistoryLimits {
-            max_assistant_history_chars: LOCAL_DEFAULT_MAX_ASSISTANT_HISTORY_CHARS,
-            max_tool_result_history_chars: LOCAL_DEFAULT_MAX_TOOL_RESULT_HISTORY_CHARS,
-            max_api_messages: LOCAL_DEFAULT_MAX_API_MESSAGES,
-        }
+/// `tool_result_budget_chars` is the per-result share of the token budget,
+/// used unless `VEX_MAX_TOOL_RESULT_HISTORY_CHARS` is set.
+fn resolve_history_limits(
+    is_local_endpoint: bool,
+    tool_result_budget_chars: usize,
+) -> HistoryLimits {
+    let default_assistant_chars = if is_local_endpoint {
+        LOCAL_DEFAULT_MAX_ASSISTANT_HISTORY_CHARS
     } else {
-        HistoryLimits {
-            max_assistant_history_chars: REMOTE_DEFAULT_MAX_ASSISTANT_HISTORY_CHARS,
-            max_tool_result_history_chars: REMOTE_DEFAULT_MAX_TOOL_RESULT_HISTORY_CHARS,
-            max_api_messages: REMOTE_DEFAULT_MAX_API_MESSAGES,
-        }
+        REMOTE_DEFAULT_MAX_ASSISTANT_HISTORY_CHARS
     };
 
     HistoryLimits {
         max_assistant_history_chars: env_override_usize(
             "VEX_MAX_ASSISTANT_HISTORY_CHARS",
-            defaults.max_assistant_history_chars,
+            default_assistant_chars,
             200,
             20_000,
         ),
         max_tool_result_history_chars: env_override_usize(
             "VEX_MAX_TOOL_RESULT_HISTORY_CHARS",
-            defaults.max_tool_result_history_chars,
+            tool_result_budget_chars.clamp(1_000, 40_000),
             200,
             40_000,
         ),
-        max_api_messages: env_override_usize(
-            "VEX_MAX_API_MESSAGES",
-            defaults.max_api_messages,
-            4,
-            128,
-        ),
+        max_api_messages: std::env::var("VEX_MAX_API_MESSAGES")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .map(|v| v.clamp(4, 128)),
     }
 }
 
@@ -1364,6 +2776,21 @@ fn resolve_tool_timeout(is_local_endpoint: bool) -> Duration {
     Duration::from_secs(secs)
 }
 
+/// Run tool hooks off the async runtime, reporting each hook as it finishes.
+async fn run_hooks_blocking<T: Send + 'static>(
+    stream_delta_tx: Option<&mpsc::UnboundedSender<ConversationStreamUpdate>>,
+    run: impl FnOnce(&mut dyn FnMut(HookRun)) -> T + Send + 'static,
+) -> Result<T> {
+    let hook_tx = stream_delta_tx.cloned();
+    tokio::task::spawn_blocking(move || {
+        run(&mut |hook_run: HookRun| {
+            emit_stream_update(hook_tx.as_ref(), ConversationStreamUpdate::Hook(hook_run));
+        })
+    })
+    .await
+    .map_err(|error| anyhow::anyhow!("tool hook task failed: {error}"))
+}
+
 fn resolve_max_tool_rounds(is_local_endpoint: bool) -> usize {
     let default_rounds = if is_local_endpoint { 12 } else { 24 };
     std::env::var("VEX_MAX_TOOL_ROUNDS")
@@ -1373,6 +2800,58 @@ fn resolve_max_tool_rounds(is_local_endpoint: bool) -> usize {
         .clamp(2, 64)
 }
 
+fn resolve_delegate_max_rounds() -> usize {
+    env_override_usize(
+        "VEX_DELEGATE_MAX_ROUNDS",
+        DEFAULT_DELEGATE_MAX_ROUNDS,
+        2,
+        64,
+    )
+}
+
+fn resolve_stream_timeouts(is_local_endpoint: bool) -> StreamTimeouts {
+    let (first_token_secs, idle_secs) = if is_local_endpoint {
+        (
+            LOCAL_DEFAULT_FIRST_TOKEN_TIMEOUT_SECS,
+            LOCAL_DEFAULT_STREAM_IDLE_TIMEOUT_SECS,
+        )
+    } else {
+        (
+            REMOTE_DEFAULT_FIRST_TOKEN_TIMEOUT_SECS,
+            REMOTE_DEFAULT_STREAM_IDLE_TIMEOUT_SECS,
+        )
+    };
+    StreamTimeouts {
+        first_token: env_timeout_secs("VEX_FIRST_TOKEN_TIMEOUT_SECS", first_token_secs),
+        idle: env_timeout_secs("VEX_STREAM_IDLE_TIMEOUT_SECS", idle_secs),
+        stall_retries: env_override_usize("VEX_STALL_RETRIES", DEFAULT_STALL_RETRIES, 0, 5),
+    }
+}
+
+/// Timeout in seconds from `key`; `0` disables it.
+fn env_timeout_secs(key: &str, default_secs: usize) -> Option<Duration> {
+    let secs = env_override_usize(key, default_secs, 0, 3_600);
+    (secs > 0).then(|| Duration::from_secs(secs as u64))
+}
+
+fn resolve_auto_compact_percent() -> usize {
+    env_override_usize(
+        "VEX_AUTO_COMPACT_PERCENT",
+        DEFAULT_AUTO_COMPACT_PERCENT,
+        0,
+        95,
+    )
+}
+
+fn resolve_max_continuations() -> usize {
+    env_override_usize(
+        "VEX_MAX_CONTINUATIONS",
+        DEFAULT_MAX_CONTINUATIONS,
+        0,
+        MAX_CONTINUATIONS_LIMIT,
+    )
+}
+
 fn env_override_usize(key: &str, default: usize, min: usize, max: usize) -> usize {
     std::env::var(key)
         .ok()
@@ -1770,6 +3249,7 @@ fn tool_round_signature(blocks: &[ContentBlock]) -> Vec<String> {
 mod tests {
     use super::*;
     use crate::api::ApiClient;
+    use crate::tools::HookEvent;
     use serde_json::json;
     use tempfile::TempDir;
     use tokio::sync::mpsc;
@@ -2013,7 +3493,9 @@ data: {"type":"message_stop"}"#.to_string(),
                 match block {
                     StreamBlock::Thinking { .. } => saw_thinking_start = true,
                     StreamBlock::FinalText { content } => final_block_content = content,
-                    StreamBlock::ToolCall { .. } | StreamBlock::ToolResult { .. } => {}
+                    StreamBlock::ToolCall { .. }
+                    | StreamBlock::ToolResult { .. }
+                    | StreamBlock::Reasoning { .. } => {}
                 }
             }
         }
@@ -2085,15 +3567,28 @@ data: {"type":"message_stop"}"#.to_string(),
                             match block {
                                 StreamBlock::Thinking { .. } => saw_thinking_start = true,
                                 StreamBlock::FinalText { .. } => saw_final_start = true,
-                                StreamBlock::ToolCall { .. } | StreamBlock::ToolResult { .. } => {}
+                                StreamBlock::ToolCall { .. }
+                    | StreamBlock::ToolResult { .. }
+                    | StreamBlock::Reasoning { .. } => {}
                             }
                         }
                         ConversationStreamUpdate::ToolApprovalRequest(request) => {
-                            let _ = request.response_tx.send(true);
+                            let _ = request.response_tx.send(ApprovalDecision::Approved);
                         }
                         ConversationStreamUpdate::Delta(_)
                         | ConversationStreamUpdate::BlockDelta { .. }
-                        | ConversationStreamUpdate::BlockComplete { .. } => {}
+                        | ConversationStreamUpdate::BlockComplete { .. }
+                        | ConversationStreamUpdate::Usage(_)
+                        | ConversationStreamUpdate::OutputTruncated(_)
+                        | ConversationStreamUpdate::AwaitingFirstToken
+                        | ConversationStreamUpdate::FirstTokenReceived
+                        | ConversationStreamUpdate::StreamStalled(_)
+                        | ConversationStreamUpdate::ContextPruned { .. }
+                        | ConversationStreamUpdate::Compacted(_)
+                        | ConversationStreamUpdate::CompactionFailed(_)
+                        | ConversationStreamUpdate::Delegate(_)
+                        | ConversationStreamUpdate::Hook(_)
+                        | ConversationStreamUpdate::AuditFailed(_) => {}
                     }
                 }
             }
@@ -2106,7 +3601,9 @@ data: {"type":"message_stop"}"#.to_string(),
                 match block {
                     StreamBlock::Thinking { .. } => saw_thinking_start = true,
                     StreamBlock::FinalText { .. } => saw_final_start = true,
-                    StreamBlock::ToolCall { .. } | StreamBlock::ToolResult { .. } => {}
+                    StreamBlock::ToolCall { .. }
+                    | StreamBlock::ToolResult { .. }
+                    | StreamBlock::Reasoning { .. } => {}
                 }
             }
         }
@@ -2116,6 +3613,370 @@ data: {"type":"message_stop"}"#.to_string(),
         Ok(())
     }
 
+    /// A structured round calling each `(id, name, input)` tool in order.
+    fn tool_use_round(message_id: &str, calls: &[(&str, &str, serde_json::Value)]) -> Vec<String> {
+        let event = |name: &str, data: serde_json::Value| format!("event: {name}\ndata: {data}");
+        let mut events = vec![event(
+            "message_start",
+            json!({"type": "message_start", "message": {"id": message_id, "type": "message", "role": "assistant", "model": "mock-model", "content": [], "stop_reason": null, "stop_sequence": null, "usage": {"input_tokens": 10, "output_tokens": 1}}}),
+        )];
+        for (index, (id, name, input)) in calls.iter().enumerate() {
+            events.push(event(
+                "content_block_start",
+                json!({"type": "content_block_start", "index": index, "content_block": {"type": "tool_use", "id": id, "name": name, "input": {}}}),
+            ));
+            events.push(event(
+                "content_block_delta",
+                json!({"type": "content_block_delta", "index": index, "delta": {"type": "input_json_delta", "partial_json": input.to_string()}}),
+            ));
+            events.push(event(
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": index}),
+            ));
+        }
+        events.push(event(
+            "message_delta",
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use", "stop_sequence": null}, "usage": {"output_tokens": 10}}),
+        ));
+        events.push(event("message_stop", json!({"type": "message_stop"})));
+        events
+    }
+
+    #[tokio::test]
+    async fn test_delegate_runs_read_only_sub_agent_and_returns_summary() -> Result<()> {
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                tool_use_round(
+                    "msg_parent_1",
+                    &[(
+                        "toolu_delegate",
+                        "delegate",
+                        json!({ "task": "Find the greeting" }),
+                    )],
+                ),
+                tool_use_round(
+                    "msg_sub_1",
+                    &[
+                        ("toolu_read", "read_file", json!({ "path": "file.txt" })),
+                        (
+                            "toolu_write",
+                            "write_file",
+                            json!({ "path": "file.txt", "content": "bye" }),
+                        ),
+                    ],
+                ),
+                plain_text_round("msg_sub_2", "The greeting is hello, in file.txt:1."),
+                plain_text_round("msg_parent_2", "It says hello."),
+            ])));
+        let mut mock_tool_responses = HashMap::new();
+        mock_tool_responses.insert("file.txt".to_string(), "hello".to_string());
+        let mut manager = ConversationManager::new_mock(mock_api_client, mock_tool_responses);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut delegate_updates = Vec::new();
+        let mut approvals = Vec::new();
+        let final_text = {
+            let mut send_future =
+                std::pin::pin!(manager.send_message("what does it say?".to_string(), Some(&tx)));
+            loop {
+                tokio::select! {
+                    result = &mut send_future => break result?,
+                    Some(update) = rx.recv() => match update {
+                        ConversationStreamUpdate::Delegate(update) => delegate_updates.push(update),
+                        ConversationStreamUpdate::ToolApprovalRequest(request) => {
+                            approvals.push(request.tool_name.clone());
+                            let _ = request.response_tx.send(ApprovalDecision::Approved);
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        };
+        while let Ok(update) = rx.try_recv() {
+            if let ConversationStreamUpdate::Delegate(update) = update {
+                delegate_updates.push(update);
+            }
+        }
+
+        assert_eq!(final_text, "It says hello.");
+        assert!(!approvals.contains(&"write_file".to_string()));
+        assert_eq!(
+            delegate_updates,
+            vec![
+                DelegateUpdate::Started {
+                    task: "Find the greeting".to_string()
+                },
+                DelegateUpdate::Step("read_file file.txt".to_string()),
+                DelegateUpdate::Finished {
+                    tool_calls: 1,
+                    ok: true
+                },
+            ]
+        );
+
+        // Only the summary reaches the parent; the sub-agent's reads do not.
+        let messages = manager.messages_for_api();
+        assert_eq!(messages.len(), 4);
+        let Content::Blocks(blocks) = &messages[2].content else {
+            panic!("expected a tool_result message");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolResult { content, is_error: false, .. }
+                if content == "The greeting is hello, in file.txt:1."
+        ));
+        // Sub-agent responses are billed to this session.
+        assert_eq!(manager.usage_report().turn.output_tokens, 38);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_plan_mode_blocks_mutating_tools_without_asking() -> Result<()> {
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                tool_use_round(
+                    "msg_plan_1",
+                    &[(
+                        "toolu_write",
+                        "write_file",
+                        json!({ "path": "file.txt", "content": "bye" }),
+                    )],
+                ),
+                plain_text_round("msg_plan_2", "1. Replace the greeting in file.txt"),
+            ])));
+        let mut manager = ConversationManager::new_mock(mock_api_client, HashMap::new());
+        manager.set_plan_mode(true);
+        assert!(manager.client().system_prompt().ends_with(PLAN_MODE_PROMPT));
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let reply = manager
+            .send_message("change the greeting".to_string(), Some(&tx))
+            .await?;
+        assert_eq!(reply, "1. Replace the greeting in file.txt");
+        while let Ok(update) = rx.try_recv() {
+            assert!(!matches!(
+                update,
+                ConversationStreamUpdate::ToolApprovalRequest(_)
+            ));
+        }
+        let messages = manager.messages_for_api();
+        let Content::Blocks(blocks) = &messages[2].content else {
+            panic!("expected a tool_result message");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolResult { content, is_error: true, .. }
+                if content.starts_with("write_file is blocked in plan mode")
+        ));
+
+        manager.set_plan_mode(false);
+        assert!(!manager.client().system_prompt().contains(PLAN_MODE_PROMPT));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tool_hooks_veto_calls_and_extend_results() -> Result<()> {
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                tool_use_round(
+                    "msg_hook_1",
+                    &[
+                        ("toolu_secret", "read_file", json!({ "path": "secret.txt" })),
+                        ("toolu_notes", "read_file", json!({ "path": "notes.txt" })),
+                    ],
+                ),
+                plain_text_round("msg_hook_2", "Read the notes."),
+            ])));
+        let mut manager = ConversationManager::new_mock(
+            mock_api_client,
+            HashMap::from([
+                ("secret.txt".to_string(), "hunter2".to_string()),
+                ("notes.txt".to_string(), "buy milk".to_string()),
+            ]),
+        );
+        manager.set_tool_hooks(ToolHooks::from_json(
+            r#"{
+                "pre_tool_use": [{
+                    "tools": "read_file",
+                    "command": "grep -q secret && { echo 'secrets stay private'; exit 1; }; exit 0"
+                }],
+                "post_tool_use": [{"command": "echo \"hooked $VEX_TOOL_NAME\""}]
+            }"#,
+        )?);
+        let temp = TempDir::new()?;
+        let log_path = temp.path().join("audit.jsonl");
+        manager.set_audit_log(Some(AuditLog::new(log_path.clone())));
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager
+            .send_message("read my files".to_string(), Some(&tx))
+            .await?;
+
+        let messages = manager.messages_for_api();
+        let Content::Blocks(blocks) = &messages[2].content else {
+            panic!("expected a tool_result message");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolResult { content, is_error: true, .. }
+                if content.contains("blocked by hook") && content.contains("secrets stay private")
+        ));
+        assert!(matches!(
+            &blocks[1],
+            ContentBlock::ToolResult { content, is_error: false, .. }
+                if content.contains("buy milk") && content.ends_with("hooked read_file")
+        ));
+
+        let mut runs = Vec::new();
+        while let Ok(update) = rx.try_recv() {
+            if let ConversationStreamUpdate::Hook(run) = update {
+                runs.push((run.event, run.failure.is_some()));
+            }
+        }
+        assert_eq!(
+            runs,
+            vec![
+                (HookEvent::PreToolUse, true),
+                (HookEvent::PreToolUse, false),
+                (HookEvent::PostToolUse, false),
+            ]
+        );
+
+        let entries = crate::audit::read_audit_log(&log_path)?;
+        let decisions: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.decision, entry.decided_by, entry.status))
+            .collect();
+        assert_eq!(
+            decisions,
+            vec![
+                (
+                    AuditDecision::Denied,
+                    AuditApprover::Hook,
+                    crate::audit::AuditStatus::NotRun,
+                ),
+                (
+                    AuditDecision::Approved,
+                    AuditApprover::Rule,
+                    crate::audit::AuditStatus::Ok,
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_hook_outliving_the_tool_timeout_keeps_the_result() -> Result<()> {
+        let client = ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(
+            vec![],
+        )));
+        let mut manager = ConversationManager::new_mock(
+            client,
+            HashMap::from([("notes.txt".to_string(), "buy milk".to_string())]),
+        );
+        manager.set_tool_hooks(ToolHooks::from_json(
+            r#"{"post_tool_use": [{"command": "sleep 2; echo formatted", "timeout_secs": 10}]}"#,
+        )?);
+
+        let ToolRun::Finished(result, notes) = manager
+            .execute_tool_with_timeout(
+                "read_file",
+                &json!({ "path": "notes.txt" }),
+                Duration::from_secs(1),
+                None,
+            )
+            .await
+        else {
+            panic!("read_file should have finished");
+        };
+
+        assert!(result?.contains("buy milk"));
+        assert!(notes.ends_with("formatted"), "{notes}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records_each_tool_call_with_its_decision() -> Result<()> {
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                tool_use_round(
+                    "msg_audit_1",
+                    &[
+                        ("toolu_read", "read_file", json!({ "path": "file.txt" })),
+                        (
+                            "toolu_write",
+                            "write_file",
+                            json!({ "path": "file.txt", "content": "bye" }),
+                        ),
+                    ],
+                ),
+                plain_text_round("msg_audit_2", "Done."),
+            ])));
+        let mut mock_tool_responses = HashMap::new();
+        mock_tool_responses.insert("file.txt".to_string(), "hello".to_string());
+        let mut manager = ConversationManager::new_mock(mock_api_client, mock_tool_responses);
+        let temp = TempDir::new()?;
+        let log_path = temp.path().join("audit.jsonl");
+        manager.set_audit_log(Some(AuditLog::new(log_path.clone())));
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let final_text = {
+            let mut send_future =
+                std::pin::pin!(manager.send_message("rewrite it".to_string(), Some(&tx)));
+            loop {
+                tokio::select! {
+                    result = &mut send_future => break result?,
+                    Some(update) = rx.recv() => {
+                        if let ConversationStreamUpdate::ToolApprovalRequest(request) = update {
+                            let _ = request.response_tx.send(ApprovalDecision::ApprovedForSession);
+                        }
+                    }
+                }
+            }
+        };
+        assert_eq!(final_text, "Done.");
+
+        let entries = crate::audit::read_audit_log(&log_path)?;
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.turn,
+                    entry.tool.as_str(),
+                    entry.decision,
+                    entry.decided_by,
+                    entry.status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    1,
+                    "read_file",
+                    AuditDecision::Approved,
+                    AuditApprover::Rule,
+                    crate::audit::AuditStatus::Ok,
+                ),
+                (
+                    1,
+                    "write_file",
+                    AuditDecision::Approved,
+                    AuditApprover::SessionAuto,
+                    crate::audit::AuditStatus::Ok,
+                ),
+            ]
+        );
+        assert_eq!(entries[1].input["path"], "file.txt");
+        assert!(entries[1].input["content"]
+            .as_str()
+            .is_some_and(|content| content.starts_with("[3 bytes, sha256:")));
+        assert!(entries.iter().all(|entry| Some(entry.session_id.as_str())
+            == manager.audit_log().map(AuditLog::session_id)));
+        Ok(())
+    }
+
     #[test]
     fn test_parse_tagged_tool_calls() {
         let text = r#"I can do this.
@@ -2408,11 +4269,22 @@ data: {"type":"message_stop"}"#.to_string(),
                             }
                         }
                         ConversationStreamUpdate::ToolApprovalRequest(request) => {
-                            let _ = request.response_tx.send(true);
+                            let _ = request.response_tx.send(ApprovalDecision::Approved);
                         }
                         ConversationStreamUpdate::Delta(_)
                         | ConversationStreamUpdate::BlockDelta { .. }
-                        | ConversationStreamUpdate::BlockComplete { .. } => {}
+                        | ConversationStreamUpdate::BlockComplete { .. }
+                        | ConversationStreamUpdate::Usage(_)
+                        | ConversationStreamUpdate::OutputTruncated(_)
+                        | ConversationStreamUpdate::AwaitingFirstToken
+                        | ConversationStreamUpdate::FirstTokenReceived
+                        | ConversationStreamUpdate::StreamStalled(_)
+                        | ConversationStreamUpdate::ContextPruned { .. }
+                        | ConversationStreamUpdate::Compacted(_)
+                        | ConversationStreamUpdate::CompactionFailed(_)
+                        | ConversationStreamUpdate::Delegate(_)
+                        | ConversationStreamUpdate::Hook(_)
+                        | ConversationStreamUpdate::AuditFailed(_) => {}
                     }
                 }
             }
@@ -2474,6 +4346,215 @@ data: {"type":"message_stop"}"#.to_string(),
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_thinking_blocks_replay_with_signature_in_tool_round() -> Result<()> {
+        let first_response_sse = vec![
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#.to_string(),
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Need the file first."}}"#.to_string(),
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig-abc=="}}"#.to_string(),
+            r#"data: {"type":"content_block_stop","index":0}"#.to_string(),
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"redacted_thinking","data":"opaque-blob"}}"#.to_string(),
+            r#"data: {"type":"content_block_stop","index":1}"#.to_string(),
+            r#"data: {"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_think_1","name":"read_file","input":{"path":"file.txt"}}}"#.to_string(),
+            r#"data: {"type":"content_block_stop","index":2}"#.to_string(),
+            r#"data: {"type":"message_stop"}"#.to_string(),
+        ];
+        let second_response_sse = vec![
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#.to_string(),
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Done."}}"#.to_string(),
+            r#"data: {"type":"content_block_stop","index":0}"#.to_string(),
+            r#"data: {"type":"message_stop"}"#.to_string(),
+        ];
+
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                first_response_sse,
+                second_response_sse,
+            ])));
+        let mut mock_tool_responses = HashMap::new();
+        mock_tool_responses.insert("file.txt".to_string(), "hello".to_string());
+        let mut manager = ConversationManager::new_mock(mock_api_client, mock_tool_responses);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let final_text = manager.send_message("Read file".into(), Some(&tx)).await?;
+        assert_eq!(final_text, "Done.");
+
+        let Content::Blocks(blocks) = &manager.api_messages[1].content else {
+            panic!("expected assistant blocks");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::Thinking { thinking, signature }
+                if thinking == "Need the file first." && signature == "sig-abc=="
+        ));
+        assert!(matches!(
+            &blocks[1],
+            ContentBlock::RedactedThinking { data } if data == "opaque-blob"
+        ));
+        assert!(matches!(&blocks[2], ContentBlock::ToolUse { name, .. } if name == "read_file"));
+
+        let mut reasoning_deltas = String::new();
+        let mut saw_reasoning_start = false;
+        while let Ok(update) = rx.try_recv() {
+            match update {
+                ConversationStreamUpdate::BlockStart {
+                    index: 0,
+                    block: StreamBlock::Reasoning { redacted, .. },
+                } => saw_reasoning_start = !redacted,
+                ConversationStreamUpdate::BlockDelta { index: 0, delta }
+                    if saw_reasoning_start && reasoning_deltas.is_empty() =>
+                {
+                    reasoning_deltas = delta;
+                }
+                _ => {}
+            }
+        }
+        assert!(saw_reasoning_start);
+        assert_eq!(reasoning_deltas, "Need the file first.");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_think_tags_are_stripped_from_final_text_and_history() -> Result<()> {
+        let response = |text: &str| {
+            let chunk = json!({"choices": [{"index": 0, "delta": {"content": text}, "finish_reason": "stop"}]});
+            vec![format!("data: {chunk}"), "data: [DONE]".to_string()]
+        };
+        let history_text = |manager: &ConversationManager| {
+            let Content::Blocks(blocks) = &manager.api_messages[1].content else {
+                panic!("expected assistant blocks");
+            };
+            blocks
+                .iter()
+                .find_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.clone()),
+                    _ => None,
+                })
+                .expect("expected a text block")
+        };
+        let openai_client = |text: &str| {
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                response(text),
+            ])))
+            .with_api_protocol(crate::api::ApiProtocol::OpenAiChatCompletions)
+        };
+
+        let mut manager = ConversationManager::new_mock(
+            openai_client("<think>hidden plan</think>\n\nVisible answer."),
+            HashMap::new(),
+        );
+        let final_text = manager.send_message("hello".into(), None).await?;
+        assert_eq!(final_text, "Visible answer.");
+        assert_eq!(history_text(&manager), "Visible answer.");
+
+        // The template opened the block, so the first close tag ends reasoning.
+        let mut manager = ConversationManager::new_mock(
+            openai_client("hidden plan</think>\n\nVisible answer.").with_think_opened_by_template(),
+            HashMap::new(),
+        );
+        let final_text = manager.send_message("hello".into(), None).await?;
+        assert_eq!(final_text, "Visible answer.");
+        assert_eq!(history_text(&manager), "Visible answer.");
+
+        // Without a template-opened block, a close tag mid-answer is answer text.
+        let answer = "Close the block with </think> when done.";
+        let mut manager = ConversationManager::new_mock(openai_client(answer), HashMap::new());
+        let final_text = manager.send_message("hello".into(), None).await?;
+        assert_eq!(final_text, answer);
+        assert_eq!(history_text(&manager), answer);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_staged_and_mentioned_images_attach_to_user_message() -> Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        for name in ["staged.png", "shot.png"] {
+            image::DynamicImage::new_rgb8(4, 4).save(temp.path().join(name))?;
+        }
+        let text_round = |id: &str| {
+            vec![
+                format!(r#"data: {{"type":"content_block_start","index":0,"content_block":{{"type":"text","text":""}},"id":"{id}"}}"#),
+                r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Looks fine."}}"#.to_string(),
+                r#"data: {"type":"message_stop"}"#.to_string(),
+            ]
+        };
+        let client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                text_round("a"),
+                text_round("b"),
+            ])));
+        let mut manager =
+            ConversationManager::new(client, ToolOperator::new(temp.path().to_path_buf()));
+
+        manager.attach_image("staged.png")?;
+        manager
+            .send_message("compare with @missing.png".into(), None)
+            .await?;
+        let Content::Blocks(blocks) = &manager.api_messages[0].content else {
+            panic!("expected user blocks");
+        };
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(&blocks[0], ContentBlock::Image { .. }));
+        assert!(
+            matches!(&blocks[1], ContentBlock::Text { text } if text == "compare with @missing.png")
+        );
+        assert!(manager.attach_image("missing.png").is_err());
+        manager.api_messages.clear();
+
+        manager.attach_image("staged.png")?;
+        manager
+            .send_message("compare with @shot.png, please".into(), None)
+            .await?;
+        let Content::Blocks(blocks) = &manager.api_messages[0].content else {
+            panic!("expected user blocks");
+        };
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(&blocks[0], ContentBlock::Image { .. }));
+        assert!(matches!(&blocks[1], ContentBlock::Image { .. }));
+        assert!(
+            matches!(&blocks[2], ContentBlock::Text { text } if text == "compare with @shot.png, please")
+        );
+        assert!(manager.pending_images.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_instructions_updates_system_prompt() -> Result<()> {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        let temp = TempDir::new()?;
+        let global = TempDir::new()?;
+        std::fs::write(
+            temp.path().join("VEX.md"),
+            "Run cargo fmt before finishing.",
+        )?;
+        std::env::set_var("XDG_CONFIG_HOME", global.path());
+        let client = ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(
+            vec![],
+        )));
+        let mut manager =
+            ConversationManager::new(client, ToolOperator::new(temp.path().to_path_buf()));
+
+        let instructions = manager.reload_instructions();
+        assert_eq!(instructions.loaded_files().count(), 1);
+        assert!(manager
+            .client()
+            .system_prompt()
+            .ends_with("## VEX.md\nRun cargo fmt before finishing."));
+
+        std::fs::remove_file(temp.path().join("VEX.md"))?;
+        let instructions = manager.reload_instructions();
+        std::env::remove_var("XDG_CONFIG_HOME");
+        assert!(instructions.files.is_empty());
+        assert!(!manager
+            .client()
+            .system_prompt()
+            .contains("Project instructions"));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_mutating_tool_prompts_approval_when_tool_confirm_env_is_off() -> Result<()> {
         let _env_lock = crate::test_support::ENV_LOCK.lock().await;
@@ -2514,7 +4595,7 @@ data: {"type":"message_stop"}"#.to_string(),
                     let Some(update) = maybe_update else { continue; };
                     if let ConversationStreamUpdate::ToolApprovalRequest(request) = update {
                         saw_approval_request = true;
-                        let _ = request.response_tx.send(false);
+                        let _ = request.response_tx.send(ApprovalDecision::Denied);
                     }
                 }
             }
@@ -2813,52 +4894,149 @@ data: {"type":"message_stop"}"#.to_string(),
     }
 
     #[tokio::test]
-    async fn test_repeated_read_only_round_returns_guard_message_instead_of_error() -> Result<()> {
+    async fn test_repeated_read_only_round_returns_guard_message_instead_of_error() -> Result<()> {
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                tagged_read_file_round("msg_loop_guard_01"),
+                tagged_read_file_round("msg_loop_guard_02"),
+                tagged_read_file_round("msg_loop_guard_03"),
+                tagged_read_file_round("msg_loop_guard_04"),
+            ])));
+        let mut mock_tool_responses = HashMap::new();
+        mock_tool_responses.insert("file.txt".to_string(), "loop sample".to_string());
+        let mut manager = ConversationManager::new_mock(mock_api_client, mock_tool_responses);
+
+        let final_text = manager.send_message("read file".to_string(), None).await?;
+        assert!(final_text.contains("[loop guard]"));
+        assert!(final_text.contains("Repeated identical read/search tool calls"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repeated_mutating_round_returns_guard_message_instead_of_looping() -> Result<()> {
+        let mutating_round = vec![
+            r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_mut_loop_01","type":"message","role":"assistant","model":"mock-model","content":[],"stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":10,"output_tokens":1}}}"#.to_string(),
+            r#"event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_mut_loop_01","name":"edit_file","input":{"path":"src/calculator.rs","old_str":"","new_str":"x"}}}"#.to_string(),
+            r#"event: content_block_stop
+data: {"type":"content_block_stop","index":0}"#.to_string(),
+            r#"event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":3}}"#.to_string(),
+            r#"event: message_stop
+data: {"type":"message_stop"}"#.to_string(),
+        ];
+
+        let mock_api_client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                mutating_round.clone(),
+                mutating_round,
+            ])));
+        let mut manager = ConversationManager::new_mock(mock_api_client, HashMap::new());
+
+        let final_text = manager
+            .send_message("edit calculator".to_string(), None)
+            .await?;
+        assert!(final_text.contains("[loop guard]"));
+        assert!(final_text.contains("Repeated identical mutating tool calls"));
+        Ok(())
+    }
+
+    fn max_tokens_text_round(message_id: &str, text: &str) -> Vec<String> {
+        plain_text_round(message_id, text)
+            .into_iter()
+            .map(|event| {
+                event.replace(
+                    r#""stop_reason":"end_turn""#,
+                    r#""stop_reason":"max_tokens""#,
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_max_tokens_stop_continues_text_until_end_turn() -> Result<()> {
         let mock_api_client =
             ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
-                tagged_read_file_round("msg_loop_guard_01"),
-                tagged_read_file_round("msg_loop_guard_02"),
-                tagged_read_file_round("msg_loop_guard_03"),
-                tagged_read_file_round("msg_loop_guard_04"),
+                max_tokens_text_round("msg_cut_01", "First half, "),
+                plain_text_round("msg_cut_02", "second half."),
             ])));
-        let mut mock_tool_responses = HashMap::new();
-        mock_tool_responses.insert("file.txt".to_string(), "loop sample".to_string());
-        let mut manager = ConversationManager::new_mock(mock_api_client, mock_tool_responses);
+        let mut manager = ConversationManager::new_mock(mock_api_client, HashMap::new());
+        let (tx, mut rx) = mpsc::unbounded_channel();
 
-        let final_text = manager.send_message("read file".to_string(), None).await?;
-        assert!(final_text.contains("[loop guard]"));
-        assert!(final_text.contains("Repeated identical read/search tool calls"));
+        let final_text = manager
+            .send_message("write a long answer".to_string(), Some(&tx))
+            .await?;
+        assert_eq!(final_text, "First half, second half.");
+        assert!(matches!(
+            &manager.api_messages[1].content,
+            Content::Text(text) if text == "First half, "
+        ));
+        assert!(matches!(
+            &manager.api_messages[2].content,
+            Content::Text(text) if *text == manager.policy.continuation_instruction(manager.policy_protocol())
+        ));
 
+        drop(tx);
+        let mut notices = Vec::new();
+        while let Ok(update) = rx.try_recv() {
+            if let ConversationStreamUpdate::OutputTruncated(notice) = update {
+                notices.push(notice);
+            }
+        }
+        assert_eq!(
+            notices,
+            vec![TruncationNotice {
+                continuation: 1,
+                max_continuations: DEFAULT_MAX_CONTINUATIONS,
+                continuing: true,
+                discarded_tool_calls: 0,
+            }]
+        );
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_repeated_mutating_round_returns_guard_message_instead_of_looping() -> Result<()> {
-        let mutating_round = vec![
+    async fn test_max_tokens_stop_discards_partial_tool_call_and_rerequests() -> Result<()> {
+        let truncated_tool_round = vec![
             r#"event: message_start
-data: {"type":"message_start","message":{"id":"msg_mut_loop_01","type":"message","role":"assistant","model":"mock-model","content":[],"stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":10,"output_tokens":1}}}"#.to_string(),
+data: {"type":"message_start","message":{"id":"msg_cut_tool_01","type":"message","role":"assistant","model":"mock-model","content":[],"stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":10,"output_tokens":1}}}"#.to_string(),
             r#"event: content_block_start
-data: {"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_mut_loop_01","name":"edit_file","input":{"path":"src/calculator.rs","old_str":"","new_str":"x"}}}"#.to_string(),
+data: {"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_cut_01","name":"write_file","input":{}}}"#.to_string(),
+            r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"path\":\"big.rs\",\"content\":\"fn ma"}}"#.to_string(),
             r#"event: content_block_stop
 data: {"type":"content_block_stop","index":0}"#.to_string(),
             r#"event: message_delta
-data: {"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":3}}"#.to_string(),
+data: {"type":"message_delta","delta":{"stop_reason":"max_tokens","stop_sequence":null},"usage":{"output_tokens":4096}}"#.to_string(),
             r#"event: message_stop
 data: {"type":"message_stop"}"#.to_string(),
         ];
-
         let mock_api_client =
             ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
-                mutating_round.clone(),
-                mutating_round,
+                truncated_tool_round,
+                plain_text_round("msg_cut_tool_02", "I will split the file."),
             ])));
         let mut manager = ConversationManager::new_mock(mock_api_client, HashMap::new());
 
         let final_text = manager
-            .send_message("edit calculator".to_string(), None)
+            .send_message("write big.rs".to_string(), None)
             .await?;
-        assert!(final_text.contains("[loop guard]"));
-        assert!(final_text.contains("Repeated identical mutating tool calls"));
+        assert_eq!(final_text, "I will split the file.");
+        assert!(matches!(
+            &manager.api_messages[1].content,
+            Content::Text(text) if text == TRUNCATED_RESPONSE_PLACEHOLDER
+        ));
+        assert!(matches!(
+            &manager.api_messages[2].content,
+            Content::Text(text) if *text == manager.policy.truncated_tool_call_instruction(manager.policy_protocol())
+        ));
+        assert!(!manager.api_messages.iter().any(|message| matches!(
+            &message.content,
+            Content::Blocks(blocks)
+                if blocks.iter().any(|block| matches!(block, ContentBlock::ToolResult { .. }))
+        )));
         Ok(())
     }
 
@@ -3114,6 +5292,132 @@ data: {"type":"message_stop"}"#.to_string(),
         );
     }
 
+    fn tool_exchange(id: &str, result: String) -> [ApiMessage; 2] {
+        [
+            ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "read_file".to_string(),
+                    input: serde_json::json!({ "path": "src/lib.rs" }),
+                }]),
+            },
+            ApiMessage {
+                role: "user".to_string(),
+                content: Content::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: id.to_string(),
+                    content: result,
+                    is_error: false,
+                }]),
+            },
+        ]
+    }
+
+    fn tool_result_content(message: &ApiMessage) -> Option<(&str, &str)> {
+        match &message.content {
+            Content::Blocks(blocks) => blocks.iter().find_map(|block| match block {
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    ..
+                } => Some((tool_use_id.as_str(), content.as_str())),
+                _ => None,
+            }),
+            Content::Text(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_fit_history_to_budget_evicts_largest_tool_result_in_place() {
+        let mock_api_client = ApiClient::new_mock(Arc::new(
+            crate::api::mock_client::MockApiClient::new(vec![]),
+        ));
+        let mut manager = ConversationManager::new_mock(mock_api_client, HashMap::new());
+        let estimator = TokenEstimator::for_model("gpt-4o");
+
+        let mut messages = vec![ApiMessage {
+            role: "user".to_string(),
+            content: Content::Text("summarize the crate".to_string()),
+        }];
+        messages.extend(tool_exchange("t1", "small".repeat(200)));
+        messages.extend(tool_exchange("t2", "x".repeat(36_000)));
+        messages.extend(tool_exchange("t3", "fresh".repeat(3_000)));
+        manager.api_messages = messages;
+
+        let before = estimator.history_tokens(&manager.api_messages);
+        let limit = before - 5_000;
+        let anchor = manager.fit_history_to_budget(&estimator, limit, 0);
+
+        assert_eq!(anchor, 0);
+        assert_eq!(
+            manager.api_messages.len(),
+            7,
+            "no exchange should be dropped"
+        );
+        assert!(estimator.history_tokens(&manager.api_messages) <= limit);
+        let (id, content) = tool_result_content(&manager.api_messages[4]).unwrap();
+        assert_eq!(id, "t2");
+        assert!(content.starts_with(EVICTED_TOOL_RESULT_PREFIX), "{content}");
+        let (_, small) = tool_result_content(&manager.api_messages[2]).unwrap();
+        assert_eq!(small.len(), 1_000, "smaller result should survive");
+        let (_, fresh) = tool_result_content(&manager.api_messages[6]).unwrap();
+        assert_eq!(fresh.len(), 15_000, "newest result is never evicted");
+
+        let transcript = manager.transcript();
+        let (_, exported) = tool_result_content(&transcript.messages[4]).unwrap();
+        assert_eq!(exported.len(), 36_000, "export keeps the evicted result");
+    }
+
+    #[test]
+    fn test_fit_history_to_budget_drops_exchanges_without_orphaning_results() {
+        let mock_api_client = ApiClient::new_mock(Arc::new(
+            crate::api::mock_client::MockApiClient::new(vec![]),
+        ));
+        let mut manager = ConversationManager::new_mock(mock_api_client, HashMap::new());
+        let estimator = TokenEstimator::for_model("claude-sonnet-4-5");
+
+        let mut messages = Vec::new();
+        for turn in 0..6 {
+            messages.push(ApiMessage {
+                role: "user".to_string(),
+                content: Content::Text(format!("question {turn}: {}", "q".repeat(2_000))),
+            });
+            messages.extend(tool_exchange(&format!("t{turn}"), "r".repeat(3_000)));
+            messages.push(ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Text(format!("answer {turn}: {}", "a".repeat(2_000))),
+            });
+        }
+        let anchor = 20;
+        manager.api_messages = messages;
+
+        let new_anchor = manager.fit_history_to_budget(&estimator, 3_000, anchor);
+
+        assert!(manager.api_messages.len() < 24);
+        assert_eq!(
+            manager.archived_messages().len() + manager.api_messages.len(),
+            24,
+            "pruned messages move to the archive"
+        );
+        assert!(estimator.history_tokens(&manager.api_messages) <= 3_000);
+        let first = &manager.api_messages[0];
+        assert!(first.role == "user" && !message_contains_tool_result(first));
+        assert!(
+            matches!(&manager.api_messages[new_anchor].content, Content::Text(text) if text.starts_with("question 5")),
+            "turn anchor must survive budget pruning"
+        );
+        for (index, message) in manager.api_messages.iter().enumerate() {
+            if let Some((id, _)) = tool_result_content(message) {
+                let Content::Blocks(blocks) = &manager.api_messages[index - 1].content else {
+                    panic!("tool_result at {index} lost its tool_use");
+                };
+                assert!(blocks.iter().any(
+                    |block| matches!(block, ContentBlock::ToolUse { id: use_id, .. } if use_id == id)
+                ));
+            }
+        }
+    }
+
     #[test]
     fn test_prune_message_history_clears_if_no_user_remains() {
         let mock_api_client = ApiClient::new_mock(Arc::new(
@@ -3267,4 +5571,456 @@ data: {"type":"message_stop"}"#.to_string(),
 
         assert!(manager.api_messages.is_empty());
     }
+
+    /// Serves scripted streams; a `None` entry never sends a byte, and
+    /// `stall_after_chunks` leaves each stream open after its last chunk.
+    struct StallingStreamProducer {
+        responses: Mutex<Vec<Option<Vec<String>>>>,
+        stall_after_chunks: bool,
+    }
+
+    impl crate::api::client::MockStreamProducer for StallingStreamProducer {
+        fn create_mock_stream(&self, _messages: &[ApiMessage]) -> Result<ByteStream> {
+            let next = self.responses.lock().unwrap().remove(0);
+            let Some(events) = next else {
+                return Ok(Box::pin(futures::stream::pending()));
+            };
+            let chunks = futures::stream::iter(
+                events
+                    .into_iter()
+                    .map(|event| Ok(bytes::Bytes::from(format!("{event}\n\n")))),
+            );
+            if self.stall_after_chunks {
+                Ok(Box::pin(chunks.chain(futures::stream::pending())))
+            } else {
+                Ok(Box::pin(chunks))
+            }
+        }
+    }
+
+    fn stalling_manager(
+        responses: Vec<Option<Vec<String>>>,
+        stall_after_chunks: bool,
+    ) -> ConversationManager {
+        let client = ApiClient::new_mock(Arc::new(StallingStreamProducer {
+            responses: Mutex::new(responses),
+            stall_after_chunks,
+        }));
+        ConversationManager::new_mock(client, HashMap::new())
+    }
+
+    #[tokio::test]
+    async fn test_first_token_stall_retries_then_streams() -> Result<()> {
+        let _env_lock = crate::test_support::ENV_LOCK.lock().await;
+        std::env::set_var("VEX_FIRST_TOKEN_TIMEOUT_SECS", "1");
+        std::env::remove_var("VEX_STALL_RETRIES");
+        let mut manager = stalling_manager(
+            vec![None, Some(plain_text_round("msg_stall_01", "Recovered."))],
+            false,
+        );
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let result = manager.send_message("hello".to_string(), Some(&tx)).await;
+        std::env::remove_var("VEX_FIRST_TOKEN_TIMEOUT_SECS");
+        assert_eq!(result?, "Recovered.");
+
+        drop(tx);
+        let mut waits = 0;
+        let mut received = 0;
+        let mut stalls = Vec::new();
+        while let Ok(update) = rx.try_recv() {
+            match update {
+                ConversationStreamUpdate::AwaitingFirstToken => waits += 1,
+                ConversationStreamUpdate::FirstTokenReceived => received += 1,
+                ConversationStreamUpdate::StreamStalled(notice) => stalls.push(notice),
+                _ => {}
+            }
+        }
+        assert_eq!((waits, received), (2, 1));
+        assert_eq!(stalls.len(), 1);
+        assert_eq!((stalls[0].retry, stalls[0].max_retries), (1, 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_first_token_stall_without_retries_ends_turn_with_error() {
+        let _env_lock = crate::test_support::ENV_LOCK.lock().await;
+        std::env::set_var("VEX_FIRST_TOKEN_TIMEOUT_SECS", "1");
+        std::env::set_var("VEX_STALL_RETRIES", "0");
+        let mut manager = stalling_manager(vec![None], false);
+        let result = manager.send_message("hello".to_string(), None).await;
+        std::env::remove_var("VEX_FIRST_TOKEN_TIMEOUT_SECS");
+        std::env::remove_var("VEX_STALL_RETRIES");
+
+        let error = result.expect_err("stall should fail the turn").to_string();
+        assert!(error.contains("first token"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_mid_stream_stall_hits_idle_timeout() {
+        let _env_lock = crate::test_support::ENV_LOCK.lock().await;
+        std::env::set_var("VEX_STREAM_IDLE_TIMEOUT_SECS", "1");
+        let partial = plain_text_round("msg_idle_01", "Partial")
+            .into_iter()
+            .take(3)
+            .collect();
+        let mut manager = stalling_manager(vec![Some(partial)], true);
+        let result = manager.send_message("hello".to_string(), None).await;
+        std::env::remove_var("VEX_STREAM_IDLE_TIMEOUT_SECS");
+
+        let error = result
+            .expect_err("idle stream should fail the turn")
+            .to_string();
+        assert!(error.contains("VEX_STREAM_IDLE_TIMEOUT_SECS"), "{error}");
+    }
+
+    /// Rejects the first request as too long, then serves scripted streams.
+    struct ContextOverflowProducer {
+        rejected: Mutex<bool>,
+        then: crate::api::mock_client::MockApiClient,
+    }
+
+    impl crate::api::client::MockStreamProducer for ContextOverflowProducer {
+        fn create_mock_stream(&self, messages: &[ApiMessage]) -> Result<ByteStream> {
+            let mut rejected = self.rejected.lock().unwrap();
+            if !*rejected {
+                *rejected = true;
+                return Err(ApiError::ContextLengthExceeded {
+                    message: "prompt is too long: 9000 tokens > 8192 maximum".to_string(),
+                }
+                .into());
+            }
+            self.then.create_mock_stream(messages)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_length_error_prunes_history_and_retries() -> Result<()> {
+        let client = ApiClient::new_mock(Arc::new(ContextOverflowProducer {
+            rejected: Mutex::new(false),
+            then: crate::api::mock_client::MockApiClient::new(vec![plain_text_round(
+                "msg_overflow_01",
+                "Fits now.",
+            )]),
+        }));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        for index in 0..4 {
+            manager.push_user_message(format!("old question {index}"));
+            manager.api_messages.push(ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Text(format!("old answer {index}")),
+            });
+        }
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let reply = manager
+            .send_message("new question".to_string(), Some(&tx))
+            .await?;
+        assert_eq!(reply, "Fits now.");
+        assert!(matches!(
+            &manager.api_messages[0].content,
+            Content::Text(text) if text != "old question 0"
+        ));
+
+        drop(tx);
+        let pruned: Vec<usize> = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter_map(|update| match update {
+                ConversationStreamUpdate::ContextPruned { removed_messages } => {
+                    Some(removed_messages)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pruned.len(), 1);
+        assert!(pruned[0] > 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_context_length_error_event_mid_stream_prunes_history_and_retries() -> Result<()> {
+        let overflow_event = vec![
+            r#"event: error
+data: {"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#
+                .to_string(),
+        ];
+        let client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                overflow_event,
+                plain_text_round("msg_overflow_02", "Fits now."),
+            ])));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        for index in 0..4 {
+            manager.push_user_message(format!("old question {index}"));
+            manager.api_messages.push(ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Text(format!("old answer {index}")),
+            });
+        }
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let reply = manager
+            .send_message("new question".to_string(), Some(&tx))
+            .await?;
+        assert_eq!(reply, "Fits now.");
+
+        drop(tx);
+        let pruned = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter(|update| matches!(update, ConversationStreamUpdate::ContextPruned { .. }))
+            .count();
+        assert_eq!(pruned, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_recorded_when_stream_fails_after_message_start() -> Result<()> {
+        let failing_round = |error: &str| {
+            vec![
+                r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_failed_01","type":"message","role":"assistant","model":"mock-model","content":[],"stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":1200,"output_tokens":0}}}"#
+                    .to_string(),
+                format!("event: error\ndata: {{\"type\":\"error\",\"error\":{error}}}"),
+            ]
+        };
+
+        let client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                failing_round(r#"{"type":"overloaded_error","message":"Overloaded"}"#),
+            ])));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        assert!(manager
+            .send_message("question".to_string(), None)
+            .await
+            .is_err());
+        assert_eq!(manager.usage_report().session.input_tokens, 1200);
+
+        // A round retried after a context overflow still bills its prompt.
+        let client = ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(
+            vec![
+                failing_round(
+                    r#"{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}"#,
+                ),
+                plain_text_round("msg_retry_02", "Fits now."),
+            ],
+        )));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        for index in 0..4 {
+            manager.push_user_message(format!("old question {index}"));
+            manager.api_messages.push(ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Text(format!("old answer {index}")),
+            });
+        }
+        let reply = manager
+            .send_message("new question".to_string(), None)
+            .await?;
+        assert_eq!(reply, "Fits now.");
+        assert_eq!(manager.usage_report().session.input_tokens, 1210);
+        Ok(())
+    }
+
+    struct RecordingProducer {
+        requests: Arc<Mutex<Vec<Vec<ApiMessage>>>>,
+        then: crate::api::mock_client::MockApiClient,
+    }
+
+    impl crate::api::client::MockStreamProducer for RecordingProducer {
+        fn create_mock_stream(&self, messages: &[ApiMessage]) -> Result<ByteStream> {
+            self.requests.lock().unwrap().push(messages.to_vec());
+            self.then.create_mock_stream(messages)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compact_replaces_older_turns_with_summary_and_archives_them() -> Result<()> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let client = ApiClient::new_mock(Arc::new(RecordingProducer {
+            requests: Arc::clone(&requests),
+            then: crate::api::mock_client::MockApiClient::new(vec![plain_text_round(
+                "msg_compact_01",
+                "Decisions: use tokio. Files: src/main.rs. Open TODOs: tests.",
+            )]),
+        }));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        for index in 0..3 {
+            manager.push_user_message(format!("question {index}"));
+            manager.api_messages.push(ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Text(format!("answer {index}")),
+            });
+        }
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let notice = manager.compact(Some(&tx)).await?;
+
+        assert_eq!(notice.summarized_messages, 4);
+        assert!(!notice.automatic);
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(request.len(), 5, "older turns plus the summary request");
+        assert!(matches!(
+            &request[4].content,
+            Content::Text(text) if text.contains("Open TODOs")
+        ));
+
+        let texts: Vec<&str> = manager
+            .api_messages
+            .iter()
+            .map(|message| match &message.content {
+                Content::Text(text) => text.as_str(),
+                Content::Blocks(_) => "",
+            })
+            .collect();
+        assert_eq!(texts.len(), 4);
+        assert!(texts[0].starts_with("[summary of earlier conversation]"));
+        assert!(texts[0].contains("Files: src/main.rs"));
+        assert_eq!(manager.api_messages[1].role, "assistant");
+        assert_eq!(&texts[2..], ["question 2", "answer 2"]);
+        assert_eq!(manager.archived_messages().len(), 4);
+
+        drop(tx);
+        let compacted = std::iter::from_fn(|| rx.try_recv().ok()).any(|update| {
+            matches!(update, ConversationStreamUpdate::Compacted(notice) if notice.summarized_messages == 4)
+        });
+        assert!(compacted);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compact_without_earlier_turns_fails_and_keeps_history() {
+        let client = ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(
+            vec![],
+        )));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        manager.push_user_message("only question".to_string());
+
+        let error = manager.compact(None).await.unwrap_err();
+
+        assert!(error.to_string().contains("nothing to compact"), "{error}");
+        assert_eq!(manager.api_messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_message_auto_compacts_when_history_nears_budget() -> Result<()> {
+        let client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                plain_text_round("msg_auto_01", "Decisions: keep going."),
+                plain_text_round("msg_auto_02", "Next answer."),
+            ])));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        for index in 0..3 {
+            manager.push_user_message(format!("question {index}"));
+            manager.api_messages.push(ApiMessage {
+                role: "assistant".to_string(),
+                content: Content::Text("x".repeat(20_000)),
+            });
+        }
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let reply = manager
+            .send_message("question 3".to_string(), Some(&tx))
+            .await?;
+
+        assert_eq!(reply, "Next answer.");
+        assert_eq!(manager.archived_messages().len(), 4);
+        drop(tx);
+        let notice = std::iter::from_fn(|| rx.try_recv().ok())
+            .find_map(|update| match update {
+                ConversationStreamUpdate::Compacted(notice) => Some(notice),
+                _ => None,
+            })
+            .expect("automatic compaction notice");
+        assert!(notice.automatic);
+        assert!(notice.tokens_after < notice.tokens_before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewind_drops_newest_turns_and_skips_tool_results() {
+        let client = ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(
+            vec![],
+        )));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        manager.test_push_exchange("first", "one");
+        manager.test_push_exchange("tool_result read_file:\nfn main() {}", "read");
+        manager.test_push_exchange("second", "two");
+        manager.test_push_exchange("third", "three");
+        assert_eq!(manager.user_turns(), ["first", "second", "third"]);
+
+        let error = manager.rewind(4).unwrap_err();
+        assert!(error.to_string().contains("3 turn(s) remain"), "{error}");
+
+        assert_eq!(manager.rewind(2).unwrap(), "second");
+        assert_eq!(manager.user_turns(), ["first"]);
+        assert_eq!(manager.api_messages.len(), 4);
+    }
+
+    #[test]
+    fn test_switch_branch_swaps_active_history() {
+        let client = ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(
+            vec![],
+        )));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+        manager.test_push_exchange("shared", "ok");
+        let saved = manager.fork();
+        manager.test_push_exchange("diverged", "ok");
+
+        let previous = manager.switch_branch(saved);
+        assert_eq!(manager.user_turns(), ["shared"]);
+        assert_eq!(previous.message_count(), 4);
+
+        manager.switch_branch(previous);
+        assert_eq!(manager.user_turns(), ["shared", "diverged"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_message_records_turn_timing_until_rewound() -> Result<()> {
+        let client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                plain_text_round("msg_timing_01", "first answer"),
+                plain_text_round("msg_timing_02", "second answer"),
+            ])));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+
+        manager.send_message("first".to_string(), None).await?;
+        manager.send_message("second".to_string(), None).await?;
+
+        let transcript = manager.transcript();
+        assert_eq!(transcript.user_turn_count(), 2);
+        let turns: Vec<usize> = transcript.turn_timings.iter().map(|t| t.turn).collect();
+        assert_eq!(turns, [0, 1]);
+        assert!(transcript.turn_timings[1].usage.output_tokens > 0);
+
+        manager.rewind(1)?;
+        assert_eq!(manager.transcript().turn_timings.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transcript_keeps_full_text_of_replies_shortened_in_history() -> Result<()> {
+        let reply = format!("{}tail", "long answer ".repeat(1_000));
+        let client =
+            ApiClient::new_mock(Arc::new(crate::api::mock_client::MockApiClient::new(vec![
+                plain_text_round("msg_full_01", &reply),
+            ])));
+        let mut manager = ConversationManager::new_mock(client, HashMap::new());
+
+        manager.send_message("explain".to_string(), None).await?;
+
+        let text_of = |message: &ApiMessage| match &message.content {
+            Content::Blocks(blocks) => match blocks.as_slice() {
+                [ContentBlock::Text { text }] => text.clone(),
+                other => panic!("unexpected blocks: {other:?}"),
+            },
+            Content::Text(text) => text.clone(),
+        };
+        let history = text_of(manager.api_messages.last().unwrap());
+        assert!(
+            history.len() < reply.len(),
+            "history copy should be truncated"
+        );
+        let transcript = manager.transcript();
+        assert_eq!(text_of(transcript.messages.last().unwrap()), reply);
+
+        manager.rewind(1)?;
+        assert!(manager.transcript().messages.is_empty());
+        Ok(())
+    }
 }
@@ -0,0 +1,60 @@
+use serde_json::Value;
+
+/// Tool the model calls to hand a research task to a sub-agent.
+pub(super) const DELEGATE_TOOL_NAME: &str = "delegate";
+
+/// Tools a sub-agent may call: reading and inspecting only, and no further
+/// delegation.
+pub(super) const DELEGATE_TOOLS: &[&str] = &[
+    "read_file",
+    "list_files",
+    "list_directory",
+    "search_files",
+    "search",
+    "git_status",
+    "git_diff",
+    "git_log",
+    "git_show",
+];
+
+/// Default round limit for a sub-agent; `VEX_DELEGATE_MAX_ROUNDS` overrides it.
+pub(super) const DEFAULT_DELEGATE_MAX_ROUNDS: usize = 12;
+
+const DELEGATE_PROMPT: &str = "\
+You are a sub-agent doing research for another agent. Investigate the task \
+below with the read-only tools you have; you cannot change files. When you \
+are done, reply with a concise summary: the answer, the relevant file paths \
+with line numbers, and anything you could not confirm. Your final reply is \
+the only thing the other agent will see.";
+
+/// Progress of a `delegate` call, streamed to the frontend as a nested block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DelegateUpdate {
+    Started {
+        task: String,
+    },
+    /// One tool call made by the sub-agent, e.g. `read_file src/lib.rs`.
+    Step(String),
+    Finished {
+        tool_calls: usize,
+        ok: bool,
+    },
+}
+
+pub(super) fn delegate_prompt(task: &str) -> String {
+    format!("{DELEGATE_PROMPT}\n\nTask: {}", task.trim())
+}
+
+/// `name target` for a progress line, using the first argument that names
+/// what the tool looks at.
+pub(super) fn delegate_step_summary(name: &str, input: &Value) -> String {
+    let target = ["path", "file_path", "query", "revision"]
+        .iter()
+        .find_map(|key| input.get(key).and_then(Value::as_str))
+        .map(str::trim)
+        .filter(|target| !target.is_empty());
+    match target {
+        Some(target) => format!("{name} {target}"),
+        None => name.to_string(),
+    }
+}
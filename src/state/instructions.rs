@@ -0,0 +1,377 @@
+use crate::util::parse_bool_flag;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File names recognised as project instructions, in merge order per directory.
+const INSTRUCTION_FILE_NAMES: [&str; 2] = ["VEX.md", "AGENTS.md"];
+/// How far below the working directory subdirectory instructions are found.
+const MAX_SUBDIR_DEPTH: usize = 3;
+/// Upper bound on directories visited while scanning for nested instructions.
+const MAX_SUBDIRS_SCANNED: usize = 2_000;
+const SKIPPED_DIR_NAMES: [&str; 3] = ["target", "node_modules", "vendor"];
+
+const DEFAULT_MAX_FILE_BYTES: usize = 16 * 1024;
+const DEFAULT_MAX_TOTAL_BYTES: usize = 48 * 1024;
+const TRUNCATION_MARKER: &str = "\n[... truncated by vex: instruction file exceeds size limit ...]";
+
+/// Where an instruction file was found; decides its merge position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionScope {
+    Global,
+    Project,
+    Subdirectory,
+}
+
+/// One instruction file considered for the system prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionFile {
+    /// Path shown to the user and the model: relative to the working
+    /// directory when inside it, absolute otherwise.
+    pub display_path: String,
+    pub scope: InstructionScope,
+    /// Bytes merged into the prompt (0 when skipped).
+    pub bytes: usize,
+    pub truncated: bool,
+    /// Left out entirely because the total budget was already spent.
+    pub skipped: bool,
+}
+
+/// Instruction files discovered for a working directory, merged into one
+/// system prompt section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectInstructions {
+    pub files: Vec<InstructionFile>,
+    prompt_section: Option<String>,
+}
+
+impl ProjectInstructions {
+    /// Discover and merge instruction files for `working_dir`. Returns an
+    /// empty set when `VEX_INSTRUCTIONS` disables loading.
+    pub fn load(working_dir: &Path) -> Self {
+        if !resolve_instructions_enabled() {
+            return Self::default();
+        }
+        let candidates = discover_instruction_files(working_dir, global_config_dir().as_deref());
+        Self::merge(
+            working_dir,
+            candidates,
+            resolve_max_file_bytes(),
+            resolve_max_total_bytes(),
+        )
+    }
+
+    /// Merged text appended to the system prompt, if any file was loaded.
+    pub fn prompt_section(&self) -> Option<&str> {
+        self.prompt_section.as_deref()
+    }
+
+    pub fn loaded_files(&self) -> impl Iterator<Item = &InstructionFile> {
+        self.files.iter().filter(|file| !file.skipped)
+    }
+
+    fn merge(
+        working_dir: &Path,
+        candidates: Vec<(PathBuf, InstructionScope)>,
+        max_file_bytes: usize,
+        max_total_bytes: usize,
+    ) -> Self {
+        let mut files = Vec::new();
+        let mut sections = Vec::new();
+        let mut remaining = max_total_bytes;
+
+        for (path, scope) in candidates {
+            let Ok(raw) = fs::read(&path) else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(&raw);
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let display_path = display_path(working_dir, &path);
+            if remaining == 0 {
+                files.push(InstructionFile {
+                    display_path,
+                    scope,
+                    bytes: 0,
+                    truncated: false,
+                    skipped: true,
+                });
+                continue;
+            }
+
+            let limit = max_file_bytes.min(remaining);
+            let (body, truncated) = truncate_at_char_boundary(text, limit);
+            remaining -= body.len();
+            sections.push(format_section(&display_path, scope, body, truncated));
+            files.push(InstructionFile {
+                display_path,
+                scope,
+                bytes: body.len(),
+                truncated,
+                skipped: false,
+            });
+        }
+
+        let prompt_section = (!sections.is_empty()).then(|| {
+            format!(
+                "# Project instructions\n\
+                 The user and repository provided the instruction files below. \
+                 Follow them; when they conflict, files listed later (closer to the code) take precedence.\n\n{}",
+                sections.join("\n\n")
+            )
+        });
+        Self {
+            files,
+            prompt_section,
+        }
+    }
+}
+
+fn format_section(
+    display_path: &str,
+    scope: InstructionScope,
+    body: &str,
+    truncated: bool,
+) -> String {
+    let heading = match scope {
+        InstructionScope::Global => format!("## {display_path} (user-global)"),
+        InstructionScope::Project => format!("## {display_path}"),
+        InstructionScope::Subdirectory => {
+            let dir = Path::new(display_path)
+                .parent()
+                .map(|parent| parent.display().to_string())
+                .unwrap_or_default();
+            format!("## {display_path} (applies to {dir}/)")
+        }
+    };
+    let marker = if truncated { TRUNCATION_MARKER } else { "" };
+    format!("{heading}\n{body}{marker}")
+}
+
+/// Candidate files in merge order: user-global, then the repository root down
+/// to the working directory, then nested subdirectories in path order.
+fn discover_instruction_files(
+    working_dir: &Path,
+    global_dir: Option<&Path>,
+) -> Vec<(PathBuf, InstructionScope)> {
+    let mut candidates = Vec::new();
+    if let Some(global_dir) = global_dir {
+        candidates
+            .extend(instruction_files_in(global_dir).map(|path| (path, InstructionScope::Global)));
+    }
+
+    for dir in project_dirs(working_dir) {
+        candidates.extend(instruction_files_in(&dir).map(|path| (path, InstructionScope::Project)));
+    }
+
+    let mut subdirs = Vec::new();
+    collect_subdirs(working_dir, 1, &mut subdirs);
+    subdirs.sort();
+    for dir in subdirs {
+        candidates
+            .extend(instruction_files_in(&dir).map(|path| (path, InstructionScope::Subdirectory)));
+    }
+    candidates
+}
+
+/// Directories from the repository root (nearest ancestor holding `.git`)
+/// down to `working_dir`. Outside a repository only `working_dir` is used.
+fn project_dirs(working_dir: &Path) -> Vec<PathBuf> {
+    let working_dir = fs::canonicalize(working_dir).unwrap_or_else(|_| working_dir.to_path_buf());
+    let mut dirs = Vec::new();
+    for dir in working_dir.ancestors() {
+        dirs.push(dir.to_path_buf());
+        if dir.join(".git").exists() {
+            dirs.reverse();
+            return dirs;
+        }
+    }
+    vec![working_dir]
+}
+
+fn collect_subdirs(dir: &Path, depth: usize, out: &mut Vec<PathBuf>) {
+    if depth > MAX_SUBDIR_DEPTH || out.len() >= MAX_SUBDIRS_SCANNED {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut children = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| !name.starts_with('.') && !SKIPPED_DIR_NAMES.contains(&name))
+        })
+        .collect::<Vec<_>>();
+    children.sort();
+    for child in children {
+        if out.len() >= MAX_SUBDIRS_SCANNED {
+            return;
+        }
+        out.push(child.clone());
+        collect_subdirs(&child, depth + 1, out);
+    }
+}
+
+fn instruction_files_in(dir: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    INSTRUCTION_FILE_NAMES
+        .iter()
+        .map(move |name| dir.join(name))
+        .filter(|path| path.is_file())
+}
+
+fn display_path(working_dir: &Path, path: &Path) -> String {
+    let canonical_dir = fs::canonicalize(working_dir).unwrap_or_else(|_| working_dir.to_path_buf());
+    let canonical_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    canonical_path
+        .strip_prefix(&canonical_dir)
+        .map(|relative| relative.display().to_string())
+        .unwrap_or_else(|_| canonical_path.display().to_string())
+}
+
+fn truncate_at_char_boundary(text: &str, limit: usize) -> (&str, bool) {
+    if text.len() <= limit {
+        return (text, false);
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&text[..end], true)
+}
+
+/// `$XDG_CONFIG_HOME/vex`, falling back to `~/.config/vex`.
+fn global_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("vex"))
+}
+
+fn resolve_instructions_enabled() -> bool {
+    std::env::var("VEX_INSTRUCTIONS")
+        .ok()
+        .and_then(parse_bool_flag)
+        .unwrap_or(true)
+}
+
+fn resolve_max_file_bytes() -> usize {
+    std::env::var("VEX_INSTRUCTIONS_MAX_FILE_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .map(|value| value.clamp(1024, 256 * 1024))
+        .unwrap_or(DEFAULT_MAX_FILE_BYTES)
+}
+
+fn resolve_max_total_bytes() -> usize {
+    std::env::var("VEX_INSTRUCTIONS_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .map(|value| value.clamp(1024, 1024 * 1024))
+        .unwrap_or(DEFAULT_MAX_TOTAL_BYTES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_discovery_orders_global_root_cwd_then_subdirectories() {
+        let global = TempDir::new().unwrap();
+        let repo = TempDir::new().unwrap();
+        fs::create_dir(repo.path().join(".git")).unwrap();
+        let cwd = repo.path().join("crates/app");
+        write(&global.path().join("VEX.md"), "global rules");
+        write(&repo.path().join("AGENTS.md"), "root agents");
+        write(&repo.path().join("VEX.md"), "root vex");
+        write(&cwd.join("VEX.md"), "cwd rules");
+        write(&cwd.join("src/api/AGENTS.md"), "api rules");
+        write(&cwd.join("target/VEX.md"), "ignored");
+        write(&cwd.join(".hidden/VEX.md"), "ignored");
+        write(&cwd.join("a/b/c/d/VEX.md"), "too deep");
+
+        let found = discover_instruction_files(&cwd, Some(global.path()))
+            .into_iter()
+            .map(|(path, scope)| (fs::read_to_string(path).unwrap(), scope))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                ("global rules".to_string(), InstructionScope::Global),
+                ("root vex".to_string(), InstructionScope::Project),
+                ("root agents".to_string(), InstructionScope::Project),
+                ("cwd rules".to_string(), InstructionScope::Project),
+                ("api rules".to_string(), InstructionScope::Subdirectory),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_project_dirs_without_repository_uses_working_dir_only() {
+        let dir = TempDir::new().unwrap();
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        let dirs = project_dirs(&nested);
+        assert_eq!(dirs, vec![fs::canonicalize(&nested).unwrap()]);
+    }
+
+    #[test]
+    fn test_merge_truncates_files_and_skips_past_total_budget() {
+        let dir = TempDir::new().unwrap();
+        write(&dir.path().join("VEX.md"), &"a".repeat(3000));
+        write(&dir.path().join("docs/VEX.md"), &"b".repeat(3000));
+        write(&dir.path().join("src/VEX.md"), "never reached");
+        write(&dir.path().join("empty/VEX.md"), "   \n");
+        let candidates = discover_instruction_files(dir.path(), None);
+
+        let merged = ProjectInstructions::merge(dir.path(), candidates, 2048, 3072);
+        let summary = merged
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file.display_path.as_str(),
+                    file.bytes,
+                    file.truncated,
+                    file.skipped,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("VEX.md", 2048, true, false),
+                ("docs/VEX.md", 1024, true, false),
+                ("src/VEX.md", 0, false, true),
+            ]
+        );
+        let section = merged.prompt_section().unwrap();
+        assert!(section.starts_with("# Project instructions"));
+        assert!(section.contains("## docs/VEX.md (applies to docs/)"));
+        assert!(section.contains(TRUNCATION_MARKER));
+        assert!(!section.contains("never reached"));
+    }
+
+    #[test]
+    fn test_load_respects_disable_flag() {
+        let _guard = crate::test_support::ENV_LOCK.blocking_lock();
+        let dir = TempDir::new().unwrap();
+        write(&dir.path().join("VEX.md"), "rules");
+        std::env::set_var("VEX_INSTRUCTIONS", "off");
+        let disabled = ProjectInstructions::load(dir.path());
+        std::env::remove_var("VEX_INSTRUCTIONS");
+        assert!(disabled.files.is_empty());
+        assert!(disabled.prompt_section().is_none());
+    }
+}
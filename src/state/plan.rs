@@ -0,0 +1,42 @@
+/// Added to the system prompt while plan mode is on.
+pub(super) const PLAN_MODE_PROMPT: &str = "\
+Plan mode is on. Investigate with read-only tools only: write_file, \
+edit_file, rename_file, git_add and git_commit are blocked, and this \
+overrides any instruction above to make changes right away. When you \
+understand the task, reply with a numbered plan, one step per line, naming \
+the files and functions each step touches and how the result will be \
+verified. The user approves, edits or rejects the plan before anything runs.";
+
+const PLAN_APPROVED_PROMPT: &str = "\
+Plan mode is off. Carry out the approved plan below step by step, then \
+verify the result as the plan describes.";
+
+/// The instruction that starts execution once the user approves `plan`.
+pub fn plan_approval_prompt(plan: &str) -> String {
+    format!("{PLAN_APPROVED_PROMPT}\n\n{}", plan.trim())
+}
+
+/// Whether a reply contains a numbered list (`1.` or `1)`), as a plan does;
+/// a clarifying question does not.
+pub fn is_numbered_plan(text: &str) -> bool {
+    text.lines().any(|line| {
+        let line = line.trim_start();
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        digits > 0 && matches!(line[digits..].chars().next(), Some('.' | ')'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_numbered_plan_needs_a_numbered_step() {
+        assert!(is_numbered_plan(
+            "Here is the plan:\n1. Read main.rs\n2) Edit it"
+        ));
+        assert!(is_numbered_plan("  10. Run the tests"));
+        assert!(!is_numbered_plan("Which file should change?"));
+        assert!(!is_numbered_plan("Version 2 is current."));
+    }
+}
@@ -20,6 +20,12 @@ pub enum StreamBlock {
     },
     /// Final assistant text for the turn.
     FinalText { content: String },
+    /// Model reasoning from extended thinking, shown collapsed by default.
+    Reasoning {
+        content: String,
+        redacted: bool,
+        collapsed: bool,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -0,0 +1,231 @@
+use crate::types::{ApiMessage, Content, ContentBlock};
+
+/// Flat estimate for an attached image; base64 payload size says nothing about
+/// the tokens it costs.
+const IMAGE_TOKENS: usize = 1_600;
+/// Role and framing tokens every message costs on top of its content.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+/// Framing tokens for a tool_use or tool_result block.
+const TOOL_BLOCK_OVERHEAD_TOKENS: usize = 8;
+/// Share of the context window held back for estimation error.
+const SAFETY_MARGIN_PERCENT: usize = 10;
+/// Floor for the history budget so a tiny window still fits the current turn.
+const MIN_HISTORY_TOKENS: usize = 1_024;
+
+/// Tokenizer family of a model, used to pick a chars-per-token ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFamily {
+    Claude,
+    Gpt,
+    OpenWeight,
+    Other,
+}
+
+impl ModelFamily {
+    pub fn detect(model: &str) -> Self {
+        let model = model.to_ascii_lowercase();
+        let name = model.rsplit('/').next().unwrap_or(&model);
+        if name.contains("claude") {
+            ModelFamily::Claude
+        } else if name.contains("gpt")
+            || ["o1", "o3", "o4"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        {
+            ModelFamily::Gpt
+        } else if [
+            "qwen",
+            "llama",
+            "mistral",
+            "mixtral",
+            "codestral",
+            "devstral",
+            "deepseek",
+            "gemma",
+            "phi",
+            "glm",
+            "kimi",
+        ]
+        .iter()
+        .any(|family| name.contains(family))
+        {
+            ModelFamily::OpenWeight
+        } else {
+            ModelFamily::Other
+        }
+    }
+
+    /// Average ASCII characters per token for source code and English prose.
+    fn chars_per_token(self) -> f64 {
+        match self {
+            ModelFamily::Claude => 3.2,
+            ModelFamily::Gpt => 3.6,
+            ModelFamily::OpenWeight => 3.3,
+            ModelFamily::Other => 3.0,
+        }
+    }
+}
+
+/// Cheap token estimate for history, deliberately on the high side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenEstimator {
+    family: ModelFamily,
+}
+
+impl TokenEstimator {
+    pub fn for_model(model: &str) -> Self {
+        Self {
+            family: ModelFamily::detect(model),
+        }
+    }
+
+    /// ASCII text is divided by the family ratio; every other character is
+    /// counted as a whole token, which over-counts accented Latin but keeps
+    /// CJK and emoji from blowing the budget.
+    pub fn text_tokens(&self, text: &str) -> usize {
+        let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), ch| {
+            if ch.is_ascii() {
+                (ascii + 1, other)
+            } else {
+                (ascii, other + 1)
+            }
+        });
+        self.chars_to_tokens(ascii) + other
+    }
+
+    pub fn chars_to_tokens(&self, chars: usize) -> usize {
+        (chars as f64 / self.family.chars_per_token()).ceil() as usize
+    }
+
+    pub fn tokens_to_chars(&self, tokens: usize) -> usize {
+        (tokens as f64 * self.family.chars_per_token()) as usize
+    }
+
+    pub fn block_tokens(&self, block: &ContentBlock) -> usize {
+        match block {
+            ContentBlock::Text { text } => self.text_tokens(text),
+            ContentBlock::ToolUse { name, input, .. } => {
+                TOOL_BLOCK_OVERHEAD_TOKENS
+                    + self.text_tokens(name)
+                    + self.text_tokens(&input.to_string())
+            }
+            ContentBlock::ToolResult { content, .. } => {
+                TOOL_BLOCK_OVERHEAD_TOKENS + self.text_tokens(content)
+            }
+            // Signatures and redacted payloads are opaque and not billed as
+            // context, so only the visible reasoning is counted.
+            ContentBlock::Thinking { thinking, .. } => self.text_tokens(thinking),
+            ContentBlock::RedactedThinking { .. } => 0,
+            ContentBlock::Image { .. } => IMAGE_TOKENS,
+        }
+    }
+
+    pub fn message_tokens(&self, message: &ApiMessage) -> usize {
+        MESSAGE_OVERHEAD_TOKENS
+            + match &message.content {
+                Content::Text(text) => self.text_tokens(text),
+                Content::Blocks(blocks) => blocks.iter().map(|b| self.block_tokens(b)).sum(),
+            }
+    }
+
+    pub fn history_tokens(&self, messages: &[ApiMessage]) -> usize {
+        messages.iter().map(|m| self.message_tokens(m)).sum()
+    }
+}
+
+/// Tokens available to message history in one request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBudget {
+    pub context_window: usize,
+    pub reserved_output: usize,
+    pub prompt_overhead: usize,
+}
+
+impl TokenBudget {
+    /// The context window minus the safety margin, the output reservation and
+    /// the system prompt and tool schemas sent with every request.
+    pub fn history_limit(&self) -> usize {
+        let usable = self.context_window - self.context_window * SAFETY_MARGIN_PERCENT / 100;
+        usable
+            .saturating_sub(self.reserved_output + self.prompt_overhead)
+            .max(MIN_HISTORY_TOKENS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ImageSource;
+
+    #[test]
+    fn test_model_family_detection() {
+        assert_eq!(
+            ModelFamily::detect("claude-sonnet-4-5"),
+            ModelFamily::Claude
+        );
+        assert_eq!(
+            ModelFamily::detect("anthropic/claude-3-5-haiku"),
+            ModelFamily::Claude
+        );
+        assert_eq!(ModelFamily::detect("gpt-4o"), ModelFamily::Gpt);
+        assert_eq!(ModelFamily::detect("openai/o3-mini"), ModelFamily::Gpt);
+        assert_eq!(
+            ModelFamily::detect("Qwen2.5-Coder-32B-Instruct"),
+            ModelFamily::OpenWeight
+        );
+        assert_eq!(ModelFamily::detect("llama3.1:8b"), ModelFamily::OpenWeight);
+        assert_eq!(ModelFamily::detect("mock-model"), ModelFamily::Other);
+    }
+
+    #[test]
+    fn test_text_tokens_use_family_ratio_and_count_non_ascii_per_char() {
+        let claude = TokenEstimator::for_model("claude-sonnet-4-5");
+        let gpt = TokenEstimator::for_model("gpt-4o");
+        let text = "x".repeat(360);
+        assert_eq!(claude.text_tokens(&text), 113);
+        assert_eq!(gpt.text_tokens(&text), 100);
+        assert_eq!(gpt.text_tokens("日本語"), 3);
+        assert_eq!(gpt.text_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_message_tokens_price_images_flat_and_skip_signatures() {
+        let estimator = TokenEstimator::for_model("claude-sonnet-4-5");
+        let image = ApiMessage {
+            role: "user".to_string(),
+            content: Content::Blocks(vec![ContentBlock::Image {
+                source: ImageSource::Base64 {
+                    media_type: "image/png".to_string(),
+                    data: "A".repeat(200_000),
+                },
+            }]),
+        };
+        assert_eq!(estimator.message_tokens(&image), 4 + 1_600);
+
+        let thinking = ApiMessage {
+            role: "assistant".to_string(),
+            content: Content::Blocks(vec![ContentBlock::Thinking {
+                thinking: String::new(),
+                signature: "S".repeat(10_000),
+            }]),
+        };
+        assert_eq!(estimator.message_tokens(&thinking), 4);
+    }
+
+    #[test]
+    fn test_history_limit_subtracts_output_overhead_and_margin() {
+        let budget = TokenBudget {
+            context_window: 200_000,
+            reserved_output: 4_096,
+            prompt_overhead: 3_000,
+        };
+        assert_eq!(budget.history_limit(), 180_000 - 7_096);
+
+        let tiny = TokenBudget {
+            context_window: 2_048,
+            reserved_output: 1_024,
+            prompt_overhead: 3_000,
+        };
+        assert_eq!(tiny.history_limit(), 1_024);
+    }
+}
@@ -0,0 +1,164 @@
+use super::compaction::{is_summary_message, is_turn_start, SUMMARY_MESSAGE_PREFIX};
+use super::conversation::user_prompt_text;
+use crate::types::{ApiMessage, Content, ContentBlock, ImageSource, Usage};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// When a user turn ran and what it cost.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TurnTiming {
+    /// 0-based position of the turn's prompt among the session's user turns.
+    pub turn: usize,
+    pub started_at_unix_ms: u64,
+    pub duration_ms: u64,
+    pub model: String,
+    pub usage: Usage,
+}
+
+impl TurnTiming {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+/// Everything the session still holds, oldest first: archived messages
+/// followed by the live history, plus timing for the turns that finished.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTranscript {
+    pub model: String,
+    pub messages: Vec<ApiMessage>,
+    pub turn_timings: Vec<TurnTiming>,
+}
+
+/// One readable piece of a [`SessionTranscript`], in session order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptEntry<'a> {
+    /// A user prompt; `number` is 1-based.
+    Prompt {
+        number: usize,
+        text: String,
+        timing: Option<&'a TurnTiming>,
+    },
+    /// A compaction summary standing in for archived turns.
+    Summary(&'a str),
+    AssistantText(&'a str),
+    Reasoning(&'a str),
+    ToolCall {
+        name: &'a str,
+        input: &'a serde_json::Value,
+    },
+    /// `name` is unknown for text-protocol results.
+    ToolResult {
+        name: Option<&'a str>,
+        content: &'a str,
+        is_error: bool,
+    },
+    Image {
+        media_type: &'a str,
+    },
+}
+
+impl SessionTranscript {
+    pub fn user_turn_count(&self) -> usize {
+        count_user_turns(&self.messages)
+    }
+
+    pub fn entries(&self) -> Vec<TranscriptEntry<'_>> {
+        let tool_names: HashMap<&str, &str> = self
+            .messages
+            .iter()
+            .filter_map(|message| match &message.content {
+                Content::Blocks(blocks) => Some(blocks),
+                Content::Text(_) => None,
+            })
+            .flatten()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, .. } => Some((id.as_str(), name.as_str())),
+                _ => None,
+            })
+            .collect();
+
+        let mut entries = Vec::new();
+        let mut turn = 0usize;
+        for message in &self.messages {
+            if is_user_turn(message) {
+                entries.push(TranscriptEntry::Prompt {
+                    number: turn + 1,
+                    text: user_prompt_text(message),
+                    timing: self.turn_timings.iter().find(|timing| timing.turn == turn),
+                });
+                entries.extend(image_entries(message));
+                turn += 1;
+                continue;
+            }
+            match &message.content {
+                Content::Text(text) if is_summary_message(message) => entries.push(
+                    TranscriptEntry::Summary(text[SUMMARY_MESSAGE_PREFIX.len()..].trim_start()),
+                ),
+                Content::Text(text) if message.role == "user" => {
+                    entries.push(TranscriptEntry::ToolResult {
+                        name: None,
+                        content: text,
+                        is_error: false,
+                    })
+                }
+                Content::Text(text) => entries.push(TranscriptEntry::AssistantText(text)),
+                Content::Blocks(blocks) => {
+                    entries.extend(blocks.iter().filter_map(|block| match block {
+                        ContentBlock::Text { text } if message.role == "assistant" => {
+                            Some(TranscriptEntry::AssistantText(text))
+                        }
+                        ContentBlock::Thinking { thinking, .. } if !thinking.is_empty() => {
+                            Some(TranscriptEntry::Reasoning(thinking))
+                        }
+                        ContentBlock::ToolUse { name, input, .. } => {
+                            Some(TranscriptEntry::ToolCall { name, input })
+                        }
+                        ContentBlock::ToolResult {
+                            tool_use_id,
+                            content,
+                            is_error,
+                        } => Some(TranscriptEntry::ToolResult {
+                            name: tool_names.get(tool_use_id.as_str()).copied(),
+                            content,
+                            is_error: *is_error,
+                        }),
+                        ContentBlock::Image {
+                            source: ImageSource::Base64 { media_type, .. },
+                        } => Some(TranscriptEntry::Image { media_type }),
+                        _ => None,
+                    }))
+                }
+            }
+        }
+        entries
+    }
+}
+
+fn image_entries(message: &ApiMessage) -> Vec<TranscriptEntry<'_>> {
+    match &message.content {
+        Content::Blocks(blocks) => blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Image {
+                    source: ImageSource::Base64 { media_type, .. },
+                } => Some(TranscriptEntry::Image { media_type }),
+                _ => None,
+            })
+            .collect(),
+        Content::Text(_) => Vec::new(),
+    }
+}
+
+/// A user prompt that starts a turn; summaries and tool results do not.
+pub(super) fn is_user_turn(message: &ApiMessage) -> bool {
+    is_turn_start(message) && !is_summary_message(message)
+}
+
+pub(super) fn count_user_turns<'a>(messages: impl IntoIterator<Item = &'a ApiMessage>) -> usize {
+    messages
+        .into_iter()
+        .filter(|message| is_user_turn(message))
+        .count()
+}
@@ -0,0 +1,95 @@
+use crate::api::ModelPricing;
+use crate::types::Usage;
+
+/// Snapshot of token usage and cost published to the frontend after each response.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageReport {
+    pub model: String,
+    pub turn: Usage,
+    pub session: Usage,
+    pub turn_cost_usd: Option<f64>,
+    pub session_cost_usd: Option<f64>,
+}
+
+/// Per-turn and per-session usage accumulator owned by `ConversationManager`.
+///
+/// Cost is accumulated per response so a session that spans several models
+/// is priced with the model that served each response.
+#[derive(Debug, Clone, Default)]
+pub struct UsageLedger {
+    turn: Usage,
+    session: Usage,
+    turn_cost_usd: Option<f64>,
+    session_cost_usd: Option<f64>,
+}
+
+impl UsageLedger {
+    pub fn begin_turn(&mut self) {
+        self.turn = Usage::default();
+        self.turn_cost_usd = None;
+    }
+
+    pub fn record_response(&mut self, pricing: Option<ModelPricing>, usage: &Usage) {
+        self.turn.accumulate(usage);
+        self.session.accumulate(usage);
+        if let Some(pricing) = pricing {
+            let cost = pricing.cost_usd(usage);
+            *self.turn_cost_usd.get_or_insert(0.0) += cost;
+            *self.session_cost_usd.get_or_insert(0.0) += cost;
+        }
+    }
+
+    /// Add another ledger's session totals to this turn, e.g. a sub-agent's.
+    pub fn absorb(&mut self, report: &UsageReport) {
+        self.turn.accumulate(&report.session);
+        self.session.accumulate(&report.session);
+        if let Some(cost) = report.session_cost_usd {
+            *self.turn_cost_usd.get_or_insert(0.0) += cost;
+            *self.session_cost_usd.get_or_insert(0.0) += cost;
+        }
+    }
+
+    pub fn report(&self, model: &str) -> UsageReport {
+        UsageReport {
+            model: model.to_string(),
+            turn: self.turn,
+            session: self.session,
+            turn_cost_usd: self.turn_cost_usd,
+            session_cost_usd: self.session_cost_usd,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ModelCatalog;
+
+    #[test]
+    fn test_usage_ledger_accumulates_turn_and_session() {
+        let _env_lock = crate::test_support::ENV_LOCK.blocking_lock();
+        std::env::remove_var("VEX_MODEL_PRICING");
+        let sonnet = ModelCatalog::builtin().pricing("claude-sonnet-4-5");
+
+        let mut ledger = UsageLedger::default();
+        let response = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 100_000,
+            ..Usage::default()
+        };
+        ledger.begin_turn();
+        ledger.record_response(sonnet, &response);
+        ledger.begin_turn();
+        ledger.record_response(sonnet, &response);
+
+        let report = ledger.report("claude-sonnet-4-5");
+        assert_eq!(report.turn.input_tokens, 1_000_000);
+        assert_eq!(report.session.input_tokens, 2_000_000);
+        assert_eq!(report.turn_cost_usd, Some(4.5));
+        assert_eq!(report.session_cost_usd, Some(9.0));
+
+        let mut unpriced = UsageLedger::default();
+        unpriced.record_response(None, &response);
+        assert_eq!(unpriced.report("local/llama").session_cost_usd, None);
+    }
+}
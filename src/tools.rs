@@ -1,3 +1,7 @@
+mod attachment;
+mod hooks;
 mod operator;
 
+pub use attachment::{is_image_path, load_image_attachment, ImageAttachment};
+pub use hooks::{HookEvent, HookRun, ToolHooks};
 pub use operator::ToolOperator;
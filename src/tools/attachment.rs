@@ -0,0 +1,225 @@
+use super::ToolOperator;
+use crate::types::{ContentBlock, ImageSource};
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+/// Largest source file read from disk before any decoding happens.
+const MAX_SOURCE_IMAGE_BYTES: u64 = 32 * 1024 * 1024;
+/// Anthropic downscales anything past this edge length server-side anyway.
+const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 1568;
+/// Per-image payload limit on the Anthropic Messages API.
+const DEFAULT_MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Workspace image prepared for a user message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageAttachment {
+    pub path: String,
+    pub media_type: String,
+    /// Base64-encoded image bytes.
+    pub data: String,
+    pub width: u32,
+    pub height: u32,
+    pub encoded_bytes: usize,
+    pub downscaled: bool,
+}
+
+impl ImageAttachment {
+    pub fn to_content_block(&self) -> ContentBlock {
+        ContentBlock::Image {
+            source: ImageSource::Base64 {
+                media_type: self.media_type.clone(),
+                data: self.data.clone(),
+            },
+        }
+    }
+}
+
+/// Returns true when `path` names a file type that can be attached as an image.
+pub fn is_image_path(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Load a workspace image through the operator's path guards, downscaling it
+/// to the configured edge length and byte limit when needed.
+pub fn load_image_attachment(operator: &ToolOperator, path: &str) -> Result<ImageAttachment> {
+    let bytes = operator.read_file_bytes(path, MAX_SOURCE_IMAGE_BYTES)?;
+    prepare_image(
+        path,
+        bytes,
+        resolve_max_image_dimension(),
+        resolve_max_image_bytes(),
+    )
+}
+
+fn prepare_image(
+    path: &str,
+    bytes: Vec<u8>,
+    max_dimension: u32,
+    max_bytes: usize,
+) -> Result<ImageAttachment> {
+    let format =
+        image::guess_format(&bytes).with_context(|| format!("{path} is not a recognized image"))?;
+    let media_type = match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        other => bail!("{path}: unsupported image format {other:?} (use png, jpeg, gif or webp)"),
+    };
+    let (width, height) = ImageReader::with_format(Cursor::new(&bytes), format)
+        .into_dimensions()
+        .with_context(|| format!("Failed to read image header for {path}"))?;
+
+    if width <= max_dimension && height <= max_dimension && bytes.len() <= max_bytes {
+        return Ok(build_attachment(
+            path, media_type, &bytes, width, height, false,
+        ));
+    }
+
+    let decoded = image::load_from_memory_with_format(&bytes, format)
+        .with_context(|| format!("Failed to decode image {path}"))?;
+    let resized = if width > max_dimension || height > max_dimension {
+        decoded.resize(max_dimension, max_dimension, FilterType::Triangle)
+    } else {
+        decoded
+    };
+
+    let (encoded, media_type) = if format == ImageFormat::Jpeg {
+        (encode_jpeg(&resized)?, "image/jpeg")
+    } else {
+        let mut png = Vec::new();
+        resized
+            .write_to(Cursor::new(&mut png), ImageFormat::Png)
+            .context("Failed to encode downscaled image")?;
+        if png.len() <= max_bytes {
+            (png, "image/png")
+        } else {
+            (encode_jpeg(&resized)?, "image/jpeg")
+        }
+    };
+    if encoded.len() > max_bytes {
+        bail!(
+            "{path} is still {} KB after downscaling (limit {} KB)",
+            encoded.len() / 1024,
+            max_bytes / 1024
+        );
+    }
+
+    Ok(build_attachment(
+        path,
+        media_type,
+        &encoded,
+        resized.width(),
+        resized.height(),
+        true,
+    ))
+}
+
+fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut jpeg = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+        .context("Failed to encode downscaled image as JPEG")?;
+    Ok(jpeg)
+}
+
+fn build_attachment(
+    path: &str,
+    media_type: &str,
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    downscaled: bool,
+) -> ImageAttachment {
+    ImageAttachment {
+        path: path.to_string(),
+        media_type: media_type.to_string(),
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        width,
+        height,
+        encoded_bytes: bytes.len(),
+        downscaled,
+    }
+}
+
+fn resolve_max_image_dimension() -> u32 {
+    std::env::var("VEX_IMAGE_MAX_DIMENSION")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .map(|value| value.clamp(256, 8_000))
+        .unwrap_or(DEFAULT_MAX_IMAGE_DIMENSION)
+}
+
+fn resolve_max_image_bytes() -> usize {
+    std::env::var("VEX_IMAGE_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .map(|value| value.clamp(64 * 1024, 20 * 1024 * 1024))
+        .unwrap_or(DEFAULT_MAX_IMAGE_BYTES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, 90])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_prepare_image_passes_small_png_through() {
+        let bytes = png_bytes(40, 20);
+        let attachment = prepare_image("shot.png", bytes.clone(), 1568, 5 * 1024 * 1024).unwrap();
+        assert_eq!(attachment.media_type, "image/png");
+        assert_eq!((attachment.width, attachment.height), (40, 20));
+        assert!(!attachment.downscaled);
+        assert_eq!(
+            attachment.data,
+            base64::engine::general_purpose::STANDARD.encode(&bytes)
+        );
+    }
+
+    #[test]
+    fn test_prepare_image_downscales_to_max_dimension() {
+        let attachment =
+            prepare_image("wide.png", png_bytes(800, 200), 400, 5 * 1024 * 1024).unwrap();
+        assert!(attachment.downscaled);
+        assert_eq!((attachment.width, attachment.height), (400, 100));
+    }
+
+    #[test]
+    fn test_prepare_image_rejects_non_images() {
+        let error = prepare_image("notes.png", b"plain text".to_vec(), 1568, 1024).unwrap_err();
+        assert!(error.to_string().contains("not a recognized image"));
+    }
+
+    #[test]
+    fn test_is_image_path_matches_known_extensions() {
+        assert!(is_image_path("docs/Diagram.PNG"));
+        assert!(is_image_path("shot.webp"));
+        assert!(!is_image_path("src/main.rs"));
+        assert!(!is_image_path("png"));
+    }
+}
@@ -0,0 +1,418 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const HOOKS_ENV: &str = "VEX_HOOKS";
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 60;
+/// Hook output kept for the tool result and the transcript.
+const MAX_HOOK_OUTPUT_CHARS: usize = 4_000;
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long to wait for output after the hook exits.
+const HOOK_OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Commands run before and after tool calls, configured by the JSON file in
+/// `VEX_HOOKS`.
+#[derive(Debug, Clone, Default)]
+pub struct ToolHooks {
+    pre_tool_use: Vec<ToolHook>,
+    post_tool_use: Vec<ToolHook>,
+}
+
+#[derive(Debug, Clone)]
+struct ToolHook {
+    /// Matches the whole tool name.
+    tools: Regex,
+    command: String,
+    timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// Runs before the tool; a failing hook vetoes the call.
+    PreToolUse,
+    /// Runs after the tool succeeded; its output is appended to the result.
+    PostToolUse,
+}
+
+impl HookEvent {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::PreToolUse => "pre_tool_use",
+            Self::PostToolUse => "post_tool_use",
+        }
+    }
+}
+
+/// One finished hook command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookRun {
+    pub event: HookEvent,
+    pub tool: String,
+    pub command: String,
+    /// `None` when the command exited 0.
+    pub failure: Option<String>,
+    /// Combined stdout and stderr, trimmed and truncated.
+    pub output: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HooksConfig {
+    pre_tool_use: Vec<ConfiguredHook>,
+    post_tool_use: Vec<ConfiguredHook>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfiguredHook {
+    /// Regex over tool names, e.g. `write_file|edit_file`; all tools if unset.
+    #[serde(default)]
+    tools: Option<String>,
+    command: String,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+impl ToolHooks {
+    /// Hooks from the `VEX_HOOKS` file; none when it is unset.
+    pub fn load() -> Result<Self> {
+        let Some(path) = std::env::var(HOOKS_ENV)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+        else {
+            return Ok(Self::default());
+        };
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {HOOKS_ENV} file '{path}'"))?;
+        Self::from_json(&raw).with_context(|| format!("Invalid {HOOKS_ENV} file '{path}'"))
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self> {
+        let config: HooksConfig = serde_json::from_str(raw)?;
+        Ok(Self {
+            pre_tool_use: compile_hooks(HookEvent::PreToolUse, config.pre_tool_use)?,
+            post_tool_use: compile_hooks(HookEvent::PostToolUse, config.post_tool_use)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pre_tool_use.is_empty() && self.post_tool_use.is_empty()
+    }
+
+    /// Run the pre hooks matching `tool` in order. The first failing hook
+    /// vetoes the call; its output, or a note naming it, is the reason.
+    pub fn run_pre(
+        &self,
+        tool: &str,
+        input: &Value,
+        working_dir: &Path,
+        report: &mut dyn FnMut(HookRun),
+    ) -> Result<(), String> {
+        let payload = json!({
+            "event": HookEvent::PreToolUse.label(),
+            "tool": tool,
+            "input": input,
+        });
+        for hook in self.pre_tool_use.iter().filter(|hook| hook.matches(tool)) {
+            let run = hook.run(HookEvent::PreToolUse, tool, &payload, working_dir);
+            let veto = run.failure.as_ref().map(|failure| {
+                if run.output.is_empty() {
+                    format!("{tool} was blocked by hook `{}` ({failure})", run.command)
+                } else {
+                    format!(
+                        "{tool} was blocked by hook `{}`: {}",
+                        run.command, run.output
+                    )
+                }
+            });
+            report(run);
+            if let Some(veto) = veto {
+                return Err(veto);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the post hooks matching `tool` on its successful `output` and
+    /// return what they printed, to be appended to the tool result.
+    pub fn run_post(
+        &self,
+        tool: &str,
+        input: &Value,
+        output: &str,
+        working_dir: &Path,
+        report: &mut dyn FnMut(HookRun),
+    ) -> String {
+        let payload = json!({
+            "event": HookEvent::PostToolUse.label(),
+            "tool": tool,
+            "input": input,
+            "output": output,
+        });
+        let mut notes = String::new();
+        for hook in self.post_tool_use.iter().filter(|hook| hook.matches(tool)) {
+            let run = hook.run(HookEvent::PostToolUse, tool, &payload, working_dir);
+            if run.failure.is_some() || !run.output.is_empty() {
+                let status = run.failure.as_deref().unwrap_or("ok");
+                notes.push_str(&format!("\n\n[hook `{}`: {status}]", run.command));
+                if !run.output.is_empty() {
+                    notes.push('\n');
+                    notes.push_str(&run.output);
+                }
+            }
+            report(run);
+        }
+        notes
+    }
+}
+
+impl ToolHook {
+    fn matches(&self, tool: &str) -> bool {
+        self.tools.is_match(tool)
+    }
+
+    /// Run the command through the shell in `working_dir` with `payload` on
+    /// stdin, killing it at the timeout.
+    fn run(&self, event: HookEvent, tool: &str, payload: &Value, working_dir: &Path) -> HookRun {
+        let (failure, output) = match self.spawn(event, tool, working_dir) {
+            Ok(child) => self.wait(child, payload),
+            Err(error) => (Some(format!("could not start: {error}")), String::new()),
+        };
+        HookRun {
+            event,
+            tool: tool.to_string(),
+            command: self.command.clone(),
+            failure,
+            output,
+        }
+    }
+
+    fn spawn(&self, event: HookEvent, tool: &str, working_dir: &Path) -> std::io::Result<Child> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command
+            .arg(&self.command)
+            .current_dir(working_dir)
+            .env("VEX_HOOK_EVENT", event.label())
+            .env("VEX_TOOL_NAME", tool)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+
+    fn wait(&self, mut child: Child, payload: &Value) -> (Option<String>, String) {
+        // Written from a thread so a hook that never reads its input still
+        // times out; one that exits early closes the pipe.
+        let payload = payload.to_string();
+        if let Some(mut stdin) = child.stdin.take() {
+            std::thread::spawn(move || {
+                let _ = stdin.write_all(payload.as_bytes());
+            });
+        }
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let started = Instant::now();
+        let mut timed_out = false;
+        let failure = loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => break None,
+                Ok(Some(status)) => {
+                    break Some(match status.code() {
+                        Some(code) => format!("exit {code}"),
+                        None => "killed by signal".to_string(),
+                    })
+                }
+                Ok(None) if started.elapsed() >= self.timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    timed_out = true;
+                    break Some(format!("timed out after {}s", self.timeout.as_secs()));
+                }
+                Ok(None) => std::thread::sleep(HOOK_POLL_INTERVAL),
+                Err(error) => break Some(format!("wait failed: {error}")),
+            }
+        };
+
+        // Processes the hook left running can hold the pipes open; their
+        // output is not waited for.
+        let grace = if timed_out {
+            Duration::ZERO
+        } else {
+            HOOK_OUTPUT_GRACE
+        };
+        let output = [stdout, stderr]
+            .into_iter()
+            .map(|reader| reader.recv_timeout(grace).unwrap_or_default())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        (failure, truncate_output(output))
+    }
+}
+
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        let _ = tx.send(String::from_utf8_lossy(&bytes).into_owned());
+    });
+    rx
+}
+
+fn truncate_output(output: String) -> String {
+    match output.char_indices().nth(MAX_HOOK_OUTPUT_CHARS) {
+        Some((cut, _)) => format!("{}\n[hook output truncated]", &output[..cut]),
+        None => output,
+    }
+}
+
+fn compile_hooks(event: HookEvent, hooks: Vec<ConfiguredHook>) -> Result<Vec<ToolHook>> {
+    hooks
+        .into_iter()
+        .enumerate()
+        .map(|(index, hook)| {
+            let field = format!("{}[{index}]", event.label());
+            let command = hook.command.trim();
+            if command.is_empty() {
+                bail!("{field}: empty command");
+            }
+            let pattern = hook.tools.as_deref().unwrap_or(".*");
+            let tools = Regex::new(&format!("^(?:{pattern})$"))
+                .with_context(|| format!("{field}: invalid tools pattern '{pattern}'"))?;
+            let timeout_secs = hook.timeout_secs.unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS);
+            if timeout_secs == 0 {
+                bail!("{field}: timeout_secs must be at least 1");
+            }
+            Ok(ToolHook {
+                tools,
+                command: command.to_string(),
+                timeout: Duration::from_secs(timeout_secs),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pre_hook_vetoes_matching_tools_with_its_output() {
+        let temp = TempDir::new().expect("temp dir");
+        let hooks = ToolHooks::from_json(
+            r#"{"pre_tool_use": [{
+                "tools": "write_file|edit_file",
+                "command": "grep -q '\"path\":\"src/' || { echo \"only src/ may change\"; exit 1; }"
+            }]}"#,
+        )
+        .expect("valid hooks");
+
+        let mut runs = Vec::new();
+        let outside = json!({"path": "Cargo.toml", "content": ""});
+        let veto = hooks
+            .run_pre("write_file", &outside, temp.path(), &mut |run| {
+                runs.push(run)
+            })
+            .expect_err("write outside src/ is vetoed");
+        assert!(veto.contains("only src/ may change"), "{veto}");
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].failure.as_deref(), Some("exit 1"));
+
+        let inside = json!({"path": "src/lib.rs", "content": ""});
+        assert!(hooks
+            .run_pre("write_file", &inside, temp.path(), &mut |run| runs
+                .push(run))
+            .is_ok());
+        assert!(hooks
+            .run_pre("write_files", &outside, temp.path(), &mut |run| runs
+                .push(run))
+            .is_ok());
+        assert_eq!(runs.len(), 2);
+
+        let silent = ToolHooks::from_json(r#"{"pre_tool_use": [{"command": "exit 3"}]}"#)
+            .expect("valid hooks");
+        let veto = silent
+            .run_pre("read_file", &inside, temp.path(), &mut |_| {})
+            .expect_err("failing hook vetoes");
+        assert_eq!(veto, "read_file was blocked by hook `exit 3` (exit 3)");
+    }
+
+    #[test]
+    fn test_post_hook_output_is_collected_for_tool_result() {
+        let temp = TempDir::new().expect("temp dir");
+        let hooks = ToolHooks::from_json(
+            r#"{"post_tool_use": [
+                {"tools": "edit_file", "command": "echo \"checked $VEX_TOOL_NAME\""},
+                {"tools": "edit_file", "command": "true"},
+                {"tools": "edit_file", "command": "echo broken >&2; exit 2"}
+            ]}"#,
+        )
+        .expect("valid hooks");
+
+        let mut runs = Vec::new();
+        let notes = hooks.run_post(
+            "edit_file",
+            &json!({"path": "src/lib.rs"}),
+            "Edited src/lib.rs",
+            temp.path(),
+            &mut |run| runs.push(run),
+        );
+        assert_eq!(
+            notes,
+            "\n\n[hook `echo \"checked $VEX_TOOL_NAME\"`: ok]\nchecked edit_file\n\n[hook `echo broken >&2; exit 2`: exit 2]\nbroken"
+        );
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[1].failure, None);
+        assert_eq!(runs[1].output, "");
+    }
+
+    #[test]
+    fn test_hook_timeout_kills_the_command() {
+        let temp = TempDir::new().expect("temp dir");
+        let hooks = ToolHooks::from_json(
+            r#"{"pre_tool_use": [{"command": "sleep 5", "timeout_secs": 1}]}"#,
+        )
+        .expect("valid hooks");
+        let started = Instant::now();
+        let veto = hooks
+            .run_pre("git_status", &json!({}), temp.path(), &mut |_| {})
+            .expect_err("timed out hook vetoes");
+        assert!(veto.contains("timed out after 1s"), "{veto}");
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_invalid_hook_config_is_rejected() {
+        for raw in [
+            r#"{"pre_tool_use": [{"command": " "}]}"#,
+            r#"{"pre_tool_use": [{"tools": "(", "command": "true"}]}"#,
+            r#"{"post_tool_use": [{"command": "true", "timeout_secs": 0}]}"#,
+            r#"{"pre_tool_use": [{"command": "true", "matcher": "x"}]}"#,
+            r#"{"on_tool_use": []}"#,
+        ] {
+            assert!(ToolHooks::from_json(raw).is_err(), "{raw}");
+        }
+        assert!(ToolHooks::from_json("{}").expect("empty config").is_empty());
+    }
+}
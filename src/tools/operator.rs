@@ -23,6 +23,10 @@ impl ToolOperator {
         }
     }
 
+    pub fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+
     fn resolve_path(&self, path: &str) -> Result<PathBuf> {
         let path = path.trim();
         if path.is_empty() {
@@ -93,6 +97,12 @@ impl ToolOperator {
         out
     }
 
+    /// Returns true when `path` resolves inside the workspace to a regular file.
+    pub fn is_file(&self, path: &str) -> bool {
+        self.resolve_path(path)
+            .is_ok_and(|resolved| resolved.is_file())
+    }
+
     pub fn read_file(&self, path: &str) -> Result<String> {
         let resolved = self.resolve_path(path)?;
         if resolved.is_dir() {
@@ -101,6 +111,20 @@ impl ToolOperator {
         fs::read_to_string(resolved).context("Failed to read file")
     }
 
+    pub fn read_file_bytes(&self, path: &str, max_bytes: u64) -> Result<Vec<u8>> {
+        let resolved = self.resolve_path(path)?;
+        if resolved.is_dir() {
+            bail!("expected a file path, got a directory: {path}");
+        }
+        let size = fs::metadata(&resolved)
+            .context("Failed to read file")?
+            .len();
+        if size > max_bytes {
+            bail!("{path} is {size} bytes, over the {max_bytes} byte limit");
+        }
+        fs::read(resolved).context("Failed to read file")
+    }
+
     pub fn write_file(&self, path: &str, content: &str) -> Result<()> {
         let resolved = self.resolve_path(path)?;
         if resolved.is_dir() {
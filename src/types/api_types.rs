@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiMessage {
@@ -31,6 +31,27 @@ pub enum ContentBlock {
         #[serde(default)]
         is_error: bool,
     },
+    /// Extended thinking output. `signature` must be sent back unchanged.
+    Thinking {
+        #[serde(default)]
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    /// Encrypted thinking returned when reasoning is flagged by safety systems.
+    RedactedThinking {
+        data: String,
+    },
+    /// Image attached to a user message.
+    Image {
+        source: ImageSource,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 { media_type: String, data: String },
 }
 
 fn default_json_object() -> serde_json::Value {
@@ -56,6 +77,8 @@ pub enum StreamEvent {
     },
     MessageDelta {
         delta: MessageDelta,
+        #[serde(default)]
+        usage: Option<Usage>,
     },
     MessageStop,
     #[serde(other)]
@@ -71,6 +94,10 @@ pub struct Delta {
     pub text: Option<String>,
     #[serde(default)]
     pub partial_json: Option<String>,
+    #[serde(default)]
+    pub thinking: Option<String>,
+    #[serde(default)]
+    pub signature: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -78,6 +105,8 @@ pub struct MessageStartData {
     pub id: String,
     pub role: String,
     pub model: String,
+    #[serde(default)]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -85,6 +114,67 @@ pub struct MessageDelta {
     pub stop_reason: Option<String>,
 }
 
+/// Token counts reported by the server for one response.
+///
+/// Anthropic reports `input_tokens` exclusive of cached prefix tokens; the
+/// OpenAI mapping in `StreamParser` normalizes to the same convention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default, deserialize_with = "deserialize_token_count")]
+    pub input_tokens: u64,
+    #[serde(default, deserialize_with = "deserialize_token_count")]
+    pub output_tokens: u64,
+    #[serde(default, deserialize_with = "deserialize_token_count")]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default, deserialize_with = "deserialize_token_count")]
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    /// Merge a streamed usage snapshot. Servers repeat or grow counts across
+    /// `message_start` and `message_delta`, so non-zero fields replace.
+    pub fn absorb(&mut self, update: &Usage) {
+        let merge = |current: &mut u64, incoming: u64| {
+            if incoming > 0 {
+                *current = incoming;
+            }
+        };
+        merge(&mut self.input_tokens, update.input_tokens);
+        merge(&mut self.output_tokens, update.output_tokens);
+        merge(
+            &mut self.cache_creation_input_tokens,
+            update.cache_creation_input_tokens,
+        );
+        merge(
+            &mut self.cache_read_input_tokens,
+            update.cache_read_input_tokens,
+        );
+    }
+
+    /// Add the counts of a completed response.
+    pub fn accumulate(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+
+    pub fn total_input_tokens(&self) -> u64 {
+        self.input_tokens + self.cache_creation_input_tokens + self.cache_read_input_tokens
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Usage::default()
+    }
+}
+
+fn deserialize_token_count<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<u64>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,4 +194,40 @@ mod tests {
             "Missing 'content' key in JSON!"
         );
     }
+
+    #[test]
+    fn test_usage_absorb_keeps_latest_non_zero_counts() {
+        let mut usage = Usage::default();
+        usage.absorb(&Usage {
+            input_tokens: 120,
+            output_tokens: 1,
+            cache_read_input_tokens: 40,
+            ..Usage::default()
+        });
+        usage.absorb(&Usage {
+            output_tokens: 57,
+            ..Usage::default()
+        });
+
+        assert_eq!(usage.input_tokens, 120);
+        assert_eq!(usage.output_tokens, 57);
+        assert_eq!(usage.cache_read_input_tokens, 40);
+        assert_eq!(usage.total_input_tokens(), 160);
+    }
+
+    #[test]
+    fn test_message_delta_usage_parses_with_null_cache_counts() {
+        let event: StreamEvent = serde_json::from_str(
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":9,"cache_read_input_tokens":null}}"#,
+        )
+        .unwrap();
+        match event {
+            StreamEvent::MessageDelta { usage, .. } => {
+                let usage = usage.expect("usage should parse");
+                assert_eq!(usage.output_tokens, 9);
+                assert_eq!(usage.cache_read_input_tokens, 0);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
 }
@@ -2,15 +2,18 @@ use ratatui::layout::{Constraint, Direction, Layout, Rect};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ThreePaneLayout {
+    /// Session tabs; zero height while only one session is open.
+    pub tabs: Rect,
     pub header: Rect,
     pub history: Rect,
     pub input: Rect,
 }
 
-pub fn split_three_pane_layout(area: Rect, input_rows: u16) -> ThreePaneLayout {
+pub fn split_three_pane_layout(area: Rect, input_rows: u16, show_tabs: bool) -> ThreePaneLayout {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
+            Constraint::Length(u16::from(show_tabs)),
             Constraint::Length(1),
             Constraint::Min(1),
             Constraint::Length(input_rows.max(1)),
@@ -18,9 +21,10 @@ pub fn split_three_pane_layout(area: Rect, input_rows: u16) -> ThreePaneLayout {
         .split(area);
 
     ThreePaneLayout {
-        header: chunks[0],
-        history: chunks[1],
-        input: chunks[2],
+        tabs: chunks[0],
+        header: chunks[1],
+        history: chunks[2],
+        input: chunks[3],
     }
 }
 
@@ -31,7 +35,7 @@ mod tests {
     #[test]
     fn layout_splits_into_three_panes() {
         let area = Rect::new(0, 0, 80, 20);
-        let panes = split_three_pane_layout(area, 4);
+        let panes = split_three_pane_layout(area, 4, false);
 
         assert_eq!(panes.header.height, 1);
         assert_eq!(panes.history.height, 15);
@@ -44,10 +48,21 @@ mod tests {
     #[test]
     fn layout_preserves_dynamic_input_height() {
         let area = Rect::new(0, 0, 80, 12);
-        let panes = split_three_pane_layout(area, 6);
+        let panes = split_three_pane_layout(area, 6, false);
 
         assert_eq!(panes.input.height, 6);
         assert_eq!(panes.header.height, 1);
         assert_eq!(panes.history.height, 5);
     }
+
+    #[test]
+    fn layout_reserves_tab_row_when_shown() {
+        let area = Rect::new(0, 0, 80, 12);
+        let panes = split_three_pane_layout(area, 2, true);
+
+        assert_eq!(panes.tabs.height, 1);
+        assert_eq!(panes.header.y, 1);
+        assert_eq!(panes.history.height, 8);
+        assert_eq!(panes.input.y, 10);
+    }
 }
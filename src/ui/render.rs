@@ -20,6 +20,10 @@ pub enum OverlayModal<'a> {
         input_preview: &'a str,
         auto_approve_enabled: bool,
     },
+    /// Earlier user prompts, newest first.
+    Rewind { prompts: &'a [String] },
+    /// A plan written in plan mode, waiting for approval.
+    Plan { plan: &'a str },
 }
 
 pub fn input_visual_rows(input: &str, width: usize) -> usize {
@@ -179,6 +183,28 @@ pub fn render_status_line(frame: &mut Frame<'_>, area: Rect, status: &str) {
     );
 }
 
+/// One row of session tabs; the active tab is highlighted.
+pub fn render_tab_bar(frame: &mut Frame<'_>, area: Rect, labels: &[String], active: usize) {
+    if area.height == 0 || area.width == 0 {
+        return;
+    }
+
+    let mut spans = Vec::with_capacity(labels.len() * 2);
+    for (index, label) in labels.iter().enumerate() {
+        let style = if index == active {
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        spans.push(Span::styled(format!(" {label} "), style));
+        spans.push(Span::raw(" "));
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
 pub fn render_overlay_modal(frame: &mut Frame<'_>, modal: OverlayModal<'_>) {
     if frame.area().width == 0 || frame.area().height == 0 {
         return;
@@ -306,6 +332,58 @@ fn modal_content(
                 "1 yes   2 allow this session   3/esc cancel",
             )
         }
+        OverlayModal::Rewind { prompts } => {
+            let mut body = Vec::new();
+            body.push(Line::from(
+                "Rewind to before a turn. Files on disk are not restored.",
+            ));
+            body.push(Line::from(""));
+            for (index, prompt) in prompts.iter().enumerate() {
+                let first_line = prompt.lines().next().unwrap_or_default();
+                body.push(Line::from(vec![
+                    Span::styled(
+                        format!("{} ", index + 1),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!("> {}", truncate_line(first_line, 80))),
+                ]));
+            }
+            (
+                "Rewind",
+                Color::Magenta,
+                body,
+                "1-9 rewind to before that turn   esc cancel",
+            )
+        }
+        OverlayModal::Plan { plan } => {
+            let mut body = Vec::new();
+            body.push(Line::from(
+                "Approve to leave plan mode and carry out this plan.",
+            ));
+            body.push(Line::from(""));
+            let plan_lines: Vec<&str> = plan.lines().collect();
+            let max_plan_lines = 20;
+            for line in plan_lines.iter().take(max_plan_lines) {
+                body.push(Line::from(line.to_string()));
+            }
+            if plan_lines.len() > max_plan_lines {
+                body.push(Line::styled(
+                    format!(
+                        "... ({} more lines, e to see all)",
+                        plan_lines.len() - max_plan_lines
+                    ),
+                    Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::DIM),
+                ));
+            }
+            (
+                "Plan",
+                Color::Cyan,
+                body,
+                "y/1 approve   e/2 edit   n/3/esc reject",
+            )
+        }
     }
 }
 
@@ -374,6 +452,15 @@ mod tests {
                 input_preview: "echo hi",
                 auto_approve_enabled: false,
             },
+            OverlayModal::Rewind {
+                prompts: &[
+                    "fix the parser".to_string(),
+                    "explain src/lib.rs".to_string(),
+                ],
+            },
+            OverlayModal::Plan {
+                plan: "1. Read src/lib.rs\n2. Add the flag",
+            },
         ];
 
         for modal in modals {
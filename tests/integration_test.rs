@@ -25,3 +25,30 @@ fn test_config_validation_allows_local_endpoint_without_api_key() {
 
     assert!(config.validate().is_ok());
 }
+
+#[test]
+fn test_config_validation_uses_model_catalog_for_remote_api() {
+    let openai = Config {
+        api_key: Some("test-key".to_string()),
+        model: "gpt-4o-2024-11-20".to_string(),
+        api_url: "https://api.openai.com/v1/chat/completions".to_string(),
+        anthropic_version: "2023-06-01".to_string(),
+        working_dir: std::env::current_dir().expect("cwd"),
+    };
+    assert!(openai.validate().is_ok());
+
+    let uncataloged = Config {
+        model: "mystery-model".to_string(),
+        ..openai
+    };
+    assert!(uncataloged.validate().is_ok());
+
+    let unknown = Config {
+        api_url: "https://api.anthropic.com/v1/messages".to_string(),
+        ..uncataloged
+    };
+    let error = unknown
+        .validate()
+        .expect_err("unknown remote Anthropic model");
+    assert!(error.to_string().contains("VEX_MODEL_CATALOG"));
+}
@@ -0,0 +1,140 @@
+use futures::StreamExt;
+use std::path::Path;
+use vexcoder::api::{ApiClient, ApiError};
+use vexcoder::config::Config;
+use vexcoder::mock_server::{MockScript, MockServer};
+use vexcoder::state::ConversationManager;
+use vexcoder::tools::ToolOperator;
+
+async fn start(fixture: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/mock_server")
+        .join(fixture);
+    let script = MockScript::load(&path).expect("fixture");
+    let server = MockServer::bind("127.0.0.1:0", script).await.expect("bind");
+    let addr = server.local_addr().expect("addr");
+    tokio::spawn(server.serve());
+    format!("http://{addr}")
+}
+
+fn local_config(api_url: String, working_dir: &Path) -> Config {
+    Config {
+        api_key: None,
+        model: "local/mock-model".to_string(),
+        api_url,
+        anthropic_version: "2023-06-01".to_string(),
+        working_dir: working_dir.to_path_buf(),
+    }
+}
+
+async fn run_tool_round(path: &str) -> String {
+    let base = start("tool_round.json").await;
+    let workspace = tempfile::tempdir().expect("tempdir");
+    std::fs::write(
+        workspace.path().join("Cargo.toml"),
+        "[package]\nname = \"vexcoder\"\n",
+    )
+    .expect("write manifest");
+
+    let config = local_config(format!("{base}{path}"), workspace.path());
+    let client = ApiClient::new(&config).expect("client");
+    let mut manager =
+        ConversationManager::new(client, ToolOperator::new(workspace.path().to_path_buf()));
+    manager
+        .send_message("What is the package name in Cargo.toml?".to_string(), None)
+        .await
+        .expect("turn completes")
+}
+
+#[tokio::test]
+async fn test_text_protocol_tool_round_over_anthropic_messages() {
+    let reply = run_tool_round("/v1/messages").await;
+    assert!(reply.contains("vexcoder"), "{reply}");
+}
+
+#[tokio::test]
+async fn test_text_protocol_tool_round_over_openai_chat_completions() {
+    let reply = run_tool_round("/v1/chat/completions").await;
+    assert!(reply.contains("vexcoder"), "{reply}");
+}
+
+#[tokio::test]
+async fn test_scripted_faults_surface_as_client_errors() {
+    let base = start("faults.json").await;
+    let workspace = tempfile::tempdir().expect("tempdir");
+    let config = local_config(format!("{base}/v1/messages"), workspace.path());
+    let client = ApiClient::new(&config).expect("client");
+
+    let error = match client.create_stream(&[]).await {
+        Ok(_) => panic!("scripted 529 should fail the request"),
+        Err(error) => error,
+    };
+    assert!(
+        matches!(
+            error.downcast_ref::<ApiError>(),
+            Some(ApiError::Overloaded { message }) if message == "Overloaded"
+        ),
+        "{error}"
+    );
+
+    let mut stream = client.create_stream(&[]).await.expect("stream opens");
+    let mut chunks = 0;
+    let mut failed = false;
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(_) => chunks += 1,
+            Err(_) => failed = true,
+        }
+    }
+    assert_eq!(chunks, 3);
+    assert!(failed, "disconnect should surface as a stream error");
+
+    let mut stream = client.create_stream(&[]).await.expect("stream opens");
+    let mut body = Vec::new();
+    while let Some(item) = stream.next().await {
+        body.extend_from_slice(&item.expect("complete stream"));
+    }
+    let body = String::from_utf8(body).expect("utf-8");
+    assert!(body.contains("event: message_stop"), "{body}");
+}
+
+#[tokio::test]
+async fn test_rejected_stream_options_are_dropped_for_the_session() {
+    let script = MockScript::parse(
+        r#"{"turns": [
+            {"error": {"status": 400, "message": "Unrecognized request argument supplied: stream_options"}},
+            {"text": "first"},
+            {"error": {"status": 400, "message": "bad request"}},
+            {"text": "second"}
+        ]}"#,
+    )
+    .expect("script");
+    let server = MockServer::bind("127.0.0.1:0", script).await.expect("bind");
+    let addr = server.local_addr().expect("addr");
+    tokio::spawn(server.serve());
+    let workspace = tempfile::tempdir().expect("tempdir");
+    let config = local_config(
+        format!("http://{addr}/v1/chat/completions"),
+        workspace.path(),
+    );
+    let client = ApiClient::new(&config).expect("client");
+
+    let mut stream = client
+        .create_stream(&[])
+        .await
+        .expect("retry without stream_options succeeds");
+    while stream.next().await.is_some() {}
+    // The second request no longer carries `stream_options`, so its 400 is
+    // not retried.
+    let error = match client.create_stream(&[]).await {
+        Ok(_) => panic!("scripted 400 should fail the request"),
+        Err(error) => error,
+    };
+    assert!(
+        matches!(
+            error.downcast_ref::<ApiError>(),
+            Some(ApiError::InvalidRequest { message, .. }) if message == "bad request"
+        ),
+        "{error}"
+    );
+}
@@ -1,4 +1,5 @@
 use vexcoder::api::stream::StreamParser;
+use vexcoder::api::{ApiError, ApiProtocol};
 use vexcoder::types::{ContentBlock, StreamEvent};
 
 #[test]
@@ -99,7 +100,7 @@ fn test_openai_tool_call_stream_maps_to_unified_events() {
     let events2 = parser
         .process(chunk2)
         .expect("openai tool call delta should parse");
-    assert_eq!(events2.len(), 3);
+    assert_eq!(events2.len(), 4);
 
     match &events2[0] {
         StreamEvent::ContentBlockStart {
@@ -130,4 +131,440 @@ fn test_openai_tool_call_stream_maps_to_unified_events() {
         StreamEvent::ContentBlockStop { index } => assert_eq!(*index, 1),
         other => panic!("unexpected event: {other:?}"),
     }
+
+    match &events2[3] {
+        StreamEvent::MessageDelta { delta, usage } => {
+            assert_eq!(delta.stop_reason.as_deref(), Some("tool_use"));
+            assert!(usage.is_none());
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+}
+
+#[test]
+fn test_openai_usage_chunk_maps_to_message_delta_usage() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"data: {"id":"chatcmpl-1","object":"chat.completion.chunk","choices":[],"usage":{"prompt_tokens":1200,"completion_tokens":45,"prompt_tokens_details":{"cached_tokens":1000}}}
+
+"#;
+    let events = parser
+        .process(chunk)
+        .expect("openai usage chunk should parse");
+    assert_eq!(events.len(), 1);
+    match &events[0] {
+        StreamEvent::MessageDelta {
+            usage: Some(usage), ..
+        } => {
+            assert_eq!(usage.input_tokens, 200);
+            assert_eq!(usage.cache_read_input_tokens, 1000);
+            assert_eq!(usage.output_tokens, 45);
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+}
+
+#[test]
+fn test_thinking_and_signature_deltas_parse() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Check the file."}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"EqQB"}}
+
+"#;
+    let events = parser.process(chunk).expect("thinking events should parse");
+    assert_eq!(events.len(), 3);
+    match &events[0] {
+        StreamEvent::ContentBlockStart {
+            content_block: ContentBlock::Thinking { thinking, .. },
+            ..
+        } => assert!(thinking.is_empty()),
+        other => panic!("unexpected event: {other:?}"),
+    }
+    match &events[1] {
+        StreamEvent::ContentBlockDelta { delta, .. } => {
+            assert_eq!(delta.thinking.as_deref(), Some("Check the file."));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    match &events[2] {
+        StreamEvent::ContentBlockDelta { delta, .. } => {
+            assert_eq!(delta.signature.as_deref(), Some("EqQB"));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+}
+
+#[test]
+fn test_openai_reasoning_content_maps_to_thinking_block() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"data: {"choices":[{"index":0,"delta":{"reasoning_content":"Check the file."},"finish_reason":null}]}
+
+data: {"choices":[{"index":0,"delta":{"content":"Reading."},"finish_reason":null}]}
+
+data: {"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_file","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}
+
+"#;
+    let events = parser
+        .process(chunk)
+        .expect("reasoning chunks should parse");
+    let summary: Vec<String> = events
+        .iter()
+        .map(|event| match event {
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                ContentBlock::Thinking { .. } => format!("start:{index}:thinking"),
+                ContentBlock::ToolUse { name, .. } => format!("start:{index}:{name}"),
+                other => panic!("unexpected block: {other:?}"),
+            },
+            StreamEvent::ContentBlockDelta { index, delta } => {
+                if let Some(thinking) = &delta.thinking {
+                    format!("thinking:{index}:{thinking}")
+                } else if let Some(text) = &delta.text {
+                    format!("text:{index}:{text}")
+                } else {
+                    format!("json:{index}")
+                }
+            }
+            StreamEvent::ContentBlockStop { index } => format!("stop:{index}"),
+            StreamEvent::MessageDelta { delta, .. } => {
+                format!(
+                    "stop_reason:{}",
+                    delta.stop_reason.as_deref().unwrap_or("none")
+                )
+            }
+            other => panic!("unexpected event: {other:?}"),
+        })
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            "start:0:thinking",
+            "thinking:0:Check the file.",
+            "stop:0",
+            "text:1:Reading.",
+            "start:2:read_file",
+            "json:2",
+            "stop:2",
+            "stop_reason:tool_use",
+        ]
+    );
+}
+
+#[test]
+fn test_openai_reasoning_and_reasoning_content_in_one_chunk() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"data: {"choices":[{"index":0,"delta":{"reasoning_content":"Check the file.","reasoning":"Check the file."},"finish_reason":null}]}
+
+data: {"choices":[{"index":0,"delta":{"reasoning":"Then answer."},"finish_reason":null}]}
+
+"#;
+    let events = parser
+        .process(chunk)
+        .expect("chunks with both reasoning keys should parse");
+    let thinking: String = events
+        .iter()
+        .filter_map(|event| match event {
+            StreamEvent::ContentBlockDelta { delta, .. } => delta.thinking.clone(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(thinking, "Check the file.Then answer.");
+}
+
+#[test]
+fn test_openai_inline_think_tags_map_to_thinking_block() {
+    let mut parser = StreamParser::new();
+
+    let chunk =
+        br#"data: {"choices":[{"index":0,"delta":{"content":"<think>plan"},"finish_reason":null}]}
+
+data: {"choices":[{"index":0,"delta":{"content":"</think>\n\nDone."},"finish_reason":"stop"}]}
+
+"#;
+    let events = parser
+        .process(chunk)
+        .expect("think-tag chunks should parse");
+    assert!(matches!(
+        &events[0],
+        StreamEvent::ContentBlockStart {
+            content_block: ContentBlock::Thinking { .. },
+            ..
+        }
+    ));
+    let thinking: String = events
+        .iter()
+        .filter_map(|event| match event {
+            StreamEvent::ContentBlockDelta { delta, .. } => delta.thinking.clone(),
+            _ => None,
+        })
+        .collect();
+    let text: String = events
+        .iter()
+        .filter_map(|event| match event {
+            StreamEvent::ContentBlockDelta { delta, .. } => delta.text.clone(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(thinking, "plan");
+    assert_eq!(text, "Done.");
+}
+
+#[test]
+fn test_ollama_ndjson_stream_maps_text_tool_calls_and_usage() {
+    let mut parser = StreamParser::for_protocol(ApiProtocol::OllamaChat);
+
+    let chunk1 = br#"{"model":"qwen2.5-coder:14b","created_at":"2025-06-01T10:00:00Z","message":{"role":"assistant","content":"Reading "},"done":false}
+{"model":"qwen2.5-coder:14b","created_at":"2025-06-01T10:00:00Z","message":{"role":"assistant","content":"it."},"do"#;
+    let events1 = parser.process(chunk1).expect("ndjson text should parse");
+    assert_eq!(events1.len(), 1);
+    match &events1[0] {
+        StreamEvent::ContentBlockDelta { index, delta } => {
+            assert_eq!(*index, 0);
+            assert_eq!(delta.text.as_deref(), Some("Reading "));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+
+    let chunk2 = br#"ne":false}
+{"model":"qwen2.5-coder:14b","created_at":"2025-06-01T10:00:01Z","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"read_file","arguments":{"path":"cal.rs"}}}]},"done":false}
+{"model":"qwen2.5-coder:14b","created_at":"2025-06-01T10:00:01Z","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":812,"eval_count":31}
+"#;
+    let events2 = parser
+        .process(chunk2)
+        .expect("ndjson tool call should parse");
+    assert_eq!(events2.len(), 6);
+    match &events2[1] {
+        StreamEvent::ContentBlockStart {
+            index,
+            content_block: ContentBlock::ToolUse { id, name, .. },
+        } => {
+            assert_eq!(*index, 1);
+            assert_eq!(id, "toolu_ollama_1");
+            assert_eq!(name, "read_file");
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    match &events2[2] {
+        StreamEvent::ContentBlockDelta { delta, .. } => {
+            assert_eq!(delta.partial_json.as_deref(), Some(r#"{"path":"cal.rs"}"#));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert!(matches!(
+        events2[3],
+        StreamEvent::ContentBlockStop { index: 1 }
+    ));
+    match &events2[4] {
+        StreamEvent::MessageDelta {
+            delta,
+            usage: Some(usage),
+        } => {
+            assert_eq!(delta.stop_reason.as_deref(), Some("tool_use"));
+            assert_eq!(usage.input_tokens, 812);
+            assert_eq!(usage.output_tokens, 31);
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert!(matches!(events2[5], StreamEvent::MessageStop));
+}
+
+#[test]
+fn test_unterminated_final_line_is_parsed_at_end_of_stream() {
+    let mut parser = StreamParser::for_protocol(ApiProtocol::OllamaChat);
+    let events = parser
+        .process(
+            br#"{"message":{"role":"assistant","content":"Hi"},"done":false}
+{"message":{"role":"assistant","content":""},"done":true,"done_reason":"stop"}"#,
+        )
+        .expect("ndjson should parse");
+    assert_eq!(events.len(), 1);
+    let events = parser.finish().expect("final line should parse");
+    assert!(matches!(events.last(), Some(StreamEvent::MessageStop)));
+    assert!(parser.finish().expect("nothing left").is_empty());
+
+    let mut parser = StreamParser::for_protocol(ApiProtocol::AnthropicMessages);
+    let events = parser
+        .process(b"event: message_stop\ndata: {\"type\":\"message_stop\"}")
+        .expect("sse should parse");
+    assert!(events.is_empty());
+    let events = parser.finish().expect("final event should parse");
+    assert!(matches!(events.as_slice(), [StreamEvent::MessageStop]));
+}
+
+#[test]
+fn test_ollama_ndjson_thinking_and_error_lines() {
+    let mut parser = StreamParser::for_protocol(ApiProtocol::OllamaChat);
+
+    let chunk = br#"{"message":{"role":"assistant","content":"","thinking":"Check cal.rs first."},"done":false}
+{"message":{"role":"assistant","content":"Done."},"done":false}
+"#;
+    let events = parser.process(chunk).expect("ndjson thinking should parse");
+    assert_eq!(events.len(), 4);
+    match &events[0] {
+        StreamEvent::ContentBlockStart {
+            index: 0,
+            content_block: ContentBlock::Thinking { .. },
+        } => {}
+        other => panic!("unexpected event: {other:?}"),
+    }
+    match &events[1] {
+        StreamEvent::ContentBlockDelta { delta, .. } => {
+            assert_eq!(delta.thinking.as_deref(), Some("Check cal.rs first."));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert!(matches!(
+        events[2],
+        StreamEvent::ContentBlockStop { index: 0 }
+    ));
+    match &events[3] {
+        StreamEvent::ContentBlockDelta { index, delta } => {
+            assert_eq!(*index, 1);
+            assert_eq!(delta.text.as_deref(), Some("Done."));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+
+    let error = parser
+        .process(b"{\"error\":\"model 'missing' not found\"}\n")
+        .expect_err("ndjson error line should fail the stream");
+    assert!(error.to_string().contains("model 'missing' not found"));
+}
+
+#[test]
+fn test_openai_responses_events_map_to_unified_events() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"event: response.created
+data: {"type":"response.created","response":{"id":"resp_1","status":"in_progress"}}
+
+event: response.output_text.delta
+data: {"type":"response.output_text.delta","item_id":"msg_1","output_index":0,"content_index":0,"delta":"Reading it."}
+
+event: response.output_item.added
+data: {"type":"response.output_item.added","output_index":1,"item":{"type":"function_call","id":"fc_1","call_id":"call_abc","name":"read_file","arguments":""}}
+
+event: response.function_call_arguments.delta
+data: {"type":"response.function_call_arguments.delta","item_id":"fc_1","output_index":1,"delta":"{\"path\":\"cal.rs\"}"}
+
+event: response.function_call_arguments.done
+data: {"type":"response.function_call_arguments.done","item_id":"fc_1","output_index":1,"arguments":"{\"path\":\"cal.rs\"}"}
+
+event: response.output_item.done
+data: {"type":"response.output_item.done","output_index":1,"item":{"type":"function_call","id":"fc_1","call_id":"call_abc","name":"read_file","arguments":"{\"path\":\"cal.rs\"}"}}
+
+event: response.completed
+data: {"type":"response.completed","response":{"id":"resp_1","status":"completed","usage":{"input_tokens":900,"output_tokens":20,"input_tokens_details":{"cached_tokens":600}}}}
+
+"#;
+    let events = parser
+        .process(chunk)
+        .expect("responses events should parse");
+    assert_eq!(events.len(), 6);
+    match &events[0] {
+        StreamEvent::ContentBlockDelta { index, delta } => {
+            assert_eq!(*index, 0);
+            assert_eq!(delta.text.as_deref(), Some("Reading it."));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    match &events[1] {
+        StreamEvent::ContentBlockStart {
+            index,
+            content_block: ContentBlock::ToolUse { id, name, .. },
+        } => {
+            assert_eq!(*index, 1);
+            assert_eq!(id, "call_abc");
+            assert_eq!(name, "read_file");
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    match &events[2] {
+        StreamEvent::ContentBlockDelta { index, delta } => {
+            assert_eq!(*index, 1);
+            assert_eq!(delta.partial_json.as_deref(), Some(r#"{"path":"cal.rs"}"#));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert!(matches!(
+        events[3],
+        StreamEvent::ContentBlockStop { index: 1 }
+    ));
+    match &events[4] {
+        StreamEvent::MessageDelta {
+            delta,
+            usage: Some(usage),
+        } => {
+            assert_eq!(delta.stop_reason.as_deref(), Some("tool_use"));
+            assert_eq!(usage.input_tokens, 300);
+            assert_eq!(usage.cache_read_input_tokens, 600);
+            assert_eq!(usage.output_tokens, 20);
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert!(matches!(events[5], StreamEvent::MessageStop));
+}
+
+#[test]
+fn test_openai_responses_failed_event_is_an_error() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"event: response.failed
+data: {"type":"response.failed","response":{"id":"resp_2","status":"failed","error":{"code":"server_error","message":"upstream overloaded"}}}
+
+"#;
+    let error = parser
+        .process(chunk)
+        .expect_err("failed response should surface as an error");
+    assert!(error.to_string().contains("upstream overloaded"));
+}
+
+#[test]
+fn test_openai_length_finish_reason_maps_to_max_tokens() {
+    let mut parser = StreamParser::new();
+
+    let chunk = br#"data: {"choices":[{"index":0,"delta":{"content":"fn main() {"},"finish_reason":"length"}]}
+
+"#;
+    let events = parser.process(chunk).expect("length stop should parse");
+    let stop_reason = events.iter().find_map(|event| match event {
+        StreamEvent::MessageDelta { delta, .. } => delta.stop_reason.clone(),
+        _ => None,
+    });
+    assert_eq!(stop_reason.as_deref(), Some("max_tokens"));
+}
+
+#[test]
+fn test_in_stream_error_events_become_typed_api_errors() {
+    let mut parser = StreamParser::new();
+    let error = parser
+        .process(b"event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n")
+        .expect_err("anthropic error event should fail the stream");
+    assert_eq!(
+        error.downcast_ref::<ApiError>(),
+        Some(&ApiError::Overloaded {
+            message: "Overloaded".to_string()
+        })
+    );
+
+    let mut parser = StreamParser::new();
+    let error = parser
+        .process(b"data: {\"error\":{\"message\":\"maximum context length is 8192 tokens\",\"type\":\"invalid_request_error\",\"code\":\"context_length_exceeded\"}}\n\n")
+        .expect_err("openai error chunk should fail the stream");
+    assert!(matches!(
+        error.downcast_ref::<ApiError>(),
+        Some(ApiError::ContextLengthExceeded { .. })
+    ));
 }
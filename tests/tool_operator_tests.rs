@@ -274,3 +274,21 @@ fn run_git(path: &Path, args: &[&str]) {
         String::from_utf8_lossy(&output.stderr)
     );
 }
+
+#[test]
+fn test_read_file_bytes_enforces_guards_and_size_limit() {
+    let temp = TempDir::new().expect("temp dir");
+    let executor = ToolOperator::new(temp.path().to_path_buf());
+    fs::write(temp.path().join("shot.png"), [0u8; 64]).expect("seed file");
+
+    assert_eq!(
+        executor
+            .read_file_bytes("shot.png", 64)
+            .expect("file within limit")
+            .len(),
+        64
+    );
+    assert!(executor.read_file_bytes("shot.png", 63).is_err());
+    assert!(executor.read_file_bytes("../shot.png", 1024).is_err());
+    assert!(executor.read_file_bytes("/etc/hosts", 1024).is_err());
+}